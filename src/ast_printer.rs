@@ -0,0 +1,384 @@
+use crate::{
+    error::json_string,
+    expr::{Expr, ExprKind},
+    scanner::{Literal, Token, TokenKind},
+    stmt::Stmt,
+};
+
+/// Renders whole programs as s-expressions, or as JSON arrays of the same
+/// shape, for tools and golden tests. Unlike the `Display` impls on `Stmt`
+/// and `Expr`, the output is always balanced and breaks nodes that don't fit
+/// in `width` over indented lines.
+///
+/// Every node is a list headed by its kind, like `(if (identifier x) (print
+/// (literal 1)))`. Literals keep their type: strings are quoted, and in JSON
+/// numbers, booleans and `nil` become their JSON counterparts.
+#[derive(Clone, Copy, Debug)]
+pub struct AstPrinter {
+    width: usize,
+}
+
+impl Default for AstPrinter {
+    fn default() -> Self {
+        Self { width: 80 }
+    }
+}
+
+impl AstPrinter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Print nodes on one line as long as they fit in `width` columns.
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// One s-expression per top level statement, each ending in a newline.
+    pub fn print(&self, statements: &[Stmt]) -> String {
+        let mut out = String::new();
+        for stmt in statements.iter() {
+            self.render(&stmt_node(stmt), 0, &mut out);
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn print_stmt(&self, stmt: &Stmt) -> String {
+        let mut out = String::new();
+        self.render(&stmt_node(stmt), 0, &mut out);
+        out
+    }
+
+    pub fn print_expr(&self, expr: &Expr) -> String {
+        let mut out = String::new();
+        self.render(&expr_node(expr), 0, &mut out);
+        out
+    }
+
+    /// The program as a single line JSON array of statements, each an array
+    /// headed by its kind like the s-expressions.
+    pub fn to_json(&self, statements: &[Stmt]) -> String {
+        Node::List(statements.iter().map(stmt_node).collect()).json()
+    }
+
+    fn render(&self, node: &Node, indent: usize, out: &mut String) {
+        let flat = node.flat();
+        let children = match node {
+            Node::List(children) if indent + flat.len() > self.width => children,
+            _ => {
+                out.push_str(&flat);
+                return;
+            }
+        };
+        // Keep the kind and any leading atoms, like names, on the first line
+        out.push('(');
+        let leading = children
+            .iter()
+            .take_while(|child| !matches!(child, Node::List(_)))
+            .count();
+        let head: Vec<String> = children[..leading].iter().map(Node::flat).collect();
+        out.push_str(&head.join(" "));
+        for child in children[leading..].iter() {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent + 2));
+            self.render(child, indent + 2, out);
+        }
+        out.push(')');
+    }
+}
+
+/// An s-expression.
+enum Node {
+    List(Vec<Node>),
+    Symbol(String),
+    String(String),
+    Number(f64),
+    Boolean(bool),
+    Nil,
+}
+
+impl Node {
+    fn flat(&self) -> String {
+        match self {
+            Self::List(children) => format!(
+                "({})",
+                children
+                    .iter()
+                    .map(Node::flat)
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
+            Self::Symbol(symbol) => symbol.clone(),
+            Self::String(value) => json_string(value),
+            Self::Number(value) => value.to_string(),
+            Self::Boolean(value) => value.to_string(),
+            Self::Nil => "nil".into(),
+        }
+    }
+
+    fn json(&self) -> String {
+        match self {
+            Self::List(children) => format!(
+                "[{}]",
+                children
+                    .iter()
+                    .map(Node::json)
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            Self::Symbol(value) | Self::String(value) => json_string(value),
+            // JSON has no infinity or NaN
+            Self::Number(value) if !value.is_finite() => json_string(&value.to_string()),
+            Self::Nil => "null".into(),
+            node => node.flat(),
+        }
+    }
+}
+
+fn list(kind: &str, children: impl IntoIterator<Item = Node>) -> Node {
+    let mut nodes = vec![symbol(kind)];
+    nodes.extend(children);
+    Node::List(nodes)
+}
+
+fn symbol(name: impl Into<String>) -> Node {
+    Node::Symbol(name.into())
+}
+
+fn name(token: &Token) -> Node {
+    symbol(token.lexeme_str())
+}
+
+fn literal(token: &Token) -> Node {
+    match (&token.literal, token.kind) {
+        (Some(Literal::Number(value)), _) => Node::Number(*value),
+        (Some(Literal::String(value)), _) => Node::String(value.clone()),
+        (Some(Literal::True), _) | (None, TokenKind::True) => Node::Boolean(true),
+        (Some(Literal::False), _) | (None, TokenKind::False) => Node::Boolean(false),
+        (None, TokenKind::Nil) => Node::Nil,
+        (None, _) => name(token),
+    }
+}
+
+fn stmt_node(stmt: &Stmt) -> Node {
+    match stmt {
+        Stmt::Expr(expr) => list("expr", [expr_node(expr)]),
+        Stmt::Print(expr) => list("print", [expr_node(expr)]),
+        Stmt::Var {
+            name: var,
+            initializer,
+            mutable,
+        } => list(
+            if *mutable { "var" } else { "const" },
+            [name(var)]
+                .into_iter()
+                .chain(initializer.as_deref().map(expr_node)),
+        ),
+        Stmt::Destructure { names, initializer } => list(
+            "var",
+            [
+                Node::List(names.iter().map(name).collect()),
+                expr_node(initializer),
+            ],
+        ),
+        Stmt::Block { statements, .. } => list("block", statements.iter().map(stmt_node)),
+        Stmt::IfElse {
+            condition,
+            body,
+            else_branch,
+        } => list(
+            "if",
+            [expr_node(condition), stmt_node(body)]
+                .into_iter()
+                .chain(else_branch.as_deref().map(stmt_node)),
+        ),
+        Stmt::WhileLoop { condition, body } => {
+            list("while", [expr_node(condition), stmt_node(body)])
+        }
+        Stmt::ForLoop {
+            initializer,
+            condition,
+            increment,
+            body,
+        } => list(
+            "for",
+            [
+                stmt_node(initializer),
+                expr_node(condition),
+                expr_node(increment),
+                stmt_node(body),
+            ],
+        ),
+        Stmt::ForIn {
+            name: var,
+            iterable,
+            body,
+        } => list("for", [name(var), expr_node(iterable), stmt_node(body)]),
+        Stmt::Fun {
+            name: fun,
+            params,
+            rest,
+            body,
+            kind,
+        } => {
+            let mut params: Vec<Node> = params.iter().map(name).collect();
+            if let (true, Some(Node::Symbol(last))) = (rest, params.last_mut()) {
+                last.insert_str(0, "...");
+            }
+            list(
+                &kind.to_string(),
+                [name(fun), Node::List(params)]
+                    .into_iter()
+                    .chain(body.iter().map(stmt_node)),
+            )
+        }
+        Stmt::Return(value) => list("return", [expr_node(value)]),
+        Stmt::Defer { body, .. } => list("defer", [stmt_node(body)]),
+        Stmt::Throw { value, .. } => list("throw", [expr_node(value)]),
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+            ..
+        } => list(
+            "try",
+            [stmt_node(body)]
+                .into_iter()
+                .chain(
+                    catch
+                        .iter()
+                        .map(|(var, handler)| list("catch", [name(var), stmt_node(handler)])),
+                )
+                .chain(
+                    finally
+                        .iter()
+                        .map(|finally| list("finally", [stmt_node(finally)])),
+                ),
+        ),
+        Stmt::Using {
+            name: var,
+            initializer,
+            body,
+            ..
+        } => list(
+            "using",
+            [name(var), expr_node(initializer), stmt_node(body)],
+        ),
+        Stmt::Class {
+            name: class,
+            superclass,
+            methods,
+        } => list(
+            "class",
+            [name(class)]
+                .into_iter()
+                .chain(
+                    superclass
+                        .iter()
+                        .map(|superclass| list("<", [expr_node(superclass)])),
+                )
+                .chain(methods.iter().map(|member| match member {
+                    Stmt::Var {
+                        name: field,
+                        initializer: Some(initializer),
+                        ..
+                    } => list("static", [name(field), expr_node(initializer)]),
+                    method => stmt_node(method),
+                })),
+        ),
+    }
+}
+
+fn expr_node(expr: &Expr) -> Node {
+    match &expr.kind {
+        ExprKind::Literal(token) => list("literal", [literal(token)]),
+        ExprKind::Unary { operator, right } => list(&operator.lexeme_str(), [expr_node(right)]),
+        ExprKind::Binary {
+            operator,
+            left,
+            right,
+        }
+        | ExprKind::Logical {
+            operator,
+            left,
+            right,
+        } => list(&operator.lexeme_str(), [expr_node(left), expr_node(right)]),
+        ExprKind::Grouping(inner) => list("grouping", [expr_node(inner)]),
+        ExprKind::Identifier(token) => list("identifier", [name(token)]),
+        ExprKind::Assignment { name: var, value } => list("=", [name(var), expr_node(value)]),
+        ExprKind::Call { callee, arguments } => list(
+            "call",
+            [expr_node(callee)]
+                .into_iter()
+                .chain(arguments.iter().map(expr_node)),
+        ),
+        ExprKind::Get { left, name, .. } => list("get", [expr_node(left), symbol(&**name)]),
+        ExprKind::Set {
+            object,
+            name,
+            operator,
+            value,
+            ..
+        } => list(
+            &operator.as_ref().map_or("set".into(), |operator| {
+                format!("{}=", operator.lexeme_str())
+            }),
+            [expr_node(object), symbol(&**name), expr_node(value)],
+        ),
+        ExprKind::This(_) => list("this", []),
+        ExprKind::Super(method) => list("super", [name(method)]),
+        ExprKind::Tuple(values) => list("tuple", values.iter().map(expr_node)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse;
+
+    fn printed(source: &str, width: usize) -> String {
+        let statements = parse(source).statements;
+        AstPrinter::new().with_width(width).print(&statements)
+    }
+
+    #[test]
+    fn s_expressions() {
+        assert_eq!(
+            printed("if (x > 1) print \"big\"; else { x = nil; }", 100),
+            "(if (> (identifier x) (literal 1)) (print (literal \"big\")) (block (expr (= x (literal nil)))))\n"
+        );
+        assert_eq!(
+            printed("while (true) f(1, a.b);", 100),
+            "(while (literal true) (expr (call (identifier f) (literal 1) (get (identifier a) b))))\n"
+        );
+        assert_eq!(
+            printed(
+                "class B < A { static N = 1; init(...args) { super.init(); } }\nconst c = 1;",
+                30
+            ),
+            r#"(class B
+  (< (identifier A))
+  (static N (literal 1))
+  (fun init
+    (...args)
+    (expr (call (super init)))))
+(const c (literal 1))
+"#
+        );
+        assert_eq!(
+            printed("try { throw \"oops\"; } catch (e) {} finally {}", 100),
+            "(try (block (throw (literal \"oops\"))) (catch e (block)) (finally (block)))\n"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let statements = parse("var x = 1.5; print \"a\" == nil or !false;").statements;
+        assert_eq!(
+            AstPrinter::new().to_json(&statements),
+            r#"[["var","x",["literal",1.5]],["print",["or",["==",["literal","a"],["literal",null]],["!",["literal",false]]]]]"#
+        );
+    }
+}
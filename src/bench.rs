@@ -0,0 +1,86 @@
+use lox::{LoxInterpreter, LoxResult};
+use std::{
+    cell::RefCell,
+    fmt,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// The scripts `lox bench` runs when none are given.
+pub const SUITE: [(&str, &str); 3] = [
+    ("fib", include_str!("../scripts/bench/fib.lox")),
+    ("loop", include_str!("../scripts/bench/loop.lox")),
+    ("strings", include_str!("../scripts/bench/strings.lox")),
+];
+
+/// How long each run of a script took.
+pub struct Measurement {
+    pub name: String,
+    samples: Vec<Duration>,
+}
+
+impl Measurement {
+    /// Run `source` once to warm up, then `iterations` times, each with a
+    /// fresh interpreter. Output is discarded.
+    pub fn run(name: &str, source: &str, iterations: usize) -> LoxResult<Self> {
+        run_once(source)?;
+        let mut samples = Vec::with_capacity(iterations);
+        for _ in 0..iterations.max(1) {
+            samples.push(run_once(source)?);
+        }
+        samples.sort();
+        Ok(Self {
+            name: name.into(),
+            samples,
+        })
+    }
+
+    pub fn min(&self) -> Duration {
+        self.samples[0]
+    }
+
+    pub fn median(&self) -> Duration {
+        self.samples[self.samples.len() / 2]
+    }
+
+    pub fn mean(&self) -> Duration {
+        self.samples.iter().sum::<Duration>() / self.samples.len() as u32
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<12} min {:>10.3?}  median {:>10.3?}  mean {:>10.3?}  ({} runs)",
+            self.name,
+            self.min(),
+            self.median(),
+            self.mean(),
+            self.samples.len()
+        )
+    }
+}
+
+fn run_once(source: &str) -> LoxResult<Duration> {
+    let mut lox = LoxInterpreter::new();
+    lox.set_output(Box::new(Rc::new(RefCell::new(String::new()))));
+    let start = Instant::now();
+    lox.exec(source)?;
+    Ok(start.elapsed())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn suite() -> LoxResult {
+        for (name, source) in SUITE.iter() {
+            let measurement = Measurement::run(name, source, 1)?;
+            assert!(measurement.min() <= measurement.median());
+            assert!(measurement.to_string().starts_with(name));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,680 @@
+use super::{array_values, new_array, BuiltinPack};
+use crate::{
+    class::*, environment::*, error::*, function::*, object::*, pretty, suggest::did_you_mean,
+    value::*,
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+/// Language essentials: the Array and Map classes and reflection helpers.
+pub struct CorePack;
+
+impl BuiltinPack for CorePack {
+    fn name(&self) -> &'static str {
+        "core"
+    }
+
+    fn install(&self, env: &mut Environment) {
+        let class_array = LoxClass {
+            name: "Array".into(),
+            superclass: None,
+            methods: {
+                let init = LoxFunction::native("init", vec![], |_, _, meta| {
+                    let this = meta
+                        .this_value
+                        .expect("Expected a this value")
+                        .get_object(meta.line)?;
+                    this.borrow_mut()
+                        .set("__vec__".into(), Vec::<LoxValue>::new().into());
+                    Ok(LoxValue::Nil)
+                });
+
+                let method_len = LoxFunction::native("len", vec![], |_, _, meta| {
+                    let __vec__ = vec_of(&meta)?;
+                    let len = __vec__.borrow().len() as f64;
+                    Ok(len.into())
+                });
+
+                let method_get = LoxFunction::native("get", vec!["index"], |_, args, meta| {
+                    if args.is_empty() {
+                        return Err(LoxError::Runtime("Expected 1 argument".into(), meta.line));
+                    }
+                    let index = args[0].get_number(meta.line)? as usize;
+                    let __vec__ = vec_of(&meta)?;
+                    if index > __vec__.borrow().len() {
+                        return Err(LoxError::Runtime(format!("Index {index} out of range"), 0));
+                    }
+                    let elem = &__vec__.borrow()[index];
+                    Ok(elem.clone())
+                });
+
+                let method_set =
+                    LoxFunction::native("set", vec!["index", "value"], |_, args, meta| {
+                        if args.len() < 2 {
+                            return Err(LoxError::Runtime(
+                                "Expected 2 arguments".into(),
+                                meta.line,
+                            ));
+                        }
+                        let index = args[0].get_number(meta.line)? as usize;
+                        let __vec__ = vec_of(&meta)?;
+                        if index > __vec__.borrow().len() {
+                            return Err(LoxError::Runtime(
+                                format!("Index {index} out of range"),
+                                meta.line,
+                            ));
+                        }
+                        __vec__.borrow_mut()[index] = args[1].clone();
+                        Ok(LoxValue::Nil)
+                    });
+
+                let method_push = LoxFunction::native("push", vec!["value"], |_, args, meta| {
+                    if args.is_empty() {
+                        return Err(LoxError::Runtime("Expected 1 argument".into(), meta.line));
+                    }
+                    let __vec__ = vec_of(&meta)?;
+                    __vec__.borrow_mut().push(args[0].clone());
+                    Ok(LoxValue::Nil)
+                });
+
+                let method_pop = LoxFunction::native("pop", vec![], |_, _, meta| {
+                    let __vec__ = vec_of(&meta)?;
+                    let value = __vec__.borrow_mut().pop();
+                    Ok(value.unwrap_or(LoxValue::Nil))
+                });
+
+                // `+` concatenates into a new Array
+                let method_add =
+                    LoxFunction::native("__add__", vec!["other"], |state, args, meta| {
+                        let this = meta.this_value.expect("Expected a this value");
+                        let mut values = array_values(&this, meta.line)?;
+                        values.extend(array_values(&args[0], meta.line)?);
+                        new_array(state, values, meta.line)
+                    });
+
+                let mut methods = HashMap::<String, LoxFunction>::new();
+                methods.insert("init".into(), init);
+                methods.insert("len".into(), method_len);
+                methods.insert("get".into(), method_get);
+                methods.insert("set".into(), method_set);
+                methods.insert("push".into(), method_push);
+                methods.insert("pop".into(), method_pop);
+                methods.insert("__add__".into(), method_add);
+                methods
+            },
+            setters: HashMap::new(),
+            statics: HashMap::new(),
+            static_fields: HashMap::new(),
+            origin: None,
+        };
+
+        env.define_builtin("Array".into(), class_array.into());
+
+        let class_map = LoxClass {
+            name: "Map".into(),
+            superclass: None,
+            methods: {
+                let init = LoxFunction::native("init", vec![], |state, _, meta| {
+                    let this = meta
+                        .this_value
+                        .expect("Expected a this value")
+                        .get_object(meta.line)?;
+                    this.borrow_mut()
+                        .set("__map__".into(), state.env.new_properties().into());
+                    Ok(LoxValue::Nil)
+                });
+
+                // Missing keys are `nil`, use `has` to tell them apart
+                let method_get = LoxFunction::native("get", vec!["key"], |_, args, meta| {
+                    let key = args[0].get_string(meta.line)?;
+                    let __map__ = map_of(&meta)?;
+                    let value = __map__.borrow().get(&key).cloned();
+                    Ok(value.unwrap_or(LoxValue::Nil))
+                });
+
+                let method_set =
+                    LoxFunction::native("set", vec!["key", "value"], |_, args, meta| {
+                        let key = args[0].get_string(meta.line)?;
+                        map_of(&meta)?.borrow_mut().insert(key, args[1].clone());
+                        Ok(LoxValue::Nil)
+                    });
+
+                let method_has = LoxFunction::native("has", vec!["key"], |_, args, meta| {
+                    let key = args[0].get_string(meta.line)?;
+                    let has = map_of(&meta)?.borrow().contains_key(&key);
+                    Ok(has.into())
+                });
+
+                let method_remove = LoxFunction::native("remove", vec!["key"], |_, args, meta| {
+                    let key = args[0].get_string(meta.line)?;
+                    let value = map_of(&meta)?.borrow_mut().remove(&key);
+                    Ok(value.unwrap_or(LoxValue::Nil))
+                });
+
+                let method_keys = LoxFunction::native("keys", vec![], |state, _, meta| {
+                    let keys = sorted_entries(&map_of(&meta)?.borrow())
+                        .into_iter()
+                        .map(|(key, _)| LoxValue::from(key))
+                        .collect();
+                    new_array(state, keys, meta.line)
+                });
+
+                let method_len = LoxFunction::native("len", vec![], |_, _, meta| {
+                    let len = map_of(&meta)?.borrow().len() as f64;
+                    Ok(len.into())
+                });
+
+                // `+` merges into a new Map, keys on the right take precedence
+                let method_add =
+                    LoxFunction::native("__add__", vec!["other"], |state, args, meta| {
+                        let mut entries = map_of(&meta)?.borrow().clone();
+                        let other = args[0]
+                            .get_object(meta.line)?
+                            .borrow()
+                            .get("__map__")
+                            .ok_or_else(|| LoxError::Runtime("Expected a Map".into(), meta.line))?
+                            .get_map(meta.line)?;
+                        entries.extend(
+                            other
+                                .borrow()
+                                .iter()
+                                .map(|(key, value)| (key.clone(), value.clone())),
+                        );
+                        let class_map = state
+                            .env
+                            .get(None, "Map")
+                            .ok_or_else(|| {
+                                LoxError::Runtime(
+                                    "Expected the Map builtin to exist".into(),
+                                    meta.line,
+                                )
+                            })?
+                            .get_class(meta.line)?;
+                        let merged = LoxClass::instantiate(&class_map, state, &[], meta.line)?;
+                        merged
+                            .get_object(meta.line)?
+                            .borrow_mut()
+                            .set("__map__".into(), entries.into());
+                        Ok(merged)
+                    });
+
+                let mut methods = HashMap::<String, LoxFunction>::new();
+                methods.insert("init".into(), init);
+                methods.insert("get".into(), method_get);
+                methods.insert("set".into(), method_set);
+                methods.insert("has".into(), method_has);
+                methods.insert("remove".into(), method_remove);
+                methods.insert("keys".into(), method_keys);
+                methods.insert("len".into(), method_len);
+                methods.insert("__add__".into(), method_add);
+                methods
+            },
+            setters: HashMap::new(),
+            statics: HashMap::new(),
+            static_fields: HashMap::new(),
+            origin: None,
+        };
+
+        env.define_builtin("Map".into(), class_map.into());
+
+        let func_get_prop =
+            LoxFunction::native("get_prop", vec!["object", "name"], |state, args, meta| {
+                let obj = args[0].get_object(meta.line)?;
+                let name = args[1].get_string(meta.line)?;
+                let value = state.get_property(&obj, &name, meta.line)?;
+                value.ok_or_else(|| {
+                    let suggestion = did_you_mean(&name, &LoxObject::property_names(&obj));
+                    LoxError::Runtime(
+                        format!("Undefined property \"{}\"{}", name, suggestion),
+                        meta.line,
+                    )
+                })
+            });
+
+        env.define_builtin("get_prop".into(), func_get_prop.into());
+
+        let func_set_prop = LoxFunction::native(
+            "set_prop",
+            vec!["object", "name", "value"],
+            |state, args, meta| {
+                let obj = args[0].get_object(meta.line)?;
+                let name = args[1].get_string(meta.line)?;
+                state.set_property(&obj, &name, args[2].clone(), meta.line)?;
+                Ok(args[2].clone())
+            },
+        );
+
+        env.define_builtin("set_prop".into(), func_set_prop.into());
+
+        let func_call = LoxFunction::native("call", vec!["callee", "args"], |state, args, meta| {
+            let values = array_values(&args[1], meta.line)?;
+            args[0].call(state, values, meta.line)
+        });
+
+        env.define_builtin("call".into(), func_call.into());
+
+        let func_methods_of =
+            LoxFunction::native("methods_of", vec!["value"], |state, args, meta| {
+                let names = match &args[0] {
+                    LoxValue::Class(class) => class.borrow().method_names(),
+                    LoxValue::Object(obj) => LoxObject::method_names(obj),
+                    value => {
+                        return Err(LoxError::Runtime(
+                            format!(
+                                "Expected Class or Object, got \"{}\"{}",
+                                value.type_str(),
+                                value.origin_note()
+                            ),
+                            meta.line,
+                        ))
+                    }
+                };
+                new_array(
+                    state,
+                    names.into_iter().map(LoxValue::from).collect(),
+                    meta.line,
+                )
+            });
+
+        env.define_builtin("methods_of".into(), func_methods_of.into());
+
+        let func_fields_of =
+            LoxFunction::native("fields_of", vec!["object"], |state, args, meta| {
+                let names = LoxObject::field_names(&args[0].get_object(meta.line)?);
+                new_array(
+                    state,
+                    names.into_iter().map(LoxValue::from).collect(),
+                    meta.line,
+                )
+            });
+
+        env.define_builtin("fields_of".into(), func_fields_of.into());
+
+        let func_type_of = LoxFunction::native("type_of", vec!["value"], |_, args, _| {
+            Ok(args[0].type_str().into())
+        });
+
+        env.define_builtin("type_of".into(), func_type_of.into());
+
+        let func_repr = LoxFunction::native("repr", vec!["value"], |state, args, meta| {
+            Ok(pretty::repr(&args[0], state, meta.line)?.into())
+        });
+
+        env.define_builtin("repr".into(), func_repr.into());
+
+        let func_deep_equals = LoxFunction::native("deep_equals", vec!["a", "b"], |_, args, _| {
+            Ok(args[0].deep_equals(&args[1]).into())
+        });
+
+        env.define_builtin("deep_equals".into(), func_deep_equals.into());
+
+        // Replaces the handler, nil removes it. See `LoxState::recover`.
+        let func_on_error =
+            LoxFunction::native("on_error", vec!["handler"], |state, args, meta| {
+                match &args[0] {
+                    LoxValue::Nil => state.error_handler = None,
+                    handler @ (LoxValue::Function(_) | LoxValue::Class(_)) => {
+                        state.error_handler = Some(handler.clone())
+                    }
+                    value => {
+                        return Err(LoxError::Type("a function or nil".into(), value.type_str())
+                            .at_line(meta.line))
+                    }
+                }
+                Ok(LoxValue::Nil)
+            });
+
+        env.define_builtin("on_error".into(), func_on_error.into());
+
+        // Fields or methods, without calling getters. Anything other than an
+        // instance has no properties.
+        let func_has_property =
+            LoxFunction::native("has_property", vec!["value", "name"], |_, args, meta| {
+                let name = args[1].get_string(meta.line)?;
+                Ok(match &args[0] {
+                    LoxValue::Object(obj) => LoxObject::lookup(obj, &name).is_some(),
+                    _ => false,
+                }
+                .into())
+            });
+
+        env.define_builtin("has_property".into(), func_has_property.into());
+
+        let func_properties =
+            LoxFunction::native("properties", vec!["object"], |state, args, meta| {
+                let mut names = LoxObject::property_names(&args[0].get_object(meta.line)?);
+                names.sort();
+                names.dedup();
+                new_array(
+                    state,
+                    names.into_iter().map(LoxValue::from).collect(),
+                    meta.line,
+                )
+            });
+
+        env.define_builtin("properties".into(), func_properties.into());
+
+        let func_class_of = LoxFunction::native("class_of", vec!["value"], |_, args, _| {
+            Ok(match &args[0] {
+                LoxValue::Object(obj) => obj.borrow().class.clone().into(),
+                _ => LoxValue::Nil,
+            })
+        });
+
+        env.define_builtin("class_of".into(), func_class_of.into());
+    }
+}
+
+/// The elements backing the Array instance a method was called on.
+fn vec_of(meta: &FunctionCallMetadata) -> LoxResult<Rc<RefCell<Vec<LoxValue>>>> {
+    internal_prop(meta, "__vec__", "Array")?.get_vec(meta.line)
+}
+
+/// The entries backing the Map instance a method was called on.
+fn map_of(meta: &FunctionCallMetadata) -> LoxResult<Rc<RefCell<LoxProperties>>> {
+    internal_prop(meta, "__map__", "Map")?.get_map(meta.line)
+}
+
+/// Read the internal prop `key` a builtin class's `init` sets, which is
+/// missing if a subclass's `init` doesn't call `super.init()`.
+fn internal_prop(meta: &FunctionCallMetadata, key: &str, class_name: &str) -> LoxResult<LoxValue> {
+    meta.this_value
+        .as_ref()
+        .expect("Expected a this value")
+        .get_object(meta.line)?
+        .borrow()
+        .get(key)
+        .ok_or_else(|| {
+            LoxError::Runtime(
+                format!(
+                    "{} was not initialized, call super.init() from init()",
+                    class_name
+                ),
+                meta.line,
+            )
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::*, interpreter::*};
+    use mock_logger::MockLogger;
+
+    #[test]
+    fn array() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            var arr = Array();
+            arr.push(1);
+            arr.push(2);
+            arr.push(3);
+            arr.pop();
+            arr.set(1, 4);
+            print arr.len();
+            print arr.get(0);
+            print arr.get(1);
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 3);
+            assert_eq!(entries[0].body, "2");
+            assert_eq!(entries[1].body, "1");
+            assert_eq!(entries[2].body, "4");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn deep_equals() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            class Node {
+                init(value) {
+                    this.value = value;
+                    this.next = nil;
+                }
+            }
+            var a = Node(1);
+            var b = Node(1);
+            print a == b;
+            print a == a;
+            print deep_equals(a, b);
+            a.next = a;
+            b.next = b;
+            print deep_equals(a, b);
+            b.value = 2;
+            print deep_equals(a, b);
+            var xs = Array();
+            xs.push(1);
+            var ys = Array();
+            ys.push(1);
+            print xs == ys;
+            print deep_equals(xs, ys);
+            print deep_equals(1, 1);
+            print deep_equals(xs, 1);
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            let bodies: Vec<&str> = entries.iter().map(|entry| entry.body.as_str()).collect();
+            assert_eq!(
+                bodies,
+                vec!["false", "true", "true", "true", "false", "false", "true", "true", "false"]
+            );
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn map() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            var ages = Map();
+            ages.set("bob", 32);
+            ages.set("alice", 30);
+            ages.set("carol", 41);
+            print ages.get("alice");
+            print ages.get("dave");
+            print ages.has("bob");
+            print ages.remove("bob");
+            print ages.has("bob");
+            print ages.len();
+            var keys = ages.keys();
+            print keys.get(0) + " " + keys.get(1);
+            for (var name in ages) print name;
+            var more = Map();
+            more.set("alice", 31);
+            more.set("dave", 25);
+            var merged = ages + more;
+            print merged.len();
+            print merged.get("alice");
+            print ages.get("alice");
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            let bodies: Vec<&str> = entries.iter().map(|entry| entry.body.as_str()).collect();
+            assert_eq!(
+                bodies,
+                vec![
+                    "30",
+                    "nil",
+                    "true",
+                    "32",
+                    "false",
+                    "2",
+                    "alice carol",
+                    "alice",
+                    "carol",
+                    "3",
+                    "31",
+                    "30"
+                ]
+            );
+        });
+        assert!(lox.exec("Map().set(1, 2);").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reflective_props() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            class Point {
+                init(x) {
+                    this.x = x;
+                }
+            }
+            var point = Point(1);
+            var name = "y";
+            set_prop(point, name, 2);
+            print point.y;
+            print get_prop(point, "x") + get_prop(point, name);
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].body, "2");
+            assert_eq!(entries[1].body, "3");
+        });
+        assert!(lox.exec("get_prop(Point(1), \"z\");").is_err());
+        assert!(lox.exec("set_prop(1, \"z\", 2);").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn dynamic_call() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            fun greet(name) {
+                return "Hello, " + name;
+            }
+            class Counter {
+                init(start) {
+                    this.count = start;
+                }
+
+                add(amount) {
+                    this.count = this.count + amount;
+                    return this.count;
+                }
+            }
+            var args = Array();
+            args.push("world");
+            print call(greet, args);
+            var initial = Array();
+            initial.push(10);
+            var counter = call(Counter, initial);
+            print call(counter.add, initial);
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].body, "Hello, world");
+            assert_eq!(entries[1].body, "20");
+        });
+        assert!(lox.exec("call(Counter, Array());").is_err());
+        assert!(lox.exec("call(1, Array());").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn enumeration() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            class Shape {
+                area() {
+                    return 0;
+                }
+            }
+            class Square < Shape {
+                init(size) {
+                    this.size = size;
+                }
+
+                describe() {
+                    return "square";
+                }
+            }
+            var square = Square(2);
+            square.name = "unit";
+            var methods = methods_of(Square);
+            print methods.len();
+            print methods.get(0);
+            print methods_of(square).get(1);
+            var fields = fields_of(square);
+            print fields.len();
+            print fields.get(0);
+            print fields.get(1);
+            print fields_of(Array()).len();
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 7);
+            assert_eq!(entries[0].body, "3");
+            assert_eq!(entries[1].body, "area");
+            assert_eq!(entries[2].body, "describe");
+            assert_eq!(entries[3].body, "2");
+            assert_eq!(entries[4].body, "name");
+            assert_eq!(entries[5].body, "size");
+            assert_eq!(entries[6].body, "0");
+        });
+        assert!(lox.exec("methods_of(1);").is_err());
+        assert!(lox.exec("fields_of(Square);").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reflection() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            class Shape {
+                area() {
+                    return 0;
+                }
+            }
+            class Square < Shape {
+                init(size) {
+                    this.size = size;
+                }
+            }
+            var square = Square(2);
+            print type_of(square) + " " + type_of(1) + " " + type_of("a") + " " + type_of(nil);
+            print has_property(square, "size");
+            print has_property(square, "area");
+            print has_property(square, "color");
+            print has_property(1, "size");
+            var props = properties(square);
+            print props.get(0) + " " + props.get(1) + " " + props.get(2);
+            print class_of(square) == Square;
+            print class_of(square)(3).size;
+            print class_of(1);
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 9);
+            assert_eq!(entries[0].body, "Object Number String nil");
+            assert_eq!(entries[1].body, "true");
+            assert_eq!(entries[2].body, "true");
+            assert_eq!(entries[3].body, "false");
+            assert_eq!(entries[4].body, "false");
+            assert_eq!(entries[5].body, "area init size");
+            assert_eq!(entries[6].body, "true");
+            assert_eq!(entries[7].body, "3");
+            assert_eq!(entries[8].body, "nil");
+        });
+        assert!(lox.exec("properties(1);").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use super::BuiltinPack;
+use crate::{environment::*, function::*};
+
+/// Runtime metrics for investigating recursion and memory use during
+/// development. Only installed when asked for, with the `debug` option or by
+/// listing the pack.
+pub struct DebugPack;
+
+impl BuiltinPack for DebugPack {
+    fn name(&self) -> &'static str {
+        "debug"
+    }
+
+    fn opt_in(&self) -> bool {
+        true
+    }
+
+    fn install(&self, env: &mut Environment) {
+        let func_stack_depth = LoxFunction::native("debug_stack_depth", vec![], |state, _, _| {
+            // Every script function call in progress has a return slot
+            Ok((state.stack.len() as f64).into())
+        });
+
+        env.define_builtin("debug_stack_depth".into(), func_stack_depth.into());
+
+        let func_scope_count = LoxFunction::native("debug_scope_count", vec![], |state, _, _| {
+            Ok((state.env.scope_count() as f64).into())
+        });
+
+        env.define_builtin("debug_scope_count".into(), func_scope_count.into());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{config::LoxConfig, error::*, interpreter::*, value::LoxValue};
+
+    #[test]
+    fn metrics() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        assert!(lox.exec("debug_stack_depth();").is_err());
+
+        let mut lox = LoxInterpreter::with_config(LoxConfig {
+            debug: true,
+            ..Default::default()
+        });
+        lox.exec(
+            r#"
+            fun depth(n) {
+                if (n == 0) return debug_stack_depth();
+                return depth(n - 1);
+            }
+        "#,
+        )?;
+        assert!(lox.eval("debug_stack_depth();")? == 0.0.into());
+        assert!(lox.eval("depth(3);")? == 4.0.into());
+        let LoxValue::Number(scopes) = lox.eval("debug_scope_count();")? else {
+            panic!("Expected a number");
+        };
+        assert_eq!(scopes as usize, lox.scope_count());
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use super::BuiltinPack;
+use crate::{class::*, environment::*, error::*, function::*, value::*};
+use std::collections::HashMap;
+
+/// Reading and writing text files, whole or through `File` handles opened
+/// with `open(path)`. Files are accessed through the interpreter's
+/// `FileSystem`.
+pub struct FsPack;
+
+impl BuiltinPack for FsPack {
+    fn name(&self) -> &'static str {
+        "fs"
+    }
+
+    fn install(&self, env: &mut Environment) {
+        let func_read_file = LoxFunction::native("read_file", vec!["path"], |state, args, meta| {
+            let path = args[0].get_string(meta.line)?;
+            let contents = state
+                .fs
+                .read_to_string(&path)
+                .map_err(|err| io_error("read", &path, err, meta.line))?;
+            Ok(contents.into())
+        });
+
+        env.define_builtin("read_file".into(), func_read_file.into());
+
+        let func_write_file = LoxFunction::native(
+            "write_file",
+            vec!["path", "contents"],
+            |state, args, meta| {
+                let path = args[0].get_string(meta.line)?;
+                let contents = args[1].get_string(meta.line)?;
+                state
+                    .fs
+                    .write(&path, &contents)
+                    .map_err(|err| io_error("write", &path, err, meta.line))?;
+                Ok(LoxValue::Nil)
+            },
+        );
+
+        env.define_builtin("write_file".into(), func_write_file.into());
+
+        let func_append_file = LoxFunction::native(
+            "append_file",
+            vec!["path", "contents"],
+            |state, args, meta| {
+                let path = args[0].get_string(meta.line)?;
+                let contents = args[1].get_string(meta.line)?;
+                state
+                    .fs
+                    .append(&path, &contents)
+                    .map_err(|err| io_error("append to", &path, err, meta.line))?;
+                Ok(LoxValue::Nil)
+            },
+        );
+
+        env.define_builtin("append_file".into(), func_append_file.into());
+
+        let class_file = LoxClass {
+            name: "File".into(),
+            superclass: None,
+            methods: {
+                let init = LoxFunction::native("init", vec!["path"], |state, args, meta| {
+                    let path = args[0].get_string(meta.line)?;
+                    // Creating the file up front reports a bad path at `open`
+                    state
+                        .fs
+                        .append(&path, "")
+                        .map_err(|err| io_error("open", &path, err, meta.line))?;
+                    let this = meta
+                        .this_value
+                        .expect("Expected a this value")
+                        .get_object(meta.line)?;
+                    let mut this = this.borrow_mut();
+                    this.set("__path__".into(), path.into());
+                    this.set("__open__".into(), true.into());
+                    Ok(LoxValue::Nil)
+                });
+
+                let method_read = LoxFunction::native("read", vec![], |state, _, meta| {
+                    let path = open_path(meta.this_value, meta.line)?;
+                    let contents = state
+                        .fs
+                        .read_to_string(&path)
+                        .map_err(|err| io_error("read", &path, err, meta.line))?;
+                    Ok(contents.into())
+                });
+
+                let method_write =
+                    LoxFunction::native("write", vec!["text"], |state, args, meta| {
+                        let path = open_path(meta.this_value, meta.line)?;
+                        let text = args[0].get_string(meta.line)?;
+                        state
+                            .fs
+                            .append(&path, &text)
+                            .map_err(|err| io_error("write", &path, err, meta.line))?;
+                        Ok(LoxValue::Nil)
+                    });
+
+                let method_close = LoxFunction::native("close", vec![], |_, _, meta| {
+                    let this = meta
+                        .this_value
+                        .expect("Expected a this value")
+                        .get_object(meta.line)?;
+                    this.borrow_mut().set("__open__".into(), false.into());
+                    Ok(LoxValue::Nil)
+                });
+
+                let method_is_open = LoxFunction::native("is_open", vec![], |_, _, meta| {
+                    let this = meta
+                        .this_value
+                        .expect("Expected a this value")
+                        .get_object(meta.line)?;
+                    let open = this.borrow().get("__open__").unwrap_or(LoxValue::Nil);
+                    Ok(open.is_truthy().into())
+                });
+
+                let mut methods = HashMap::<String, LoxFunction>::new();
+                methods.insert("init".into(), init);
+                methods.insert("read".into(), method_read);
+                methods.insert("write".into(), method_write);
+                methods.insert("close".into(), method_close);
+                methods.insert("is_open".into(), method_is_open);
+                methods
+            },
+            setters: HashMap::new(),
+            statics: HashMap::new(),
+            static_fields: HashMap::new(),
+            origin: None,
+        };
+
+        env.define_builtin("File".into(), class_file.into());
+
+        let func_open = LoxFunction::native("open", vec!["path"], |state, args, meta| {
+            let class_file = state
+                .env
+                .get(None, "File")
+                .ok_or_else(|| {
+                    LoxError::Runtime("Expected the File builtin to exist".into(), meta.line)
+                })?
+                .get_class(meta.line)?;
+            LoxClass::instantiate(&class_file, state, args, meta.line)
+        });
+
+        env.define_builtin("open".into(), func_open.into());
+    }
+}
+
+/// The path of a `File` handle, unless it was closed.
+fn open_path(this: Option<LoxValue>, line: u32) -> LoxResult<String> {
+    let this = this.expect("Expected a this value").get_object(line)?;
+    let this = this.borrow();
+    let path = this
+        .get("__path__")
+        .ok_or_else(|| LoxError::Runtime("Expected a File".into(), line))?
+        .get_string(line)?;
+    if !this.get("__open__").is_some_and(|open| open.is_truthy()) {
+        return Err(LoxError::Runtime(
+            format!("File \"{}\" is closed", path),
+            line,
+        ));
+    }
+    Ok(path)
+}
+
+/// Report a failed file operation as a runtime error on `line`.
+fn io_error(action: &str, path: &str, err: std::io::Error, line: u32) -> LoxError {
+    LoxError::Runtime(format!("Could not {} \"{}\": {}", action, path, err), line)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::*, interpreter::*};
+    use mock_logger::MockLogger;
+    use std::{env, fs};
+
+    #[test]
+    fn files() -> LoxResult {
+        mock_logger::init();
+        let path = env::temp_dir().join(format!("lox-fs-test-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap().replace('\\', "/");
+        let mut lox = LoxInterpreter::new();
+        lox.exec(&format!(
+            r#"
+            var path = "{path}";
+            write_file(path, "one");
+            append_file(path, ", two");
+            print read_file(path);
+        "#
+        ))?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].body, "one, two");
+        });
+
+        // Handles are closed on every way out of `using`
+        lox.exec(&format!(
+            r#"
+            var handle;
+            using (var file = open("{path}")) {{
+                handle = file;
+                file.write("!");
+                print file.read();
+            }}
+            print handle.is_open();
+            fun fail(path) {{
+                using (var file = open(path)) {{
+                    handle = file;
+                    -"fail";
+                }}
+            }}
+        "#
+        ))?;
+        assert!(lox.exec("fail(path);").is_err());
+        assert!(lox.eval("handle.is_open();")? == false.into());
+        assert!(matches!(
+            lox.exec("handle.read();"),
+            Err(LoxError::Runtime(message, 0)) if message.ends_with("is closed"),
+        ));
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 3);
+            assert_eq!(entries[1].body, "one, two!");
+            assert_eq!(entries[2].body, "false");
+        });
+        fs::remove_file(&path)?;
+        assert!(matches!(
+            lox.exec("read_file(path);"),
+            Err(LoxError::Runtime(message, 0)) if message.starts_with(&format!("Could not read \"{path}\"")),
+        ));
+        Ok(())
+    }
+}
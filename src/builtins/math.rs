@@ -0,0 +1,136 @@
+use super::{number::to_fixed, BuiltinPack};
+use crate::{environment::*, error::*, function::*, native_fn, value::*};
+
+/// Math functions, e.g. `floor(x)` and `max(a, b, c)`, and converting
+/// between numbers and strings.
+pub struct MathPack;
+
+impl BuiltinPack for MathPack {
+    fn name(&self) -> &'static str {
+        "math"
+    }
+
+    fn install(&self, env: &mut Environment) {
+        let func_floor = native_fn!("floor", |x: f64| x.floor());
+        env.define_builtin("floor".into(), func_floor.into());
+
+        let func_ceil = native_fn!("ceil", |x: f64| x.ceil());
+        env.define_builtin("ceil".into(), func_ceil.into());
+
+        // Halfway cases round away from zero
+        let func_round = native_fn!("round", |x: f64| x.round());
+        env.define_builtin("round".into(), func_round.into());
+
+        let func_abs = native_fn!("abs", |x: f64| x.abs());
+        env.define_builtin("abs".into(), func_abs.into());
+
+        let func_sqrt = native_fn!("sqrt", |x: f64| x.sqrt());
+        env.define_builtin("sqrt".into(), func_sqrt.into());
+
+        let func_min = LoxFunction::native_variadic("min", vec!["first"], |_, args, meta| {
+            numbers(args, meta.line).map(|numbers| numbers.fold(f64::INFINITY, f64::min).into())
+        });
+        env.define_builtin("min".into(), func_min.into());
+
+        let func_max = LoxFunction::native_variadic("max", vec!["first"], |_, args, meta| {
+            numbers(args, meta.line).map(|numbers| numbers.fold(f64::NEG_INFINITY, f64::max).into())
+        });
+        env.define_builtin("max".into(), func_max.into());
+
+        let func_random = LoxFunction::native("random", vec![], |state, _, meta| {
+            Ok(LoxValue::Number(state.inputs.random(meta.line)?))
+        });
+        env.define_builtin("random".into(), func_random.into());
+
+        let func_num_to_string =
+            LoxFunction::native("num_to_string", vec!["n", "precision"], |_, args, meta| {
+                let value = args[0].get_number(meta.line)?;
+                let digits = args[1].get_number(meta.line)?;
+                Ok(to_fixed(value, digits, meta.line)?.into())
+            });
+        env.define_builtin("num_to_string".into(), func_num_to_string.into());
+
+        // Nil rather than an error for anything that isn't a finite number, so
+        // scripts can validate input with it
+        let func_parse_number =
+            LoxFunction::native("parse_number", vec!["text"], |_, args, meta| {
+                let text = args[0].get_string(meta.line)?;
+                Ok(match text.trim().parse::<f64>() {
+                    Ok(value) if value.is_finite() => value.into(),
+                    _ => LoxValue::Nil,
+                })
+            });
+        env.define_builtin("parse_number".into(), func_parse_number.into());
+    }
+}
+
+/// The arguments of `min` or `max`, which must all be numbers.
+fn numbers(args: &[LoxValue], line: u32) -> LoxResult<impl Iterator<Item = f64>> {
+    let numbers = args
+        .iter()
+        .map(|arg| arg.get_number(line))
+        .collect::<LoxResult<Vec<f64>>>()?;
+    Ok(numbers.into_iter())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::*, interpreter::*};
+    use mock_logger::MockLogger;
+
+    #[test]
+    fn math() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            print floor(2.7);
+            print ceil(2.1);
+            print round(2.5);
+            print round(-2.5);
+            print abs(-3);
+            print sqrt(16);
+            print min(3, 1, 2);
+            print max(3);
+            print num_to_string(3.14159, 2);
+            print num_to_string(1 / 0, 2);
+            print parse_number(" 42.5 ") + 1;
+            print parse_number("abc");
+            print parse_number("");
+            print parse_number("inf");
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            let lines: Vec<&str> = entries.iter().map(|entry| entry.body.as_str()).collect();
+            assert_eq!(
+                lines,
+                [
+                    "2", "3", "3", "-3", "3", "4", "1", "3", "3.14", "Infinity", "43.5", "nil",
+                    "nil", "nil"
+                ]
+            );
+        });
+        assert!(lox.exec("floor(\"1\");").is_err());
+        assert!(lox.exec("max();").is_err());
+        assert!(lox.exec("min(1, nil);").is_err());
+        assert!(lox.exec("num_to_string(1, 0.5);").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn random() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        let draw = |lox: &mut LoxInterpreter| -> LoxResult<Vec<f64>> {
+            (0..100)
+                .map(|_| lox.eval("random();")?.try_into())
+                .collect()
+        };
+        lox.set_random_seed(7);
+        let first = draw(&mut lox)?;
+        assert!(first.iter().all(|value| (0.0..1.0).contains(value)));
+        assert!(first.windows(2).any(|pair| pair[0] != pair[1]));
+        lox.set_random_seed(7);
+        assert_eq!(draw(&mut lox)?, first);
+        Ok(())
+    }
+}
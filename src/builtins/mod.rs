@@ -0,0 +1,74 @@
+mod core;
+mod debug;
+#[cfg(feature = "builtins-io")]
+mod fs;
+mod math;
+mod number;
+mod string;
+mod system;
+
+#[cfg(feature = "builtins-io")]
+pub use self::fs::FsPack;
+pub use self::{
+    core::CorePack, debug::DebugPack, math::MathPack, number::NumberPack, string::StringPack,
+    system::SystemPack,
+};
+use super::{class::LoxClass, environment::*, error::*, state::LoxState, value::*};
+
+/// A group of builtins that can be installed into an environment.
+pub trait BuiltinPack {
+    /// Unique name used to enable the pack, e.g. from `lox.toml`.
+    fn name(&self) -> &'static str;
+
+    /// Whether the pack is left out unless asked for by name.
+    fn opt_in(&self) -> bool {
+        false
+    }
+
+    fn install(&self, env: &mut Environment);
+}
+
+/// Every pack shipped with the interpreter, in install order.
+pub fn registry() -> Vec<Box<dyn BuiltinPack>> {
+    vec![
+        Box::new(CorePack),
+        Box::new(StringPack),
+        Box::new(NumberPack),
+        Box::new(MathPack),
+        Box::new(SystemPack),
+        #[cfg(feature = "builtins-io")]
+        Box::new(FsPack),
+        Box::new(DebugPack),
+    ]
+}
+
+pub fn find_pack(name: &str) -> Option<Box<dyn BuiltinPack>> {
+    registry().into_iter().find(|pack| pack.name() == name)
+}
+
+/// Instantiate the builtin Array class holding `values`.
+pub fn new_array(state: &mut LoxState, values: Vec<LoxValue>, line: u32) -> LoxResult<LoxValue> {
+    let class_vec = state
+        .env
+        .get(None, "Array")
+        .ok_or_else(|| LoxError::Runtime("Expected the Array builtin to exist".into(), line))?
+        .get_class(line)?;
+    let lox_vec = LoxClass::instantiate(&class_vec, state, &[], line)?;
+    lox_vec
+        .get_object(line)?
+        .borrow_mut()
+        .set("__vec__".into(), values.into());
+    Ok(lox_vec)
+}
+
+/// Copy the elements out of a builtin Array instance.
+fn array_values(value: &LoxValue, line: u32) -> LoxResult<Vec<LoxValue>> {
+    let __vec__ = value
+        .get_object(line)?
+        .borrow()
+        .get("__vec__")
+        .ok_or_else(|| LoxError::Runtime("Expected an Array".into(), line))?
+        .get_vec(line)?;
+    let values = __vec__.borrow().clone();
+    Ok(values)
+}
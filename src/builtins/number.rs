@@ -0,0 +1,118 @@
+use super::BuiltinPack;
+use crate::{class::*, environment::*, error::*, function::*, value::*};
+use std::collections::HashMap;
+
+/// Most decimals `to_fixed` accepts.
+const MAX_FIXED_DIGITS: f64 = 100.0;
+
+/// Methods on number values, e.g. `(2).to_fixed(2)`, looked up on the Number
+/// class.
+pub struct NumberPack;
+
+impl BuiltinPack for NumberPack {
+    fn name(&self) -> &'static str {
+        "number"
+    }
+
+    fn install(&self, env: &mut Environment) {
+        let class_number = LoxClass {
+            name: "Number".into(),
+            superclass: None,
+            methods: {
+                let method_to_fixed =
+                    LoxFunction::native("to_fixed", vec!["digits"], |_, args, meta| {
+                        let this = meta
+                            .this_value
+                            .expect("Expected a this value")
+                            .get_number(meta.line)?;
+                        let digits = args[0].get_number(meta.line)?;
+                        Ok(to_fixed(this, digits, meta.line)?.into())
+                    });
+
+                let method_to_string = LoxFunction::native("to_string", vec![], |_, _, meta| {
+                    let this = meta
+                        .this_value
+                        .expect("Expected a this value")
+                        .get_number(meta.line)?;
+                    Ok(format_number(this).into())
+                });
+
+                let mut methods = HashMap::<String, LoxFunction>::new();
+                methods.insert("to_fixed".into(), method_to_fixed);
+                methods.insert("to_string".into(), method_to_string);
+                methods
+            },
+            setters: HashMap::new(),
+            statics: HashMap::new(),
+            static_fields: HashMap::new(),
+            origin: None,
+        };
+
+        env.define_builtin("Number".into(), class_number.into());
+    }
+}
+
+/// Format `value` with `digits` decimals, or like `print` if it isn't
+/// finite.
+pub(super) fn to_fixed(value: f64, digits: f64, line: u32) -> LoxResult<String> {
+    if !(0.0..=MAX_FIXED_DIGITS).contains(&digits) || digits.fract() != 0.0 {
+        return Err(LoxError::Runtime(
+            format!(
+                "Expected an integer number of digits between 0 and {}, got {}",
+                MAX_FIXED_DIGITS,
+                format_number(digits)
+            ),
+            line,
+        ));
+    }
+    if !value.is_finite() {
+        return Ok(format_number(value));
+    }
+    Ok(format!("{:.*}", digits as usize, value))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::*, interpreter::*};
+    use mock_logger::MockLogger;
+
+    #[test]
+    fn number_formatting() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            print 2;
+            print "" + 2;
+            print 0.1 + 0.2;
+            print -0;
+            print 1 / 0;
+            var big = 1;
+            for (var i = 0; i < 21; i = i + 1) big = big * 10;
+            print big;
+            print (2).to_fixed(2);
+            print (2.345).to_fixed(1);
+            print "total: " + (1 / 3).to_fixed(3);
+            print (1.5).to_string() == "" + 1.5;
+        "#,
+        )?;
+        assert!(matches!(
+            lox.exec("print (1).to_fixed(-1);"),
+            Err(LoxError::Runtime(message, _)) if message.starts_with("Expected an integer number of digits")
+        ));
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 10);
+            assert_eq!(entries[0].body, "2");
+            assert_eq!(entries[1].body, "2");
+            assert_eq!(entries[2].body, "0.30000000000000004");
+            assert_eq!(entries[3].body, "0");
+            assert_eq!(entries[4].body, "Infinity");
+            assert_eq!(entries[5].body, "1e21");
+            assert_eq!(entries[6].body, "2.00");
+            assert_eq!(entries[7].body, "2.3");
+            assert_eq!(entries[8].body, "total: 0.333");
+            assert_eq!(entries[9].body, "true");
+        });
+        Ok(())
+    }
+}
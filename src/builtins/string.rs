@@ -0,0 +1,174 @@
+use super::{new_array, BuiltinPack};
+use crate::{class::*, environment::*, error::*, function::*, value::*};
+use std::collections::HashMap;
+
+/// Methods on string values, e.g. `"abc".len()`, looked up on the String class.
+/// Depends on the core pack for Array.
+pub struct StringPack;
+
+impl BuiltinPack for StringPack {
+    fn name(&self) -> &'static str {
+        "string"
+    }
+
+    fn install(&self, env: &mut Environment) {
+        let class_string = LoxClass {
+            name: "String".into(),
+            superclass: None,
+            methods: {
+                let method_len = LoxFunction::native("len", vec![], |_, _, meta| {
+                    let this = meta
+                        .this_value
+                        .expect("Expected a this value")
+                        .get_string(meta.line)?;
+                    Ok((this.chars().count() as f64).into())
+                });
+
+                let method_substring = LoxFunction::native(
+                    "substring",
+                    vec!["start", "end"],
+                    |_, args, meta| {
+                        let this = meta
+                            .this_value
+                            .expect("Expected a this value")
+                            .get_string(meta.line)?;
+                        let start = get_index(&args[0], meta.line)?;
+                        let end = get_index(&args[1], meta.line)?;
+                        let len = this.chars().count();
+                        if start > end || end > len {
+                            return Err(LoxError::Runtime(
+                                format!(
+                                    "Substring range {start}..{end} is out of bounds for a string of length {len}"
+                                ),
+                                meta.line,
+                            ));
+                        }
+                        let substring: String =
+                            this.chars().skip(start).take(end - start).collect();
+                        Ok(substring.into())
+                    },
+                );
+
+                let method_index_of =
+                    LoxFunction::native("index_of", vec!["needle"], |_, args, meta| {
+                        let this = meta
+                            .this_value
+                            .expect("Expected a this value")
+                            .get_string(meta.line)?;
+                        let needle = args[0].get_string(meta.line)?;
+                        // Character index of the first match, or -1
+                        let index = match this.find(&needle) {
+                            Some(byte_index) => this[..byte_index].chars().count() as f64,
+                            None => -1.0,
+                        };
+                        Ok(index.into())
+                    });
+
+                let method_to_upper = LoxFunction::native("to_upper", vec![], |_, _, meta| {
+                    let this = meta
+                        .this_value
+                        .expect("Expected a this value")
+                        .get_string(meta.line)?;
+                    Ok(this.to_uppercase().into())
+                });
+
+                let method_to_lower = LoxFunction::native("to_lower", vec![], |_, _, meta| {
+                    let this = meta
+                        .this_value
+                        .expect("Expected a this value")
+                        .get_string(meta.line)?;
+                    Ok(this.to_lowercase().into())
+                });
+
+                let method_split =
+                    LoxFunction::native("split", vec!["separator"], |state, args, meta| {
+                        let this = meta
+                            .this_value
+                            .expect("Expected a this value")
+                            .get_string(meta.line)?;
+                        let separator = args[0].get_string(meta.line)?;
+                        // An empty separator splits into characters
+                        let parts: Vec<LoxValue> = if separator.is_empty() {
+                            this.chars().map(|c| c.to_string().into()).collect()
+                        } else {
+                            this.split(separator.as_str()).map(LoxValue::from).collect()
+                        };
+                        new_array(state, parts, meta.line)
+                    });
+
+                let mut methods = HashMap::<String, LoxFunction>::new();
+                methods.insert("len".into(), method_len);
+                methods.insert("substring".into(), method_substring);
+                methods.insert("index_of".into(), method_index_of);
+                methods.insert("to_upper".into(), method_to_upper);
+                methods.insert("to_lower".into(), method_to_lower);
+                methods.insert("split".into(), method_split);
+                methods
+            },
+            setters: HashMap::new(),
+            statics: HashMap::new(),
+            static_fields: HashMap::new(),
+            origin: None,
+        };
+
+        env.define_builtin("String".into(), class_string.into());
+    }
+}
+
+/// Convert a number argument into a character index.
+fn get_index(value: &LoxValue, line: u32) -> LoxResult<usize> {
+    let index = value.get_number(line)?;
+    if index < 0.0 || index.fract() != 0.0 {
+        return Err(LoxError::Runtime(
+            format!("Expected a non-negative integer index, got {}", index),
+            line,
+        ));
+    }
+    Ok(index as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::*, interpreter::*};
+    use mock_logger::MockLogger;
+
+    #[test]
+    fn string_methods() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            var s = "Hello, World";
+            print s.len();
+            print s.substring(7, 12);
+            print s.index_of("World");
+            print s.index_of("nope");
+            print s.to_upper();
+            print s.to_lower();
+            var parts = s.split(", ");
+            print parts.len();
+            print parts.get(1);
+            print "abc".split("").len();
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 9);
+            assert_eq!(entries[0].body, "12");
+            assert_eq!(entries[1].body, "World");
+            assert_eq!(entries[2].body, "7");
+            assert_eq!(entries[3].body, "-1");
+            assert_eq!(entries[4].body, "HELLO, WORLD");
+            assert_eq!(entries[5].body, "hello, world");
+            assert_eq!(entries[6].body, "2");
+            assert_eq!(entries[7].body, "World");
+            assert_eq!(entries[8].body, "3");
+        });
+        assert!(lox.exec("\"abc\".substring(2, 4);").is_err());
+        assert!(lox.exec("\"abc\".substring(1.5, 2);").is_err());
+        assert!(matches!(
+            lox.exec("\"abc\".lenn();"),
+            Err(LoxError::Runtime(message, _)) if message == "Undefined string method \"lenn\", did you mean \"len\"?"
+        ));
+        Ok(())
+    }
+}
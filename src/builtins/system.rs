@@ -0,0 +1,289 @@
+use super::{new_array, BuiltinPack};
+use crate::{class::*, environment::*, error::*, function::*, object::*, value::*};
+use std::collections::HashMap;
+
+/// Access to the host process: the clock, command line arguments and
+/// requesting termination.
+/// Depends on the core pack for Array.
+pub struct SystemPack;
+
+impl BuiltinPack for SystemPack {
+    fn name(&self) -> &'static str {
+        "system"
+    }
+
+    fn install(&self, env: &mut Environment) {
+        let func_time = LoxFunction::native("time", vec![], |state, _, meta| {
+            Ok(LoxValue::Number(state.inputs.time(meta.line)?))
+        });
+
+        env.define_builtin("time".into(), func_time.into());
+
+        let func_get_args = LoxFunction::native("get_args", vec![], |state, _, meta| {
+            let args: Vec<LoxValue> = state
+                .inputs
+                .args(meta.line)?
+                .into_iter()
+                .map(LoxValue::from)
+                .collect();
+            new_array(state, args, meta.line)
+        });
+
+        env.define_builtin("get_args".into(), func_get_args.into());
+
+        let func_exit = LoxFunction::native("exit", vec!["code"], |_, args, meta| {
+            let code = args[0].get_number(meta.line)?;
+            if code.fract() != 0.0 || code < i32::MIN as f64 || code > i32::MAX as f64 {
+                return Err(LoxError::Runtime(
+                    format!("Expected an integer exit code, got {}", code),
+                    meta.line,
+                ));
+            }
+            Err(LoxError::Exit(code as i32))
+        });
+
+        env.define_builtin("exit".into(), func_exit.into());
+
+        // Ends the line even when the `print` statement doesn't.
+        let func_println = LoxFunction::native("println", vec!["value"], |state, args, meta| {
+            let text = args[0].display(state, meta.line)?;
+            state.output.write_line(&text);
+            Ok(LoxValue::Nil)
+        });
+
+        env.define_builtin("println".into(), func_println.into());
+
+        let class_args = LoxClass {
+            name: "Args".into(),
+            superclass: None,
+            methods: {
+                // Parses script arguments (everything after the script path) into
+                // `--name value`, `--name=value` and `--name` flags plus positionals.
+                let init = LoxFunction::native("init", vec![], |state, _, meta| {
+                    let this = meta
+                        .this_value
+                        .expect("Expected a this value")
+                        .get_object(meta.line)?;
+                    let mut flags = state.env.new_properties();
+                    let mut positional: Vec<LoxValue> = vec![];
+                    let mut args = state.inputs.args(meta.line)?.into_iter().skip(2).peekable();
+                    while let Some(arg) = args.next() {
+                        if arg == "--" {
+                            positional.extend(args.by_ref().map(LoxValue::from));
+                        } else if let Some(flag) = arg.strip_prefix("--") {
+                            let (name, value) = match flag.split_once('=') {
+                                Some((name, value)) => (name, LoxValue::from(value)),
+                                None => match args.next_if(|next| !next.starts_with("--")) {
+                                    Some(value) => (flag, LoxValue::from(value)),
+                                    None => (flag, LoxValue::Boolean(true)),
+                                },
+                            };
+                            flags.insert(name.into(), value);
+                        } else {
+                            positional.push(arg.into());
+                        }
+                    }
+                    let mut this = this.borrow_mut();
+                    this.set(
+                        "__flags__".into(),
+                        LoxObject {
+                            class_name: "Args".into(),
+                            props: flags,
+                            native: true,
+                            class: None,
+                            origin: Some(meta.line),
+                        }
+                        .into(),
+                    );
+                    this.set("__positional__".into(), positional.into());
+                    Ok(LoxValue::Nil)
+                });
+
+                let method_has = LoxFunction::native("has", vec!["name"], |_, args, meta| {
+                    let value = get_flag(meta.this_value, &args[0], meta.line)?;
+                    Ok(value.is_some().into())
+                });
+
+                let method_flag = LoxFunction::native("flag", vec!["name"], |_, args, meta| {
+                    let enabled = match get_flag(meta.this_value, &args[0], meta.line)? {
+                        Some(LoxValue::String(value)) => value != "false" && value != "0",
+                        Some(value) => value.is_truthy(),
+                        None => false,
+                    };
+                    Ok(enabled.into())
+                });
+
+                let method_string =
+                    LoxFunction::native("string", vec!["name", "default"], |_, args, meta| {
+                        match get_flag(meta.this_value, &args[0], meta.line)? {
+                            Some(LoxValue::String(value)) => Ok(value.into()),
+                            Some(_) => Err(LoxError::Runtime(
+                                format!("Flag \"--{}\" expects a value", args[0].to_string()),
+                                meta.line,
+                            )),
+                            None => Ok(args[1].clone()),
+                        }
+                    });
+
+                let method_number =
+                    LoxFunction::native("number", vec!["name", "default"], |_, args, meta| {
+                        match get_flag(meta.this_value, &args[0], meta.line)? {
+                            Some(LoxValue::String(value)) => value
+                                .trim()
+                                .parse::<f64>()
+                                .map(LoxValue::from)
+                                .map_err(|_| {
+                                    LoxError::Runtime(
+                                        format!(
+                                            "Flag \"--{}\" expects a number, got \"{}\"",
+                                            args[0].to_string(),
+                                            value
+                                        ),
+                                        meta.line,
+                                    )
+                                }),
+                            Some(_) => Err(LoxError::Runtime(
+                                format!("Flag \"--{}\" expects a number", args[0].to_string()),
+                                meta.line,
+                            )),
+                            None => Ok(args[1].clone()),
+                        }
+                    });
+
+                let method_positional =
+                    LoxFunction::native("positional", vec![], |state, _, meta| {
+                        let this = meta
+                            .this_value
+                            .expect("Expected a this value")
+                            .get_object(meta.line)?;
+                        let positional = this
+                            .borrow()
+                            .get("__positional__")
+                            .expect("Missing __positional__")
+                            .get_vec(meta.line)?;
+                        let values = positional.borrow().clone();
+                        new_array(state, values, meta.line)
+                    });
+
+                let mut methods = HashMap::<String, LoxFunction>::new();
+                methods.insert("init".into(), init);
+                methods.insert("has".into(), method_has);
+                methods.insert("flag".into(), method_flag);
+                methods.insert("string".into(), method_string);
+                methods.insert("number".into(), method_number);
+                methods.insert("positional".into(), method_positional);
+                methods
+            },
+            setters: HashMap::new(),
+            statics: HashMap::new(),
+            static_fields: HashMap::new(),
+            origin: None,
+        };
+
+        env.define_builtin("Args".into(), class_args.into());
+    }
+}
+
+/// Look up a parsed flag on an `Args` instance.
+fn get_flag(this: Option<LoxValue>, name: &LoxValue, line: u32) -> LoxResult<Option<LoxValue>> {
+    let name = name.get_string(line)?;
+    let this = this.expect("Expected a this value").get_object(line)?;
+    let flags = this
+        .borrow()
+        .get("__flags__")
+        .expect("Missing __flags__")
+        .get_object(line)?;
+    let value = flags.borrow().get(&name);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::*, interpreter::*};
+    use mock_logger::MockLogger;
+
+    #[test]
+    fn args() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.set_args(
+            [
+                "lox",
+                "script.lox",
+                "--count",
+                "3",
+                "input.txt",
+                "--name=lox",
+                "--verbose",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        );
+        lox.exec(
+            r#"
+            var args = Args();
+            print args.flag("verbose");
+            print args.flag("quiet");
+            print args.number("count", 1);
+            print args.number("retries", 5);
+            print args.string("name", "world");
+            print args.has("missing");
+            print args.positional().get(0);
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 7);
+            assert_eq!(entries[0].body, "true");
+            assert_eq!(entries[1].body, "false");
+            assert_eq!(entries[2].body, "3");
+            assert_eq!(entries[3].body, "5");
+            assert_eq!(entries[4].body, "lox");
+            assert_eq!(entries[5].body, "false");
+            assert_eq!(entries[6].body, "input.txt");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn args_invalid_number() {
+        let mut lox = LoxInterpreter::new();
+        lox.set_args(
+            ["lox", "script.lox", "--count", "many"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+        assert!(lox.exec("Args().number(\"count\", 1);").is_err());
+    }
+
+    #[test]
+    fn exit() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        let result = lox.exec(
+            r#"
+            fun stop() {
+                exit(3);
+                print "unreachable";
+            }
+            print "before";
+            stop();
+            print "after";
+        "#,
+        );
+        assert!(matches!(result, Err(LoxError::Exit(3))));
+        // The interpreter is still usable afterwards
+        lox.exec("print \"again\";")?;
+        assert!(matches!(
+            lox.exec("exit(1.5);"),
+            Err(LoxError::Runtime(message, _)) if message == "Expected an integer exit code, got 1.5"
+        ));
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].body, "before");
+            assert_eq!(entries[1].body, "again");
+        });
+        Ok(())
+    }
+}
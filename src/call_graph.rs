@@ -0,0 +1,371 @@
+use super::{
+    expr::{Expr, ExprKind},
+    stmt::Stmt,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+/// Caller name of code outside any function.
+pub const SCRIPT: &str = "<script>";
+
+/// Which functions refer to which, built from the source without running it.
+///
+/// Callers are the top level script, functions, classes (which refer to their
+/// superclass) and methods, named `Class.method`. Callees are the functions and classes they refer to by
+/// name, whether called or passed around, plus any name they call that the
+/// script doesn't declare, i.e. builtins and host functions. Methods are
+/// only known as callees when called on `this` or `super`. Like the dead code
+/// report, declarations are matched by name regardless of scope.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct CallGraph {
+    edges: BTreeMap<String, BTreeSet<String>>,
+    declared: BTreeSet<String>,
+}
+
+impl CallGraph {
+    pub fn build(statements: &[Stmt]) -> Self {
+        let mut graph = Self::default();
+        graph.edges.insert(SCRIPT.into(), BTreeSet::new());
+        graph.declare(statements);
+        let mut builder = Builder {
+            graph: &mut graph,
+            caller: SCRIPT.into(),
+            class: None,
+        };
+        builder.visit_all(statements);
+        graph
+    }
+
+    /// Everything declared in the script: functions, classes and methods.
+    pub fn declared(&self) -> impl Iterator<Item = &str> {
+        self.declared.iter().map(String::as_str)
+    }
+
+    /// What `caller` refers to, sorted by name.
+    pub fn callees(&self, caller: &str) -> impl Iterator<Item = &str> {
+        self.edges
+            .get(caller)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+    }
+
+    /// Names called anywhere that the script doesn't declare, e.g. to check a
+    /// script only calls approved builtins.
+    pub fn external_calls(&self) -> BTreeSet<&str> {
+        self.edges
+            .values()
+            .flatten()
+            .filter(|callee| !self.declared.contains(*callee))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Render the graph in Graphviz DOT format.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph calls {\n");
+        for (caller, callees) in self.edges.iter() {
+            writeln!(out, "    \"{}\";", caller).unwrap();
+            for callee in callees.iter() {
+                writeln!(out, "    \"{}\" -> \"{}\";", caller, callee).unwrap();
+            }
+        }
+        out.push('}');
+        out
+    }
+
+    fn declare(&mut self, statements: &[Stmt]) {
+        for stmt in statements.iter() {
+            match stmt {
+                Stmt::Fun { name, body, .. } => {
+                    self.declared.insert(name.lexeme_str());
+                    self.declare(body);
+                }
+                Stmt::Class { name, methods, .. } => {
+                    self.declared.insert(name.lexeme_str());
+                    for method in methods.iter() {
+                        if let Stmt::Fun {
+                            name: method_name,
+                            body,
+                            ..
+                        } = method
+                        {
+                            self.declared.insert(format!(
+                                "{}.{}",
+                                name.lexeme_str(),
+                                method_name.lexeme_str()
+                            ));
+                            self.declare(body);
+                        }
+                    }
+                }
+                Stmt::Block { statements, .. } => self.declare(statements),
+                Stmt::IfElse {
+                    body, else_branch, ..
+                } => {
+                    self.declare(std::slice::from_ref(body));
+                    if let Some(else_branch) = else_branch {
+                        self.declare(std::slice::from_ref(else_branch));
+                    }
+                }
+                Stmt::ForLoop {
+                    initializer, body, ..
+                } => {
+                    self.declare(std::slice::from_ref(initializer));
+                    self.declare(std::slice::from_ref(body));
+                }
+                Stmt::WhileLoop { body, .. }
+                | Stmt::ForIn { body, .. }
+                | Stmt::Defer { body, .. }
+                | Stmt::Using { body, .. } => {
+                    self.declare(std::slice::from_ref(body));
+                }
+                Stmt::Try {
+                    body,
+                    catch,
+                    finally,
+                    ..
+                } => {
+                    self.declare(std::slice::from_ref(body));
+                    if let Some((_, handler)) = catch {
+                        self.declare(std::slice::from_ref(handler));
+                    }
+                    if let Some(finally) = finally {
+                        self.declare(std::slice::from_ref(finally));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+struct Builder<'a> {
+    graph: &'a mut CallGraph,
+    caller: String,
+    /// Name and superclass name of the class whose methods are being visited.
+    class: Option<(String, Option<String>)>,
+}
+
+impl Builder<'_> {
+    fn add_edge(&mut self, callee: String) {
+        self.graph
+            .edges
+            .entry(self.caller.clone())
+            .or_default()
+            .insert(callee);
+    }
+
+    fn visit_all(&mut self, statements: &[Stmt]) {
+        for stmt in statements.iter() {
+            self.visit_stmt(stmt);
+        }
+    }
+
+    /// Visit the body of a declaration as its own caller.
+    fn visit_body(&mut self, caller: String, body: &[Stmt]) {
+        let outer = std::mem::replace(&mut self.caller, caller);
+        self.graph.edges.entry(self.caller.clone()).or_default();
+        self.visit_all(body);
+        self.caller = outer;
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Fun { name, body, .. } => self.visit_body(name.lexeme_str(), body),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let superclass_name = match superclass.as_deref().map(|expr| &expr.kind) {
+                    Some(ExprKind::Identifier(token)) => Some(token.lexeme_str()),
+                    _ => None,
+                };
+                // A class refers to its superclass
+                let edges = self.graph.edges.entry(name.lexeme_str()).or_default();
+                edges.extend(superclass_name.clone());
+                let outer = self
+                    .class
+                    .replace((name.lexeme_str(), superclass_name.clone()));
+                for method in methods.iter() {
+                    match method {
+                        Stmt::Fun {
+                            name: method_name,
+                            body,
+                            ..
+                        } => {
+                            let caller =
+                                format!("{}.{}", name.lexeme_str(), method_name.lexeme_str());
+                            self.visit_body(caller, body);
+                        }
+                        // Static fields are evaluated by the declaration itself
+                        Stmt::Var {
+                            initializer: Some(initializer),
+                            ..
+                        } => {
+                            let outer = std::mem::replace(&mut self.caller, name.lexeme_str());
+                            self.visit_expr(initializer);
+                            self.caller = outer;
+                        }
+                        _ => {}
+                    }
+                }
+                self.class = outer;
+            }
+            Stmt::Expr(expr) | Stmt::Print(expr) | Stmt::Return(expr) => self.visit_expr(expr),
+            Stmt::Var { initializer, .. } => {
+                if let Some(expr) = initializer {
+                    self.visit_expr(expr);
+                }
+            }
+            Stmt::Destructure { initializer, .. } => self.visit_expr(initializer),
+            Stmt::Block { statements, .. } => self.visit_all(statements),
+            Stmt::IfElse {
+                condition,
+                body,
+                else_branch,
+            } => {
+                self.visit_expr(condition);
+                self.visit_stmt(body);
+                if let Some(else_branch) = else_branch {
+                    self.visit_stmt(else_branch);
+                }
+            }
+            Stmt::WhileLoop { condition, body } => {
+                self.visit_expr(condition);
+                self.visit_stmt(body);
+            }
+            Stmt::ForLoop {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.visit_stmt(initializer);
+                self.visit_expr(condition);
+                self.visit_stmt(body);
+                self.visit_expr(increment);
+            }
+            Stmt::ForIn { iterable, body, .. } => {
+                self.visit_expr(iterable);
+                self.visit_stmt(body);
+            }
+            Stmt::Defer { body, .. } => self.visit_stmt(body),
+            Stmt::Using {
+                initializer, body, ..
+            } => {
+                self.visit_expr(initializer);
+                self.visit_stmt(body);
+            }
+            Stmt::Throw { value, .. } => self.visit_expr(value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.visit_stmt(body);
+                if let Some((_, handler)) = catch {
+                    self.visit_stmt(handler);
+                }
+                if let Some(finally) = finally {
+                    self.visit_stmt(finally);
+                }
+            }
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) | ExprKind::This(_) => {}
+            ExprKind::Identifier(name) => {
+                // Variables are only interesting when they name a declaration
+                let name = name.lexeme_str();
+                if self.graph.declared.contains(&name) {
+                    self.add_edge(name);
+                }
+            }
+            ExprKind::Assignment { value, .. } => self.visit_expr(value),
+            ExprKind::Unary { right, .. } => self.visit_expr(right),
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.visit_expr(left);
+                self.visit_expr(right);
+            }
+            ExprKind::Grouping(inner) => self.visit_expr(inner),
+            ExprKind::Call { callee, arguments } => {
+                match &callee.kind {
+                    // Calls of undeclared names go to builtins
+                    ExprKind::Identifier(name) => self.add_edge(name.lexeme_str()),
+                    ExprKind::Get { left, name, .. } if matches!(left.kind, ExprKind::This(_)) => {
+                        if let Some((class, _)) = &self.class {
+                            self.add_edge(format!("{}.{}", class, name));
+                        }
+                    }
+                    _ => self.visit_expr(callee),
+                }
+                for arg in arguments.iter() {
+                    self.visit_expr(arg);
+                }
+            }
+            ExprKind::Get { left, .. } => self.visit_expr(left),
+            ExprKind::Set { object, value, .. } => {
+                self.visit_expr(object);
+                self.visit_expr(value);
+            }
+            ExprKind::Super(method) => {
+                if let Some((_, Some(superclass))) = &self.class {
+                    self.add_edge(format!("{}.{}", superclass, method.lexeme_str()));
+                }
+            }
+            ExprKind::Tuple(values) => {
+                for value in values.iter() {
+                    self.visit_expr(value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parser::parse, test_scripts::*};
+
+    #[test]
+    fn call_graph() {
+        let program = parse(CLASS_INHERITANCE_TEST);
+        let graph = CallGraph::build(&program.statements);
+        let callees = |caller: &str| graph.callees(caller).collect::<Vec<&str>>();
+        assert_eq!(callees(SCRIPT), vec!["HelloGreeter", "HowdyGreeter"]);
+        assert_eq!(callees("HelloGreeter"), vec!["Greeter"]);
+        assert_eq!(callees("HelloGreeter.init"), vec!["Greeter.init"]);
+        assert!(callees("Greeter.greet").is_empty());
+
+        let program = parse(FUNCTION_TEST);
+        let graph = CallGraph::build(&program.statements);
+        assert_eq!(
+            graph.callees(SCRIPT).collect::<Vec<&str>>(),
+            vec!["get_name", "greet"]
+        );
+        assert_eq!(
+            graph.callees("greet").collect::<Vec<&str>>(),
+            vec!["greeting"]
+        );
+        assert!(graph.external_calls().is_empty());
+
+        let program = parse("fun tick() { print time(); } tick(); clock();");
+        let graph = CallGraph::build(&program.statements);
+        assert_eq!(
+            graph.external_calls().into_iter().collect::<Vec<&str>>(),
+            vec!["clock", "time"]
+        );
+        assert_eq!(
+            graph.to_dot(),
+            "digraph calls {\n    \"<script>\";\n    \"<script>\" -> \"clock\";\n    \
+             \"<script>\" -> \"tick\";\n    \"tick\";\n    \"tick\" -> \"time\";\n}"
+        );
+    }
+}
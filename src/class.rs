@@ -1,8 +1,6 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use crate::{
-    environment::LoxProperties, error::LoxResult, object::*, state::LoxState, value::LoxValue,
-};
+use crate::{error::LoxResult, object::*, state::LoxState, value::LoxValue};
 
 use super::function::*;
 
@@ -11,35 +9,161 @@ pub struct LoxClass {
     pub name: String,
     pub superclass: Option<Rc<RefCell<LoxClass>>>,
     pub methods: HashMap<String, LoxFunction>,
+    /// Methods declared with `set`, called when the property is written.
+    pub setters: HashMap<String, LoxFunction>,
+    /// Methods declared with `static`, called on the class itself.
+    pub statics: HashMap<String, LoxFunction>,
+    /// Constants declared with `static NAME = value;`, read on the class.
+    pub static_fields: HashMap<String, LoxValue>,
+    /// Line of the class declaration, `None` for native classes.
+    pub origin: Option<u32>,
+}
+
+/// The value of `super` inside a method: the superclass of the class that
+/// defined the method, bound to the instance the method was called on.
+#[derive(PartialEq, Clone)]
+pub struct LoxSuper {
+    pub class: Rc<RefCell<LoxClass>>,
+    pub this_value: LoxValue,
+}
+
+impl LoxSuper {
+    /// Look up `name` on the superclass chain and bind it to the instance.
+    pub fn get(&self, name: &str) -> Option<LoxValue> {
+        let (method, owner) = LoxClass::find_method(&self.class, name)?;
+        Some(LoxClass::bind_method(method, &owner, self.this_value.clone()).into())
+    }
 }
 
 impl LoxClass {
-    /// Intended to be used from builtins. Does not look up super classes
+    /// Find the method `name` on `class` or its superclasses, along with the
+    /// class that defines it.
+    pub fn find_method(
+        class: &Rc<RefCell<LoxClass>>,
+        name: &str,
+    ) -> Option<(LoxFunction, Rc<RefCell<LoxClass>>)> {
+        Self::find_in(class, |class| class.methods.get(name).cloned())
+    }
+
+    /// Find the setter `name` on `class` or its superclasses, along with the
+    /// class that defines it.
+    pub fn find_setter(
+        class: &Rc<RefCell<LoxClass>>,
+        name: &str,
+    ) -> Option<(LoxFunction, Rc<RefCell<LoxClass>>)> {
+        Self::find_in(class, |class| class.setters.get(name).cloned())
+    }
+
+    /// Find the static method `name` on `class` or its superclasses, bound to
+    /// `class`.
+    pub fn find_static(class: &Rc<RefCell<LoxClass>>, name: &str) -> Option<LoxFunction> {
+        let (method, owner) = Self::find_in(class, |class| class.statics.get(name).cloned())?;
+        Some(Self::bind_method(method, &owner, class.clone().into()))
+    }
+
+    /// The static field `name` of `class` or its superclasses.
+    pub fn find_static_field(class: &Rc<RefCell<LoxClass>>, name: &str) -> Option<LoxValue> {
+        let mut current = Some(class.clone());
+        while let Some(class) = current {
+            if let Some(value) = class.borrow().static_fields.get(name) {
+                return Some(value.clone());
+            }
+            current = class.borrow().superclass.clone();
+        }
+        None
+    }
+
+    /// Sorted names of the static methods and fields of this class and its
+    /// superclasses.
+    pub fn static_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .statics
+            .keys()
+            .chain(self.static_fields.keys())
+            .cloned()
+            .collect();
+        if let Some(superclass) = &self.superclass {
+            names.extend(superclass.borrow().static_names());
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn find_in(
+        class: &Rc<RefCell<LoxClass>>,
+        find: impl Fn(&LoxClass) -> Option<LoxFunction>,
+    ) -> Option<(LoxFunction, Rc<RefCell<LoxClass>>)> {
+        let mut current = Some(class.clone());
+        while let Some(class) = current {
+            if let Some(method) = find(&class.borrow()) {
+                return Some((method, class.clone()));
+            }
+            current = class.borrow().superclass.clone();
+        }
+        None
+    }
+
+    /// Bind a method defined on `owner` to `this_value`, giving it access to
+    /// the superclass of `owner` through `super`.
+    pub fn bind_method(
+        mut method: LoxFunction,
+        owner: &Rc<RefCell<LoxClass>>,
+        this_value: LoxValue,
+    ) -> LoxFunction {
+        method.super_value = owner.borrow().superclass.clone().map(|class| {
+            LoxSuper {
+                class,
+                this_value: this_value.clone(),
+            }
+            .into()
+        });
+        method.this_value = Some(this_value);
+        method
+    }
+
+    /// Whether this class or any of its superclasses defines native methods.
+    pub fn is_native(&self) -> bool {
+        self.methods
+            .values()
+            .any(|method| matches!(method.body, FunctionBody::Native(_)))
+            || self
+                .superclass
+                .as_ref()
+                .is_some_and(|superclass| superclass.borrow().is_native())
+    }
+
+    /// Sorted names of the methods defined on this class and its superclasses.
+    pub fn method_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.methods.keys().cloned().collect();
+        if let Some(superclass) = &self.superclass {
+            names.extend(superclass.borrow().method_names());
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Whether `class` is the builtin class `name` or inherits from it.
+    pub fn extends_builtin(class: &Rc<RefCell<LoxClass>>, name: &str) -> bool {
+        let mut current = Some(class.clone());
+        while let Some(class) = current {
+            if class.borrow().origin.is_none() && class.borrow().name == name {
+                return true;
+            }
+            current = class.borrow().superclass.clone();
+        }
+        false
+    }
+
+    /// Intended to be used from builtins, instantiates `class` with borrowed
+    /// arguments the same way calling it from a script does.
     pub fn instantiate(
-        &self,
+        class: &Rc<RefCell<LoxClass>>,
         state: &mut LoxState,
         args: &[LoxValue],
         line: u32,
     ) -> LoxResult<LoxValue> {
-        let obj = Rc::new(RefCell::new(LoxObject {
-            class_name: self.name.clone(),
-            props: LoxProperties::new(),
-        }));
-        let this_value = LoxValue::from(obj.clone());
-        for (name, func) in self.methods.iter() {
-            let mut method = func.clone();
-            method.this_value = Some(this_value.clone());
-            obj.borrow_mut().props.insert(name.clone(), method.into());
-        }
-        let init = {
-            obj.borrow()
-                .props
-                .get("init")
-                .and_then(|init| init.get_fun(line).ok())
-        };
-        if let Some(init) = init {
-            init.borrow().call_native(state, args, line)?;
-        }
-        Ok(obj.into())
+        LoxObject::instantiate(class.clone(), state, args.to_vec(), line)
     }
 }
@@ -0,0 +1,153 @@
+use lox::{Backend, LoxError, LoxResult};
+
+/// What to do with the program instead of running it.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum Inspect {
+    #[default]
+    Nothing,
+    /// `--tokens`: print the scanner's tokens.
+    Tokens,
+    /// `--ast`: print the parsed statements as s-expressions.
+    Ast,
+    /// `--ast-json`: print the parsed statements as JSON.
+    AstJson,
+    /// `--stats`: report on the program.
+    Stats,
+}
+
+/// Options for running Lox, from the flags before the script path. The
+/// script path and everything after it is passed on to the script.
+#[derive(PartialEq, Debug, Default)]
+pub struct Options {
+    pub config_path: Option<String>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    pub coverage: bool,
+    /// `--hotspots <n>`: report the `n` most executed lines.
+    pub hotspots: Option<usize>,
+    pub backend: Backend,
+    pub trace: bool,
+    pub json: bool,
+    /// `--timings`: report the time spent in each phase.
+    pub timings: bool,
+    /// `--debug`: install the debug builtins.
+    pub debug: bool,
+    pub inspect: Inspect,
+    /// Source given with `-e`, run instead of a script.
+    pub eval: Option<String>,
+    pub script: Option<String>,
+    /// The binary name, the script path (`-e` for `--eval`) and the script's
+    /// arguments, so script arguments keep their usual indices.
+    pub script_args: Vec<String>,
+}
+
+impl Options {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> LoxResult<Self> {
+        let mut options = Options {
+            script_args: args.next().into_iter().collect(),
+            ..Default::default()
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => options.config_path = Some(flag_value(&mut args, &arg)?),
+                "--record" => options.record = Some(flag_value(&mut args, &arg)?),
+                "--replay" => options.replay = Some(flag_value(&mut args, &arg)?),
+                "--coverage" => options.coverage = true,
+                "--hotspots" => {
+                    let value = flag_value(&mut args, &arg)?;
+                    let count = value.parse().map_err(|_| {
+                        LoxError::General(format!(
+                            "Expected a number after --hotspots, got {}",
+                            value
+                        ))
+                    })?;
+                    options.hotspots = Some(count);
+                }
+                "--stats" => options.inspect = Inspect::Stats,
+                "--tokens" => options.inspect = Inspect::Tokens,
+                "--ast" => options.inspect = Inspect::Ast,
+                "--ast-json" => options.inspect = Inspect::AstJson,
+                "--vm" => options.backend = Backend::Bytecode,
+                "--trace" => options.trace = true,
+                "--json" => options.json = true,
+                "--timings" => options.timings = true,
+                "--debug" => options.debug = true,
+                "-e" | "--eval" => {
+                    options.eval = Some(flag_value(&mut args, &arg)?);
+                    options.script_args.push("-e".into());
+                    break;
+                }
+                _ => {
+                    options.script = Some(arg.clone());
+                    options.script_args.push(arg);
+                    break;
+                }
+            }
+        }
+        options.script_args.extend(args);
+        Ok(options)
+    }
+
+    /// The source to inspect or run, if not the REPL.
+    pub fn source(&self) -> LoxResult<Option<String>> {
+        match (&self.eval, &self.script) {
+            (Some(source), _) => Ok(Some(source.clone())),
+            (None, Some(path)) => Ok(Some(std::fs::read_to_string(path)?)),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+fn flag_value(args: &mut impl Iterator<Item = String>, flag: &str) -> LoxResult<String> {
+    args.next()
+        .ok_or_else(|| LoxError::General(format!("Expected a value after {}", flag)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> LoxResult<Options> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn options() -> LoxResult {
+        let options = parse(&[
+            "lox",
+            "--vm",
+            "--debug",
+            "--timings",
+            "--ast",
+            "script.lox",
+            "--ast",
+            "x",
+        ])?;
+        assert_eq!(options.backend, Backend::Bytecode);
+        assert!(options.debug);
+        assert!(options.timings);
+        assert_eq!(options.inspect, Inspect::Ast);
+        assert_eq!(options.script.as_deref(), Some("script.lox"));
+        // Flags after the script are the script's
+        assert_eq!(options.script_args, ["lox", "script.lox", "--ast", "x"]);
+
+        let options = parse(&["lox", "--tokens", "-e", "print 1 + 2;", "arg"])?;
+        assert_eq!(options.inspect, Inspect::Tokens);
+        assert_eq!(options.eval.as_deref(), Some("print 1 + 2;"));
+        assert_eq!(options.script, None);
+        assert_eq!(options.script_args, ["lox", "-e", "arg"]);
+        assert_eq!(options.source()?, Some("print 1 + 2;".into()));
+
+        assert_eq!(
+            parse(&["lox"])?,
+            Options {
+                script_args: vec!["lox".into()],
+                ..Default::default()
+            }
+        );
+        assert!(parse(&["lox", "-e"]).is_err());
+        assert_eq!(parse(&["lox", "--hotspots", "5"])?.hotspots, Some(5));
+        assert!(parse(&["lox", "--hotspots", "many"]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use crate::error::LoxResult;
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Where the interpreter reads the current time from. Hosts without a system
+/// clock, like WASM, or with their own notion of time, like games, provide
+/// their own.
+pub trait Clock {
+    /// Time elapsed since the Unix epoch.
+    fn now(&self) -> LoxResult<Duration>;
+}
+
+/// The host system's clock, used by default.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> LoxResult<Duration> {
+        Ok(SystemTime::now().duration_since(UNIX_EPOCH)?)
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests. Clones
+/// share the same time, so a test can keep one to advance the clock it gave
+/// the interpreter.
+#[derive(Clone, Default)]
+pub struct MockClock {
+    now: Rc<Cell<Duration>>,
+}
+
+impl MockClock {
+    pub fn new(now: Duration) -> Self {
+        Self {
+            now: Rc::new(Cell::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: Duration) {
+        self.now.set(now);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> LoxResult<Duration> {
+        Ok(self.now.get())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::*;
+
+    #[test]
+    fn mock_clock() -> LoxResult {
+        let clock = MockClock::new(Duration::from_millis(1000));
+        let mut lox = LoxInterpreter::new();
+        lox.set_clock(Box::new(clock.clone()));
+        lox.enable_audit();
+        assert!(lox.eval("time();")? == 1000.0.into());
+        clock.advance(Duration::from_millis(250));
+        assert!(lox.eval("time();")? == 1250.0.into());
+        let calls = lox.audit_log().expect("Expected an audit log");
+        assert_eq!(calls[1].timestamp, Duration::from_millis(1250));
+        Ok(())
+    }
+}
@@ -0,0 +1,462 @@
+use super::{builtins::find_pack, error::*, parser::ParseLimits, state::ExecLimits};
+#[cfg(feature = "log")]
+use log::Level;
+#[cfg(feature = "log")]
+use std::str::FromStr;
+use std::{fs, path::Path, time::Duration};
+
+pub const CONFIG_FILE_NAME: &str = "lox.toml";
+
+/// Interpreter options, loaded from a `lox.toml` file or built by embedders.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LoxConfig {
+    /// Names of the builtin packs to install, or all of them when `None`.
+    pub packs: Option<Vec<String>>,
+    /// Names of the builtins to expose to scripts, or all of them when `None`.
+    pub builtins: Option<Vec<String>>,
+    /// Log level used by the command line binary.
+    #[cfg(feature = "log")]
+    pub log_level: Option<Level>,
+    /// Raise a runtime error when arithmetic produces infinity or NaN.
+    pub checked_math: bool,
+    /// End the output of each `print` statement with a newline, `true` when
+    /// `None`. See `LoxInterpreter::set_print_newline`.
+    pub print_newline: Option<bool>,
+    /// Reject adding properties to an instance outside of its class's `init`.
+    pub strict_properties: bool,
+    /// Seed for hashing, making the iteration order of instance props and
+    /// Maps reproducible across runs.
+    pub hash_seed: Option<u64>,
+    /// Bounds on the size of scripts, from the `[limits]` section.
+    pub limits: ParseLimits,
+    /// Bounds on the work done by each run, also from `[limits]`.
+    pub exec_limits: ExecLimits,
+    /// How deeply functions may call each other, `DEFAULT_STACK_DEPTH` when
+    /// `None`. Only raise it when running on a thread with a bigger stack.
+    pub stack_depth: Option<usize>,
+    /// Bytes of Rust stack calls may use, `DEFAULT_STACK_SIZE` when `None`.
+    /// Only raise it when running on a thread with a bigger stack.
+    pub stack_size: Option<usize>,
+    /// Install the debug pack of runtime metrics on top of the other packs.
+    pub debug: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ConfigValue {
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<ConfigValue>),
+}
+
+/// Builtins without side effects, exposed to rules.
+const RULE_BUILTINS: [&str; 20] = [
+    "Array",
+    "Map",
+    "String",
+    "Number",
+    "get_prop",
+    "methods_of",
+    "fields_of",
+    "type_of",
+    "has_property",
+    "properties",
+    "class_of",
+    "floor",
+    "ceil",
+    "round",
+    "abs",
+    "sqrt",
+    "min",
+    "max",
+    "num_to_string",
+    "parse_number",
+];
+
+impl LoxConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Preset for evaluating untrusted single expressions with
+    /// `LoxInterpreter::evaluate_rule`: no I/O or other side effecting
+    /// builtins, checked math, and small bounds on the size of rules.
+    pub fn rule_engine() -> Self {
+        Self {
+            packs: Some(vec![
+                "core".into(),
+                "string".into(),
+                "number".into(),
+                "math".into(),
+            ]),
+            builtins: Some(RULE_BUILTINS.iter().map(|name| name.to_string()).collect()),
+            checked_math: true,
+            limits: ParseLimits {
+                max_source_bytes: Some(4096),
+                max_tokens: Some(1024),
+                max_statements: Some(1),
+                max_depth: Some(64),
+            },
+            ..Self::default()
+        }
+    }
+
+    pub fn load(path: &Path) -> LoxResult<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Look for a config file in the directory containing `script`.
+    pub fn find(script: &str) -> LoxResult<Option<Self>> {
+        let path = Path::new(script)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(CONFIG_FILE_NAME);
+        if path.is_file() {
+            Ok(Some(Self::load(&path)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parse the supported TOML subset: `[section]` headers and `key = value`
+    /// pairs whose values are strings, numbers, booleans or arrays of those.
+    pub fn parse(source: &str) -> LoxResult<Self> {
+        let mut config = Self::new();
+        let mut section = String::new();
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i as u32 + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                section = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| config_error("Expected closing \"]\"", line_number))?
+                    .trim()
+                    .to_string();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| config_error("Expected \"key = value\"", line_number))?;
+            let key = if section.is_empty() {
+                key.trim().to_string()
+            } else {
+                format!("{}.{}", section, key.trim())
+            };
+            let mut chars = value.trim().chars().peekable();
+            let value = parse_value(&mut chars, line_number)?;
+            skip_whitespace(&mut chars);
+            if !matches!(chars.peek(), None | Some('#')) {
+                return Err(config_error(
+                    "Unexpected characters after value",
+                    line_number,
+                ));
+            }
+            config.set(&key, value, line_number)?;
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, key: &str, value: ConfigValue, line: u32) -> LoxResult {
+        match key {
+            "packs" => {
+                let names = string_array(key, value, line)?;
+                if let Some(name) = names.iter().find(|name| find_pack(name).is_none()) {
+                    return Err(config_error(
+                        &format!("Unknown builtin pack \"{}\"", name),
+                        line,
+                    ));
+                }
+                self.packs = Some(names);
+            }
+            "builtins" => {
+                self.builtins = Some(string_array(key, value, line)?);
+            }
+            // Accepted but unused without the `log` feature
+            #[cfg(not(feature = "log"))]
+            "log_level" => match value {
+                ConfigValue::String(_) => {}
+                _ => return Err(config_error("Expected \"log_level\" to be a string", line)),
+            },
+            #[cfg(feature = "log")]
+            "log_level" => match value {
+                ConfigValue::String(level) => {
+                    self.log_level = Some(Level::from_str(&level).map_err(|_| {
+                        config_error(&format!("Unknown log level \"{}\"", level), line)
+                    })?);
+                }
+                _ => return Err(config_error("Expected \"log_level\" to be a string", line)),
+            },
+            "checked_math" => match value {
+                ConfigValue::Boolean(checked) => self.checked_math = checked,
+                _ => {
+                    return Err(config_error(
+                        "Expected \"checked_math\" to be a boolean",
+                        line,
+                    ))
+                }
+            },
+            "strict_properties" => match value {
+                ConfigValue::Boolean(strict) => self.strict_properties = strict,
+                _ => {
+                    return Err(config_error(
+                        "Expected \"strict_properties\" to be a boolean",
+                        line,
+                    ))
+                }
+            },
+            "print_newline" => match value {
+                ConfigValue::Boolean(newline) => self.print_newline = Some(newline),
+                _ => {
+                    return Err(config_error(
+                        "Expected \"print_newline\" to be a boolean",
+                        line,
+                    ))
+                }
+            },
+            "debug" => match value {
+                ConfigValue::Boolean(debug) => self.debug = debug,
+                _ => return Err(config_error("Expected \"debug\" to be a boolean", line)),
+            },
+            "hash_seed" => self.hash_seed = Some(count(key, value, line)? as u64),
+            "stack_depth" => self.stack_depth = Some(count(key, value, line)?),
+            "stack_size" => self.stack_size = Some(count(key, value, line)?),
+            "limits.max_source_bytes" => {
+                self.limits.max_source_bytes = Some(count(key, value, line)?);
+            }
+            "limits.max_tokens" => self.limits.max_tokens = Some(count(key, value, line)?),
+            "limits.max_statements" => {
+                self.limits.max_statements = Some(count(key, value, line)?);
+            }
+            "limits.max_depth" => self.limits.max_depth = Some(count(key, value, line)?),
+            "limits.max_steps" => self.exec_limits.max_steps = Some(count(key, value, line)?),
+            "limits.max_call_depth" => {
+                self.exec_limits.max_call_depth = Some(count(key, value, line)?);
+            }
+            "limits.timeout_ms" => {
+                let millis = count(key, value, line)? as u64;
+                self.exec_limits.timeout = Some(Duration::from_millis(millis));
+            }
+            _ => return Err(config_error(&format!("Unknown option \"{}\"", key), line)),
+        }
+        Ok(())
+    }
+}
+
+fn string_array(key: &str, value: ConfigValue, line: u32) -> LoxResult<Vec<String>> {
+    match value {
+        ConfigValue::Array(values) => values
+            .into_iter()
+            .map(|value| match value {
+                ConfigValue::String(name) => Ok(name),
+                _ => Err(config_error(
+                    &format!("Expected \"{}\" to only contain strings", key),
+                    line,
+                )),
+            })
+            .collect(),
+        _ => Err(config_error(
+            &format!("Expected \"{}\" to be an array", key),
+            line,
+        )),
+    }
+}
+
+fn count(key: &str, value: ConfigValue, line: u32) -> LoxResult<usize> {
+    match value {
+        ConfigValue::Number(n) if n >= 0.0 && n.fract() == 0.0 => Ok(n as usize),
+        _ => Err(config_error(
+            &format!("Expected \"{}\" to be a positive whole number", key),
+            line,
+        )),
+    }
+}
+
+fn config_error(message: &str, line: u32) -> LoxError {
+    LoxError::General(format!("Config error on line {}: {}", line, message))
+}
+
+fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn parse_value(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    line: u32,
+) -> LoxResult<ConfigValue> {
+    skip_whitespace(chars);
+    match chars.peek() {
+        Some('"') => {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => return Ok(ConfigValue::String(value)),
+                    Some('\\') => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some(c) => value.push(c),
+                        None => return Err(config_error("Unterminated string", line)),
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err(config_error("Unterminated string", line)),
+                }
+            }
+        }
+        Some('[') => {
+            chars.next();
+            let mut values = vec![];
+            loop {
+                skip_whitespace(chars);
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    return Ok(ConfigValue::Array(values));
+                }
+                values.push(parse_value(chars, line)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Ok(ConfigValue::Array(values)),
+                    _ => return Err(config_error("Expected \",\" or \"]\" in array", line)),
+                }
+            }
+        }
+        Some(_) => {
+            let mut word = String::new();
+            while let Some(c) = chars.peek() {
+                if c.is_alphanumeric() || matches!(c, '.' | '-' | '+' | '_') {
+                    word.push(*c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            match word.as_str() {
+                "true" => Ok(ConfigValue::Boolean(true)),
+                "false" => Ok(ConfigValue::Boolean(false)),
+                _ => word
+                    .replace('_', "")
+                    .parse::<f64>()
+                    .map(ConfigValue::Number)
+                    .map_err(|_| config_error(&format!("Invalid value \"{}\"", word), line)),
+            }
+        }
+        None => Err(config_error("Expected a value", line)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::*;
+
+    #[test]
+    fn parse() -> LoxResult {
+        let config = LoxConfig::parse(
+            r#"
+            # Only allow the clock
+            packs = ["core", "system"]
+            builtins = ["time", "Array"] # trailing comment
+            log_level = "warn"
+            checked_math = true
+            print_newline = false
+            strict_properties = true
+            hash_seed = 42
+            stack_depth = 64
+            stack_size = 65536
+            debug = true
+
+            [limits]
+            max_tokens = 1000
+            max_depth = 64
+            max_steps = 100000
+            timeout_ms = 500
+        "#,
+        )?;
+        assert_eq!(
+            config.builtins,
+            Some(vec!["time".to_string(), "Array".to_string()])
+        );
+        assert_eq!(
+            config.packs,
+            Some(vec!["core".to_string(), "system".to_string()])
+        );
+        assert!(config.checked_math);
+        assert_eq!(config.print_newline, Some(false));
+        assert!(config.strict_properties);
+        assert_eq!(config.hash_seed, Some(42));
+        assert_eq!(config.stack_depth, Some(64));
+        assert_eq!(config.stack_size, Some(65536));
+        assert!(config.debug);
+        assert_eq!(config.limits.max_tokens, Some(1000));
+        assert_eq!(config.limits.max_depth, Some(64));
+        assert_eq!(config.limits.max_statements, None);
+        assert_eq!(config.exec_limits.max_steps, Some(100000));
+        assert_eq!(config.exec_limits.timeout, Some(Duration::from_millis(500)));
+        assert_eq!(config.exec_limits.max_call_depth, None);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn log_level() -> LoxResult {
+        let config = LoxConfig::parse("log_level = \"warn\"")?;
+        assert_eq!(config.log_level, Some(Level::Warn));
+        assert!(LoxConfig::parse("log_level = \"loud\"").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(LoxConfig::parse("unknown = 1").is_err());
+        assert!(LoxConfig::parse("builtins = \"time\"").is_err());
+        assert!(LoxConfig::parse("checked_math = 1").is_err());
+        assert!(LoxConfig::parse("print_newline = \"no\"").is_err());
+        assert!(LoxConfig::parse("debug = \"yes\"").is_err());
+        assert!(LoxConfig::parse("[limits]\nmax_depth = 1.5").is_err());
+        assert!(LoxConfig::parse("packs = [\"missing\"]").is_err());
+        assert!(LoxConfig::parse("builtins = [\"time\"").is_err());
+    }
+
+    #[test]
+    fn builtins() -> LoxResult {
+        let mut lox = LoxInterpreter::with_config(LoxConfig {
+            builtins: Some(vec!["time".into()]),
+            ..LoxConfig::default()
+        });
+        lox.exec("time();")?;
+        assert!(lox.exec("get_args();").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn packs() -> LoxResult {
+        let mut lox = LoxInterpreter::with_config(LoxConfig {
+            packs: Some(vec!["core".into()]),
+            ..LoxConfig::default()
+        });
+        lox.exec("Array();")?;
+        assert!(lox.exec("time();").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn limits() {
+        let mut lox = LoxInterpreter::with_config(LoxConfig {
+            limits: ParseLimits {
+                max_statements: Some(2),
+                ..ParseLimits::default()
+            },
+            ..LoxConfig::default()
+        });
+        assert!(lox.exec("var a = 1; var b = 2;").is_ok());
+        assert!(matches!(
+            lox.exec("var a = 1; var b = 2; var c = 3;"),
+            Err(LoxError::LimitExceeded(_))
+        ));
+    }
+}
@@ -0,0 +1,137 @@
+use super::stmt::Stmt;
+use std::collections::BTreeMap;
+
+/// Per-line statement execution counts for a run.
+#[derive(Clone, Default)]
+pub struct Coverage {
+    hits: BTreeMap<u32, u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register every executable line in a program so unexecuted lines are reported.
+    pub fn register(&mut self, statements: &[Stmt]) {
+        for stmt in statements.iter() {
+            match stmt {
+                Stmt::Block { statements, .. } => self.register(statements),
+                Stmt::IfElse {
+                    body, else_branch, ..
+                } => {
+                    self.hits.entry(stmt.line()).or_insert(0);
+                    self.register(std::slice::from_ref(body));
+                    if let Some(else_branch) = else_branch {
+                        self.register(std::slice::from_ref(else_branch));
+                    }
+                }
+                Stmt::ForLoop {
+                    initializer, body, ..
+                } => {
+                    self.register(std::slice::from_ref(initializer));
+                    self.register(std::slice::from_ref(body));
+                }
+                Stmt::WhileLoop { body, .. }
+                | Stmt::ForIn { body, .. }
+                | Stmt::Defer { body, .. }
+                | Stmt::Using { body, .. } => {
+                    self.hits.entry(stmt.line()).or_insert(0);
+                    self.register(std::slice::from_ref(body));
+                }
+                Stmt::Try {
+                    body,
+                    catch,
+                    finally,
+                    ..
+                } => {
+                    self.hits.entry(stmt.line()).or_insert(0);
+                    self.register(std::slice::from_ref(body));
+                    if let Some((_, handler)) = catch {
+                        self.register(std::slice::from_ref(handler));
+                    }
+                    if let Some(finally) = finally {
+                        self.register(std::slice::from_ref(finally));
+                    }
+                }
+                Stmt::Fun { body, .. } => {
+                    self.hits.entry(stmt.line()).or_insert(0);
+                    self.register(body);
+                }
+                Stmt::Class { methods, .. } => {
+                    self.hits.entry(stmt.line()).or_insert(0);
+                    for method in methods.iter() {
+                        if let Stmt::Fun { body, .. } = method {
+                            self.register(body);
+                        }
+                    }
+                }
+                _ => {
+                    self.hits.entry(stmt.line()).or_insert(0);
+                }
+            }
+        }
+    }
+
+    pub fn hit(&mut self, line: u32) {
+        *self.hits.entry(line).or_insert(0) += 1;
+    }
+
+    /// Number of times statements on `line` were executed.
+    pub fn hits(&self, line: u32) -> Option<u64> {
+        self.hits.get(&line).copied()
+    }
+
+    pub fn lines_found(&self) -> usize {
+        self.hits.len()
+    }
+
+    pub fn lines_hit(&self) -> usize {
+        self.hits.values().filter(|count| **count > 0).count()
+    }
+
+    /// Render the coverage as an lcov tracefile record for `source_file`.
+    pub fn to_lcov(&self, source_file: &str) -> String {
+        let mut out = format!("TN:\nSF:{}\n", source_file);
+        for (line, count) in self.hits.iter() {
+            // Scanner lines are zero based, lcov lines are one based
+            out.push_str(&format!("DA:{},{}\n", line + 1, count));
+        }
+        out.push_str(&format!(
+            "LF:{}\nLH:{}\nend_of_record\n",
+            self.lines_found(),
+            self.lines_hit()
+        ));
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::*, interpreter::*};
+
+    #[test]
+    fn coverage() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.enable_coverage();
+        lox.exec(
+            r#"var i = 0;
+while (i < 3) {
+    i = i + 1;
+}
+if (i > 5) {
+    print "unreachable";
+}
+"#,
+        )?;
+        let coverage = lox.coverage().expect("Expected coverage to be enabled");
+        assert_eq!(coverage.hits(0), Some(1));
+        assert_eq!(coverage.hits(2), Some(3));
+        assert_eq!(coverage.hits(5), Some(0));
+        assert_eq!(coverage.lines_found(), 5);
+        assert_eq!(coverage.lines_hit(), 4);
+        assert!(coverage.to_lcov("test.lox").contains("DA:6,0\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{
+    config::LoxConfig,
+    interpreter::{Backend, LoxInterpreter},
+};
+use std::{cell::RefCell, fmt, rc::Rc};
+
+/// What running a script on one backend produced: everything printed, then
+/// the value of the last statement or the error that stopped it.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Outcome {
+    pub output: String,
+    pub result: Result<String, String>,
+}
+
+impl Outcome {
+    pub fn run(backend: Backend, source: &str) -> Self {
+        let mut lox = LoxInterpreter::with_backend(LoxConfig::default(), backend);
+        let buffer = Rc::new(RefCell::new(String::new()));
+        lox.set_output(Box::new(buffer.clone()));
+        let result = lox
+            .eval(source)
+            .map(|value| value.to_string())
+            .map_err(|err| format!("{:?}: {}", err.line(), err));
+        Self {
+            output: buffer.take(),
+            result,
+        }
+    }
+}
+
+/// A backend that didn't behave like the tree-walker.
+pub struct Divergence {
+    pub backend: Backend,
+    pub expected: Outcome,
+    pub actual: Outcome,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:?} backend diverged from {:?}",
+            self.backend,
+            Backend::TreeWalk
+        )?;
+        if self.actual.output != self.expected.output {
+            writeln!(f, "expected output:\n{}", self.expected.output)?;
+            writeln!(f, "actual output:\n{}", self.actual.output)?;
+        }
+        if self.actual.result != self.expected.result {
+            writeln!(f, "expected result: {:?}", self.expected.result)?;
+            writeln!(f, "actual result: {:?}", self.actual.result)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `source` on every backend, comparing each against the tree-walker.
+pub fn compare(source: &str) -> Result<Outcome, Divergence> {
+    let expected = Outcome::run(Backend::TreeWalk, source);
+    for backend in Backend::ALL {
+        let actual = Outcome::run(backend, source);
+        if actual != expected {
+            return Err(Divergence {
+                backend,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(expected)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spec;
+
+    #[test]
+    fn backends_agree() {
+        for case in spec::test_cases() {
+            if let Err(divergence) = compare(case.source) {
+                panic!("{}\n{}", case.name, divergence);
+            }
+        }
+        for source in ["{}", "while (false) {}", "var x = 1; { {} } x;"] {
+            if let Err(divergence) = compare(source) {
+                panic!("{:?}\n{}", source, divergence);
+            }
+        }
+    }
+
+    #[test]
+    fn divergence() {
+        let expected = Outcome::run(Backend::TreeWalk, "print 1; 2;");
+        assert_eq!(expected.output, "1\n");
+        assert_eq!(expected.result, Ok("2".into()));
+        let divergence = Divergence {
+            backend: Backend::Bytecode,
+            actual: Outcome {
+                output: expected.output.clone(),
+                result: Err("Some(0): Runtime Error: oops".into()),
+            },
+            expected,
+        };
+        let report = divergence.to_string();
+        assert!(report.starts_with("Bytecode backend diverged from TreeWalk"));
+        assert!(!report.contains("expected output"));
+        assert!(report.contains("actual result: Err("));
+    }
+}
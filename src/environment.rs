@@ -1,7 +1,103 @@
-use super::{builtins::*, value::*};
-use std::collections::HashMap;
+use super::{
+    builtins::*,
+    class::{LoxClass, LoxSuper},
+    error::*,
+    function::*,
+    hashing::LoxHasher,
+    object::LoxObject,
+    value::*,
+};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::{Rc, Weak},
+};
 
-pub type LoxProperties = HashMap<String, LoxValue>;
+pub type LoxProperties = HashMap<String, LoxValue, LoxHasher>;
+
+/// Global variables copied out of an interpreter by
+/// `Environment::export_globals`, in declaration order.
+pub type LoxVars = Vec<LoxVar>;
+
+/// A global variable detached from the interpreter it came from. It holds no
+/// `Rc`s, so it can be sent to other threads and imported into any number of
+/// interpreters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoxVar {
+    pub name: String,
+    pub value: LoxData,
+    pub constant: bool,
+}
+
+/// A deep copy of a value made only of data: primitives, and containers and
+/// instances holding data.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoxData {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Vec(Vec<LoxData>),
+    Map(Vec<(String, LoxData)>),
+    /// An instance with its props. Importing binds it to the class of the same
+    /// name in the importing interpreter, if it had a class.
+    Object {
+        class_name: String,
+        has_class: bool,
+        props: Vec<(String, LoxData)>,
+    },
+}
+
+impl LoxData {
+    /// Copy `value`, or `None` if it holds functions or classes, which belong
+    /// to the scopes of their interpreter, or contains itself.
+    pub fn copy(value: &LoxValue) -> Option<Self> {
+        copy_data(value, &mut vec![])
+    }
+}
+
+fn copy_data(value: &LoxValue, path: &mut Vec<*const ()>) -> Option<LoxData> {
+    let ptr = match value {
+        LoxValue::Nil => return Some(LoxData::Nil),
+        LoxValue::Boolean(value) => return Some(LoxData::Boolean(*value)),
+        LoxValue::Number(value) => return Some(LoxData::Number(*value)),
+        LoxValue::String(value) => return Some(LoxData::String(value.clone())),
+        LoxValue::Function(_) | LoxValue::Class(_) | LoxValue::Super(_) => return None,
+        LoxValue::Vec(vec) => Rc::as_ptr(vec) as *const (),
+        LoxValue::Map(map) => Rc::as_ptr(map) as *const (),
+        LoxValue::Object(obj) => Rc::as_ptr(obj) as *const (),
+    };
+    if path.contains(&ptr) {
+        return None;
+    }
+    path.push(ptr);
+    let copy_entries = |entries: &LoxProperties, path: &mut Vec<*const ()>| {
+        sorted_entries(entries)
+            .into_iter()
+            .map(|(key, value)| Some((key, copy_data(&value, path)?)))
+            .collect::<Option<Vec<(String, LoxData)>>>()
+    };
+    let data = match value {
+        LoxValue::Vec(vec) => vec
+            .borrow()
+            .iter()
+            .map(|value| copy_data(value, path))
+            .collect::<Option<Vec<LoxData>>>()
+            .map(LoxData::Vec),
+        LoxValue::Map(map) => copy_entries(&map.borrow(), path).map(LoxData::Map),
+        LoxValue::Object(obj) => {
+            let obj = obj.borrow();
+            copy_entries(&obj.props, path).map(|props| LoxData::Object {
+                class_name: obj.class_name.clone(),
+                has_class: obj.class.is_some(),
+                props,
+            })
+        }
+        _ => unreachable!(),
+    };
+    path.pop();
+    data
+}
 
 #[derive(PartialEq, Clone, Copy)]
 pub struct ScopeHandle(usize);
@@ -12,44 +108,115 @@ impl std::fmt::Display for ScopeHandle {
     }
 }
 
+impl ScopeHandle {
+    /// Position of the scope in the environment, for logs.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
 pub const GLOBAL_SCOPE: ScopeHandle = ScopeHandle(0);
 
 pub struct Scope {
-    vars: LoxProperties,
+    /// Variable names in declaration order, their values are in `values` at
+    /// the same position. The resolver predicts these positions as slots.
+    names: Vec<String>,
+    values: Vec<LoxValue>,
+    /// Whether the variable at the same position was declared with `const`.
+    constants: Vec<bool>,
+    /// Position of each name, for lookups without a slot.
+    positions: HashMap<String, usize, LoxHasher>,
     parent: Option<ScopeHandle>,
     children: Vec<ScopeHandle>,
+    /// Whether something other than a child scope refers to this scope.
+    captured: bool,
+    /// Whether this root scope was given up by its owner, so the collector
+    /// doesn't keep it alive as a root.
+    released: bool,
+}
+
+impl Scope {
+    /// Where the variable `key` is stored, checking `slot` first.
+    fn position(&self, slot: Option<usize>, key: &str) -> Option<usize> {
+        match slot {
+            Some(slot) if self.names.get(slot).is_some_and(|name| name == key) => Some(slot),
+            _ => self.positions.get(key).copied(),
+        }
+    }
 }
 
 pub struct Environment {
     builtins: LoxProperties,
     scopes: Vec<Option<Scope>>,
+    hasher: LoxHasher,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
     pub fn new() -> Self {
+        let mut env = Self::empty();
+        for pack in registry().iter().filter(|pack| !pack.opt_in()) {
+            pack.install(&mut env);
+        }
+        env
+    }
+
+    /// An environment without any builtins installed.
+    pub fn empty() -> Self {
         Self {
-            builtins: get_builtins(),
+            builtins: LoxProperties::default(),
             scopes: vec![
                 // Root scope
                 Some(Scope {
-                    vars: HashMap::new(),
+                    names: vec![],
+                    values: vec![],
+                    constants: vec![],
+                    positions: HashMap::default(),
                     parent: None,
                     children: vec![],
+                    captured: false,
+                    released: false,
                 }),
             ],
+            hasher: LoxHasher::default(),
         }
     }
 
+    /// The hasher new scopes, instances and Maps are created with.
+    pub fn hasher(&self) -> &LoxHasher {
+        &self.hasher
+    }
+
+    /// Hash maps created from now on with `hasher`, existing ones are unaffected.
+    pub fn set_hasher(&mut self, hasher: LoxHasher) {
+        self.hasher = hasher;
+    }
+
+    /// An empty map using the environment's hasher.
+    pub fn new_properties(&self) -> LoxProperties {
+        LoxProperties::with_hasher(self.hasher.clone())
+    }
+
     pub fn new_scope(&mut self, parent: Option<ScopeHandle>) -> ScopeHandle {
         let id = self.get_empty();
         let scope = Scope {
-            vars: HashMap::new(),
+            names: vec![],
+            values: vec![],
+            constants: vec![],
+            positions: HashMap::with_hasher(self.hasher.clone()),
             parent,
             children: vec![],
+            captured: false,
+            released: false,
         };
         self.scopes[id.0] = Some(scope);
-        if let Some(id) = parent {
-            self.get_scope_mut(id)
+        if let Some(parent) = parent {
+            self.get_scope_mut(parent)
                 .expect("Invalid scope")
                 .children
                 .push(id);
@@ -57,6 +224,61 @@ impl Environment {
         id
     }
 
+    /// A new scope next to `handle`, under the same parent, holding copies of
+    /// its variables in the same slots.
+    pub fn copy_scope(&mut self, handle: ScopeHandle) -> ScopeHandle {
+        let scope = self.get_scope(handle).expect("Invalid scope");
+        let (names, values, constants, positions, parent) = (
+            scope.names.clone(),
+            scope.values.clone(),
+            scope.constants.clone(),
+            scope.positions.clone(),
+            scope.parent,
+        );
+        let id = self.new_scope(parent);
+        let copy = self.get_scope_mut(id).expect("Invalid scope");
+        copy.names = names;
+        copy.values = values;
+        copy.constants = constants;
+        copy.positions = positions;
+        id
+    }
+
+    /// Keep `handle` from being released, e.g. because class methods use it as
+    /// their closure.
+    pub fn capture(&mut self, handle: ScopeHandle) {
+        if let Some(scope) = self.get_scope_mut(handle) {
+            scope.captured = true;
+        }
+    }
+
+    /// Free a scope that is being exited if nothing can refer to it anymore,
+    /// that is it has no child scopes, like those of closures, and was not
+    /// captured. Scopes that are kept are left to `collect`.
+    pub fn release(&mut self, handle: ScopeHandle) -> bool {
+        let Some(scope) = self.get_scope(handle) else {
+            return false;
+        };
+        if scope.captured || !scope.children.is_empty() {
+            return false;
+        }
+        if let Some(parent) = scope.parent.and_then(|parent| self.get_scope_mut(parent)) {
+            parent.children.retain(|child| *child != handle);
+        }
+        self.scopes[handle.0] = None;
+        true
+    }
+
+    /// Give up the root scope of a context. It's freed now if nothing refers
+    /// to it, and otherwise by `collect` once nothing reachable does.
+    pub fn release_root(&mut self, handle: ScopeHandle) {
+        if !self.release(handle) {
+            if let Some(scope) = self.get_scope_mut(handle) {
+                scope.released = true;
+            }
+        }
+    }
+
     pub fn parent_scope(&self, handle: ScopeHandle) -> Option<ScopeHandle> {
         self.get_scope(handle).and_then(|scope| scope.parent)
     }
@@ -70,35 +292,331 @@ impl Environment {
         }
     }
 
+    /// Walk up to the global scope of the context `handle` belongs to.
+    pub fn root_scope(&self, handle: ScopeHandle) -> ScopeHandle {
+        match self.parent_scope(handle) {
+            Some(parent) => self.root_scope(parent),
+            None => handle,
+        }
+    }
+
     pub fn get(&self, handle: Option<ScopeHandle>, key: &str) -> Option<LoxValue> {
-        let scope = self.get_scope(handle.unwrap_or(GLOBAL_SCOPE))?;
-        scope
-            .vars
-            .get(key)
-            .cloned()
-            .or_else(|| self.get_builtin(key))
+        self.get_at(handle.unwrap_or(GLOBAL_SCOPE), None, key)
+    }
+
+    /// Like `get`, reading the variable straight from `slot` if it holds
+    /// `key`. Slots are computed by the resolver and the name check catches
+    /// declarations that didn't happen in the order it expected.
+    pub fn get_at(&self, handle: ScopeHandle, slot: Option<usize>, key: &str) -> Option<LoxValue> {
+        let scope = self.get_scope(handle)?;
+        match scope.position(slot, key) {
+            Some(position) => Some(scope.values[position].clone()),
+            None => self.get_builtin(key),
+        }
+    }
+
+    /// Names of every variable visible from `handle`, including builtins.
+    pub fn visible_names(&self, handle: ScopeHandle) -> Vec<String> {
+        let mut names: Vec<String> = self.builtins.keys().cloned().collect();
+        let mut current = Some(handle);
+        while let Some(scope) = current.and_then(|handle| self.get_scope(handle)) {
+            names.extend(scope.names.iter().cloned());
+            current = scope.parent;
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The variable `key` declared in the scope `handle` itself, ignoring
+    /// parents and builtins.
+    pub fn get_declared(&self, handle: ScopeHandle, key: &str) -> Option<LoxValue> {
+        let scope = self.get_scope(handle)?;
+        let position = scope.position(None, key)?;
+        Some(scope.values[position].clone())
     }
 
     pub fn declare(&mut self, handle: Option<ScopeHandle>, key: String, value: LoxValue) {
+        self.declare_binding(handle, key, value, false);
+    }
+
+    /// Like `declare`, for a variable that can't be assigned to afterwards.
+    /// Declaring the name again replaces the constant.
+    pub fn declare_constant(&mut self, handle: Option<ScopeHandle>, key: String, value: LoxValue) {
+        self.declare_binding(handle, key, value, true);
+    }
+
+    fn declare_binding(
+        &mut self,
+        handle: Option<ScopeHandle>,
+        key: String,
+        value: LoxValue,
+        constant: bool,
+    ) {
         if let Some(scope) = self.get_scope_mut(handle.unwrap_or(GLOBAL_SCOPE)) {
-            scope.vars.insert(key, value);
+            match scope.position(None, &key) {
+                Some(position) => {
+                    scope.values[position] = value;
+                    scope.constants[position] = constant;
+                }
+                None => {
+                    scope.positions.insert(key.clone(), scope.names.len());
+                    scope.names.push(key);
+                    scope.values.push(value);
+                    scope.constants.push(constant);
+                }
+            }
         }
     }
 
+    /// Whether the variable `key` declared in the scope `handle` is a
+    /// constant. See `get_at` for `slot`.
+    pub fn is_constant_at(&self, handle: ScopeHandle, slot: Option<usize>, key: &str) -> bool {
+        self.get_scope(handle)
+            .and_then(|scope| Some(scope.constants[scope.position(slot, key)?]))
+            .unwrap_or(false)
+    }
+
     pub fn assign(
         &mut self,
         handle: Option<ScopeHandle>,
         key: String,
         value: LoxValue,
     ) -> Option<LoxValue> {
-        let scope = self
-            .get_scope_mut(handle.unwrap_or(GLOBAL_SCOPE))
-            .expect("Invalid scope");
-        assert!(
-            scope.vars.contains_key(&key),
-            "Cannot assign variable before declaration"
-        );
-        scope.vars.insert(key, value)
+        self.assign_at(handle.unwrap_or(GLOBAL_SCOPE), None, &key, value)
+    }
+
+    /// Like `assign`, writing straight to `slot` if it holds `key`. See
+    /// `get_at`. Returns the old value, or `None` if `key` isn't declared in
+    /// the scope.
+    pub fn assign_at(
+        &mut self,
+        handle: ScopeHandle,
+        slot: Option<usize>,
+        key: &str,
+        value: LoxValue,
+    ) -> Option<LoxValue> {
+        let scope = self.get_scope_mut(handle)?;
+        let position = scope.position(slot, key)?;
+        Some(std::mem::replace(&mut scope.values[position], value))
+    }
+
+    pub fn define_builtin(&mut self, key: String, value: LoxValue) {
+        self.builtins.insert(key, value);
+    }
+
+    /// Remove every builtin whose name is not listed in `names`.
+    pub fn retain_builtins(&mut self, names: &[String]) {
+        self.builtins.retain(|key, _| names.contains(key));
+    }
+
+    /// Copy the global variables holding only data, see `LoxData::copy`.
+    /// Functions and classes are left out: run their declarations in the
+    /// importing interpreter, whose classes imported instances are bound to.
+    pub fn export_globals(&self) -> LoxVars {
+        let Some(scope) = self.get_scope(GLOBAL_SCOPE) else {
+            return vec![];
+        };
+        scope
+            .names
+            .iter()
+            .zip(scope.values.iter())
+            .zip(scope.constants.iter())
+            .filter_map(|((name, value), constant)| {
+                Some(LoxVar {
+                    name: name.clone(),
+                    value: LoxData::copy(value)?,
+                    constant: *constant,
+                })
+            })
+            .collect()
+    }
+
+    /// Declare the exported `vars` as globals, replacing variables of the
+    /// same name. Fails without declaring anything if an instance's class
+    /// isn't declared here.
+    pub fn import_globals(&mut self, vars: LoxVars) -> LoxResult {
+        let values = vars
+            .iter()
+            .map(|var| self.import_data(&var.value))
+            .collect::<LoxResult<Vec<LoxValue>>>()?;
+        for (var, value) in vars.into_iter().zip(values) {
+            self.declare_binding(None, var.name, value, var.constant);
+        }
+        Ok(())
+    }
+
+    fn import_data(&self, data: &LoxData) -> LoxResult<LoxValue> {
+        let import_entries = |entries: &[(String, LoxData)]| -> LoxResult<LoxProperties> {
+            let mut props = self.new_properties();
+            for (key, value) in entries.iter() {
+                props.insert(key.clone(), self.import_data(value)?);
+            }
+            Ok(props)
+        };
+        Ok(match data {
+            LoxData::Nil => LoxValue::Nil,
+            LoxData::Boolean(value) => LoxValue::Boolean(*value),
+            LoxData::Number(value) => LoxValue::Number(*value),
+            LoxData::String(value) => LoxValue::String(value.clone()),
+            LoxData::Vec(values) => values
+                .iter()
+                .map(|value| self.import_data(value))
+                .collect::<LoxResult<Vec<LoxValue>>>()?
+                .into(),
+            LoxData::Map(entries) => LoxValue::Map(Rc::new(RefCell::new(import_entries(entries)?))),
+            LoxData::Object {
+                class_name,
+                has_class,
+                props,
+            } => {
+                let props = import_entries(props)?;
+                let obj = if *has_class {
+                    let Some(LoxValue::Class(class)) = self.get(None, class_name) else {
+                        return Err(LoxError::General(format!(
+                            "Cannot import an instance of \"{}\", the class isn't declared",
+                            class_name
+                        )));
+                    };
+                    LoxObject::new_instance(&class, props, 0)
+                } else {
+                    Rc::new(RefCell::new(LoxObject {
+                        class_name: class_name.clone(),
+                        props,
+                        native: false,
+                        class: None,
+                        origin: None,
+                    }))
+                };
+                obj.into()
+            }
+        })
+    }
+
+    /// Number of scopes currently allocated.
+    pub fn scope_count(&self) -> usize {
+        self.scopes.iter().filter(|scope| scope.is_some()).count()
+    }
+
+    /// Free every scope that is not reachable from a root scope, a builtin,
+    /// `values` or a heap value held outside the environment. Only safe
+    /// between top level statements, since scopes held by the Rust stack mid
+    /// evaluation are not known to the environment. Returns the number of
+    /// freed scopes.
+    pub fn collect(&mut self, values: &[LoxValue]) -> usize {
+        let (marked, _) = self.mark(values);
+        let mut freed = 0;
+        for (i, scope) in self.scopes.iter_mut().enumerate() {
+            if !marked[i] && scope.is_some() {
+                *scope = None;
+                freed += 1;
+            }
+        }
+        for scope in self.scopes.iter_mut().flatten() {
+            scope.children.retain(|child| marked[child.0]);
+        }
+        freed
+    }
+
+    /// Every script function reachable from the root scopes, builtins or `values`.
+    pub fn live_functions(&self, values: &[LoxValue]) -> Vec<Rc<RefCell<LoxFunction>>> {
+        self.mark(values).1
+    }
+
+    /// Find the reachable scopes and script functions.
+    fn mark(&self, values: &[LoxValue]) -> (Vec<bool>, Vec<Rc<RefCell<LoxFunction>>>) {
+        let mut marked = vec![false; self.scopes.len()];
+        let mut functions = vec![];
+        let mut pending_scopes: Vec<ScopeHandle> = self
+            .scopes
+            .iter()
+            .enumerate()
+            .filter(|(_, scope)| {
+                scope
+                    .as_ref()
+                    .is_some_and(|scope| scope.parent.is_none() && !scope.released)
+            })
+            .map(|(i, _)| ScopeHandle(i))
+            .collect();
+        let mut pending_values: Vec<LoxValue> = values.to_vec();
+        pending_values.extend(self.builtins.values().cloned());
+        pending_values.extend(self.held_outside());
+        // Heap values can form cycles, so only visit each allocation once
+        let mut seen: HashSet<*const ()> = HashSet::new();
+        while !pending_scopes.is_empty() || !pending_values.is_empty() {
+            while let Some(handle) = pending_scopes.pop() {
+                if marked[handle.0] {
+                    continue;
+                }
+                marked[handle.0] = true;
+                if let Some(scope) = self.get_scope(handle) {
+                    pending_scopes.extend(scope.parent);
+                    pending_values.extend(scope.values.iter().cloned());
+                }
+            }
+            while let Some(value) = pending_values.pop() {
+                if !heap_address(&value).is_some_and(|address| seen.insert(address)) {
+                    continue;
+                }
+                match &value {
+                    LoxValue::Function(func)
+                        if matches!(func.borrow().body, FunctionBody::Block(..)) =>
+                    {
+                        functions.push(func.clone());
+                    }
+                    LoxValue::Class(class) => {
+                        let class = class.borrow();
+                        functions.extend(
+                            class
+                                .methods
+                                .values()
+                                .chain(class.setters.values())
+                                .chain(class.statics.values())
+                                .filter(|method| matches!(method.body, FunctionBody::Block(..)))
+                                .map(|method| Rc::new(RefCell::new(method.clone()))),
+                        );
+                    }
+                    _ => {}
+                }
+                references(&value, &mut pending_values, &mut pending_scopes);
+            }
+        }
+        (marked, functions)
+    }
+
+    /// Heap values referenced from outside the environment, like closures
+    /// the host got back from `eval`. `mark` can't see those holders, so
+    /// they are found by comparing the reference count of every allocation
+    /// with the references to it stored in scopes and other heap values.
+    fn held_outside(&self) -> Vec<LoxValue> {
+        let mut stored: HashMap<*const (), (usize, WeakValue)> = HashMap::new();
+        let mut pending: Vec<LoxValue> = self
+            .scopes
+            .iter()
+            .flatten()
+            .flat_map(|scope| scope.values.iter().cloned())
+            .chain(self.builtins.values().cloned())
+            .collect();
+        let mut closures = vec![];
+        while let Some(value) = pending.pop() {
+            let (Some(address), Some(weak)) = (heap_address(&value), WeakValue::new(&value)) else {
+                continue;
+            };
+            match stored.get_mut(&address) {
+                Some((count, _)) => *count += 1,
+                None => {
+                    // The weak reference also keeps the address from being
+                    // reused while counting
+                    stored.insert(address, (1, weak));
+                    references(&value, &mut pending, &mut closures);
+                }
+            }
+        }
+        stored
+            .into_values()
+            .filter(|(count, weak)| weak.strong_count() > *count)
+            .filter_map(|(_, weak)| weak.upgrade())
+            .collect()
     }
 
     fn get_scope(&self, handle: ScopeHandle) -> Option<&Scope> {
@@ -127,6 +645,107 @@ impl Environment {
     }
 }
 
+/// Where the heap value `value` is allocated, `None` for primitives.
+fn heap_address(value: &LoxValue) -> Option<*const ()> {
+    match value {
+        LoxValue::Function(func) => Some(Rc::as_ptr(func) as *const ()),
+        LoxValue::Class(class) => Some(Rc::as_ptr(class) as *const ()),
+        LoxValue::Object(obj) => Some(Rc::as_ptr(obj) as *const ()),
+        LoxValue::Super(value) => Some(Rc::as_ptr(value) as *const ()),
+        LoxValue::Vec(vec) => Some(Rc::as_ptr(vec) as *const ()),
+        LoxValue::Map(map) => Some(Rc::as_ptr(map) as *const ()),
+        LoxValue::Nil | LoxValue::Boolean(_) | LoxValue::Number(_) | LoxValue::String(_) => None,
+    }
+}
+
+/// Add the values the heap value `value` refers to, and the scopes its
+/// functions close over.
+fn references(value: &LoxValue, values: &mut Vec<LoxValue>, scopes: &mut Vec<ScopeHandle>) {
+    let mut function_references = |func: &LoxFunction, values: &mut Vec<LoxValue>| {
+        if let FunctionBody::Block(_, closure) = &func.body {
+            scopes.push(*closure);
+        }
+        values.extend(func.this_value.clone());
+        values.extend(func.super_value.clone());
+    };
+    match value {
+        LoxValue::Function(func) => function_references(&func.borrow(), values),
+        LoxValue::Class(class) => {
+            let class = class.borrow();
+            for method in class
+                .methods
+                .values()
+                .chain(class.setters.values())
+                .chain(class.statics.values())
+            {
+                function_references(method, values);
+            }
+            values.extend(class.superclass.clone().map(LoxValue::Class));
+            values.extend(class.static_fields.values().cloned());
+        }
+        LoxValue::Object(obj) => {
+            let obj = obj.borrow();
+            values.extend(obj.props.values().cloned());
+            values.extend(obj.class.clone().map(LoxValue::Class));
+        }
+        LoxValue::Super(value) => {
+            values.push(LoxValue::Class(value.class.clone()));
+            values.push(value.this_value.clone());
+        }
+        LoxValue::Vec(vec) => values.extend(vec.borrow().iter().cloned()),
+        LoxValue::Map(map) => values.extend(map.borrow().values().cloned()),
+        LoxValue::Nil | LoxValue::Boolean(_) | LoxValue::Number(_) | LoxValue::String(_) => {}
+    }
+}
+
+/// A heap value that doesn't keep its allocation alive.
+enum WeakValue {
+    Function(Weak<RefCell<LoxFunction>>),
+    Class(Weak<RefCell<LoxClass>>),
+    Object(Weak<RefCell<LoxObject>>),
+    Super(Weak<LoxSuper>),
+    Vec(Weak<RefCell<Vec<LoxValue>>>),
+    Map(Weak<RefCell<LoxProperties>>),
+}
+
+impl WeakValue {
+    fn new(value: &LoxValue) -> Option<Self> {
+        Some(match value {
+            LoxValue::Function(func) => Self::Function(Rc::downgrade(func)),
+            LoxValue::Class(class) => Self::Class(Rc::downgrade(class)),
+            LoxValue::Object(obj) => Self::Object(Rc::downgrade(obj)),
+            LoxValue::Super(value) => Self::Super(Rc::downgrade(value)),
+            LoxValue::Vec(vec) => Self::Vec(Rc::downgrade(vec)),
+            LoxValue::Map(map) => Self::Map(Rc::downgrade(map)),
+            LoxValue::Nil | LoxValue::Boolean(_) | LoxValue::Number(_) | LoxValue::String(_) => {
+                return None
+            }
+        })
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Self::Function(weak) => weak.strong_count(),
+            Self::Class(weak) => weak.strong_count(),
+            Self::Object(weak) => weak.strong_count(),
+            Self::Super(weak) => weak.strong_count(),
+            Self::Vec(weak) => weak.strong_count(),
+            Self::Map(weak) => weak.strong_count(),
+        }
+    }
+
+    fn upgrade(&self) -> Option<LoxValue> {
+        match self {
+            Self::Function(weak) => weak.upgrade().map(LoxValue::Function),
+            Self::Class(weak) => weak.upgrade().map(LoxValue::Class),
+            Self::Object(weak) => weak.upgrade().map(LoxValue::Object),
+            Self::Super(weak) => weak.upgrade().map(LoxValue::Super),
+            Self::Vec(weak) => weak.upgrade().map(LoxValue::Vec),
+            Self::Map(weak) => weak.upgrade().map(LoxValue::Map),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -161,5 +780,75 @@ mod test {
         env.declare(Some(three), "foo".into(), "three".into());
         assert!(env.ancestor_scope(three, 2).unwrap() == one);
         assert!(env.get(env.ancestor_scope(three, 2), "foo") == Some("one".into()));
+        assert!(env.root_scope(three) == one);
+        assert!(env.root_scope(GLOBAL_SCOPE) == GLOBAL_SCOPE);
+    }
+
+    #[test]
+    fn slots() {
+        let mut env = Environment::new();
+        let scope = env.new_scope(None);
+        env.declare(Some(scope), "a".into(), "one".into());
+        env.declare(Some(scope), "b".into(), "two".into());
+        assert!(env.get_at(scope, Some(1), "b") == Some("two".into()));
+        // A slot holding another variable falls back to looking up the name
+        assert!(env.get_at(scope, Some(0), "b") == Some("two".into()));
+        assert!(env.get_at(scope, Some(5), "a") == Some("one".into()));
+        assert!(env.assign_at(scope, Some(0), "a", "three".into()) == Some("one".into()));
+        // Redeclaring keeps the slot
+        env.declare(Some(scope), "a".into(), "four".into());
+        assert!(env.get_at(scope, Some(0), "a") == Some("four".into()));
+        assert_eq!(
+            env.visible_names(scope).len(),
+            env.visible_names(GLOBAL_SCOPE).len() + 2
+        );
+    }
+
+    #[test]
+    fn collect() {
+        let mut env = Environment::new();
+        let kept = env.new_scope(Some(GLOBAL_SCOPE));
+        let closure = env.new_scope(Some(kept));
+        let dropped = env.new_scope(Some(GLOBAL_SCOPE));
+        env.new_scope(Some(dropped));
+        let mut func = LoxFunction::native("f", vec![], |_, _, _| Ok(LoxValue::Nil));
+        func.body = FunctionBody::Block(vec![], closure);
+        env.declare(None, "f".into(), func.into());
+        assert_eq!(env.scope_count(), 5);
+        assert_eq!(env.collect(&[]), 2);
+        assert_eq!(env.scope_count(), 3);
+        assert!(env.parent_scope(closure) == Some(kept));
+        // Freed slots are reused
+        assert!(env.new_scope(None).0 < 5);
+    }
+
+    #[test]
+    fn collect_held_outside() {
+        let mut env = Environment::new();
+        let closure = env.new_scope(Some(GLOBAL_SCOPE));
+        let mut func = LoxFunction::native("f", vec![], |_, _, _| Ok(LoxValue::Nil));
+        func.body = FunctionBody::Block(vec![], closure);
+        let func: LoxValue = func.into();
+        // Stored in its own closure, and held by the host
+        env.declare(Some(closure), "f".into(), func.clone());
+        assert_eq!(env.collect(&[]), 0);
+        assert!(env.get_declared(closure, "f").is_some());
+        drop(func);
+        assert_eq!(env.collect(&[]), 1);
+    }
+
+    #[test]
+    fn release() {
+        let mut env = Environment::new();
+        let block = env.new_scope(Some(GLOBAL_SCOPE));
+        let nested = env.new_scope(Some(block));
+        // Scopes with children are kept until they're released
+        assert!(!env.release(block));
+        assert!(env.release(nested));
+        assert!(env.release(block));
+        assert!(env.get_scope(block).is_none());
+        let captured = env.new_scope(Some(GLOBAL_SCOPE));
+        env.capture(captured);
+        assert!(!env.release(captured));
     }
 }
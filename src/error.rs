@@ -2,25 +2,169 @@ use std::fmt::Display;
 
 use thiserror::Error;
 
+use crate::resolver::Local;
+
 #[derive(Clone, Debug)]
 pub struct SyntaxError {
     message: String,
     line: u32,
+    column: Option<u32>,
+    hint: Option<String>,
 }
 
 impl SyntaxError {
     pub fn new(message: String, line: u32) -> Self {
-        Self { message, line }
+        Self {
+            message,
+            line,
+            column: None,
+            hint: None,
+        }
+    }
+
+    pub fn with_column(mut self, column: u32) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    /// Attach a suggestion for fixing the error.
+    pub fn with_hint(mut self, hint: Option<String>) -> Self {
+        self.hint = hint;
+        self
+    }
+
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
     }
+
+    pub fn column(&self) -> Option<u32> {
+        self.column
+    }
+}
+
+/// A broken interpreter invariant, with what the interpreter knew at the
+/// time. Its `Display` is meant to be pasted into a bug report as is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InternalError {
+    message: String,
+    line: Option<u32>,
+    node: Option<String>,
+    local: Option<String>,
+}
+
+impl InternalError {
+    pub fn new(message: String) -> Self {
+        Self {
+            message,
+            line: None,
+            node: None,
+            local: None,
+        }
+    }
+
+    pub fn with_line(mut self, line: u32) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    /// Attach the source of the expression or statement being evaluated.
+    pub fn with_node(mut self, node: &impl Display) -> Self {
+        self.node = Some(node.to_string());
+        self
+    }
+
+    /// Attach what the resolver recorded for the node, if anything.
+    pub fn with_local(mut self, local: Option<Local>) -> Self {
+        self.local = Some(match local {
+            Some(local) => format!("depth {}, slot {}", local.depth, local.slot),
+            None => "global".into(),
+        });
+        self
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    pub fn node(&self) -> Option<&str> {
+        self.node.as_deref()
+    }
+
+    pub fn local(&self) -> Option<&str> {
+        self.local.as_deref()
+    }
+
+    /// The version of the interpreter that raised the error.
+    pub fn version(&self) -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+}
+
+impl Display for InternalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(line) = self.line {
+            write!(f, " on line {}", line + 1)?;
+        }
+        if let Some(node) = &self.node {
+            write!(f, "\n  node: {}", node)?;
+        }
+        if let Some(local) = &self.local {
+            write!(f, "\n  resolved: {}", local)?;
+        }
+        write!(
+            f,
+            "\n  lox {}, please include this message when reporting the bug",
+            self.version()
+        )
+    }
+}
+
+impl From<InternalError> for LoxError {
+    fn from(err: InternalError) -> Self {
+        Self::Internal(Box::new(err))
+    }
+}
+
+/// The location an error points at in the source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub line: u32,
+    /// Zero based, when known.
+    pub column: Option<u32>,
 }
 
 impl Display for SyntaxError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Syntax error on line {}: {}", self.line, self.message)
+        write!(
+            f,
+            "Syntax error on line {}: {}",
+            self.line + 1,
+            self.message
+        )?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n  help: {}", hint)?;
+        }
+        Ok(())
     }
 }
 
+/// The single error type surfaced to embedders. New variants may be added in
+/// minor releases, so matches outside the crate need a wildcard arm.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum LoxError {
     #[error("IO Error: {0}")]
     IO(#[from] std::io::Error),
@@ -28,12 +172,316 @@ pub enum LoxError {
     SystemTime(#[from] std::time::SystemTimeError),
     #[error("{0}")]
     Syntax(SyntaxError),
-    #[error("Resolution Error: {0} on line {1}")]
+    #[error("Resolution Error: {0} on line {}", .1 + 1)]
     Resolution(String, u32),
-    #[error("Runtime Error: {0} on line {1}")]
+    #[error("Runtime Error: {0} on line {}", .1 + 1)]
     Runtime(String, u32),
+    /// A value conversion failed, holding the expected and actual type names.
+    /// Use `at_line` to turn it into a runtime error.
+    #[error("Type Error: Expected {0}, got \"{1}\"")]
+    Type(String, String),
     #[error("{0}")]
     General(String),
+    /// Untrusted input went over one of the configured `ParseLimits` or
+    /// `ExecLimits`.
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(String),
+    /// Unwinds out of a function body on `return`, leaving the return value on
+    /// the interpreter stack. Caught by `LoxFunction::call`.
+    #[error("Cannot return outside of a function")]
+    Return,
+    /// The script called `exit(code)`. Unwinds all the way out of `exec`
+    /// so the host decides what terminating means.
+    #[error("Script exited with code {0}")]
+    Exit(i32),
+    /// Unwinds from `throw` to the nearest `catch`, leaving the thrown value
+    /// in `LoxState::thrown`. Holds the value as text for when nothing
+    /// catches it.
+    #[error("Uncaught exception: {0} on line {}", .1 + 1)]
+    Thrown(String, u32),
+    /// A bug in the interpreter: a broken invariant, or a panic caught before
+    /// it reached the host. After a panic the interpreter is poisoned until
+    /// `LoxInterpreter::reset` is called.
+    #[error("Internal error: {0}")]
+    Internal(Box<InternalError>),
+}
+
+impl LoxError {
+    /// A stable identifier for the kind of error, for hosts that categorize
+    /// errors without matching on message text.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::IO(_) => "io",
+            Self::SystemTime(_) => "system_time",
+            Self::Syntax(_) => "syntax",
+            Self::Resolution(..) => "resolution",
+            Self::Runtime(..) => "runtime",
+            Self::Type(..) => "type",
+            Self::General(_) => "general",
+            Self::LimitExceeded(_) => "limit_exceeded",
+            Self::Return => "return",
+            Self::Exit(_) => "exit",
+            Self::Thrown(..) => "thrown",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    /// A stable code for the kind of error, shown in diagnostics, e.g.
+    /// `E0003` for runtime errors.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            Self::Syntax(_) => "E0001",
+            Self::Resolution(..) => "E0002",
+            Self::Runtime(..) => "E0003",
+            Self::Type(..) => "E0004",
+            Self::LimitExceeded(_) => "E0005",
+            Self::IO(_) => "E0006",
+            Self::SystemTime(_) => "E0007",
+            Self::General(_) => "E0008",
+            Self::Return => "E0009",
+            Self::Exit(_) => "E0010",
+            Self::Internal(_) => "E0011",
+            Self::Thrown(..) => "E0012",
+        }
+    }
+
+    /// Describe the error for tools, located at `span`, or the error's own
+    /// span if that's `None`.
+    pub fn diagnostic(&self, span: Option<Span>) -> Diagnostic {
+        let (message, hint) = match self {
+            Self::Syntax(err) => (err.message.clone(), err.hint.clone()),
+            Self::Resolution(message, _) | Self::Runtime(message, _) => (message.clone(), None),
+            err => (err.to_string(), None),
+        };
+        Diagnostic {
+            code: self.error_code(),
+            severity: Severity::Error,
+            message,
+            span: span.or_else(|| self.span()),
+            hint,
+        }
+    }
+
+    /// The source line the error was raised on, if known. Zero based like
+    /// `Span`, while messages show lines one based.
+    pub fn line(&self) -> Option<u32> {
+        match self {
+            Self::Syntax(err) => Some(err.line()),
+            Self::Resolution(_, line) | Self::Runtime(_, line) | Self::Thrown(_, line) => {
+                Some(*line)
+            }
+            Self::Internal(err) => err.line(),
+            _ => None,
+        }
+    }
+
+    /// The position the error was raised at. Only syntax errors know their
+    /// column, see `LoxInterpreter::render_error` for runtime errors.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Syntax(err) => Some(Span {
+                line: err.line(),
+                column: err.column(),
+            }),
+            _ => self.line().map(|line| Span { line, column: None }),
+        }
+    }
+
+    /// Describe the error followed by the line of `source` it was raised on,
+    /// with a caret under the column of `span` if known.
+    pub fn render(&self, source: &str, span: Option<Span>) -> String {
+        let mut out = self.to_string();
+        let Some(span) = span else {
+            return out;
+        };
+        let Some(text) = source.lines().nth(span.line as usize) else {
+            return out;
+        };
+        // Scanner lines and columns are zero based, editors are one based
+        let number = (span.line + 1).to_string();
+        let gutter = " ".repeat(number.len());
+        match span.column {
+            Some(column) => {
+                out.push_str(&format!("\n{}--> {}:{}", gutter, number, column + 1));
+                out.push_str(&format!("\n{} |\n{} | {}", gutter, number, text));
+                // Keep tabs so the caret lines up with the source above
+                let indent: String = text
+                    .chars()
+                    .take(column as usize)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                out.push_str(&format!("\n{} | {}^", gutter, indent));
+            }
+            None => {
+                out.push_str(&format!("\n{}--> {}", gutter, number));
+                out.push_str(&format!("\n{} |\n{} | {}", gutter, number, text));
+            }
+        }
+        out
+    }
+
+    /// Attach a line to errors raised without one, e.g. by `TryFrom<LoxValue>`.
+    pub fn at_line(self, line: u32) -> Self {
+        match self {
+            Self::Type(expected, actual) => {
+                Self::Runtime(format!("Expected {}, got \"{}\"", expected, actual), line)
+            }
+            err => err,
+        }
+    }
 }
 
 pub type LoxResult<T = ()> = Result<T, LoxError>;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// An error or warning in a structured form, for editors and tests to
+/// consume without parsing messages. Displays as plain text, with one based
+/// positions like `LoxError::render`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Diagnostic {
+    /// Stable across releases, e.g. `E0003`.
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    /// A single line JSON object. The span is zero based, like `Span`.
+    pub fn to_json(&self) -> String {
+        let mut out = format!(
+            "{{\"code\":\"{}\",\"severity\":\"{}\",\"message\":{}",
+            self.code,
+            self.severity,
+            json_string(&self.message)
+        );
+        match self.span {
+            Some(Span { line, column }) => {
+                let column = column.map_or("null".into(), |column| column.to_string());
+                out.push_str(&format!(
+                    ",\"span\":{{\"line\":{},\"column\":{}}}",
+                    line, column
+                ));
+            }
+            None => out.push_str(",\"span\":null"),
+        }
+        match &self.hint {
+            Some(hint) => out.push_str(&format!(",\"hint\":{}}}", json_string(hint))),
+            None => out.push_str(",\"hint\":null}"),
+        }
+        out
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        if let Some(span) = self.span {
+            write!(f, "\n  --> line {}", span.line + 1)?;
+            if let Some(column) = span.column {
+                write!(f, ", column {}", column + 1)?;
+            }
+        }
+        if let Some(hint) = &self.hint {
+            write!(f, "\n  help: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Quote `value` as a JSON string.
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{interpreter::LoxInterpreter, parser::parse};
+
+    fn assert_error<T: std::error::Error + Send + Sync + 'static>() {}
+
+    #[test]
+    fn accessors() {
+        assert_error::<LoxError>();
+        let mut lox = LoxInterpreter::new();
+        let err = lox.exec("var a = 1;\nprint b;").unwrap_err();
+        assert_eq!(err.code(), "runtime");
+        assert_eq!(err.line(), Some(1));
+        assert_eq!(
+            err.span(),
+            Some(Span {
+                line: 1,
+                column: None
+            })
+        );
+        let err = parse("var = 1;").errors.remove(0);
+        assert_eq!(err.code(), "syntax");
+        assert_eq!(err.line(), Some(0));
+        let err = LoxError::General("oops".into());
+        assert_eq!(err.code(), "general");
+        assert_eq!(err.span(), None);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut lox = LoxInterpreter::new();
+        let err = lox.exec("var a = 1;\nprint a +  b;").unwrap_err();
+        let diagnostic = lox.diagnostic(&err);
+        assert_eq!(diagnostic.code, "E0003");
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(
+            diagnostic.message,
+            "Undefined variable \"b\", did you mean \"a\"?"
+        );
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[E0003]: Undefined variable \"b\", did you mean \"a\"?\n  --> line 2, column 12"
+        );
+        assert_eq!(
+            diagnostic.to_json(),
+            r#"{"code":"E0003","severity":"error","message":"Undefined variable \"b\", did you mean \"a\"?","span":{"line":1,"column":11},"hint":null}"#
+        );
+
+        let err = parse("var x = 1;\nprint x").errors.remove(0);
+        let diagnostic = err.diagnostic(None);
+        assert_eq!(diagnostic.code, "E0001");
+        assert_eq!(diagnostic.span.map(|span| span.line), Some(1));
+
+        let diagnostic = LoxError::Exit(2).diagnostic(None);
+        assert_eq!(diagnostic.code, "E0010");
+        assert_eq!(diagnostic.span, None);
+        assert_eq!(
+            diagnostic.to_json(),
+            r#"{"code":"E0010","severity":"error","message":"Script exited with code 2","span":null,"hint":null}"#
+        );
+    }
+}
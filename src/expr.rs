@@ -1,16 +1,22 @@
 use super::{
-    environment::{ScopeHandle, GLOBAL_SCOPE},
+    builtins::new_array,
+    class::LoxClass,
+    environment::ScopeHandle,
     error::*,
-    object::*,
+    object::LoxObject,
+    resolver::Locals,
     scanner::{Token, TokenKind},
     state::LoxState,
+    suggest::did_you_mean,
     value::LoxValue,
 };
 use std::{
     cell::RefCell,
     cmp::{Ord, Ordering},
+    collections::HashSet,
     fmt,
     hash::{Hash, Hasher},
+    rc::Rc,
 };
 
 thread_local! {
@@ -53,19 +59,31 @@ pub enum ExprKind {
         callee: Box<Expr>,
         arguments: Vec<Expr>,
     },
+    /// `name` is the lexeme of `right`, kept so property access doesn't
+    /// allocate.
     Get {
         left: Box<Expr>,
         right: Token,
+        name: Rc<str>,
     },
+    /// `operator` is set for compound assignments such as `a.b += c`, which
+    /// combine the current value with `value` after evaluating `object` once.
     Set {
         object: Box<Expr>,
         identifier: Token,
+        name: Rc<str>,
+        operator: Option<Token>,
         value: Box<Expr>,
     },
     This(Token),
     Super(Token),
+    /// Several values returned at once with `return a, b;`, evaluating to an
+    /// Array.
+    Tuple(Vec<Expr>),
 }
 
+/// Cloning keeps the id, so a clone is the same node as far as the resolver
+/// is concerned. Use `duplicate` to get a distinct copy.
 #[derive(PartialEq, Clone)]
 pub struct Expr {
     pub kind: ExprKind,
@@ -104,6 +122,64 @@ impl Expr {
         self._id
     }
 
+    /// Approximate source text of the expression, for error messages.
+    /// Unlike `Display` it reads like Lox, though the original spacing and
+    /// parentheses are lost.
+    pub fn to_source(&self) -> String {
+        let join = |exprs: &[Expr]| {
+            exprs
+                .iter()
+                .map(Expr::to_source)
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        match &self.kind {
+            ExprKind::Literal(token) | ExprKind::Identifier(token) => token.lexeme_str(),
+            ExprKind::Unary { operator, right } => {
+                format!("{}{}", operator.lexeme_str(), right.to_source())
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            }
+            | ExprKind::Logical {
+                operator,
+                left,
+                right,
+            } => format!(
+                "{} {} {}",
+                left.to_source(),
+                operator.lexeme_str(),
+                right.to_source()
+            ),
+            ExprKind::Grouping(inner) => format!("({})", inner.to_source()),
+            ExprKind::Assignment { name, value } => {
+                format!("{} = {}", name.lexeme_str(), value.to_source())
+            }
+            ExprKind::Call { callee, arguments } => {
+                format!("{}({})", callee.to_source(), join(arguments))
+            }
+            ExprKind::Get { left, name, .. } => format!("{}.{}", left.to_source(), name),
+            ExprKind::Set {
+                object,
+                name,
+                operator,
+                value,
+                ..
+            } => format!(
+                "{}.{} {}= {}",
+                object.to_source(),
+                name,
+                operator.as_ref().map_or("".into(), Token::lexeme_str),
+                value.to_source()
+            ),
+            ExprKind::This(_) => "this".into(),
+            ExprKind::Super(method) => format!("super.{}", method.lexeme_str()),
+            ExprKind::Tuple(values) => join(values),
+        }
+    }
+
     pub fn line(&self) -> u32 {
         match &self.kind {
             ExprKind::Literal(token) => token.line,
@@ -118,24 +194,148 @@ impl Expr {
             ExprKind::Set { object, .. } => object.line(),
             ExprKind::This(token) => token.line,
             ExprKind::Super(token) => token.line,
+            ExprKind::Tuple(values) => values[0].line(),
         }
     }
 
-    pub fn eval(&self, state: &mut LoxState, scope: ScopeHandle) -> LoxResult<LoxValue> {
-        // println!("{self}");
+    /// Zero based column of the token `line` is taken from.
+    pub fn column(&self) -> u32 {
         match &self.kind {
-            ExprKind::Literal(value) => Ok(LoxValue::from(value.clone())),
-            ExprKind::Unary { operator, right } => match operator.kind {
-                TokenKind::Bang => {
-                    // let right_value = self.evaluate_expr(scope, right)?.is_truthy();
-                    let right_value = right.eval(state, scope)?.is_truthy();
-                    Ok(LoxValue::Boolean(!right_value))
+            ExprKind::Literal(token) => token.column,
+            ExprKind::Unary { operator, .. } => operator.column,
+            ExprKind::Binary { operator, .. } => operator.column,
+            ExprKind::Grouping(expr) => expr.column(),
+            ExprKind::Identifier(token) => token.column,
+            ExprKind::Assignment { name, .. } => name.column,
+            ExprKind::Logical { operator, .. } => operator.column,
+            ExprKind::Call { callee, .. } => callee.column(),
+            ExprKind::Get { left, .. } => left.column(),
+            ExprKind::Set { object, .. } => object.column(),
+            ExprKind::This(token) => token.column,
+            ExprKind::Super(token) => token.column,
+            ExprKind::Tuple(values) => values[0].column(),
+        }
+    }
+
+    /// Collect the ids of this expression and every nested expression.
+    pub fn collect_ids(&self, ids: &mut HashSet<usize>) {
+        ids.insert(self._id);
+        match &self.kind {
+            ExprKind::Literal(_)
+            | ExprKind::Identifier(_)
+            | ExprKind::This(_)
+            | ExprKind::Super(_) => {}
+            ExprKind::Unary { right, .. } => right.collect_ids(ids),
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                left.collect_ids(ids);
+                right.collect_ids(ids);
+            }
+            ExprKind::Grouping(inner) => inner.collect_ids(ids),
+            ExprKind::Assignment { value, .. } => value.collect_ids(ids),
+            ExprKind::Call { callee, arguments } => {
+                callee.collect_ids(ids);
+                for arg in arguments.iter() {
+                    arg.collect_ids(ids);
                 }
-                _ => Err(LoxError::Runtime(
-                    format!("Unknown unary operator \"{}\"", operator),
-                    self.line(),
-                )),
+            }
+            ExprKind::Get { left, .. } => left.collect_ids(ids),
+            ExprKind::Set { object, value, .. } => {
+                object.collect_ids(ids);
+                value.collect_ids(ids);
+            }
+            ExprKind::Tuple(values) => {
+                for value in values.iter() {
+                    value.collect_ids(ids);
+                }
+            }
+        }
+    }
+
+    /// Deep copy this expression with fresh ids, carrying over any entries in
+    /// `locals` so the copy resolves the same way as the original.
+    pub fn duplicate(&self, locals: &mut Locals) -> Expr {
+        let mut dup = |expr: &Expr| Box::new(expr.duplicate(locals));
+        let kind = match &self.kind {
+            ExprKind::Literal(_)
+            | ExprKind::Identifier(_)
+            | ExprKind::This(_)
+            | ExprKind::Super(_) => self.kind.clone(),
+            ExprKind::Unary { operator, right } => ExprKind::Unary {
+                operator: operator.clone(),
+                right: dup(right),
             },
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => ExprKind::Binary {
+                operator: operator.clone(),
+                left: dup(left),
+                right: dup(right),
+            },
+            ExprKind::Logical {
+                operator,
+                left,
+                right,
+            } => ExprKind::Logical {
+                operator: operator.clone(),
+                left: dup(left),
+                right: dup(right),
+            },
+            ExprKind::Grouping(inner) => ExprKind::Grouping(dup(inner)),
+            ExprKind::Assignment { name, value } => ExprKind::Assignment {
+                name: name.clone(),
+                value: dup(value),
+            },
+            ExprKind::Call { callee, arguments } => ExprKind::Call {
+                callee: dup(callee),
+                arguments: arguments.iter().map(|arg| *dup(arg)).collect(),
+            },
+            ExprKind::Get { left, right, name } => ExprKind::Get {
+                left: dup(left),
+                right: right.clone(),
+                name: name.clone(),
+            },
+            ExprKind::Set {
+                object,
+                identifier,
+                name,
+                operator,
+                value,
+            } => ExprKind::Set {
+                object: dup(object),
+                identifier: identifier.clone(),
+                name: name.clone(),
+                operator: operator.clone(),
+                value: dup(value),
+            },
+            ExprKind::Tuple(values) => {
+                ExprKind::Tuple(values.iter().map(|value| *dup(value)).collect())
+            }
+        };
+        let copy = Expr::new(kind);
+        if let Some(local) = locals.get(self).copied() {
+            locals.insert(copy.clone(), local);
+        }
+        copy
+    }
+
+    pub fn eval(&self, state: &mut LoxState, scope: ScopeHandle) -> LoxResult<LoxValue> {
+        self.eval_kind(state, scope)
+            .inspect_err(|err| state.locate_error(err, self.line(), self.column()))
+    }
+
+    fn eval_kind(&self, state: &mut LoxState, scope: ScopeHandle) -> LoxResult<LoxValue> {
+        state.step()?;
+        if let Some(observer) = &mut state.observer {
+            observer.before_expr(self);
+        }
+        match &self.kind {
+            ExprKind::Literal(value) => Ok(LoxValue::from(value.clone())),
+            ExprKind::Unary { operator, right } => {
+                let right_value = right.eval(state, scope)?;
+                eval_unary(operator, right_value, self.line())
+            }
             ExprKind::Binary {
                 operator,
                 left,
@@ -143,137 +343,20 @@ impl Expr {
             } => {
                 let left_value = left.eval(state, scope)?;
                 let right_value = right.eval(state, scope)?;
-                match operator.kind {
-                    TokenKind::Plus => {
-                        if left_value.is_string() || right_value.is_string() {
-                            Ok(LoxValue::String(format!(
-                                "{}{}",
-                                left_value.to_string(),
-                                right_value.to_string(),
-                            )))
-                        } else if left_value.is_number() && right_value.is_number() {
-                            Ok(LoxValue::Number(
-                                left_value.get_number(self.line())?
-                                    + right_value.get_number(self.line())?,
-                            ))
-                        } else {
-                            Err(LoxError::Runtime(
-                                format!(
-                                    "Invalid operands {} + {}",
-                                    left_value.to_string(),
-                                    right_value.to_string(),
-                                ),
-                                self.line(),
-                            ))
-                        }
-                    }
-                    TokenKind::Minus => Ok(LoxValue::Number(
-                        left_value.get_number(self.line())?
-                            - right_value.get_number(self.line())?,
-                    )),
-                    TokenKind::Star => Ok(LoxValue::Number(
-                        left_value.get_number(self.line())?
-                            * right_value.get_number(self.line())?,
-                    )),
-                    TokenKind::Slash => Ok(LoxValue::Number(
-                        left_value.get_number(self.line())?
-                            / right_value.get_number(self.line())?,
-                    )),
-                    TokenKind::Greater => {
-                        if left_value.is_number() && right_value.is_number() {
-                            Ok(LoxValue::Boolean(
-                                left_value.get_number(self.line())?
-                                    > right_value.get_number(self.line())?,
-                            ))
-                        } else {
-                            Err(LoxError::Runtime(
-                                format!(
-                                    "Invalid operands {} > {}",
-                                    left_value.to_string(),
-                                    right_value.to_string(),
-                                ),
-                                self.line(),
-                            ))
-                        }
-                    }
-                    TokenKind::GreaterEqual => {
-                        if left_value.is_number() && right_value.is_number() {
-                            Ok(LoxValue::Boolean(
-                                left_value.get_number(self.line())?
-                                    >= right_value.get_number(self.line())?,
-                            ))
-                        } else {
-                            Err(LoxError::Runtime(
-                                format!(
-                                    "Invalid operands {} >= {}",
-                                    left_value.to_string(),
-                                    right_value.to_string(),
-                                ),
-                                self.line(),
-                            ))
-                        }
-                    }
-                    TokenKind::Less => {
-                        if left_value.is_number() && right_value.is_number() {
-                            Ok(LoxValue::Boolean(
-                                left_value.get_number(self.line())?
-                                    < right_value.get_number(self.line())?,
-                            ))
-                        } else {
-                            Err(LoxError::Runtime(
-                                format!(
-                                    "Invalid operands {} < {}",
-                                    left_value.to_string(),
-                                    right_value.to_string(),
-                                ),
-                                self.line(),
-                            ))
-                        }
-                    }
-                    TokenKind::LessEqual => {
-                        if left_value.is_number() && right_value.is_number() {
-                            Ok(LoxValue::Boolean(
-                                left_value.get_number(self.line())?
-                                    <= right_value.get_number(self.line())?,
-                            ))
-                        } else {
-                            Err(LoxError::Runtime(
-                                format!(
-                                    "Invalid operands {} <= {}",
-                                    left_value.to_string(),
-                                    right_value.to_string(),
-                                ),
-                                self.line(),
-                            ))
-                        }
-                    }
-                    TokenKind::EqualEqual => Ok(LoxValue::Boolean(left_value == right_value)),
-                    TokenKind::BangEqual => Ok(LoxValue::Boolean(left_value != right_value)),
-                    _ => Err(LoxError::Runtime(
-                        format!("Unknown binary operator \"{}\"", operator),
-                        self.line(),
-                    )),
-                }
+                eval_binary(state, operator, left_value, right_value, self.line())
             }
             ExprKind::Grouping(inner) => inner.eval(state, scope),
-            ExprKind::Identifier(name) => {
-                state.resolve_local(scope, self, &name.lexeme_str(), self.line())
-            }
+            ExprKind::Identifier(name) => state.resolve_local(
+                scope,
+                self,
+                name.lexeme.as_deref().unwrap_or_default(),
+                self.line(),
+            ),
             ExprKind::Assignment { name, value } => {
                 let val = value.eval(state, scope)?;
-                let scope =
-                    match state.locals.get(self) {
-                        Some(distance) => state
-                            .env
-                            .ancestor_scope(scope, *distance)
-                            .unwrap_or_else(|| {
-                                panic!("Invalid ancestor scope for \"{}\"", name.lexeme_str())
-                            }),
-                        None => GLOBAL_SCOPE,
-                    };
-                state
-                    .env
-                    .assign(Some(scope), name.lexeme_str(), val.clone());
+                let name = name.lexeme.as_deref().unwrap_or_default();
+                let (scope, slot) = state.locate(scope, self, name, self.line())?;
+                state.assign_variable_at(scope, slot, name, val.clone(), self.line())?;
                 Ok(val)
             }
             ExprKind::Logical {
@@ -303,45 +386,37 @@ impl Expr {
                     self.line(),
                 )),
             },
-            ExprKind::Call { callee, arguments } => match callee.eval(state, scope)? {
-                LoxValue::Function(func) => {
-                    func.borrow().call(state, scope, arguments, self.line())
+            ExprKind::Call { callee, arguments } => {
+                let callee = callee.eval(state, scope)?;
+                // Evaluate arguments to get their final value
+                let mut args: Vec<LoxValue> = vec![];
+                for arg in arguments.iter() {
+                    args.push(arg.eval(state, scope)?);
                 }
-                LoxValue::Class(class) => Ok(LoxObject::instantiate(
-                    class,
-                    state,
-                    scope,
-                    arguments,
-                    self.line(),
-                )?),
-                _ => Err(LoxError::Runtime(
-                    "Cannot call a non-function".into(),
-                    self.line(),
-                )),
-            },
-            ExprKind::Get { left, right } => {
-                let identifier = right.lexeme_str();
-                let value = left
-                    .eval(state, scope)?
-                    .get_object(self.line())?
-                    .borrow()
-                    .get(&identifier)
-                    .ok_or_else(|| {
-                        LoxError::Runtime(
-                            format!("Undefined variable \"{}\"", identifier),
-                            self.line(),
-                        )
-                    })?;
-                Ok(value)
+                callee.call(state, args, self.line())
+            }
+            ExprKind::Get { left, name, .. } => {
+                let left_value = left.eval(state, scope)?;
+                eval_get(state, left_value, name, self.line())
             }
             ExprKind::Set {
                 object,
-                identifier,
+                name,
+                operator,
                 value,
+                ..
             } => {
-                let obj = object.eval(state, scope)?.get_object(self.line())?;
-                let val = value.eval(state, scope)?;
-                obj.borrow_mut().set(identifier.lexeme_str(), val.clone());
+                let receiver = object.eval(state, scope)?;
+                let obj = set_target(receiver.clone(), name, || object.to_source(), self.line())?;
+                let current = match operator {
+                    Some(_) => Some(eval_get(state, receiver, name, self.line())?),
+                    None => None,
+                };
+                let mut val = value.eval(state, scope)?;
+                if let (Some(operator), Some(current)) = (operator, current) {
+                    val = eval_binary(state, operator, current, val, self.line())?;
+                }
+                state.set_property(&obj, name, val.clone(), self.line())?;
                 Ok(val)
             }
             ExprKind::This(_) => state.resolve_local(scope, self, "this", self.line()),
@@ -349,15 +424,22 @@ impl Expr {
                 let super_value = state
                     .resolve_local(scope, self, "super", self.line())?
                     .get_super(self.line())?;
-                super_value
-                    .get(&method.lexeme_str())
-                    .cloned()
-                    .ok_or_else(|| {
-                        LoxError::Runtime(
-                            format!("Undefined super method \"{}\"", method.lexeme_str()),
-                            self.line(),
-                        )
-                    })
+                let method = method.lexeme_str();
+                super_value.get(&method).ok_or_else(|| {
+                    let suggestion =
+                        did_you_mean(&method, &super_value.class.borrow().method_names());
+                    LoxError::Runtime(
+                        format!("Undefined super method \"{}\"{}", method, suggestion),
+                        self.line(),
+                    )
+                })
+            }
+            ExprKind::Tuple(values) => {
+                let values = values
+                    .iter()
+                    .map(|value| value.eval(state, scope))
+                    .collect::<LoxResult<Vec<LoxValue>>>()?;
+                new_array(state, values, self.line())
             }
         }
     }
@@ -401,7 +483,7 @@ impl fmt::Display for Expr {
                 write!(f, "(identifier {})", name.lexeme_str())
             }
             ExprKind::Assignment { name, value } => {
-                write!(f, "(= {} {})", name, value)
+                write!(f, "(= {} {})", name.lexeme_str(), value)
             }
             ExprKind::Logical {
                 operator,
@@ -428,19 +510,31 @@ impl fmt::Display for Expr {
                         .join(" ")
                 )
             }
-            ExprKind::Get { left, right } => {
-                write!(f, "(get {} {})", left, right.lexeme_str())
+            ExprKind::Get { left, name, .. } => {
+                write!(f, "(get {} {})", left, name)
             }
             ExprKind::Set {
                 object,
-                identifier,
+                name,
+                operator: None,
+                value,
+                ..
+            } => {
+                write!(f, "(set (get {} {}) {})", object, name, value)
+            }
+            ExprKind::Set {
+                object,
+                name,
+                operator: Some(operator),
                 value,
+                ..
             } => {
                 write!(
                     f,
-                    "(set (get {} {}) {})",
+                    "({}= (get {} {}) {})",
+                    operator.lexeme_str(),
                     object,
-                    identifier.lexeme_str(),
+                    name,
                     value
                 )
             }
@@ -450,6 +544,254 @@ impl fmt::Display for Expr {
             ExprKind::Super(method) => {
                 write!(f, "(super {})", method.lexeme_str())
             }
+            ExprKind::Tuple(values) => {
+                write!(
+                    f,
+                    "(tuple {})",
+                    values
+                        .iter()
+                        .map(|value| value.to_string())
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                )
+            }
         }
     }
 }
+
+/// Apply a unary operator to an evaluated operand.
+pub fn eval_unary(operator: &Token, right_value: LoxValue, line: u32) -> LoxResult<LoxValue> {
+    match operator.kind {
+        TokenKind::Bang => Ok(LoxValue::Boolean(!right_value.is_truthy())),
+        TokenKind::Minus => match right_value {
+            LoxValue::Number(num) => Ok(LoxValue::Number(-num)),
+            _ => Err(LoxError::Runtime(
+                format!("Invalid operand -{}", right_value.to_string()),
+                line,
+            )),
+        },
+        _ => Err(LoxError::Runtime(
+            format!("Unknown unary operator \"{}\"", operator),
+            line,
+        )),
+    }
+}
+
+/// Apply a numeric operator, rejecting non-finite results in checked math.
+fn arithmetic(
+    state: &LoxState,
+    operator: &Token,
+    left_value: &LoxValue,
+    right_value: &LoxValue,
+    line: u32,
+) -> LoxResult<LoxValue> {
+    let left = left_value.get_number(line)?;
+    let right = right_value.get_number(line)?;
+    let result = match operator.kind {
+        TokenKind::Plus => left + right,
+        TokenKind::Minus => left - right,
+        TokenKind::Star => left * right,
+        TokenKind::Slash => left / right,
+        _ => unreachable!("Expected an arithmetic operator"),
+    };
+    if state.checked_math && !result.is_finite() && left.is_finite() && right.is_finite() {
+        let problem = if operator.kind == TokenKind::Slash && right == 0.0 {
+            "Division by zero"
+        } else if result.is_nan() {
+            "Result is not a number"
+        } else {
+            "Numeric overflow"
+        };
+        return Err(LoxError::Runtime(
+            format!(
+                "{} in {} {} {}",
+                problem,
+                left_value.to_string(),
+                operator.lexeme_str(),
+                right_value.to_string()
+            ),
+            line,
+        ));
+    }
+    Ok(LoxValue::Number(result))
+}
+
+/// Apply a binary operator to evaluated operands, dispatching to an operator
+/// method like `__add__` when the left operand defines one.
+pub fn eval_binary(
+    state: &mut LoxState,
+    operator: &Token,
+    left_value: LoxValue,
+    right_value: LoxValue,
+    line: u32,
+) -> LoxResult<LoxValue> {
+    if let Some(method) = left_value.operator_method(operator.kind) {
+        return method.call(state, vec![right_value], line);
+    }
+    match operator.kind {
+        TokenKind::Plus => {
+            if left_value.is_string() || right_value.is_string() {
+                Ok(LoxValue::String(format!(
+                    "{}{}",
+                    left_value.to_string(),
+                    right_value.to_string(),
+                )))
+            } else if left_value.is_number() && right_value.is_number() {
+                arithmetic(state, operator, &left_value, &right_value, line)
+            } else {
+                Err(LoxError::Runtime(
+                    format!(
+                        "Invalid operands {} + {}",
+                        left_value.to_string(),
+                        right_value.to_string(),
+                    ),
+                    line,
+                ))
+            }
+        }
+        TokenKind::Minus | TokenKind::Star | TokenKind::Slash => {
+            arithmetic(state, operator, &left_value, &right_value, line)
+        }
+        TokenKind::Greater => {
+            if left_value.is_number() && right_value.is_number() {
+                Ok(LoxValue::Boolean(
+                    left_value.get_number(line)? > right_value.get_number(line)?,
+                ))
+            } else {
+                Err(LoxError::Runtime(
+                    format!(
+                        "Invalid operands {} > {}",
+                        left_value.to_string(),
+                        right_value.to_string(),
+                    ),
+                    line,
+                ))
+            }
+        }
+        TokenKind::GreaterEqual => {
+            if left_value.is_number() && right_value.is_number() {
+                Ok(LoxValue::Boolean(
+                    left_value.get_number(line)? >= right_value.get_number(line)?,
+                ))
+            } else {
+                Err(LoxError::Runtime(
+                    format!(
+                        "Invalid operands {} >= {}",
+                        left_value.to_string(),
+                        right_value.to_string(),
+                    ),
+                    line,
+                ))
+            }
+        }
+        TokenKind::Less => {
+            if left_value.is_number() && right_value.is_number() {
+                Ok(LoxValue::Boolean(
+                    left_value.get_number(line)? < right_value.get_number(line)?,
+                ))
+            } else {
+                Err(LoxError::Runtime(
+                    format!(
+                        "Invalid operands {} < {}",
+                        left_value.to_string(),
+                        right_value.to_string(),
+                    ),
+                    line,
+                ))
+            }
+        }
+        TokenKind::LessEqual => {
+            if left_value.is_number() && right_value.is_number() {
+                Ok(LoxValue::Boolean(
+                    left_value.get_number(line)? <= right_value.get_number(line)?,
+                ))
+            } else {
+                Err(LoxError::Runtime(
+                    format!(
+                        "Invalid operands {} <= {}",
+                        left_value.to_string(),
+                        right_value.to_string(),
+                    ),
+                    line,
+                ))
+            }
+        }
+        TokenKind::EqualEqual => Ok(LoxValue::Boolean(left_value == right_value)),
+        TokenKind::BangEqual => Ok(LoxValue::Boolean(left_value != right_value)),
+        _ => Err(LoxError::Runtime(
+            format!("Unknown binary operator \"{}\"", operator),
+            line,
+        )),
+    }
+}
+
+/// Read the property `identifier` of an evaluated value.
+pub fn eval_get(
+    state: &mut LoxState,
+    left_value: LoxValue,
+    identifier: &str,
+    line: u32,
+) -> LoxResult<LoxValue> {
+    match left_value {
+        LoxValue::String(_) => {
+            return state.get_primitive_method("String", left_value, identifier, line)
+        }
+        LoxValue::Number(_) => {
+            return state.get_primitive_method("Number", left_value, identifier, line)
+        }
+        LoxValue::Class(class) => {
+            return LoxClass::find_static_field(&class, identifier)
+                .or_else(|| LoxClass::find_static(&class, identifier).map(LoxValue::from))
+                .ok_or_else(|| {
+                    LoxError::Runtime(
+                        format!(
+                            "Undefined static method \"{}\" on \"{}\"{}",
+                            identifier,
+                            class.borrow().name,
+                            did_you_mean(identifier, &class.borrow().static_names())
+                        ),
+                        line,
+                    )
+                })
+        }
+        _ => {}
+    }
+    let obj = left_value.get_object(line)?;
+    let value = state.get_property(&obj, identifier, line)?.ok_or_else(|| {
+        LoxError::Runtime(
+            format!(
+                "Undefined property \"{}\" on \"{}\"{}{}",
+                identifier,
+                obj.borrow().class_name,
+                left_value.origin_note(),
+                did_you_mean(identifier, &LoxObject::property_names(&obj))
+            ),
+            line,
+        )
+    })?;
+    Ok(value)
+}
+
+/// The object to write the property `identifier` of, given the value the
+/// receiver evaluated to. `receiver` gives the receiver's source, and is only
+/// called for the error.
+pub fn set_target(
+    value: LoxValue,
+    identifier: &str,
+    receiver: impl FnOnce() -> String,
+    line: u32,
+) -> LoxResult<Rc<RefCell<LoxObject>>> {
+    match value {
+        LoxValue::Object(obj) => Ok(obj),
+        value => Err(LoxError::Runtime(
+            format!(
+                "Cannot set property \"{}\" on \"{}\", a {}{}",
+                identifier,
+                receiver(),
+                value.type_str(),
+                value.origin_note()
+            ),
+            line,
+        )),
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    rc::Rc,
+};
+
+/// Where the file builtins read and write, so hosts can sandbox scripts or
+/// run them without a real filesystem, e.g. in tests or on WASM.
+pub trait FileSystem {
+    fn read_to_string(&self, path: &str) -> io::Result<String>;
+
+    /// Replace the contents of `path`, creating it if needed.
+    fn write(&self, path: &str, contents: &str) -> io::Result<()>;
+
+    /// Add to the end of `path`, creating it if needed.
+    fn append(&self, path: &str, contents: &str) -> io::Result<()>;
+}
+
+/// The host's filesystem, used by default.
+pub struct HostFileSystem;
+
+impl FileSystem for HostFileSystem {
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write(&self, path: &str, contents: &str) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn append(&self, path: &str, contents: &str) -> io::Result<()> {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+    }
+}
+
+/// Files kept in memory. Clones share the same files, so the host can keep
+/// one to inspect what a script wrote.
+#[derive(Clone, Default)]
+pub struct MemoryFileSystem {
+    files: Rc<RefCell<HashMap<String, String>>>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, e.g. for a script to read.
+    pub fn insert(&self, path: &str, contents: &str) {
+        self.files.borrow_mut().insert(path.into(), contents.into());
+    }
+
+    pub fn get(&self, path: &str) -> Option<String> {
+        self.files.borrow().get(path).cloned()
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        self.get(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No such file"))
+    }
+
+    fn write(&self, path: &str, contents: &str) -> io::Result<()> {
+        self.insert(path, contents);
+        Ok(())
+    }
+
+    fn append(&self, path: &str, contents: &str) -> io::Result<()> {
+        self.files
+            .borrow_mut()
+            .entry(path.into())
+            .or_default()
+            .push_str(contents);
+        Ok(())
+    }
+}
+
+/// A view of another filesystem that refuses every write.
+pub struct ReadOnly<F: FileSystem>(pub F);
+
+impl<F: FileSystem> FileSystem for ReadOnly<F> {
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        self.0.read_to_string(path)
+    }
+
+    fn write(&self, _path: &str, _contents: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn append(&self, _path: &str, _contents: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+}
+
+fn read_only() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "Read-only filesystem")
+}
+
+#[cfg(all(test, feature = "builtins-io"))]
+mod test {
+    use super::*;
+    use crate::{error::*, interpreter::*};
+    use mock_logger::MockLogger;
+
+    #[test]
+    fn sandboxed() -> LoxResult {
+        mock_logger::init();
+        let files = MemoryFileSystem::new();
+        files.insert("input.txt", "in memory");
+        let mut lox = LoxInterpreter::new();
+        lox.set_file_system(Box::new(files.clone()));
+        lox.exec(
+            r#"
+            print read_file("input.txt");
+            write_file("output.txt", "a");
+            append_file("output.txt", "b");
+            using (var file = open("output.txt")) {
+                file.write("c");
+            }
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].body, "in memory");
+        });
+        assert_eq!(files.get("output.txt").as_deref(), Some("abc"));
+        assert!(lox.exec("read_file(\"missing.txt\");").is_err());
+
+        lox.set_file_system(Box::new(ReadOnly(files.clone())));
+        assert!(lox.eval("read_file(\"output.txt\");")? == "abc".into());
+        assert!(matches!(
+            lox.exec("write_file(\"output.txt\", \"\");"),
+            Err(LoxError::Runtime(message, 0)) if message.starts_with("Could not write \"output.txt\"")
+        ));
+        assert!(lox.exec("open(\"new.txt\");").is_err());
+        assert_eq!(files.get("new.txt"), None);
+        Ok(())
+    }
+}
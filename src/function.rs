@@ -1,4 +1,41 @@
-use super::{environment::*, error::*, expr::Expr, scanner::*, state::LoxState, stmt::*, value::*};
+use super::{
+    builtins::new_array, environment::*, error::*, scanner::*, state::LoxState, stmt::*, value::*,
+};
+
+/// Define a native function with typed parameters. Arguments are converted
+/// with `TryFrom<&LoxValue>` and the result with `Into<LoxValue>`; the body
+/// may use `?` on `LoxResult`s.
+///
+/// ```ignore
+/// let clamp = native_fn!("clamp", |x: f64, min: f64, max: f64| x.max(min).min(max));
+/// ```
+#[macro_export]
+macro_rules! native_fn {
+    ($name:expr, || $body:expr) => {
+        $crate::LoxFunction::native($name, vec![], |_, _, _| {
+            Ok($crate::LoxValue::from($body))
+        })
+    };
+    ($name:expr, |$($arg:ident: $ty:ty),+| $body:expr) => {
+        $crate::LoxFunction::native(
+            $name,
+            vec![$(stringify!($arg)),+],
+            |_, args, meta| {
+                let mut args = args.iter();
+                $(
+                    let $arg = <$ty>::try_from(args.next().ok_or_else(|| {
+                        $crate::LoxError::Runtime(
+                            format!("Missing argument \"{}\"", stringify!($arg)),
+                            meta.line,
+                        )
+                    })?)
+                    .map_err(|err| err.at_line(meta.line))?;
+                )+
+                Ok($crate::LoxValue::from($body))
+            },
+        )
+    };
+}
 
 pub struct FunctionCallMetadata {
     pub this_value: Option<LoxValue>,
@@ -14,6 +51,24 @@ pub type NativeFunction = fn(
     FunctionCallMetadata,
 ) -> LoxResult<LoxValue>;
 
+/// How many arguments a function takes.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Arity {
+    Fixed(usize),
+    /// At least this many. Script functions get the rest in an Array bound
+    /// to their last parameter, natives get them after the named ones.
+    Variadic(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match self {
+            Self::Fixed(arity) => count == *arity,
+            Self::Variadic(min) => count >= *min,
+        }
+    }
+}
+
 #[derive(PartialEq, Clone)]
 pub enum FunctionBody {
     Block(Vec<Stmt>, ScopeHandle),
@@ -24,24 +79,39 @@ pub enum FunctionBody {
 pub struct LoxFunction {
     pub name: Option<String>,
     pub params: Vec<Token>,
+    pub arity: Arity,
     pub body: FunctionBody,
     pub this_value: Option<LoxValue>,
     pub super_value: Option<LoxValue>,
     pub is_constructor: bool,
+    /// Called without arguments when the property is read.
+    pub is_getter: bool,
     pub line: u32,
 }
 
 impl LoxFunction {
     pub fn from_stmt(stmt: &Stmt, scope: ScopeHandle) -> LoxResult<Self> {
-        if let Stmt::Fun { name, params, body } = stmt {
+        if let Stmt::Fun {
+            name,
+            params,
+            rest,
+            body,
+            kind,
+        } = stmt
+        {
             let identifier = name.lexeme_str();
             Ok(LoxFunction {
                 name: Some(identifier.clone()),
                 params: params.clone(),
+                arity: match rest {
+                    true => Arity::Variadic(params.len() - 1),
+                    false => Arity::Fixed(params.len()),
+                },
                 body: FunctionBody::Block(body.clone(), scope),
                 this_value: None,
                 super_value: None,
                 is_constructor: false,
+                is_getter: *kind == FunKind::Getter,
                 line: stmt.line(),
             })
         } else {
@@ -55,6 +125,7 @@ impl LoxFunction {
     pub fn native(name: &str, params: Vec<&str>, body: NativeFunction) -> Self {
         LoxFunction {
             name: Some(name.into()),
+            arity: Arity::Fixed(params.len()),
             params: params
                 .into_iter()
                 .map(|param| Token::new(TokenKind::Identifier, Some(param.into()), None, 0))
@@ -63,45 +134,72 @@ impl LoxFunction {
             this_value: None,
             super_value: None,
             is_constructor: false,
+            is_getter: false,
             line: 0,
         }
     }
 
+    /// A native function taking `params` followed by any number of further
+    /// arguments, e.g. `max(first, ...)`.
+    pub fn native_variadic(name: &str, params: Vec<&str>, body: NativeFunction) -> Self {
+        LoxFunction {
+            arity: Arity::Variadic(params.len()),
+            ..Self::native(name, params, body)
+        }
+    }
+
+    /// Line of the declaration, `None` for natives.
+    pub fn origin(&self) -> Option<u32> {
+        match self.body {
+            FunctionBody::Block(..) => Some(self.line),
+            FunctionBody::Native(_) => None,
+        }
+    }
+
     pub fn call(
         &self,
         state: &mut LoxState,
-        scope: ScopeHandle,
-        arguments: &[Expr],
+        mut args: Vec<LoxValue>,
         line: u32,
     ) -> LoxResult<LoxValue> {
-        if arguments.len() != self.params.len() {
+        if !self.arity.accepts(args.len()) {
+            let origin = self
+                .origin()
+                .map(|line| format!(" (defined on line {})", line + 1))
+                .unwrap_or_default();
+            let count = match self.arity {
+                Arity::Fixed(arity) => arity.to_string(),
+                Arity::Variadic(min) => format!("at least {}", min),
+            };
             Err(LoxError::Runtime(
                 format!(
-                    "Function \"{}\" takes {} argument(s)",
+                    "Function \"{}\" takes {} argument(s){}",
                     self.name.clone().unwrap_or("".into()),
-                    self.params.len(),
+                    count,
+                    origin,
                 ),
-                self.line,
+                line,
             ))
         } else {
-            // Evaluate arguments to get their final value
-            let mut args: Vec<LoxValue> = vec![];
-            for arg in arguments.iter() {
-                args.push(arg.eval(state, scope)?);
-            }
             let return_value = match &self.body {
                 FunctionBody::Block(statements, closure) => {
+                    state.check_call_depth(line)?;
+                    // Every call gets its own scope, so recursive calls don't
+                    // overwrite each other's variables
+                    let scope = state.new_scope(*closure);
+                    if let Arity::Variadic(min) = self.arity {
+                        let rest = args.split_off(min);
+                        args.push(new_array(state, rest, line)?);
+                    }
                     // Bind arguments
                     for (i, arg) in args.drain(0..).enumerate() {
                         state
                             .env
-                            .declare(Some(*closure), self.params[i].lexeme_str(), arg);
+                            .declare(Some(scope), self.params[i].lexeme_str(), arg);
                     }
                     // Bind this value
                     let ret_value = if let Some(this) = &self.this_value {
-                        state
-                            .env
-                            .declare(Some(*closure), "this".into(), this.clone());
+                        state.env.declare(Some(scope), "this".into(), this.clone());
                         if self.is_constructor {
                             this.clone()
                         } else {
@@ -114,26 +212,24 @@ impl LoxFunction {
                     if let Some(super_value) = &self.super_value {
                         state
                             .env
-                            .declare(Some(*closure), "super".into(), super_value.clone());
+                            .declare(Some(scope), "super".into(), super_value.clone());
                     }
                     // Execute function body
                     state.stack.push(ret_value);
-                    for stmt in statements.iter() {
-                        stmt.eval(state, *closure)?;
-                        if matches!(stmt, Stmt::Return(_)) {
-                            break;
-                        }
+                    state.deferred.push(vec![]);
+                    let result = statements
+                        .iter()
+                        .try_for_each(|stmt| stmt.eval(state, scope));
+                    let result = state.run_deferred(result);
+                    let ret_value = state.stack.pop().unwrap();
+                    state.env.release(scope);
+                    match result {
+                        // The return value was left on the stack
+                        Ok(()) | Err(LoxError::Return) => ret_value,
+                        Err(err) => return Err(err),
                     }
-                    state.stack.pop().unwrap()
                 }
-                FunctionBody::Native(func) => func(
-                    state,
-                    &args,
-                    FunctionCallMetadata {
-                        this_value: self.this_value.clone(),
-                        line,
-                    },
-                )?,
+                FunctionBody::Native(_) => self.call_native(state, &args, line)?,
             };
             Ok(return_value)
         }
@@ -146,17 +242,64 @@ impl LoxFunction {
         line: u32,
     ) -> LoxResult<LoxValue> {
         match &self.body {
-            FunctionBody::Native(func) => func(
-                state,
-                args,
-                FunctionCallMetadata {
-                    this_value: self.this_value.clone(),
-                    line,
-                },
-            ),
+            FunctionBody::Native(func) => {
+                let name = self.name.as_deref().unwrap_or_default();
+                state.audit_native(name, args, line);
+                func(
+                    state,
+                    args,
+                    FunctionCallMetadata {
+                        this_value: self.this_value.clone(),
+                        line,
+                    },
+                )
+            }
             FunctionBody::Block(..) => {
                 Err(LoxError::Runtime("Expected a native function".into(), 0))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{error::*, interpreter::*};
+    use mock_logger::MockLogger;
+
+    #[test]
+    fn native_fn_macro() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        let context = lox.default_context();
+        lox.set_global(
+            context,
+            "clamp",
+            native_fn!("clamp", |x: f64, min: f64, max: f64| x.max(min).min(max)).into(),
+        );
+        lox.set_global(
+            context,
+            "shout",
+            native_fn!("shout", |text: String| text.to_uppercase()).into(),
+        );
+        lox.set_global(context, "answer", native_fn!("answer", || 42.0).into());
+        lox.exec(
+            r#"
+            print clamp(15, 0, 10);
+            print shout("hey");
+            print answer();
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 3);
+            assert_eq!(entries[0].body, "10");
+            assert_eq!(entries[1].body, "HEY");
+            assert_eq!(entries[2].body, "42");
+        });
+        assert!(matches!(
+            lox.exec("shout(1);"),
+            Err(LoxError::Runtime(message, _)) if message == "Expected String, got \"Number\""
+        ));
+        assert!(lox.exec("clamp(1, 2);").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    collections::hash_map::{DefaultHasher, RandomState},
+    hash::{BuildHasher, Hasher},
+};
+
+/// Builds the hashers of every map holding script values, i.e. scope
+/// variables, instance props and `Map` entries. Their iteration order is
+/// derived from it, so a seeded hasher makes that order reproducible across
+/// runs, e.g. for tests and replay.
+#[derive(Clone, Debug)]
+pub enum LoxHasher {
+    Random(RandomState),
+    Seeded(u64),
+}
+
+impl LoxHasher {
+    pub fn random() -> Self {
+        Self::Random(RandomState::new())
+    }
+
+    pub fn seeded(seed: u64) -> Self {
+        Self::Seeded(seed)
+    }
+}
+
+/// Test builds are deterministic unless a test opts into random hashing.
+impl Default for LoxHasher {
+    #[cfg(test)]
+    fn default() -> Self {
+        Self::seeded(0)
+    }
+
+    #[cfg(not(test))]
+    fn default() -> Self {
+        Self::random()
+    }
+}
+
+impl BuildHasher for LoxHasher {
+    type Hasher = DefaultHasher;
+
+    fn build_hasher(&self) -> DefaultHasher {
+        match self {
+            Self::Random(state) => state.build_hasher(),
+            Self::Seeded(seed) => {
+                // `DefaultHasher::new` always uses the same keys
+                let mut hasher = DefaultHasher::new();
+                hasher.write_u64(*seed);
+                hasher
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{hashing::LoxHasher, interpreter::*, value::LoxValue};
+
+    fn field_order(hasher: LoxHasher) -> Vec<String> {
+        let mut lox = LoxInterpreter::new();
+        lox.set_hasher(hasher);
+        lox.exec(
+            r#"
+            class Bag {}
+            var bag = Bag();
+            var i = 0;
+            while (i < 32) {
+                set_prop(bag, "field" + i, i);
+                i = i + 1;
+            }
+        "#,
+        )
+        .unwrap();
+        let bag = lox.get_global(lox.default_context(), "bag").unwrap();
+        let LoxValue::Object(bag) = bag else {
+            panic!("Expected an instance");
+        };
+        let order = bag.borrow().props.keys().cloned().collect();
+        order
+    }
+
+    #[test]
+    fn seeded_order() {
+        assert_eq!(
+            field_order(LoxHasher::seeded(7)),
+            field_order(LoxHasher::seeded(7))
+        );
+        assert_ne!(
+            field_order(LoxHasher::seeded(7)),
+            field_order(LoxHasher::seeded(8))
+        );
+        assert_eq!(field_order(LoxHasher::default()).len(), 32);
+    }
+}
@@ -0,0 +1,81 @@
+/// Per-line statement execution counts, for finding hot loops without a
+/// profiler. Unlike `Coverage` nothing is registered up front, so lines that
+/// never ran have a count of zero.
+#[derive(Clone, Default)]
+pub struct Hotspots {
+    counts: Vec<u64>,
+}
+
+impl Hotspots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hit(&mut self, line: u32) {
+        let line = line as usize;
+        if line >= self.counts.len() {
+            self.counts.resize(line + 1, 0);
+        }
+        self.counts[line] += 1;
+    }
+
+    /// Number of times statements on `line` were executed.
+    pub fn count(&self, line: u32) -> u64 {
+        self.counts.get(line as usize).copied().unwrap_or(0)
+    }
+
+    /// The `n` lines executed most often with their counts, busiest first.
+    /// Ties go to the earlier line.
+    pub fn top(&self, n: usize) -> Vec<(u32, u64)> {
+        let mut lines: Vec<(u32, u64)> = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(line, count)| (line as u32, *count))
+            .collect();
+        lines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        lines.truncate(n);
+        lines
+    }
+
+    /// Describe the `n` hottest lines, one per line.
+    pub fn report(&self, n: usize) -> String {
+        let mut out = String::new();
+        for (line, count) in self.top(n) {
+            // Scanner lines are zero based
+            out.push_str(&format!("line {}: {}\n", line + 1, count));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::*, interpreter::*};
+
+    #[test]
+    fn hotspots() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.enable_hotspots();
+        lox.exec(
+            r#"var i = 0;
+while (i < 10) {
+    i = i + 1;
+    if (i > 8) {
+        print i;
+    }
+}
+"#,
+        )?;
+        let hotspots = lox.hotspots().unwrap();
+        assert_eq!(hotspots.count(0), 1);
+        assert_eq!(hotspots.count(2), 10);
+        assert_eq!(hotspots.count(4), 2);
+        assert_eq!(hotspots.count(100), 0);
+        assert_eq!(hotspots.top(3), [(2, 10), (3, 10), (4, 2)]);
+        assert!(hotspots.report(1) == "line 3: 10\n");
+        Ok(())
+    }
+}
@@ -1,38 +1,788 @@
-use crate::{environment::*, error::*, parser::*, resolver::*, state::LoxState};
-use log::error;
+use crate::{
+    builtins::{find_pack, new_array, BuiltinPack, DebugPack},
+    class::LoxClass,
+    clock::Clock,
+    config::LoxConfig,
+    coverage::Coverage,
+    environment::*,
+    error::*,
+    expr::{Expr, ExprKind},
+    file_system::FileSystem,
+    function::{FunctionBody, LoxFunction, NativeFunction},
+    hashing::LoxHasher,
+    hotspots::Hotspots,
+    object::PropertyInterceptor,
+    observer::{NativeCall, Observer},
+    output::OutputSink,
+    parser::*,
+    pretty,
+    replay::{InputMode, Trace},
+    resolver::*,
+    scanner::Token,
+    state::{ExecLimits, LoxState},
+    stmt::Stmt,
+    timings::{timed, PhaseTimings},
+    value::LoxValue,
+    vm::{Chunk, Compiler, Vm},
+};
 use std::{
-    collections::HashMap,
+    any::Any,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
     fs::File,
     io::{BufRead, BufReader},
+    mem::{replace, take},
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
+    time::{Duration, Instant},
 };
 
-pub struct LoxInterpreter;
+/// Collect unreachable resolved expressions every this many `exec` calls.
+const COMPACT_INTERVAL: usize = 64;
+
+/// Sizes of the interpreter's bookkeeping, or what a compaction reclaimed.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct EnvStats {
+    pub scopes: usize,
+    pub locals: usize,
+}
+
+/// How statements are executed.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum Backend {
+    /// Evaluate the AST directly.
+    #[default]
+    TreeWalk,
+    /// Compile top-level statements to bytecode and run them on a stack VM.
+    /// Statements the compiler doesn't support, like function and class
+    /// declarations, and everything while collecting coverage or observing,
+    /// fall back to the tree-walker. Function bodies are always tree-walked.
+    Bytecode,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 2] = [Backend::TreeWalk, Backend::Bytecode];
+}
+
+/// Source parsed and resolved once, and compiled for the bytecode backend,
+/// so it can be run many times. See `LoxInterpreter::compile`.
+pub struct Program {
+    statements: Vec<Stmt>,
+    locals: Locals,
+    /// The bytecode for each statement the compiler supports, when compiled
+    /// for the bytecode backend.
+    chunks: Vec<Option<Chunk>>,
+}
+
+/// How one program of a batch went, see `LoxInterpreter::exec_many`.
+pub struct BatchResult {
+    /// The value of the last statement, or the error that stopped it.
+    pub result: LoxResult<LoxValue>,
+    pub elapsed: Duration,
+}
+
+/// An independent set of globals. Contexts share the interpreter's heap,
+/// so values can be passed between them.
+#[derive(PartialEq, Clone, Copy)]
+pub struct Context(ScopeHandle);
+
+pub struct LoxInterpreter {
+    state: LoxState,
+    gc_stress: bool,
+    exec_count: usize,
+    backend: Backend,
+    vm: Vm,
+    limits: ParseLimits,
+    timings: Option<PhaseTimings>,
+    /// A run panicked, so the state may be inconsistent. See `reset`.
+    poisoned: bool,
+}
+
+impl Default for LoxInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl LoxInterpreter {
     pub fn new() -> Self {
-        Self {}
+        Self::with_config(LoxConfig::default())
+    }
+
+    pub fn with_config(config: LoxConfig) -> Self {
+        Self::with_backend(config, Backend::TreeWalk)
+    }
+
+    pub fn with_backend(config: LoxConfig, backend: Backend) -> Self {
+        let mut env = match &config.packs {
+            Some(names) => {
+                let mut env = Environment::empty();
+                for name in names.iter() {
+                    match find_pack(name) {
+                        Some(pack) => pack.install(&mut env),
+                        None => warn!("Unknown builtin pack \"{}\"", name),
+                    }
+                }
+                env
+            }
+            None => Environment::new(),
+        };
+        if config.debug {
+            DebugPack.install(&mut env);
+        }
+        let mut state = LoxState::new(env);
+        if let Some(names) = &config.builtins {
+            state.env.retain_builtins(names);
+        }
+        state.checked_math = config.checked_math;
+        state.strict_properties = config.strict_properties;
+        if let Some(newline) = config.print_newline {
+            state.print_newline = newline;
+        }
+        state.limits = config.exec_limits;
+        if let Some(depth) = config.stack_depth {
+            state.stack_depth = depth;
+        }
+        if let Some(size) = config.stack_size {
+            state.stack_size = size;
+        }
+        if let Some(seed) = config.hash_seed {
+            state.env.set_hasher(LoxHasher::seeded(seed));
+        }
+        Self {
+            state,
+            gc_stress: false,
+            exec_count: 0,
+            backend,
+            vm: Vm::new(),
+            limits: config.limits,
+            timings: None,
+            poisoned: false,
+        }
+    }
+
+    /// An interpreter configured with `LoxConfig::rule_engine`.
+    pub fn rule_engine() -> Self {
+        Self::with_config(LoxConfig::rule_engine())
+    }
+
+    /// Install an additional group of builtins, e.g. one defined by the host.
+    pub fn install_pack(&mut self, pack: &dyn BuiltinPack) {
+        pack.install(&mut self.state.env);
+    }
+
+    /// Expose a native function to scripts as the global `name`.
+    pub fn register_native_fn(&mut self, name: &str, params: &[&str], body: NativeFunction) {
+        let fun = LoxFunction::native(name, params.to_vec(), body);
+        self.state.env.define_builtin(name.into(), fun.into());
+    }
+
+    /// Expose a class implemented by native `methods` to scripts as the global
+    /// `name`. Methods are keyed by their name, `init` is the constructor.
+    pub fn register_native_class(&mut self, name: &str, methods: Vec<LoxFunction>) {
+        let class = LoxClass {
+            name: name.into(),
+            superclass: None,
+            methods: methods
+                .into_iter()
+                .map(|method| (method.name.clone().unwrap_or_default(), method))
+                .collect(),
+            setters: HashMap::new(),
+            statics: HashMap::new(),
+            static_fields: HashMap::new(),
+            origin: None,
+        };
+        self.state.env.define_builtin(name.into(), class.into());
+    }
+
+    /// Override the arguments returned by `get_args()`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.state.inputs.set_args(args);
+    }
+
+    /// Read the time from `clock` instead of the system clock, e.g. a mock
+    /// clock in tests or the game clock when embedded in a game.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.state.inputs.set_clock(clock);
+    }
+
+    /// Make `random()` produce the same sequence on every run, e.g. for
+    /// tests.
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.state.inputs.seed_random(seed);
+    }
+
+    /// Serve the file builtins from `fs` instead of the host's filesystem,
+    /// e.g. to sandbox scripts.
+    pub fn set_file_system(&mut self, fs: Box<dyn FileSystem>) {
+        self.state.fs = fs;
+    }
+
+    /// Raise a runtime error instead of producing infinity or NaN from
+    /// arithmetic, e.g. on overflow or division by zero.
+    pub fn set_checked_math(&mut self, checked: bool) {
+        self.state.checked_math = checked;
+    }
+
+    /// Only let an instance's `init`, and what it calls, add properties to
+    /// the instance, catching typos in property names elsewhere.
+    pub fn set_strict_properties(&mut self, strict: bool) {
+        self.state.strict_properties = strict;
+    }
+
+    /// Hash with `hasher` from now on, e.g. `LoxHasher::seeded(..)` for a
+    /// reproducible iteration order of instance props and Maps.
+    pub fn set_hasher(&mut self, hasher: LoxHasher) {
+        self.state.env.set_hasher(hasher);
+    }
+
+    /// Start recording nondeterministic inputs into a fresh trace.
+    pub fn record(&mut self) {
+        self.state.inputs.mode = InputMode::Record(Trace::new());
+    }
+
+    /// Serve nondeterministic inputs from a previously recorded trace.
+    pub fn replay(&mut self, trace: Trace) {
+        self.state.inputs.mode = InputMode::Replay(trace);
+    }
+
+    /// Stop recording and return the recorded trace, if any.
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        match take(&mut self.state.inputs.mode) {
+            InputMode::Record(trace) => Some(trace),
+            mode => {
+                self.state.inputs.mode = mode;
+                None
+            }
+        }
+    }
+
+    /// Start counting statement executions per line.
+    pub fn enable_coverage(&mut self) {
+        self.state.coverage = Some(Coverage::new());
+    }
+
+    /// Start counting statement executions per line, to find the lines a
+    /// script spends its time on.
+    pub fn enable_hotspots(&mut self) {
+        self.state.hotspots = Some(Hotspots::new());
+    }
+
+    /// Start recording every native function invocation.
+    pub fn enable_audit(&mut self) {
+        self.state.audit = Some(vec![]);
+    }
+
+    /// The native function invocations recorded since auditing was enabled.
+    pub fn audit_log(&self) -> Option<&[NativeCall]> {
+        self.state.audit.as_deref()
+    }
+
+    /// Install a hook consulted on every property access of native objects.
+    pub fn set_interceptor(&mut self, interceptor: impl PropertyInterceptor + 'static) {
+        self.state.interceptor = Some(Rc::new(interceptor));
+    }
+
+    /// Collect unreachable scopes after every top level statement instead of
+    /// after every `exec`, to shake out reclamation bugs.
+    pub fn set_gc_stress(&mut self, enabled: bool) {
+        self.gc_stress = enabled;
+    }
+
+    /// Number of scopes currently allocated.
+    pub fn scope_count(&self) -> usize {
+        self.state.env.scope_count()
+    }
+
+    pub fn stats(&self) -> EnvStats {
+        EnvStats {
+            scopes: self.state.env.scope_count(),
+            locals: self.state.locals.len(),
+        }
+    }
+
+    /// Free unreachable scopes and forget resolved expressions that no live
+    /// function can evaluate anymore.
+    pub fn compact(&mut self) -> EnvStats {
+        let scopes = self.state.env.collect(&self.state.stack);
+        let mut ids = HashSet::new();
+        for func in self.state.env.live_functions(&self.state.stack) {
+            if let FunctionBody::Block(body, _) = &func.borrow().body {
+                for stmt in body.iter() {
+                    stmt.collect_ids(&mut ids);
+                }
+            }
+        }
+        let before = self.state.locals.len();
+        self.state.locals.retain(|expr, _| ids.contains(&expr.id()));
+        EnvStats {
+            scopes,
+            locals: before - self.state.locals.len(),
+        }
+    }
+
+    /// Start measuring the time spent in each phase of running scripts.
+    pub fn enable_timings(&mut self) {
+        self.timings = Some(PhaseTimings::default());
+    }
+
+    /// The time spent in each phase so far, if enabled.
+    pub fn timings(&self) -> Option<&PhaseTimings> {
+        self.timings.as_ref()
+    }
+
+    fn add_timings(&mut self, timings: PhaseTimings) {
+        if let Some(total) = &mut self.timings {
+            *total += timings;
+        }
+    }
+
+    /// Coverage collected so far, if enabled.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.state.coverage.as_ref()
+    }
+
+    /// Execution counts collected so far, if enabled.
+    pub fn hotspots(&self) -> Option<&Hotspots> {
+        self.state.hotspots.as_ref()
+    }
+
+    /// The context used by `exec`.
+    pub fn default_context(&self) -> Context {
+        Context(GLOBAL_SCOPE)
+    }
+
+    /// Create a context with its own, empty, global scope.
+    pub fn create_context(&mut self) -> Context {
+        Context(self.state.env.new_scope(None))
+    }
+
+    pub fn get_global(&self, context: Context, name: &str) -> Option<LoxValue> {
+        self.state.env.get(Some(context.0), name)
+    }
+
+    pub fn set_global(&mut self, context: Context, name: &str, value: LoxValue) {
+        self.state.env.declare(Some(context.0), name.into(), value);
+    }
+
+    /// Copy the globals holding data, e.g. to warm up other interpreters
+    /// with them. See `Environment::export_globals`.
+    pub fn export_globals(&self) -> LoxVars {
+        self.state.env.export_globals()
+    }
+
+    /// Declare globals exported from another interpreter.
+    pub fn import_globals(&mut self, vars: LoxVars) -> LoxResult {
+        self.state.env.import_globals(vars)
     }
 
     pub fn exec(&mut self, source: &str) -> LoxResult {
+        self.exec_in(Context(GLOBAL_SCOPE), source)
+    }
+
+    /// Execute `source` with the globals of `context`.
+    pub fn exec_in(&mut self, context: Context, source: &str) -> LoxResult {
+        self.run(context, source).map(drop)
+    }
+
+    /// Evaluate a single expression, like `price * quantity > 100`, with
+    /// `bindings` as its only globals. Rules can't declare anything or assign,
+    /// and bindings don't outlive the call. A trailing semicolon is optional.
+    /// Meant for interpreters created with `rule_engine`, which also keeps
+    /// side effecting builtins out of reach.
+    pub fn evaluate_rule(
+        &mut self,
+        rule: &str,
+        bindings: &[(&str, LoxValue)],
+    ) -> LoxResult<LoxValue> {
+        self.guarded(|lox| {
+            lox.state.error_span = None;
+            let source = format!("{};", rule.trim_end().trim_end_matches(';'));
+            let statements = lox.prepare(&source)?;
+            let [Stmt::Expr(expr)] = statements.as_slice() else {
+                return Err(LoxError::General(
+                    "A rule must be a single expression".into(),
+                ));
+            };
+            check_rule(expr)?;
+            let scope = lox.state.env.new_scope(None);
+            for (name, value) in bindings.iter() {
+                let value = lox.wrap_arrays(value.clone())?;
+                lox.state.env.declare(Some(scope), name.to_string(), value);
+            }
+            lox.state.reset_budget();
+            let result = expr.eval(&mut lox.state, scope);
+            lox.state.env.release(scope);
+            lox.finish();
+            result
+        })
+    }
+
+    /// Execute `source`, returning the value of the last statement if it's an
+    /// expression statement and nil otherwise.
+    pub fn eval(&mut self, source: &str) -> LoxResult<LoxValue> {
+        self.run(Context(GLOBAL_SCOPE), source)
+    }
+
+    /// Execute `source`, returning what `print` produced instead of sending it
+    /// to the output sink, along with the value of the last statement if it's
+    /// an expression statement.
+    pub fn exec_capture(&mut self, source: &str) -> LoxResult<(LoxValue, String)> {
+        let buffer = Rc::new(RefCell::new(String::new()));
+        let output = replace(&mut self.state.output, Box::new(buffer.clone()));
+        let result = self.run(Context(GLOBAL_SCOPE), source);
+        self.state.output = output;
+        Ok((result?, buffer.take()))
+    }
+
+    /// Call the global function or class `name` with `args`, e.g. a callback
+    /// defined by a script that has already been executed. Vecs, like those
+    /// converted from Rust vecs, are passed as Arrays.
+    pub fn call(&mut self, name: &str, args: Vec<LoxValue>) -> LoxResult<LoxValue> {
+        self.guarded(|lox| {
+            lox.state.error_span = None;
+            let callee = lox
+                .get_global(lox.default_context(), name)
+                .ok_or_else(|| LoxError::Runtime(format!("Undefined variable \"{}\"", name), 0))?;
+            let args = args
+                .into_iter()
+                .map(|arg| lox.wrap_arrays(arg))
+                .collect::<LoxResult<Vec<LoxValue>>>()?;
+            lox.state.reset_budget();
+            let result = callee.call(&mut lox.state, args, 0);
+            lox.finish();
+            result
+        })
+    }
+
+    /// Wrap copies of vecs, and vecs nested in them, in Array instances.
+    /// The caller's vecs are left as they were.
+    fn wrap_arrays(&mut self, value: LoxValue) -> LoxResult<LoxValue> {
+        match value {
+            LoxValue::Vec(vec) => {
+                let items = vec
+                    .borrow()
+                    .iter()
+                    .cloned()
+                    .map(|item| self.wrap_arrays(item))
+                    .collect::<LoxResult<Vec<LoxValue>>>()?;
+                new_array(&mut self.state, items, 0)
+            }
+            value => Ok(value),
+        }
+    }
+
+    /// Follow execution with `observer`.
+    pub fn set_observer(&mut self, observer: Box<dyn Observer>) {
+        self.state.observer = Some(observer);
+    }
+
+    /// Call `handler` with the message of errors escaping a top-level
+    /// statement, like a script calling `on_error(handler)`. See
+    /// `LoxState::recover`.
+    pub fn set_error_handler(&mut self, handler: Option<LoxValue>) {
+        self.state.error_handler = handler;
+    }
+
+    /// Bound the work each run of a script may do. See `ExecLimits`.
+    pub fn set_exec_limits(&mut self, limits: ExecLimits) {
+        self.state.limits = limits;
+    }
+
+    /// Send the output of `print` to `output`.
+    pub fn set_output(&mut self, output: Box<dyn OutputSink>) {
+        self.state.output = output;
+    }
+
+    /// Whether `print` ends its output with a newline, which it does by
+    /// default. Turned off, scripts end lines with `println` instead, e.g. to
+    /// draw a TUI.
+    pub fn set_print_newline(&mut self, newline: bool) {
+        self.state.print_newline = newline;
+    }
+
+    /// Parse, resolve and compile `source` ahead of time, for hosts running
+    /// the same script many times.
+    pub fn compile(&mut self, source: &str) -> LoxResult<Program> {
+        let statements = self.parse(source)?;
+        let locals = self.resolve(&statements)?;
+        if let Some(coverage) = &mut self.state.coverage {
+            coverage.register(&statements);
+        }
+        let chunks = match self.backend {
+            Backend::Bytecode => statements.iter().map(Compiler::compile).collect(),
+            Backend::TreeWalk => vec![],
+        };
+        Ok(Program {
+            statements,
+            locals,
+            chunks,
+        })
+    }
+
+    /// Run `program` with the globals of `context`, returning the value of
+    /// the last statement.
+    pub fn exec_program(&mut self, context: Context, program: &Program) -> LoxResult<LoxValue> {
+        self.guarded(|lox| {
+            lox.state.error_span = None;
+            // Compaction forgets resolutions of programs that aren't running
+            for (expr, local) in program.locals.iter() {
+                if !lox.state.locals.contains_key(expr) {
+                    lox.state.locals.insert(expr.clone(), *local);
+                }
+            }
+            lox.run_statements(context, &program.statements, &program.chunks)
+        })
+    }
+
+    /// Run each of `programs` with its own globals, sharing the builtins,
+    /// and report how each went and how long it took. A failing program
+    /// doesn't stop the batch.
+    pub fn exec_many(&mut self, programs: &[Program]) -> Vec<BatchResult> {
+        let results = programs
+            .iter()
+            .map(|program| {
+                let context = self.create_context();
+                let start = Instant::now();
+                let result = self.exec_program(context, program);
+                let elapsed = start.elapsed();
+                self.state.env.release_root(context.0);
+                BatchResult { result, elapsed }
+            })
+            .collect();
+        // Contexts holding functions or classes are only freed by collecting
+        self.state.env.collect(&self.state.stack);
+        results
+    }
+
+    /// Execute `source` with the globals of `context`, returning the value of
+    /// the last statement.
+    fn run(&mut self, context: Context, source: &str) -> LoxResult<LoxValue> {
+        self.guarded(|lox| {
+            lox.state.error_span = None;
+            let statements = lox.prepare(source)?;
+            lox.run_statements(context, &statements, &[])
+        })
+    }
+
+    /// Run `f`, turning a panic into `LoxError::Internal` rather than letting
+    /// it unwind into the host, and poisoning the interpreter.
+    fn guarded<T>(&mut self, f: impl FnOnce(&mut Self) -> LoxResult<T>) -> LoxResult<T> {
+        if self.poisoned {
+            return Err(InternalError::new(
+                "The interpreter panicked earlier, call reset() before using it again".into(),
+            )
+            .into());
+        }
+        match panic::catch_unwind(AssertUnwindSafe(|| f(self))) {
+            Ok(result) => result,
+            Err(payload) => {
+                self.poisoned = true;
+                Err(InternalError::new(panic_message(payload)).into())
+            }
+        }
+    }
+
+    /// Whether a run panicked. A poisoned interpreter refuses to run anything
+    /// until `reset` is called.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Make a poisoned interpreter usable again, dropping whatever the run
+    /// that panicked left behind. Globals are kept.
+    pub fn reset(&mut self) {
+        self.state.reset();
+        self.vm = Vm::new();
+        self.state.env.collect(&self.state.stack);
+        self.poisoned = false;
+    }
+
+    /// Run `statements`, using the bytecode in `chunks` compiled ahead of
+    /// time for them, if any.
+    fn run_statements(
+        &mut self,
+        context: Context,
+        statements: &[Stmt],
+        chunks: &[Option<Chunk>],
+    ) -> LoxResult<LoxValue> {
+        self.state.reset_budget();
+        let start = Instant::now();
+        let result = self.eval_statements(context, statements, chunks);
+        self.add_timings(PhaseTimings {
+            exec: start.elapsed(),
+            ..Default::default()
+        });
+        let value = result?;
+        self.finish();
+        Ok(value)
+    }
+
+    fn eval_statements(
+        &mut self,
+        context: Context,
+        statements: &[Stmt],
+        chunks: &[Option<Chunk>],
+    ) -> LoxResult<LoxValue> {
+        let mut value = LoxValue::Nil;
+        for (index, stmt) in statements.iter().enumerate() {
+            let result = match chunks.get(index) {
+                Some(chunk) if !self.instrumented() => match chunk {
+                    Some(chunk) => self.vm.run(chunk, &mut self.state, context.0),
+                    None => stmt.eval_value(&mut self.state, context.0),
+                },
+                _ => self.eval_stmt(stmt, context),
+            };
+            value = match result {
+                Ok(value) => value,
+                Err(err) => self.state.recover(err)?,
+            };
+            if self.gc_stress {
+                self.state.env.collect(&self.state.stack);
+            }
+        }
+        Ok(value)
+    }
+
+    /// Re-run the declarations in `source` against the globals: functions and
+    /// classes are replaced, new variables are initialized and existing ones
+    /// keep their values. Other statements are skipped. Instances of a
+    /// replaced class use the new methods.
+    pub fn reload(&mut self, source: &str) -> LoxResult {
+        self.guarded(|lox| {
+            let statements = lox.prepare(source)?;
+            for stmt in statements.iter() {
+                match stmt {
+                    // Replaced in place, see `LoxState::declare_definition`
+                    Stmt::Fun { .. } | Stmt::Class { .. } => {
+                        stmt.eval(&mut lox.state, GLOBAL_SCOPE)?
+                    }
+                    Stmt::Var { name, .. } if !lox.is_global(name) => {
+                        stmt.eval(&mut lox.state, GLOBAL_SCOPE)?
+                    }
+                    Stmt::Destructure { names, .. }
+                        if !names.iter().any(|name| lox.is_global(name)) =>
+                    {
+                        stmt.eval(&mut lox.state, GLOBAL_SCOPE)?
+                    }
+                    _ => {}
+                }
+            }
+            lox.finish();
+            Ok(())
+        })
+    }
+
+    /// Whether the global variable `name` is already declared.
+    fn is_global(&self, name: &Token) -> bool {
+        self.state
+            .env
+            .get_declared(GLOBAL_SCOPE, &name.lexeme_str())
+            .is_some()
+    }
+
+    /// Parse and resolve `source`.
+    fn prepare(&mut self, source: &str) -> LoxResult<Vec<Stmt>> {
+        let statements = self.parse(source)?;
+        for (key, value) in self.resolve(&statements)?.drain() {
+            self.state.locals.insert(key, value);
+        }
+        if let Some(coverage) = &mut self.state.coverage {
+            coverage.register(&statements);
+        }
+        Ok(statements)
+    }
+
+    fn parse(&mut self, source: &str) -> LoxResult<Vec<Stmt>> {
+        let mut timings = PhaseTimings::default();
         let ParseResult {
             statements,
-            errors: parse_errors,
-        } = parse(source);
+            errors: mut parse_errors,
+        } = parse_timed(source, self.limits, &mut timings);
+        self.add_timings(timings);
+        // Hosts need to tell oversized input apart from broken input
+        if let Some(index) = parse_errors
+            .iter()
+            .position(|err| matches!(err, LoxError::LimitExceeded(_)))
+        {
+            return Err(parse_errors.swap_remove(index));
+        }
         if !parse_errors.is_empty() {
+            // Return the first error so hosts can point at it, log the rest
+            let first = parse_errors.remove(0);
             for err in parse_errors.iter() {
                 error!("Parse Error: {}", err.to_string());
             }
-            return Err(LoxError::General("Syntax errors encountered".into()));
+            return Err(first);
+        }
+        Ok(statements)
+    }
+
+    fn resolve(&mut self, statements: &[Stmt]) -> LoxResult<Locals> {
+        let mut timings = PhaseTimings::default();
+        let locals = timed(&mut timings.resolve, || Resolver::bind(statements));
+        self.add_timings(timings);
+        locals
+    }
+
+    /// Reclaim memory after running a script.
+    fn finish(&mut self) {
+        self.exec_count += 1;
+        if self.exec_count.is_multiple_of(COMPACT_INTERVAL) {
+            self.compact();
+        } else {
+            self.state.env.collect(&self.state.stack);
         }
-        let mut locals: Locals = HashMap::new();
-        for (key, value) in Resolver::bind(&statements)?.drain() {
-            locals.insert(key, value);
+    }
+
+    /// Whether execution is being followed, which the bytecode backend
+    /// can't do.
+    fn instrumented(&self) -> bool {
+        self.state.coverage.is_some()
+            || self.state.hotspots.is_some()
+            || self.state.observer.is_some()
+    }
+
+    fn eval_stmt(&mut self, stmt: &Stmt, context: Context) -> LoxResult<LoxValue> {
+        if self.backend == Backend::Bytecode && !self.instrumented() {
+            if let Some(chunk) = Compiler::compile(stmt) {
+                return self.vm.run(&chunk, &mut self.state, context.0);
+            }
         }
-        let mut state = LoxState::new(locals);
-        for stmt in statements.iter() {
-            stmt.eval(&mut state, GLOBAL_SCOPE)?;
+        stmt.eval_value(&mut self.state, context.0)
+    }
+
+    /// Render `value` for display to a developer, e.g. `["a", 1]`. See
+    /// `pretty::repr`.
+    pub fn repr(&mut self, value: &LoxValue) -> LoxResult<String> {
+        self.guarded(|lox| pretty::repr(value, &mut lox.state, 0))
+    }
+
+    /// Render an error returned for `source` with the offending line and,
+    /// when known, a caret under the expression that raised it.
+    pub fn render_error(&self, err: &LoxError, source: &str) -> String {
+        err.render(source, self.error_span(err))
+    }
+
+    /// Describe an error returned by the last run for tools, located like
+    /// `render_error` does.
+    pub fn diagnostic(&self, err: &LoxError) -> Diagnostic {
+        err.diagnostic(self.error_span(err))
+    }
+
+    /// Where `err` was raised, including the column for runtime errors if the
+    /// innermost expression that raised it is known.
+    fn error_span(&self, err: &LoxError) -> Option<Span> {
+        match err.span() {
+            Some(Span {
+                column: Some(_), ..
+            }) => err.span(),
+            span => self
+                .state
+                .error_span
+                .filter(|located| span.is_some_and(|span| span.line == located.line))
+                .or(span),
         }
-        Ok(())
     }
 
     pub fn exec_file(&mut self, path: &str) -> LoxResult {
@@ -49,12 +799,145 @@ impl LoxInterpreter {
     }
 }
 
+/// The message a panic was raised with.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "Unknown panic".into(),
+        },
+    }
+}
+
+/// Reject expressions in a rule that would change state.
+fn check_rule(expr: &Expr) -> LoxResult {
+    match &expr.kind {
+        ExprKind::Assignment { name, .. } => Err(LoxError::Runtime(
+            format!("Rules cannot assign to \"{}\"", name.lexeme_str()),
+            expr.line(),
+        )),
+        ExprKind::Set { identifier, .. } => Err(LoxError::Runtime(
+            format!("Rules cannot set property \"{}\"", identifier.lexeme_str()),
+            expr.line(),
+        )),
+        ExprKind::Literal(_) | ExprKind::Identifier(_) | ExprKind::This(_) | ExprKind::Super(_) => {
+            Ok(())
+        }
+        ExprKind::Unary { right, .. } => check_rule(right),
+        ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+            check_rule(left)?;
+            check_rule(right)
+        }
+        ExprKind::Grouping(inner) | ExprKind::Get { left: inner, .. } => check_rule(inner),
+        ExprKind::Call { callee, arguments } => {
+            check_rule(callee)?;
+            arguments.iter().try_for_each(check_rule)
+        }
+        ExprKind::Tuple(values) => values.iter().try_for_each(check_rule),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::super::test_scripts::*;
     use super::*;
+    use crate::function::LoxFunction;
     use mock_logger::MockLogger;
 
+    #[test]
+    fn rules() -> LoxResult {
+        let mut lox = LoxInterpreter::rule_engine();
+        let rule = "price * quantity > 100 and tier == \"gold\"";
+        let bindings = [
+            ("price", 25.0.into()),
+            ("quantity", 5.0.into()),
+            ("tier", "gold".into()),
+        ];
+        assert!(lox.evaluate_rule(rule, &bindings)? == LoxValue::Boolean(true));
+        let discount = lox.evaluate_rule(
+            "total.to_fixed(2) + \" \" + tags.len()",
+            &[("total", 9.5.into()), ("tags", vec!["a", "b"].into())],
+        )?;
+        assert!(discount == LoxValue::String("9.50 2".into()));
+        assert!(
+            lox.evaluate_rule("max(round(price), 3)", &[("price", 2.6.into())])?
+                == LoxValue::Number(3.0)
+        );
+        // Bindings don't leak into later rules
+        assert!(matches!(
+            lox.evaluate_rule("price", &[]),
+            Err(LoxError::Runtime(message, _)) if message.starts_with("Undefined variable \"price\"")
+        ));
+        for (rule, message) in [
+            ("var x = 1;", "A rule must be a single expression"),
+            (
+                "1; 2;",
+                "Limit exceeded: Program has more than 1 statements",
+            ),
+            (
+                "price = 2",
+                "Runtime Error: Rules cannot assign to \"price\" on line 1",
+            ),
+            (
+                "time()",
+                "Runtime Error: Undefined variable \"time\" on line 1",
+            ),
+            (
+                "random()",
+                "Runtime Error: Undefined variable \"random\" on line 1",
+            ),
+            (
+                "set_prop(a, \"b\", 1)",
+                "Runtime Error: Undefined variable \"set_prop\", did you mean \"get_prop\"?",
+            ),
+            (
+                "1 / 0",
+                "Runtime Error: Division by zero in 1 / 0 on line 1",
+            ),
+        ] {
+            match lox.evaluate_rule(rule, &[("a", 1.0.into())]) {
+                Err(err) => assert!(err.to_string().starts_with(message), "{}", err),
+                Ok(_) => panic!("Expected \"{}\" to be rejected", rule),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn contexts() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        let plugin = lox.create_context();
+        lox.exec(
+            r#"
+            var name = "host";
+            fun get_name() {
+                return name;
+            }
+        "#,
+        )?;
+        lox.exec_in(plugin, "var name = \"plugin\";")?;
+        let get_name = lox
+            .get_global(lox.default_context(), "get_name")
+            .expect("Expected get_name to be defined");
+        lox.set_global(plugin, "host_get_name", get_name);
+        lox.exec_in(
+            plugin,
+            r#"
+            print name;
+            print host_get_name();
+        "#,
+        )?;
+        assert!(lox.exec("print host_get_name;").is_err());
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].body, "plugin");
+            assert_eq!(entries[1].body, "host");
+        });
+        Ok(())
+    }
+
     #[test]
     fn print() -> LoxResult {
         mock_logger::init();
@@ -126,74 +1009,797 @@ mod test {
     }
 
     #[test]
-    fn builtins() -> LoxResult {
+    fn for_loop_closures() -> LoxResult {
         mock_logger::init();
         let mut lox = LoxInterpreter::new();
-        lox.exec(BUILTINS_TEST)?;
+        lox.exec(FOR_CLOSURE_TEST)?;
         MockLogger::entries(|entries| {
-            assert_eq!(entries.len(), 1);
-            assert_ne!(entries[0].body, "nil");
+            assert_eq!(entries.len(), 5);
+            assert_eq!(entries[0].body, "0");
+            assert_eq!(entries[1].body, "1");
+            assert_eq!(entries[2].body, "2");
+            assert_eq!(entries[3].body, "3");
+            assert_eq!(entries[4].body, "1");
         });
         Ok(())
     }
 
     #[test]
-    fn function() -> LoxResult {
+    fn for_in_loop() -> LoxResult {
         mock_logger::init();
         let mut lox = LoxInterpreter::new();
-        lox.exec(FUNCTION_TEST)?;
+        lox.exec(FOR_IN_TEST)?;
         MockLogger::entries(|entries| {
-            assert_eq!(entries.len(), 1);
-            assert_eq!(entries[0].body, "Hello, world!");
+            assert_eq!(entries.len(), 4);
+            assert_eq!(entries[0].body, "x = 1");
+            assert_eq!(entries[1].body, "y = 2");
+            assert_eq!(entries[2].body, "a");
+            assert_eq!(entries[3].body, "b");
         });
+        assert!(lox.exec("for (var x in 1) print x;").is_err());
         Ok(())
     }
 
     #[test]
-    fn function_closure() -> LoxResult {
+    fn operator_overloading() -> LoxResult {
         mock_logger::init();
         let mut lox = LoxInterpreter::new();
-        lox.exec(FUNCTION_CLOSURE_TEST)?;
+        lox.exec(OPERATOR_OVERLOAD_TEST)?;
         MockLogger::entries(|entries| {
-            assert_eq!(entries.len(), 2);
-            assert_eq!(entries[0].body, "1");
-            assert_eq!(entries[1].body, "2")
+            assert_eq!(entries.len(), 4);
+            assert_eq!(entries[0].body, "425");
+            assert_eq!(entries[1].body, "3");
+            assert_eq!(entries[2].body, "3");
+            assert_eq!(entries[3].body, "1");
         });
+        assert!(lox.exec("left + 1;").is_err());
         Ok(())
     }
 
     #[test]
-    fn shadowing() -> LoxResult {
-        mock_logger::init();
-        let mut lox = LoxInterpreter::new();
-        lox.exec(SHADOWING_TEST)?;
-        MockLogger::entries(|entries| {
-            assert_eq!(entries.len(), 2);
-            assert_eq!(entries[0].body, "global");
-            assert_eq!(entries[1].body, "global")
-        });
+    fn constants() -> LoxResult {
+        for backend in Backend::ALL {
+            let mut lox = LoxInterpreter::with_backend(LoxConfig::default(), backend);
+            lox.exec("const limit = 10; fun twice() { const n = limit * 2; return n; }")?;
+            assert!(lox.eval("twice();")? == 20.0.into());
+            // Constants from earlier runs are checked when assigned
+            assert!(matches!(
+                lox.exec("print 1;\nlimit = 1;"),
+                Err(LoxError::Runtime(message, 1)) if message == "Cannot assign to constant \"limit\""
+            ));
+            assert!(lox.eval("limit;")? == 10.0.into());
+            assert!(lox.exec("const missing;").is_err());
+            assert!(lox.exec("const a, b = Array();").is_err());
+        }
         Ok(())
     }
 
     #[test]
-    fn class() -> LoxResult {
+    fn compound_assignment() -> LoxResult {
         mock_logger::init();
         let mut lox = LoxInterpreter::new();
-        lox.exec(CLASS_TEST)?;
+        lox.exec(COMPOUND_ASSIGNMENT_TEST)?;
         MockLogger::entries(|entries| {
-            assert_eq!(entries.len(), 1);
-            assert_eq!(entries[0].body, "Hello, world!");
+            let lines: Vec<&str> = entries.iter().map(|entry| entry.body.as_str()).collect();
+            assert_eq!(
+                lines,
+                ["6", "7", "11", "11", "13", "13", "12", "abc", "1", "1"]
+            );
         });
+        assert!(matches!(
+            lox.exec("1 += 2;"),
+            Err(LoxError::Runtime(message, _)) if message == "Invalid assignment target"
+        ));
+        assert!(lox.exec("counter.count() += 1;").is_err());
+        assert!(lox.exec("++1;").is_err());
         Ok(())
     }
 
     #[test]
-    fn class_inheritance() -> LoxResult {
-        mock_logger::init();
-        let mut lox = LoxInterpreter::new();
-        lox.exec(CLASS_INHERITANCE_TEST)?;
-        MockLogger::entries(|entries| {
-            assert_eq!(entries.len(), 2);
+    fn compound_receiver() -> LoxResult {
+        for backend in Backend::ALL {
+            let mut lox = LoxInterpreter::with_backend(LoxConfig::default(), backend);
+            lox.exec(
+                r#"
+                class Counter { init() { this.n = 1; } }
+                var counter = Counter();
+                var calls = 0;
+                fun get() { calls = calls + 1; return counter; }
+            "#,
+            )?;
+            lox.exec("get().n += 5; ++get().n; get().n -= 1;")?;
+            assert!(lox.eval("get().n++;")? == 6.0.into());
+            assert!(lox.eval("counter.n;")? == 7.0.into());
+            assert!(lox.eval("calls;")? == 4.0.into());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn exec_limits() -> LoxResult {
+        for backend in Backend::ALL {
+            let mut lox = LoxInterpreter::with_backend(LoxConfig::default(), backend);
+            lox.exec("fun down(n) { if (n > 0) down(n - 1); }")?;
+            lox.set_exec_limits(ExecLimits {
+                max_steps: Some(1000),
+                ..ExecLimits::default()
+            });
+            assert!(matches!(
+                lox.exec("var i = 0; while (true) i = i + 1;"),
+                Err(LoxError::LimitExceeded(message)) if message == "Script took more than 1000 steps"
+            ));
+            // Every run gets the full budget
+            lox.exec("var i = 0; while (i < 10) i = i + 1;")?;
+            lox.exec("var i = 0; while (i < 10) i = i + 1;")?;
+            lox.set_exec_limits(ExecLimits {
+                max_call_depth: Some(10),
+                ..ExecLimits::default()
+            });
+            lox.exec("down(9);")?;
+            assert!(matches!(
+                lox.exec("down(10);"),
+                Err(LoxError::LimitExceeded(message)) if message == "More than 10 nested calls"
+            ));
+            lox.set_exec_limits(ExecLimits {
+                timeout: Some(Duration::from_millis(20)),
+                ..ExecLimits::default()
+            });
+            // The error handler can't keep a script going past its limits
+            lox.exec("fun keep_going(err) { return true; } on_error(keep_going);")?;
+            assert!(matches!(
+                lox.exec("while (true) i = i + 1;"),
+                Err(LoxError::LimitExceeded(message)) if message.starts_with("Script ran for more than")
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn recursion() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            class Tree {
+                init(depth) {
+                    this.depth = depth;
+                }
+                size() {
+                    if (this.depth == 0) return 1;
+                    return 1 + Tree(this.depth - 1).size() + Tree(this.depth - 1).size();
+                }
+            }
+        "#,
+        )?;
+        // Every call has its own variables
+        assert!(lox.eval("fib(10);")? == 55.0.into());
+        assert!(lox.eval("Tree(3).size();")? == 15.0.into());
+        // Closures from different calls don't share their arguments
+        lox.exec(
+            r#"
+            fun make(a) {
+                fun get() {
+                    return a;
+                }
+                return get;
+            }
+            var one = make(1);
+            var two = make(2);
+        "#,
+        )?;
+        assert!(lox.eval("one() + two();")? == 3.0.into());
+        Ok(())
+    }
+
+    #[test]
+    fn method_scopes() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            class A {
+                name() {
+                    return "A";
+                }
+            }
+            class B < A {
+                init(n) {
+                    var m = n + 1;
+                    {
+                        this.n = m;
+                    }
+                }
+                name() {
+                    fun inner() {
+                        return super.name() + this.n;
+                    }
+                    return inner();
+                }
+            }
+        "#,
+        )?;
+        // `this`, `super` and the assigned local are found from nested scopes
+        assert!(lox.eval("B(1).name();")? == "A2".into());
+        Ok(())
+    }
+
+    #[test]
+    fn panics() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        lox.register_native_fn("explode", &[], |_, _, _| panic!("Kaboom"));
+        lox.exec("var survivor = 1;")?;
+        assert!(matches!(
+            lox.exec("explode();"),
+            Err(LoxError::Internal(err)) if err.message() == "Kaboom"
+        ));
+        assert!(lox.is_poisoned());
+        assert!(matches!(lox.eval("1;"), Err(LoxError::Internal(_))));
+        lox.reset();
+        assert!(!lox.is_poisoned());
+        assert!(lox.eval("survivor;")? == 1.0.into());
+        Ok(())
+    }
+
+    #[test]
+    fn broken_invariants() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        lox.exec("fun f() {\n  var a = 1;\n  a = a + 1;\n}")?;
+        for local in lox.state.locals.values_mut() {
+            local.depth = 99;
+        }
+        let Err(LoxError::Internal(err)) = lox.exec("f();") else {
+            panic!("Expected an internal error");
+        };
+        assert!(err.message() == "Invalid ancestor scope for \"a\"");
+        assert!(err.line() == Some(2));
+        assert!(err.node().is_some());
+        assert!(err.local() == Some("depth 99, slot 0"));
+        assert!(err.to_string().contains(err.version()));
+        // Not a panic, so the interpreter is still usable
+        assert!(!lox.is_poisoned());
+        Ok(())
+    }
+
+    #[test]
+    fn stack_overflow() -> LoxResult {
+        // Calls nesting statements take more stack each, which is caught
+        // before the depth limit on the test thread's small stack
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            fun go(n) {
+                try {
+                    for (var i = 0; i < 1; i = i + 1) {
+                        if (true) { { return go(n + 1) + 1; } }
+                    }
+                } finally {}
+            }
+        "#,
+        )?;
+        assert!(matches!(
+            lox.exec("go(0);"),
+            Err(LoxError::Runtime(message, _)) if message == "Stack overflow"
+        ));
+        // A bigger stack allows deeper calls
+        std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(|| {
+                let mut lox = LoxInterpreter::with_config(LoxConfig {
+                    stack_size: Some(6 * 1024 * 1024),
+                    ..LoxConfig::default()
+                });
+                lox.exec("fun forever(n) {\n    forever(n + 1);\n}")?;
+                assert!(matches!(
+                    lox.exec("forever(0);"),
+                    Err(LoxError::Runtime(message, 1)) if message == "Stack overflow"
+                ));
+                // The error handler can recover from it
+                lox.exec("fun ignore(err) { return err; } on_error(ignore);")?;
+                assert!(
+                    lox.eval("forever(0);")? == "Runtime Error: Stack overflow on line 2".into()
+                );
+                lox.exec("fun count(n) { if (n == 0) return 0; return 1 + count(n - 1); }")?;
+                assert!(lox.eval("count(100);")? == 100.0.into());
+                Ok(())
+            })
+            .expect("Expected to spawn a thread")
+            .join()
+            .expect("Expected the thread not to panic")
+    }
+
+    #[test]
+    fn defer() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            fun work(fail) {
+                print "open";
+                defer { print "close outer"; }
+                if (true) {
+                    var name = "inner";
+                    defer { print "close " + name; }
+                }
+                if (fail) -"fail";
+                print "done";
+                return "result";
+            }
+            print work(false);
+        "#,
+        )?;
+        assert!(lox.exec("work(true);").is_err());
+        MockLogger::entries(|entries| {
+            let lines: Vec<&str> = entries.iter().map(|entry| entry.body.as_str()).collect();
+            assert_eq!(
+                lines,
+                [
+                    "open",
+                    "done",
+                    "close inner",
+                    "close outer",
+                    "result",
+                    "open",
+                    "close inner",
+                    "close outer"
+                ]
+            );
+        });
+        // Still usable as a name
+        assert!(lox.eval("var defer = 1; defer;")? == 1.0.into());
+        assert!(matches!(
+            lox.exec("defer { print 1; }"),
+            Err(LoxError::Resolution(..))
+        ));
+        assert!(matches!(
+            lox.exec("fun f(x) { defer { return x; } }"),
+            Err(LoxError::Resolution(..))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn using() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            class Resource {
+                init(name) {
+                    this.name = name;
+                }
+                close() {
+                    print "close " + this.name;
+                }
+            }
+            fun first(fail) {
+                using (var a = Resource("a")) {
+                    using (var b = Resource("b")) {
+                        if (fail) -"fail";
+                        return b.name;
+                    }
+                }
+            }
+            print first(false);
+        "#,
+        )?;
+        assert!(lox.exec("first(true);").is_err());
+        MockLogger::entries(|entries| {
+            let lines: Vec<&str> = entries.iter().map(|entry| entry.body.as_str()).collect();
+            assert_eq!(lines, ["close b", "close a", "b", "close b", "close a"]);
+        });
+        assert!(matches!(
+            lox.exec("using (var x = 1) {}"),
+            Err(LoxError::Runtime(message, 0)) if message.contains("close() method")
+        ));
+        // Still callable as a function
+        assert!(lox.eval("fun using(x) { return x; } using(2);")? == 2.0.into());
+        Ok(())
+    }
+
+    #[test]
+    fn exceptions() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            class NotFound {
+                init(key) {
+                    this.key = key;
+                }
+            }
+            fun find(key) {
+                try {
+                    if (key == "missing") throw NotFound(key);
+                    return key + "!";
+                } finally {
+                    print "searched " + key;
+                }
+            }
+            print find("a");
+            try {
+                find("missing");
+                print "unreachable";
+            } catch (err) {
+                print "not found: " + err.key;
+            }
+            try {
+                -"a";
+            } catch (err) {
+                print err;
+            } finally {
+                print "finally";
+            }
+            try {
+                try {
+                    throw "inner";
+                } finally {
+                    try {
+                        throw "ignored";
+                    } catch (err) {}
+                }
+            } catch (err) {
+                print "caught " + err;
+            }
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            let lines: Vec<&str> = entries.iter().map(|entry| entry.body.as_str()).collect();
+            assert_eq!(
+                lines,
+                [
+                    "searched a",
+                    "a!",
+                    "searched missing",
+                    "not found: missing",
+                    "Runtime Error: Invalid operand -a on line 23",
+                    "finally",
+                    "caught inner"
+                ]
+            );
+        });
+        assert!(matches!(
+            lox.exec("\nthrow 42;"),
+            Err(LoxError::Thrown(message, 1)) if message == "42"
+        ));
+        // Exiting isn't an error to catch
+        assert!(matches!(
+            lox.exec("try { exit(2); } catch (err) {}"),
+            Err(LoxError::Exit(2))
+        ));
+        assert!(matches!(
+            lox.exec("try { print 1; }"),
+            Err(LoxError::Syntax(err)) if err.message() == "Expected \"catch\" or \"finally\" after a try block"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn assign_undefined() -> LoxResult {
+        for backend in Backend::ALL {
+            let mut lox = LoxInterpreter::with_backend(LoxConfig::default(), backend);
+            lox.exec("var total = 0; var caught; try { totl = 1; } catch (err) { caught = err; }")?;
+            assert!(
+                lox.eval("caught;")?
+                    == "Runtime Error: Undefined variable \"totl\", did you mean \"total\"? on line 1"
+                        .into()
+            );
+            assert!(matches!(
+                lox.exec("missing = 1;"),
+                Err(LoxError::Runtime(message, 0)) if message == "Undefined variable \"missing\""
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn on_error() -> LoxResult {
+        mock_logger::init();
+        for backend in Backend::ALL {
+            let mut lox = LoxInterpreter::with_backend(LoxConfig::default(), backend);
+            lox.exec(
+                r#"
+                var failures = 0;
+                fun skip(err) {
+                    failures += 1;
+                    print "skipped: " + err;
+                    return true;
+                }
+                on_error(skip);
+                print "a";
+                -"b";
+                print "c";
+            "#,
+            )?;
+            assert!(lox.eval("failures;")? == 1.0.into());
+            // Returning nil lets the error through
+            lox.exec("fun log(err) { print err; } on_error(log);")?;
+            assert!(matches!(
+                lox.exec("print 1; missing;"),
+                Err(LoxError::Runtime(message, 0)) if message.starts_with("Undefined variable")
+            ));
+            // Errors in the handler aren't handled again
+            lox.exec("fun broken(err) { return -\"x\"; } on_error(broken);")?;
+            assert!(lox.exec("-nil;").is_err());
+            lox.exec("on_error(skip);")?;
+            assert!(matches!(lox.exec("exit(3);"), Err(LoxError::Exit(3))));
+            lox.exec("on_error(nil);")?;
+            assert!(lox.exec("-nil;").is_err());
+            assert!(lox.exec("on_error(1);").is_err());
+        }
+        MockLogger::entries(|entries| {
+            let lines: Vec<&str> = entries.iter().map(|entry| entry.body.as_str()).collect();
+            assert_eq!(lines.len(), 10);
+            assert_eq!(
+                lines[..5],
+                [
+                    "a",
+                    "skipped: Runtime Error: Invalid operand -b on line 10",
+                    "c",
+                    "1",
+                    "Runtime Error: Undefined variable \"missing\" on line 1"
+                ]
+            );
+            assert_eq!(lines[..5], lines[5..]);
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn exec_many() -> LoxResult {
+        mock_logger::init();
+        for backend in Backend::ALL {
+            let mut lox = LoxInterpreter::with_backend(LoxConfig::default(), backend);
+            let programs = [
+                lox.compile("var total = 0; for (var i = 1; i <= 3; i += 1) total += i; total;")?,
+                lox.compile("fun double(n) { return n * 2; } print double(21);")?,
+                lox.compile("print total;")?,
+            ];
+            assert!(lox.compile("var = 1;").is_err());
+            for _ in 0..2 {
+                let results = lox.exec_many(&programs);
+                assert_eq!(results.len(), 3);
+                assert!(matches!(&results[0].result, Ok(value) if *value == 6.0.into()));
+                assert!(matches!(results[1].result, Ok(LoxValue::Nil)));
+                // Each program has its own globals
+                assert!(matches!(
+                    &results[2].result,
+                    Err(LoxError::Runtime(message, 0)) if message.starts_with("Undefined variable")
+                ));
+                // Programs keep working after their resolutions are compacted
+                lox.compact();
+            }
+            assert!(lox.get_global(lox.default_context(), "total").is_none());
+            assert!(lox.exec_program(lox.default_context(), &programs[0])? == 6.0.into());
+        }
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 4);
+            assert!(entries.iter().all(|entry| entry.body == "42"));
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn exec_many_frees_contexts() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        let baseline = lox.scope_count();
+        let programs = [
+            lox.compile("fun f() { return 1; } f();")?,
+            lox.compile("class A {}")?,
+            lox.compile("fun make() { var n = 1; fun get() { return n; } return get; } make();")?,
+        ];
+        for _ in 0..3 {
+            let mut results = lox.exec_many(&programs);
+            assert!(matches!(&results[0].result, Ok(value) if *value == 1.0.into()));
+            // A returned closure keeps its context alive while it's reachable
+            let get = results.remove(2).result?;
+            lox.set_global(lox.default_context(), "get", get);
+            lox.exec("1;")?;
+            assert!(lox.eval("get();")? == 1.0.into());
+            lox.exec("get = nil;")?;
+        }
+        lox.exec("1;")?;
+        assert_eq!(lox.scope_count(), baseline);
+        Ok(())
+    }
+
+    /// Run `source` collecting after every statement, then check that only
+    /// the scopes still reachable from globals survive.
+    fn assert_reclaimed(source: &str, retained: usize) -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        lox.set_gc_stress(true);
+        let baseline = lox.scope_count();
+        lox.exec(source)?;
+        assert_eq!(lox.scope_count(), baseline + retained);
+        Ok(())
+    }
+
+    #[test]
+    fn gc_stress() -> LoxResult {
+        mock_logger::init();
+        // Only the scope of the make_counter call, closed over by counter, survives
+        assert_reclaimed(GC_CYCLES_TEST, 1)?;
+        assert_reclaimed(WHILE_LOOP_TEST, 0)?;
+        assert_reclaimed(CLASS_INHERITANCE_TEST, 0)?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries[0].body, "3");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn host_held_closures() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            fun make() {
+                var c = 10;
+                fun inc() {
+                    c = c + 1;
+                    return c;
+                }
+                return inc;
+            }
+            fun apply(f) {
+                return f();
+            }
+            fun apply_first(functions) {
+                return functions.get(0)();
+            }
+        "#,
+        )?;
+        // Only the host refers to the closure and its scope from here on
+        let inc = lox.eval("make();")?;
+        let array = lox.eval("var a = Array(); a.push(make()); a;")?;
+        lox.exec("a = nil;")?;
+        lox.exec(
+            r#"
+            fun other() {
+                var c = 500;
+                fun g() {
+                    return c;
+                }
+                return g;
+            }
+            var keep = other();
+        "#,
+        )?;
+        lox.compact();
+        assert!(lox.call("apply", vec![inc.clone()])? == 11.0.into());
+        assert!(lox.call("apply", vec![inc])? == 12.0.into());
+        assert!(lox.call("apply_first", vec![array])? == 11.0.into());
+        assert!(lox.eval("keep();")? == 500.0.into());
+        Ok(())
+    }
+
+    #[test]
+    fn compact() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(FUNCTION_CLOSURE_TEST)?;
+        lox.exec(BLOCK_SCOPE_TEST)?;
+        let before = lox.stats();
+        let reclaimed = lox.compact();
+        assert!(reclaimed.locals > 0);
+        assert_eq!(lox.stats().locals, before.locals - reclaimed.locals);
+        // Functions defined earlier still resolve their variables
+        lox.exec("counter();")?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.last().unwrap().body, "3");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn builtins() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(BUILTINS_TEST)?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 1);
+            assert_ne!(entries[0].body, "nil");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn function() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(FUNCTION_TEST)?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].body, "Hello, world!");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn varargs() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        let count = LoxFunction::native_variadic("count", vec!["first"], |_, args, _| {
+            Ok(LoxValue::Number(args.len() as f64))
+        });
+        lox.set_global(lox.default_context(), "count", count.into());
+        lox.exec(
+            r#"
+            fun join(separator, ...parts) {
+                var out = "";
+                for (var part in parts) {
+                    if (out != "") out += separator;
+                    out += part;
+                }
+                return out;
+            }
+            print join(", ", "a", "b", "c");
+            print join(", ");
+            fun rest(...all) {
+                return all.len();
+            }
+            print rest();
+            print count(1, 2, 3);
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            let lines: Vec<&str> = entries.iter().map(|entry| entry.body.as_str()).collect();
+            assert_eq!(lines, ["a, b, c", "", "0", "3"]);
+        });
+        assert!(matches!(
+            lox.exec("join();"),
+            Err(LoxError::Runtime(message, 0))
+                if message == "Function \"join\" takes at least 1 argument(s) (defined on line 2)"
+        ));
+        assert!(lox.exec("count();").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn function_closure() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(FUNCTION_CLOSURE_TEST)?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].body, "1");
+            assert_eq!(entries[1].body, "2")
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn shadowing() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(SHADOWING_TEST)?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].body, "global");
+            assert_eq!(entries[1].body, "global")
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn class() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(CLASS_TEST)?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].body, "Hello, world!");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn class_inheritance() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(CLASS_INHERITANCE_TEST)?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
             assert_eq!(entries[0].body, "Hello, world!");
             assert_eq!(entries[1].body, "Howdy, partner!");
         });
@@ -211,4 +1817,588 @@ mod test {
         });
         Ok(())
     }
+
+    #[test]
+    fn accessors() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(ACCESSOR_TEST)?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 4);
+            assert_eq!(entries[0].body, "4");
+            assert_eq!(entries[1].body, "27");
+            assert_eq!(entries[2].body, "1");
+            assert_eq!(entries[3].body, "9");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn static_methods() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(STATIC_METHOD_TEST)?;
+        assert!(matches!(
+            lox.exec("Math.sqare(2);"),
+            Err(LoxError::Runtime(message, _))
+                if message == "Undefined static method \"sqare\" on \"Math\", did you mean \"square\"?"
+        ));
+        assert!(lox.exec("Geometry.create().square(2);").is_err());
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 3);
+            assert_eq!(entries[0].body, "9");
+            assert_eq!(entries[1].body, "8");
+            assert_eq!(entries[2].body, "16");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn static_fields() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(STATIC_FIELD_TEST)?;
+        assert!(matches!(
+            lox.exec("Color.RED = \"blue\";"),
+            Err(LoxError::Runtime(message, _))
+                if message.starts_with("Cannot set property \"RED\" on \"Color\", a Class")
+        ));
+        assert!(lox.exec("Color().RED;").is_err());
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 4);
+            assert_eq!(entries[0].body, "red");
+            assert_eq!(entries[1].body, "red!");
+            assert_eq!(entries[2].body, "red");
+            assert_eq!(entries[3].body, "6");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn to_string() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(TO_STRING_TEST)?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].body, "Point(1)");
+            assert_eq!(entries[1].body, "Plain {}");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn near_miss_suggestions() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            var counter = 1;
+            class Point {
+                init() {
+                    this.x = 1;
+                }
+                length() {
+                    return this.x;
+                }
+            }
+            var point = Point();
+        "#,
+        )?;
+        assert!(matches!(
+            lox.exec("print countr;"),
+            Err(LoxError::Runtime(message, _)) if message == "Undefined variable \"countr\", did you mean \"counter\"?"
+        ));
+        assert!(matches!(
+            lox.exec("print point.lenght;"),
+            Err(LoxError::Runtime(message, _)) if message.ends_with(", did you mean \"length\"?")
+        ));
+        assert!(matches!(
+            lox.exec("print nothing_like_it;"),
+            Err(LoxError::Runtime(message, _)) if message == "Undefined variable \"nothing_like_it\""
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn early_return() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(EARLY_RETURN_TEST)?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 5);
+            assert_eq!(entries[0].body, "3");
+            assert_eq!(entries[1].body, "1");
+            assert_eq!(entries[2].body, "truthy");
+            assert_eq!(entries[3].body, "falsy");
+            assert_eq!(entries[4].body, "done");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn import_globals() -> LoxResult {
+        let classes = r#"
+            class Point {
+                init(x, y) {
+                    this.x = x;
+                    this.y = y;
+                }
+                sum() { return this.x + this.y; }
+            }
+        "#;
+        let mut warm = LoxInterpreter::new();
+        warm.exec(classes)?;
+        warm.exec(
+            r#"
+            var count = 3;
+            const name = "lox";
+            var items = Array();
+            items.push(Point(1, 2));
+            items.push("two");
+            var settings = Map();
+            settings.set("debug", true);
+            var origin = Point(0, 0);
+            fun helper() {}
+            var cycle = Array();
+            cycle.push(cycle);
+        "#,
+        )?;
+        let vars = warm.export_globals();
+        let names: Vec<&str> = vars.iter().map(|var| var.name.as_str()).collect();
+        assert_eq!(names, ["count", "name", "items", "settings", "origin"]);
+        // Exported globals hold no Rcs, so they can move to another thread
+        let vars = std::thread::spawn(move || vars).join().unwrap();
+
+        let mut cold = LoxInterpreter::new();
+        assert!(matches!(
+            cold.import_globals(vars.clone()),
+            Err(LoxError::General(message)) if message.contains("\"Point\"")
+        ));
+        assert!(cold.get_global(cold.default_context(), "count").is_none());
+        cold.exec(classes)?;
+        cold.import_globals(vars)?;
+        assert!(cold.eval("count + items.len();")? == 5.0.into());
+        assert!(cold.eval("items.get(0).sum() + origin.sum();")? == 3.0.into());
+        assert!(cold.eval("settings.get(\"debug\");")? == true.into());
+        assert!(cold.exec("name = \"other\";").is_err());
+        // A deep copy, changes don't reach the exporting interpreter
+        cold.exec("items.get(0).x = 10;")?;
+        assert!(warm.eval("items.get(0).x;")? == 1.0.into());
+        Ok(())
+    }
+
+    #[test]
+    fn set_properties() -> LoxResult {
+        for backend in Backend::ALL {
+            let mut lox = LoxInterpreter::with_backend(LoxConfig::default(), backend);
+            lox.exec("var count = 1;")?;
+            assert!(matches!(
+                lox.exec("var x = 1;\ncount.total = 2;"),
+                Err(LoxError::Runtime(message, 1))
+                    if message == "Cannot set property \"total\" on \"count\", a Number"
+            ));
+        }
+
+        let mut lox = LoxInterpreter::new();
+        lox.set_strict_properties(true);
+        lox.exec(
+            r#"
+            class Point {
+                init(x) {
+                    this.x = x;
+                    this.reset();
+                }
+                reset() { this.y = 0; }
+            }
+            class Point3 < Point {
+                init(x) {
+                    super.init(x);
+                    this.z = 0;
+                }
+            }
+            var p = Point3(1);
+            p.x = 2;
+            p.reset();
+        "#,
+        )?;
+        assert!(lox.eval("p.x + p.y + p.z;")? == 2.0.into());
+        assert!(matches!(
+            lox.exec("p.xx = 3;"),
+            Err(LoxError::Runtime(message, 0)) if message.starts_with(
+                "Cannot add property \"xx\" to \"Point3\" outside of init in strict mode"
+            )
+        ));
+        // Construction that failed doesn't leave the instance initializing
+        lox.exec("class Bad { init() { this.a = 1; throw \"no\"; } }")?;
+        assert!(lox.exec("try { Bad(); } catch (e) {} p.w = 1;").is_err());
+        lox.set_strict_properties(false);
+        lox.exec("p.w = 1;")?;
+        Ok(())
+    }
+
+    #[test]
+    fn print_newline() -> LoxResult {
+        for backend in Backend::ALL {
+            let mut lox = LoxInterpreter::with_backend(
+                LoxConfig {
+                    print_newline: Some(false),
+                    ..LoxConfig::default()
+                },
+                backend,
+            );
+            let source = "print \"a\"; print 1; println(\"!\"); print \"b\";";
+            let (_, output) = lox.exec_capture(source)?;
+            assert_eq!(output, "a1!\nb");
+            lox.set_print_newline(true);
+            let (_, output) = lox.exec_capture(source)?;
+            assert_eq!(output, "a\n1\n!\nb\n");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn exec_capture() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        let (value, output) = lox.exec_capture(
+            r#"
+            print "Hello";
+            for (var i in Array()) print i;
+            print 1 + 1;
+            "result";
+        "#,
+        )?;
+        assert!(matches!(value, LoxValue::String(s) if s == "result"));
+        assert_eq!(output, "Hello\n2\n");
+        let (value, output) = lox.exec_capture("var x = 1;")?;
+        assert!(matches!(value, LoxValue::Nil));
+        assert_eq!(output, "");
+        // Output goes back to the logger afterwards
+        lox.exec("print 3;")?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].body, "3");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn release_scopes() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        let scopes = LoxFunction::native("scopes", vec![], |state, _, _| {
+            Ok((state.env.scope_count() as f64).into())
+        });
+        lox.state.env.define_builtin("scopes".into(), scopes.into());
+        lox.exec(
+            r#"
+            fun find(n) {
+                if (n) {
+                    return 1;
+                }
+                return 0;
+            }
+            var items = Array();
+            items.push(1);
+            var before = scopes();
+            var i = 0;
+            while (i < 100) {
+                {
+                    var x = i;
+                }
+                for (var item in items) print scopes() - before;
+                find(true);
+                i = i + 1;
+            }
+            print scopes() - before;
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 101);
+            // The loop, its body and the iteration scope
+            assert!(entries[..100].iter().all(|entry| entry.body == "3"));
+            assert_eq!(entries[100].body, "0");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn reload() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            var count = 1;
+            fun greet() {
+                return "Hello";
+            }
+            class Greeter {
+                greet() {
+                    return greet();
+                }
+            }
+            var greeter = Greeter();
+            count = count + 1;
+        "#,
+        )?;
+        lox.reload(
+            r#"
+            var count = 1;
+            var added = "new";
+            fun greet() {
+                return "Howdy";
+            }
+            class Greeter {
+                greet() {
+                    return greet() + "!";
+                }
+            }
+            print "skipped";
+        "#,
+        )?;
+        lox.exec("print count; print added; print greet(); print greeter.greet();")?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 4);
+            assert_eq!(entries[0].body, "2");
+            assert_eq!(entries[1].body, "new");
+            assert_eq!(entries[2].body, "Howdy");
+            assert_eq!(entries[3].body, "Howdy!");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn unary_minus() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        // `--` is a decrement, double negation needs a space
+        lox.exec("var x = 5; print -x; print -(-2.5); print 1 - -1; print - -x;")?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 4);
+            assert_eq!(entries[0].body, "-5");
+            assert_eq!(entries[1].body, "2.5");
+            assert_eq!(entries[2].body, "2");
+            assert_eq!(entries[3].body, "5");
+        });
+        assert!(matches!(
+            lox.exec("print -\"five\";"),
+            Err(LoxError::Runtime(message, 0)) if message == "Invalid operand -five"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn checked_math() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            var big = 1;
+            for (var i = 0; i < 308; i = i + 1) big = big * 10;
+            print big * 10;
+        "#,
+        )?;
+        lox.set_checked_math(true);
+        lox.exec("print big / 10 * 10 == big;")?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].body, "Infinity");
+            assert_eq!(entries[1].body, "true");
+        });
+        assert!(matches!(
+            lox.exec("var x = 1;\nprint big * 10;"),
+            Err(LoxError::Runtime(message, 1)) if message.starts_with("Numeric overflow in")
+        ));
+        assert!(matches!(
+            lox.exec("print 1 / 0;"),
+            Err(LoxError::Runtime(message, 0)) if message == "Division by zero in 1 / 0"
+        ));
+        let mut vm = LoxInterpreter::with_backend(
+            LoxConfig {
+                checked_math: true,
+                ..LoxConfig::default()
+            },
+            Backend::Bytecode,
+        );
+        assert!(vm.exec("var x = 0 / 0;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn scan_errors() {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        let message = |result: LoxResult| result.map_err(|err| err.to_string()).unwrap_err();
+        // The malformed number isn't dropped, leaving a valid call
+        assert_eq!(
+            message(lox.exec("fun f(...a) { print a.len(); }\nf(1.2.3);")),
+            "Syntax error on line 2: Unexpected \".\" in number \"1.2.3\""
+        );
+        assert_eq!(
+            message(lox.exec("print 1.;")),
+            "Syntax error on line 1: Expected a digit after the decimal point in \"1.\""
+        );
+        MockLogger::entries(|entries| {
+            assert!(entries.iter().all(|entry| entry.body != "0"));
+        });
+    }
+
+    #[test]
+    fn render_error() {
+        let mut lox = LoxInterpreter::new();
+        let source = "var a = 1;\nprint a +  b;";
+        let err = lox.exec(source).unwrap_err();
+        assert_eq!(
+            lox.render_error(&err, source),
+            "Runtime Error: Undefined variable \"b\", did you mean \"a\"? on line 2\n \
+             --> 2:12\n  |\n2 | print a +  b;\n  |            ^"
+        );
+        let source = "var a = 1;\n\tprint (a;";
+        let err = lox.exec(source).unwrap_err();
+        assert_eq!(
+            lox.render_error(&err, source),
+            "Syntax error on line 2: Expected closing ')'\n \
+             --> 2:10\n  |\n2 | \tprint (a;\n  | \t        ^"
+        );
+        let err = LoxError::General("oops".into());
+        assert_eq!(lox.render_error(&err, source), "oops");
+    }
+
+    #[test]
+    fn multiple_returns() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            fun minmax(items) {
+                var low = items.get(0);
+                var high = low;
+                for (var item in items) {
+                    if (item < low) low = item;
+                    if (item > high) high = item;
+                }
+                return low, high;
+            }
+            var items = Array();
+            items.push(3);
+            items.push(1);
+            items.push(4);
+            var low, high = minmax(items);
+            print low;
+            print high;
+            {
+                var a, b, c = items;
+                print a + b + c;
+            }
+            var pair = minmax(items);
+            print pair.len();
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 4);
+            assert_eq!(entries[0].body, "1");
+            assert_eq!(entries[1].body, "4");
+            assert_eq!(entries[2].body, "8");
+            assert_eq!(entries[3].body, "2");
+        });
+        assert!(matches!(
+            lox.exec("var x, y = items;"),
+            Err(LoxError::Runtime(message, 0)) if message == "Expected 2 values to unpack, got 3"
+        ));
+        assert!(matches!(
+            lox.exec("var x, y = 1;"),
+            Err(LoxError::Runtime(message, 0)) if message == "Expected Array, got \"Number\""
+        ));
+        assert!(lox.exec("{ var x, x = items; }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn native_registration() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.register_native_fn("double", &["n"], |_, args, meta| {
+            Ok((args[0].get_number(meta.line)? * 2.0).into())
+        });
+        lox.register_native_class(
+            "Counter",
+            vec![
+                LoxFunction::native("init", vec![], |_, _, meta| {
+                    let this = meta.this_value.unwrap().get_object(meta.line)?;
+                    this.borrow_mut().set("count".into(), 0.0.into());
+                    Ok(LoxValue::Nil)
+                }),
+                LoxFunction::native("increment", vec![], |_, _, meta| {
+                    let this = meta.this_value.unwrap().get_object(meta.line)?;
+                    let count = this.borrow().get("count").unwrap().get_number(meta.line)?;
+                    this.borrow_mut().set("count".into(), (count + 1.0).into());
+                    Ok((count + 1.0).into())
+                }),
+            ],
+        );
+        lox.exec(
+            r#"
+            print double(21);
+            var counter = Counter();
+            counter.increment();
+            print counter.increment();
+            print counter.count;
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 3);
+            assert_eq!(entries[0].body, "42");
+            assert_eq!(entries[1].body, "2");
+            assert_eq!(entries[2].body, "2");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn call_from_host() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            fun total(values) {
+                var sum = 0;
+                for (var value in values) sum = sum + value;
+                return sum;
+            }
+            fun shout(message) {
+                print message + "!";
+                return message == "hi";
+            }
+        "#,
+        )?;
+        let sum: f64 = lox
+            .call("total", vec![vec![1.0, 2.0, 3.5].into()])?
+            .try_into()?;
+        assert_eq!(sum, 6.5);
+        // The same vec can be passed again
+        let values: LoxValue = vec![1.0, 2.0].into();
+        assert!(lox.call("total", vec![values.clone()])? == 3.0.into());
+        assert!(lox.call("total", vec![values.clone()])? == 3.0.into());
+        let rule = [("tags", values)];
+        assert!(lox.evaluate_rule("tags.len()", &rule)? == 2.0.into());
+        assert!(lox.evaluate_rule("tags.len()", &rule)? == 2.0.into());
+        let matched: bool = lox.call("shout", vec!["hi".into()])?.try_into()?;
+        assert!(matched);
+        let items: Vec<LoxValue> = lox.call("Array", vec![])?.try_into()?;
+        assert!(items.is_empty());
+        assert!(LoxValue::from(None::<f64>) == LoxValue::Nil);
+        assert!(matches!(
+            lox.call("missing", vec![]),
+            Err(LoxError::Runtime(message, _)) if message == "Undefined variable \"missing\""
+        ));
+        assert!(matches!(
+            lox.call("total", vec![]),
+            Err(LoxError::Runtime(..))
+        ));
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].body, "hi!");
+        });
+        Ok(())
+    }
 }
@@ -0,0 +1,119 @@
+//! An interpreter for Lox, the language from Crafting Interpreters, meant to
+//! be embedded in Rust programs.
+//!
+//! [`LoxInterpreter`] runs scripts and exchanges [`LoxValue`]s with the host.
+//! Native functions and classes are built with [`LoxFunction`],
+//! [`LoxClass`] and [`BuiltinPack`], and parsing on its own is available
+//! through [`parse`] and [`scan`], producing [`Stmt`]s and [`Expr`]s. Everything else is internal, apart from
+//! the [`tools`] the `lox` binary is built from.
+//!
+//! ```
+//! use lox::{LoxInterpreter, LoxValue};
+//!
+//! let mut lox = LoxInterpreter::new();
+//! lox.exec("fun double(n) { return n * 2; }")?;
+//! assert!(lox.eval("double(21);")? == LoxValue::from(42.0));
+//! # Ok::<(), lox::LoxError>(())
+//! ```
+//!
+//! Cargo features: `cli` builds the `lox` binary, `log` sends `print` output
+//! and warnings through the `log` crate (stdout and nowhere otherwise),
+//! `builtins-io` provides the file builtins, `serde` implements `Serialize`
+//! for values and `spec` exposes the conformance suite as `spec::test_cases`.
+//! All but `serde` and `spec` are enabled by default.
+
+#[macro_use]
+mod logging;
+
+mod ast_printer;
+mod builtins;
+mod call_graph;
+mod class;
+mod clock;
+mod config;
+mod coverage;
+mod environment;
+mod error;
+mod expr;
+mod file_system;
+mod function;
+mod hashing;
+mod hotspots;
+mod interpreter;
+mod lint;
+mod object;
+mod observer;
+mod output;
+mod parser;
+mod pretty;
+mod quick;
+mod repl;
+mod replay;
+mod resolver;
+mod scanner;
+#[cfg(feature = "serde")]
+mod serialize;
+mod sheet;
+#[cfg(feature = "spec")]
+pub mod spec;
+#[cfg(all(test, not(feature = "spec")))]
+#[allow(dead_code)]
+mod spec;
+mod state;
+mod stmt;
+mod suggest;
+mod timings;
+mod value;
+mod vm;
+
+#[cfg(test)]
+mod differential;
+#[cfg(any(test, feature = "spec"))]
+#[cfg_attr(not(test), allow(dead_code))]
+mod test_scripts;
+
+#[cfg(feature = "builtins-io")]
+pub use crate::builtins::FsPack;
+#[cfg(feature = "log")]
+pub use crate::output::LogSink;
+pub use crate::{
+    builtins::{BuiltinPack, CorePack, DebugPack, MathPack, NumberPack, StringPack, SystemPack},
+    class::{LoxClass, LoxSuper},
+    clock::{Clock, MockClock, SystemClock},
+    config::LoxConfig,
+    coverage::Coverage,
+    environment::{
+        Environment, LoxData, LoxProperties, LoxVar, LoxVars, ScopeHandle, GLOBAL_SCOPE,
+    },
+    error::{Diagnostic, InternalError, LoxError, LoxResult, Severity, Span, SyntaxError},
+    expr::{Expr, ExprKind},
+    file_system::{FileSystem, HostFileSystem, MemoryFileSystem, ReadOnly},
+    function::{Arity, FunctionBody, FunctionCallMetadata, LoxFunction, NativeFunction},
+    hashing::LoxHasher,
+    hotspots::Hotspots,
+    interpreter::{Backend, BatchResult, Context, EnvStats, LoxInterpreter, Program},
+    object::{LoxObject, PropertyInterceptor},
+    observer::{NativeCall, Observer},
+    output::{OutputSink, StdoutSink},
+    parser::{parse, ParseLimits, ParseResult, ProgramInfo},
+    quick::{eval, with_interpreter},
+    replay::{Trace, TraceEntry},
+    resolver::{Local, Locals},
+    scanner::{scan, Literal, ScanResult, Token, TokenKind, KEYWORDS},
+    sheet::{FormulaError, Sheet},
+    state::{ExecLimits, LoxState, DEFAULT_STACK_DEPTH, DEFAULT_STACK_SIZE},
+    stmt::{FunKind, Stmt},
+    timings::PhaseTimings,
+    value::LoxValue,
+};
+
+/// The tooling the `lox` binary is built from: the REPL, tracing and
+/// printers for parsed programs.
+pub mod tools {
+    pub use crate::{ast_printer::AstPrinter, call_graph::CallGraph, observer::Tracer, repl::Repl};
+
+    /// Checks over parsed programs.
+    pub mod lint {
+        pub use crate::lint::{dead_code, DeadCode, DeadCodeKind};
+    }
+}
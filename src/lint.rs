@@ -0,0 +1,419 @@
+use super::{
+    error::{Diagnostic, Severity, Span},
+    expr::{Expr, ExprKind},
+    scanner::{Literal, TokenKind},
+    stmt::Stmt,
+};
+use std::{collections::HashSet, fmt};
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum DeadCodeKind {
+    Function,
+    Method,
+    Class,
+}
+
+/// A declaration that no reachable code refers to.
+#[derive(PartialEq, Clone, Debug)]
+pub struct DeadCode {
+    pub kind: DeadCodeKind,
+    /// Methods are named `Class.method`.
+    pub name: String,
+    pub line: u32,
+}
+
+impl fmt::Display for DeadCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line + 1, self.message())
+    }
+}
+
+impl DeadCode {
+    fn message(&self) -> String {
+        let kind = match self.kind {
+            DeadCodeKind::Function => "function",
+            DeadCodeKind::Method => "method",
+            DeadCodeKind::Class => "class",
+        };
+        format!("{} \"{}\" is never used", kind, self.name)
+    }
+
+    /// The finding as a warning for tools.
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            code: "W0001",
+            severity: Severity::Warning,
+            message: self.message(),
+            span: Some(Span {
+                line: self.line,
+                column: None,
+            }),
+            hint: None,
+        }
+    }
+}
+
+/// Report the functions, classes and methods of a program that are never
+/// referenced from reachable code, sorted by line.
+///
+/// Top level statements other than declarations are reachable, and so is
+/// everything they refer to, transitively. References are matched by name,
+/// so a name used anywhere reachable keeps every declaration of that name
+/// alive. Methods are referenced by property accesses, and by string
+/// literals since `get_prop` and `call` look them up dynamically.
+pub fn dead_code(statements: &[Stmt]) -> Vec<DeadCode> {
+    let mut declarations = vec![];
+    collect_declarations(statements, &mut declarations);
+    let mut refs = References::default();
+    for stmt in statements.iter() {
+        refs.visit_stmt(stmt);
+    }
+    let mut live = vec![false; declarations.len()];
+    let mut live_methods: HashSet<(usize, usize)> = HashSet::new();
+    loop {
+        let mut changed = false;
+        for (i, declaration) in declarations.iter().enumerate() {
+            match declaration {
+                Stmt::Fun { name, body, .. } => {
+                    if !live[i] && refs.names.contains(&name.lexeme_str()) {
+                        live[i] = true;
+                        changed = true;
+                        refs.visit_all(body);
+                    }
+                }
+                Stmt::Class {
+                    name,
+                    superclass,
+                    methods,
+                } => {
+                    if !live[i] && refs.names.contains(&name.lexeme_str()) {
+                        live[i] = true;
+                        changed = true;
+                        if let Some(superclass) = superclass {
+                            refs.visit_expr(superclass);
+                        }
+                        // Static fields are evaluated with the declaration
+                        for member in methods.iter() {
+                            if let Stmt::Var {
+                                initializer: Some(initializer),
+                                ..
+                            } = member
+                            {
+                                refs.visit_expr(initializer);
+                            }
+                        }
+                    }
+                    if !live[i] {
+                        continue;
+                    }
+                    for (j, method) in methods.iter().enumerate() {
+                        if let Stmt::Fun { name, body, .. } = method {
+                            let name = name.lexeme_str();
+                            if (is_implicitly_used(&name) || refs.properties.contains(&name))
+                                && live_methods.insert((i, j))
+                            {
+                                changed = true;
+                                refs.visit_all(body);
+                            }
+                        }
+                    }
+                }
+                _ => unreachable!("Expected a declaration"),
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    let mut dead = vec![];
+    for (i, declaration) in declarations.iter().enumerate() {
+        match declaration {
+            Stmt::Fun { name, .. } if !live[i] => dead.push(DeadCode {
+                kind: DeadCodeKind::Function,
+                name: name.lexeme_str(),
+                line: name.line,
+            }),
+            Stmt::Class { name, .. } if !live[i] => dead.push(DeadCode {
+                kind: DeadCodeKind::Class,
+                name: name.lexeme_str(),
+                line: name.line,
+            }),
+            Stmt::Class {
+                name: class_name,
+                methods,
+                ..
+            } => {
+                for (j, method) in methods.iter().enumerate() {
+                    if let Stmt::Fun { name, .. } = method {
+                        if !live_methods.contains(&(i, j)) {
+                            dead.push(DeadCode {
+                                kind: DeadCodeKind::Method,
+                                name: format!("{}.{}", class_name.lexeme_str(), name.lexeme_str()),
+                                line: name.line,
+                            });
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    dead.sort_by_key(|dead| dead.line);
+    dead
+}
+
+/// Methods the interpreter calls without a property access in the source.
+fn is_implicitly_used(name: &str) -> bool {
+    name == "init" || name == "to_string" || (name.starts_with("__") && name.ends_with("__"))
+}
+
+/// Every function and class declaration, at any depth. Methods stay with
+/// their class.
+fn collect_declarations<'a>(statements: &'a [Stmt], declarations: &mut Vec<&'a Stmt>) {
+    for stmt in statements.iter() {
+        match stmt {
+            Stmt::Fun { body, .. } => {
+                declarations.push(stmt);
+                collect_declarations(body, declarations);
+            }
+            Stmt::Class { methods, .. } => {
+                declarations.push(stmt);
+                for method in methods.iter() {
+                    if let Stmt::Fun { body, .. } = method {
+                        collect_declarations(body, declarations);
+                    }
+                }
+            }
+            Stmt::Block { statements, .. } => collect_declarations(statements, declarations),
+            Stmt::IfElse {
+                body, else_branch, ..
+            } => {
+                collect_declarations(std::slice::from_ref(body), declarations);
+                if let Some(else_branch) = else_branch {
+                    collect_declarations(std::slice::from_ref(else_branch), declarations);
+                }
+            }
+            Stmt::ForLoop {
+                initializer, body, ..
+            } => {
+                collect_declarations(std::slice::from_ref(initializer), declarations);
+                collect_declarations(std::slice::from_ref(body), declarations);
+            }
+            Stmt::WhileLoop { body, .. }
+            | Stmt::ForIn { body, .. }
+            | Stmt::Defer { body, .. }
+            | Stmt::Using { body, .. } => {
+                collect_declarations(std::slice::from_ref(body), declarations);
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                collect_declarations(std::slice::from_ref(body), declarations);
+                if let Some((_, handler)) = catch {
+                    collect_declarations(std::slice::from_ref(handler), declarations);
+                }
+                if let Some(finally) = finally {
+                    collect_declarations(std::slice::from_ref(finally), declarations);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Names referred to by reachable code.
+#[derive(Default)]
+struct References {
+    /// Identifiers, plus string literals.
+    names: HashSet<String>,
+    /// Accessed properties, plus string literals.
+    properties: HashSet<String>,
+}
+
+impl References {
+    fn visit_all(&mut self, statements: &[Stmt]) {
+        for stmt in statements.iter() {
+            self.visit_stmt(stmt);
+        }
+    }
+
+    /// Record the references of a reachable statement. Declarations only
+    /// become reachable once their name is referenced.
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Fun { .. } | Stmt::Class { .. } => {}
+            Stmt::Expr(expr) | Stmt::Print(expr) | Stmt::Return(expr) => self.visit_expr(expr),
+            Stmt::Var { initializer, .. } => {
+                if let Some(expr) = initializer {
+                    self.visit_expr(expr);
+                }
+            }
+            Stmt::Destructure { initializer, .. } => self.visit_expr(initializer),
+            Stmt::Block { statements, .. } => self.visit_all(statements),
+            Stmt::IfElse {
+                condition,
+                body,
+                else_branch,
+            } => {
+                self.visit_expr(condition);
+                self.visit_stmt(body);
+                if let Some(else_branch) = else_branch {
+                    self.visit_stmt(else_branch);
+                }
+            }
+            Stmt::WhileLoop { condition, body } => {
+                self.visit_expr(condition);
+                self.visit_stmt(body);
+            }
+            Stmt::ForLoop {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.visit_stmt(initializer);
+                self.visit_expr(condition);
+                self.visit_stmt(body);
+                self.visit_expr(increment);
+            }
+            Stmt::ForIn { iterable, body, .. } => {
+                self.visit_expr(iterable);
+                self.visit_stmt(body);
+            }
+            Stmt::Defer { body, .. } => self.visit_stmt(body),
+            Stmt::Using {
+                initializer, body, ..
+            } => {
+                self.visit_expr(initializer);
+                self.visit_stmt(body);
+            }
+            Stmt::Throw { value, .. } => self.visit_expr(value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.visit_stmt(body);
+                if let Some((_, handler)) = catch {
+                    self.visit_stmt(handler);
+                }
+                if let Some(finally) = finally {
+                    self.visit_stmt(finally);
+                }
+            }
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(token) => {
+                if let (TokenKind::String, Some(Literal::String(value))) =
+                    (token.kind, &token.literal)
+                {
+                    self.names.insert(value.clone());
+                    self.properties.insert(value.clone());
+                }
+            }
+            ExprKind::Identifier(name) | ExprKind::Assignment { name, .. } => {
+                self.names.insert(name.lexeme_str());
+                if let ExprKind::Assignment { value, .. } = &expr.kind {
+                    self.visit_expr(value);
+                }
+            }
+            ExprKind::Unary { right, .. } => self.visit_expr(right),
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.visit_expr(left);
+                self.visit_expr(right);
+            }
+            ExprKind::Grouping(inner) => self.visit_expr(inner),
+            ExprKind::Call { callee, arguments } => {
+                self.visit_expr(callee);
+                for arg in arguments.iter() {
+                    self.visit_expr(arg);
+                }
+            }
+            ExprKind::Get { left, name, .. } => {
+                self.visit_expr(left);
+                self.properties.insert(name.to_string());
+            }
+            ExprKind::Set { object, value, .. } => {
+                self.visit_expr(object);
+                self.visit_expr(value);
+            }
+            ExprKind::This(_) => {}
+            ExprKind::Super(method) => {
+                self.properties.insert(method.lexeme_str());
+            }
+            ExprKind::Tuple(values) => {
+                for value in values.iter() {
+                    self.visit_expr(value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn dead_code_report() {
+        let program = parse(
+            r#"
+            fun used() {
+                return helper();
+            }
+            fun helper() {
+                return Shape(1);
+            }
+            fun unused() {
+                return Orphan();
+            }
+            class Orphan {}
+            class Shape {
+                init(size) {
+                    this.size = size;
+                }
+
+                area() {
+                    return this.size * this.size;
+                }
+
+                perimeter() {
+                    return this.size * 4;
+                }
+
+                describe() {
+                    return "shape";
+                }
+            }
+            print used().area();
+            print get_prop(Shape(2), "describe")();
+        "#,
+        );
+        assert!(program.errors.is_empty());
+        let report: Vec<String> = dead_code(&program.statements)
+            .iter()
+            .map(|dead| dead.to_string())
+            .collect();
+        assert_eq!(
+            report,
+            vec![
+                "Line 8: function \"unused\" is never used",
+                "Line 11: class \"Orphan\" is never used",
+                "Line 21: method \"Shape.perimeter\" is never used",
+            ]
+        );
+        let diagnostic = dead_code(&program.statements)[0].diagnostic();
+        assert_eq!(
+            diagnostic.to_string(),
+            "warning[W0001]: function \"unused\" is never used\n  --> line 8"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+//! `warn!` and `error!` forwarding to the `log` crate, or discarding the
+//! message when built without the `log` feature.
+
+#[cfg(feature = "log")]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        log::warn!($($arg)*)
+    };
+}
+
+#[cfg(feature = "log")]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        log::error!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! error {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
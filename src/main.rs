@@ -1,27 +1,202 @@
-mod builtins;
-mod class;
-mod environment;
-mod error;
-mod expr;
-mod function;
-mod interpreter;
-mod object;
-mod parser;
-mod resolver;
-mod scanner;
-mod state;
-mod stmt;
-mod value;
+mod bench;
+mod cli;
 
-#[cfg(test)]
-mod test_scripts;
-
-use crate::{error::LoxResult, interpreter::LoxInterpreter};
-use std::env;
+use crate::{
+    bench::Measurement,
+    cli::{Inspect, Options},
+};
+use log::Level;
+use lox::{
+    parse, scan,
+    tools::{lint, AstPrinter, CallGraph, Repl, Tracer},
+    LoxConfig, LoxError, LoxInterpreter, LoxResult, ParseResult, ScanResult, Trace,
+};
+use std::{env, fs, path::Path, process};
 
 fn main() -> LoxResult {
-    simple_logger::init().unwrap();
-    let mut lox = LoxInterpreter::new();
-    let args: Vec<String> = env::args().collect();
-    lox.exec_file(&args[1])
+    let mut args = env::args();
+    if env::args().nth(1).as_deref() == Some("lint") {
+        return lint(args.skip(2));
+    }
+    if env::args().nth(1).as_deref() == Some("bench") {
+        return bench(args.skip(2));
+    }
+    let options = Options::parse(args.by_ref())?;
+    if options.inspect != Inspect::Nothing {
+        let source = options
+            .source()?
+            .ok_or_else(|| LoxError::General("Expected a script or -e to inspect".into()))?;
+        return inspect(options.inspect, &source);
+    }
+    let mut config = match &options.config_path {
+        Some(config_path) => LoxConfig::load(Path::new(config_path))?,
+        // Without a script, look for a config in the working directory
+        None => LoxConfig::find(options.script.as_deref().unwrap_or(""))?.unwrap_or_default(),
+    };
+    config.debug |= options.debug;
+    // Trace events are logged at the most verbose level
+    let log_level = if options.trace {
+        Some(Level::Trace)
+    } else {
+        config.log_level
+    };
+    match log_level {
+        Some(level) => simple_logger::init_with_level(level).unwrap(),
+        None => simple_logger::init().unwrap(),
+    }
+    let mut lox = LoxInterpreter::with_backend(config, options.backend);
+    lox.set_args(options.script_args.clone());
+    if let Some(replay) = &options.replay {
+        lox.replay(Trace::load(replay)?);
+    }
+    if options.record.is_some() {
+        lox.record();
+    }
+    if options.coverage {
+        lox.enable_coverage();
+    }
+    if options.hotspots.is_some() {
+        lox.enable_hotspots();
+    }
+    if options.trace {
+        lox.set_observer(Box::new(Tracer));
+    }
+    if options.timings {
+        lox.enable_timings();
+    }
+    let result = match (&options.eval, &options.script) {
+        (Some(source), _) => lox.exec(source),
+        (None, Some(path)) => lox.exec_file(path),
+        (None, None) => Repl::new(&mut lox).run(),
+    };
+    if let (Some(path), Some(trace)) = (&options.record, lox.take_trace()) {
+        trace.save(path)?;
+    }
+    if let Some(coverage) = lox.coverage() {
+        let name = match &options.eval {
+            Some(_) => "<eval>",
+            None => options.script.as_deref().unwrap_or("<repl>"),
+        };
+        print!("{}", coverage.to_lcov(name));
+    }
+    // On stderr, to keep the script's output clean
+    if let Some(timings) = lox.timings() {
+        eprintln!("{}", timings);
+    }
+    if let (Some(count), Some(hotspots)) = (options.hotspots, lox.hotspots()) {
+        eprint!("{}", hotspots.report(count));
+    }
+    if let Err(LoxError::Exit(code)) = result {
+        process::exit(code);
+    }
+    if let (Err(err), true) = (&result, options.json) {
+        eprintln!("{}", lox.diagnostic(err).to_json());
+        process::exit(1);
+    }
+    if let (Err(err), Some(source)) = (&result, options.source().ok().flatten()) {
+        eprintln!("{}", lox.render_error(err, &source));
+        process::exit(1);
+    }
+    result
+}
+
+/// Print the front end's view of `source` instead of running it.
+fn inspect(inspect: Inspect, source: &str) -> LoxResult {
+    match inspect {
+        Inspect::Tokens => {
+            let ScanResult { tokens, errors } = scan(source);
+            for token in tokens.iter() {
+                println!("{}:{} {}", token.line + 1, token.column + 1, token);
+            }
+            for err in errors {
+                eprintln!("{}", LoxError::Syntax(err));
+            }
+        }
+        Inspect::Ast | Inspect::AstJson => {
+            let ParseResult { statements, errors } = parse(source);
+            let printer = AstPrinter::new();
+            if inspect == Inspect::Ast {
+                print!("{}", printer.print(&statements));
+            } else {
+                println!("{}", printer.to_json(&statements));
+            }
+            for err in errors.iter() {
+                eprintln!("{}", err);
+            }
+        }
+        Inspect::Stats => println!("{}", parse(source).info()),
+        Inspect::Nothing => {}
+    }
+    Ok(())
+}
+
+/// `lox lint --dead-code <script>`: report problems without running the script.
+/// `--call-graph` prints the script's call graph in DOT format. With `--json`
+/// findings are printed as one diagnostic per line.
+fn lint(mut args: impl Iterator<Item = String>) -> LoxResult {
+    let mut dead_code = false;
+    let mut call_graph = false;
+    let mut json = false;
+    let mut script = None;
+    for arg in args.by_ref() {
+        match arg.as_str() {
+            "--dead-code" => dead_code = true,
+            "--call-graph" => call_graph = true,
+            "--json" => json = true,
+            _ => script = Some(arg),
+        }
+    }
+    let path = script.ok_or_else(|| LoxError::General("lint requires a script".into()))?;
+    if !dead_code && !call_graph {
+        return Err(LoxError::General(
+            "Expected a lint to run, e.g. --dead-code".into(),
+        ));
+    }
+    let program = parse(&fs::read_to_string(path)?);
+    if let Some(err) = program.errors.into_iter().next() {
+        return Err(err);
+    }
+    if call_graph {
+        println!("{}", CallGraph::build(&program.statements).to_dot());
+    }
+    if dead_code {
+        for dead in lint::dead_code(&program.statements) {
+            if json {
+                println!("{}", dead.diagnostic().to_json());
+            } else {
+                println!("{}", dead);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `lox bench [--iterations n] [script...]`: time running each script, or the
+/// builtin suite without any.
+fn bench(mut args: impl Iterator<Item = String>) -> LoxResult {
+    let mut iterations = 10;
+    let mut scripts = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--iterations" => {
+                iterations = args
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(|| {
+                        LoxError::General("Expected a number after --iterations".into())
+                    })?
+            }
+            _ => scripts.push(arg),
+        }
+    }
+    if scripts.is_empty() {
+        for (name, source) in bench::SUITE.iter() {
+            println!("{}", Measurement::run(name, source, iterations)?);
+        }
+    }
+    for path in scripts.iter() {
+        let source = fs::read_to_string(path)?;
+        println!("{}", Measurement::run(path, &source, iterations)?);
+    }
+    Ok(())
 }
@@ -1,66 +1,78 @@
-use super::{class::*, environment::*, error::*, expr::*, state::*, value::*};
+use super::{class::*, environment::*, error::*, state::*, value::*};
 use std::{cell::RefCell, rc::Rc};
 
 #[derive(PartialEq, Clone)]
 pub struct LoxObject {
     pub class_name: String,
     pub props: LoxProperties,
+    /// Whether the object is an instance of a class implemented natively.
+    pub native: bool,
+    /// Class providing the object's methods, `None` for plain prop bags.
+    pub class: Option<Rc<RefCell<LoxClass>>>,
+    /// Line of the call that created the object.
+    pub origin: Option<u32>,
+}
+
+/// Host hook consulted on every property access of native objects, allowing
+/// embedders to enforce access control or lazily materialize values.
+pub trait PropertyInterceptor {
+    /// Called before `name` is read from `object`. Returning a value skips the
+    /// normal lookup, returning an error vetoes the access.
+    fn get(&self, object: &mut LoxObject, name: &str, line: u32) -> LoxResult<Option<LoxValue>> {
+        let _ = (object, name, line);
+        Ok(None)
+    }
+
+    /// Called before `name` is written on `object`. Returning an error vetoes the write.
+    fn set(&self, object: &mut LoxObject, name: &str, value: &LoxValue, line: u32) -> LoxResult {
+        let _ = (object, name, value, line);
+        Ok(())
+    }
 }
 
 impl LoxObject {
+    /// Create an instance of `class`. Methods are not copied onto the
+    /// instance, they are looked up on the class and bound on access.
     pub fn instantiate(
         class: Rc<RefCell<LoxClass>>,
         state: &mut LoxState,
-        scope: ScopeHandle,
-        arguments: &[Expr],
+        args: Vec<LoxValue>,
         line: u32,
     ) -> LoxResult<LoxValue> {
-        let obj = Rc::new(RefCell::new(Self {
-            class_name: class.borrow().name.clone(),
-            props: LoxProperties::new(),
-        }));
-        let this_value = LoxValue::from(obj.clone());
-
-        let classes: Vec<Rc<RefCell<LoxClass>>> = {
-            let mut classes: Vec<Rc<RefCell<LoxClass>>> = vec![];
-            let mut current_class = Some(class.clone());
-            while let Some(class) = current_class {
-                classes.push(class.clone());
-                current_class = class.borrow().superclass.clone();
-            }
-            classes.into_iter().rev().collect()
-        };
+        let obj = Self::new_instance(&class, state.env.new_properties(), line);
+        if let Some(init) = Self::lookup(&obj, "init") {
+            state.initializing.push(obj.clone());
+            let result = init.get_fun(line)?.borrow().call(state, args, line);
+            state.initializing.pop();
+            result?;
+        }
+        Ok(obj.into())
+    }
 
-        {
-            let mut super_value: Option<Rc<LoxProperties>> = None;
-            for class in classes.into_iter() {
-                let mut super_methods = LoxProperties::new();
-                for (name, func) in class.borrow().methods.iter() {
-                    let mut method = func.clone();
-                    method.this_value = Some(this_value.clone());
-                    method.super_value = super_value
-                        .as_ref()
-                        .map(|value| LoxValue::from(value.clone()));
-                    let method_value = LoxValue::from(method);
-                    super_methods.insert(name.clone(), method_value.clone());
-                    obj.borrow_mut()
-                        .props
-                        .insert(name.clone(), method_value.clone());
-                }
-                super_value = Some(Rc::new(super_methods));
-            }
-        };
+    pub fn new_instance(
+        class: &Rc<RefCell<LoxClass>>,
+        props: LoxProperties,
+        line: u32,
+    ) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            class_name: class.borrow().name.clone(),
+            props,
+            native: class.borrow().is_native(),
+            class: Some(class.clone()),
+            origin: Some(line),
+        }))
+    }
 
-        let init = {
-            obj.borrow()
-                .props
-                .get("init")
-                .and_then(|init| init.get_fun(line).ok())
+    /// Read a prop of `obj`, falling back to a method of its class bound to it.
+    pub fn lookup(obj: &Rc<RefCell<Self>>, key: &str) -> Option<LoxValue> {
+        let (value, class) = {
+            let obj = obj.borrow();
+            (obj.get(key), obj.class.clone())
         };
-        if let Some(init) = init {
-            init.borrow().call(state, scope, arguments, line)?;
-        }
-        Ok(this_value)
+        value.or_else(|| {
+            let (method, owner) = LoxClass::find_method(&class?, key)?;
+            Some(LoxClass::bind_method(method, &owner, obj.clone().into()).into())
+        })
     }
 
     pub fn get(&self, key: &str) -> Option<LoxValue> {
@@ -70,4 +82,224 @@ impl LoxObject {
     pub fn set(&mut self, key: String, value: LoxValue) -> Option<LoxValue> {
         self.props.insert(key, value)
     }
+
+    /// Sorted names of the methods of the class of `obj`.
+    pub fn method_names(obj: &Rc<RefCell<Self>>) -> Vec<String> {
+        match &obj.borrow().class {
+            Some(class) => class.borrow().method_names(),
+            None => vec![],
+        }
+    }
+
+    /// Names of the fields and methods that can be looked up on `obj`.
+    pub fn property_names(obj: &Rc<RefCell<Self>>) -> Vec<String> {
+        let mut names = Self::field_names(obj);
+        names.extend(Self::method_names(obj));
+        names
+    }
+
+    /// Sorted names of the props set on `obj`.
+    pub fn field_names(obj: &Rc<RefCell<Self>>) -> Vec<String> {
+        let mut names: Vec<String> = obj
+            .borrow()
+            .props
+            .keys()
+            // Skip internal props used by native classes, e.g. `__vec__`
+            .filter(|name| !(name.starts_with("__") && name.ends_with("__")))
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{function::LoxFunction, interpreter::*};
+    use mock_logger::MockLogger;
+
+    struct ReadOnlyArrays;
+
+    impl PropertyInterceptor for ReadOnlyArrays {
+        fn get(&self, object: &mut LoxObject, name: &str, _: u32) -> LoxResult<Option<LoxValue>> {
+            if name == "size" {
+                let size = object.get("__vec__").map_or(0.0, |value| match value {
+                    LoxValue::Vec(vec) => vec.borrow().len() as f64,
+                    _ => 0.0,
+                });
+                object.set("size".into(), size.into());
+                Ok(Some(size.into()))
+            } else {
+                Ok(None)
+            }
+        }
+
+        fn set(&self, object: &mut LoxObject, name: &str, _: &LoxValue, line: u32) -> LoxResult {
+            Err(LoxError::Runtime(
+                format!("Cannot set \"{}\" on {}", name, object.class_name),
+                line,
+            ))
+        }
+    }
+
+    #[test]
+    fn interceptor() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.set_interceptor(ReadOnlyArrays);
+        lox.exec(
+            r#"
+            class Point {}
+            var point = Point();
+            point.x = 1;
+            var arr = Array();
+            arr.push(1);
+            print arr.size;
+            print point.x;
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].body, "1");
+            assert_eq!(entries[1].body, "1");
+        });
+        assert!(matches!(
+            lox.exec("arr.len = 2;"),
+            Err(LoxError::Runtime(message, _)) if message == "Cannot set \"len\" on Array"
+        ));
+        assert!(lox.exec("set_prop(arr, \"len\", 2);").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn origins() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"class Point {}
+fun make() {
+    return Point();
+}
+var point = make();
+"#,
+        )?;
+        let point = lox.get_global(lox.default_context(), "point").unwrap();
+        assert_eq!(point.origin(), Some(2));
+        let class = lox.get_global(lox.default_context(), "Point").unwrap();
+        assert_eq!(class.origin(), Some(0));
+        assert!(matches!(
+            lox.exec("point();"),
+            Err(LoxError::Runtime(message, _))
+                if message == "Cannot call a non-function, got \"Object\" (created on line 3)"
+        ));
+        assert!(matches!(
+            lox.exec("make(1);"),
+            Err(LoxError::Runtime(message, _))
+                if message == "Function \"make\" takes 0 argument(s) (defined on line 2)"
+        ));
+        let array = lox.get_global(lox.default_context(), "Array").unwrap();
+        assert_eq!(array.origin(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn super_resolves_at_access() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            class Base {}
+            class Child < Base {
+                greet() {
+                    return super.shout();
+                }
+            }
+            var child = Child();
+        "#,
+        )?;
+        assert!(lox.exec("child.greet();").is_err());
+        // Methods added to the superclass after instantiation are visible
+        let base = lox.get_global(lox.default_context(), "Base").unwrap();
+        base.get_class(0)?.borrow_mut().methods.insert(
+            "shout".into(),
+            LoxFunction::native("shout", vec![], |_, _, _| Ok("patched".into())),
+        );
+        lox.exec("print child.greet();")?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].body, "patched");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn lazy_methods() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            class Point {
+                init(x) {
+                    this.x = x;
+                }
+
+                get_x() {
+                    return this.x;
+                }
+            }
+            var point = Point(1);
+            var arr = Array();
+            arr.push(point.get_x());
+        "#,
+        )?;
+        let context = lox.default_context();
+        // Only fields are stored on instances, methods stay on the class
+        let point = lox.get_global(context, "point").unwrap().get_object(0)?;
+        assert_eq!(point.borrow().props.len(), 1);
+        let arr = lox.get_global(context, "arr").unwrap().get_object(0)?;
+        assert_eq!(arr.borrow().props.len(), 1);
+        assert!(LoxObject::lookup(&arr, "push").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn native_subclass() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            class Stack < Array {
+                init(name) {
+                    super.init();
+                    this.name = name;
+                }
+                peek() {
+                    return this.get(this.len() - 1);
+                }
+            }
+            var stack = Stack("s");
+            stack.push(1);
+            stack.push(2);
+            print stack.peek();
+            print stack.len();
+            print stack;
+            print stack.name;
+            class Counts < Map {}
+            var counts = Counts();
+            counts.set("a", 1);
+            print counts.get("a");
+            for (var x in stack) print x;
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            let lines: Vec<&str> = entries.iter().map(|entry| entry.body.as_str()).collect();
+            assert_eq!(lines, ["2", "2", "[1, 2]", "s", "1", "1", "2"]);
+        });
+        lox.exec("class Broken < Array { init() {} } var broken = Broken();")?;
+        assert!(matches!(
+            lox.exec("broken.push(1);"),
+            Err(LoxError::Runtime(message, 0))
+                if message == "Array was not initialized, call super.init() from init()"
+        ));
+        Ok(())
+    }
 }
@@ -0,0 +1,265 @@
+use crate::{environment::ScopeHandle, expr::Expr, resolver::Local, stmt::Stmt, value::LoxValue};
+#[cfg(feature = "log")]
+use log::trace;
+use std::time::Duration;
+
+/// The `log` target trace events are logged under.
+#[cfg(feature = "log")]
+pub const TRACE_TARGET: &str = "lox::trace";
+
+/// Hooks for following a script as it runs, e.g. to trace it.
+pub trait Observer {
+    /// Called before each statement other than blocks is executed.
+    fn before_stmt(&mut self, _stmt: &Stmt) {}
+    /// Called before each expression is evaluated.
+    fn before_expr(&mut self, _expr: &Expr) {}
+    /// Called when a block, loop or call creates a scope nested in `parent`.
+    fn scope_created(&mut self, _scope: ScopeHandle, _parent: ScopeHandle) {}
+    /// Called when the variable `name` is read from `scope`, with where the
+    /// resolver located it, or `None` for globals.
+    fn resolve_variable(
+        &mut self,
+        _name: &str,
+        _line: u32,
+        _scope: ScopeHandle,
+        _local: Option<Local>,
+    ) {
+    }
+    /// Called before each native function or method is invoked.
+    fn native_call(&mut self, _call: &NativeCall) {}
+}
+
+/// Renderings longer than this are cut off in traces.
+const TRACE_WIDTH: usize = 80;
+
+/// An invocation of a native function, as recorded by the audit log.
+#[derive(PartialEq, Clone, Debug)]
+pub struct NativeCall {
+    pub name: String,
+    /// The arguments rendered like `"text", 42, <instance Array>`, cut off
+    /// when long.
+    pub args: String,
+    pub line: u32,
+    /// When the call happened, since the Unix epoch, by the interpreter's
+    /// clock.
+    pub timestamp: Duration,
+}
+
+impl NativeCall {
+    pub fn new(name: &str, args: &[LoxValue], line: u32, timestamp: Duration) -> Self {
+        let args = args
+            .iter()
+            .map(|arg| match arg {
+                LoxValue::String(value) => format!("{:?}", value),
+                arg => arg.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        Self {
+            name: name.into(),
+            args: truncate(args),
+            line,
+            timestamp,
+        }
+    }
+}
+
+fn truncate(rendering: String) -> String {
+    if rendering.chars().count() > TRACE_WIDTH {
+        let truncated: String = rendering.chars().take(TRACE_WIDTH - 3).collect();
+        format!("{}...", truncated)
+    } else {
+        rendering
+    }
+}
+
+/// Logs statements, expressions, scopes and variable lookups at the trace
+/// level under `TRACE_TARGET`, as `key=value` fields. Only an observer with
+/// the `log` feature.
+pub struct Tracer;
+
+impl Tracer {
+    pub fn render(stmt: &Stmt) -> String {
+        format!("[line {}] {}", stmt.line() + 1, truncate(stmt.to_string()))
+    }
+}
+
+#[cfg(feature = "log")]
+impl Observer for Tracer {
+    fn before_stmt(&mut self, stmt: &Stmt) {
+        trace!(
+            target: TRACE_TARGET,
+            "event=stmt line={} code={:?}",
+            stmt.line(),
+            truncate(stmt.to_string())
+        );
+    }
+
+    fn before_expr(&mut self, expr: &Expr) {
+        trace!(
+            target: TRACE_TARGET,
+            "event=expr line={} column={} code={:?}",
+            expr.line(),
+            expr.column(),
+            truncate(expr.to_string())
+        );
+    }
+
+    fn scope_created(&mut self, scope: ScopeHandle, parent: ScopeHandle) {
+        trace!(
+            target: TRACE_TARGET,
+            "event=scope scope={} parent={}",
+            scope.index(),
+            parent.index()
+        );
+    }
+
+    fn resolve_variable(
+        &mut self,
+        name: &str,
+        line: u32,
+        scope: ScopeHandle,
+        local: Option<Local>,
+    ) {
+        match local {
+            Some(Local { depth, slot }) => trace!(
+                target: TRACE_TARGET,
+                "event=resolve name={} line={} scope={} depth={} slot={}",
+                name,
+                line,
+                scope.index(),
+                depth,
+                slot
+            ),
+            None => trace!(
+                target: TRACE_TARGET,
+                "event=resolve name={} line={} scope={} global=true",
+                name,
+                line,
+                scope.index()
+            ),
+        }
+    }
+
+    fn native_call(&mut self, call: &NativeCall) {
+        trace!(
+            target: TRACE_TARGET,
+            "event=native name={} line={} args={:?}",
+            call.name,
+            call.line,
+            call.args
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{error::*, interpreter::*};
+    #[cfg(feature = "log")]
+    use mock_logger::MockLogger;
+    use std::{cell::RefCell, rc::Rc};
+
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Observer for Recorder {
+        fn before_stmt(&mut self, stmt: &Stmt) {
+            self.0.borrow_mut().push(Tracer::render(stmt));
+        }
+    }
+
+    struct NativeRecorder(Rc<RefCell<Vec<String>>>);
+
+    impl Observer for NativeRecorder {
+        fn native_call(&mut self, call: &NativeCall) {
+            self.0.borrow_mut().push(call.name.clone());
+        }
+    }
+
+    #[test]
+    fn trace() -> LoxResult {
+        let lines = Rc::new(RefCell::new(vec![]));
+        let mut lox = LoxInterpreter::new();
+        lox.set_observer(Box::new(Recorder(lines.clone())));
+        lox.exec_capture("var i = 0;\nwhile (i < 2) {\n  i = i + 1;\n}")?;
+        assert_eq!(
+            *lines.borrow(),
+            vec![
+                "[line 1] (var i (literal 0))",
+                "[line 2] (while (< (identifier i) (literal 2)) (block (expr (= i (+ (identifier i) (li...",
+                "[line 3] (expr (= i (+ (identifier i) (literal 1))))",
+                "[line 3] (expr (= i (+ (identifier i) (literal 1))))",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn trace_events() -> LoxResult {
+        mock_logger::init();
+        // The mock logger stops at debug
+        log::set_max_level(log::LevelFilter::Trace);
+        let mut lox = LoxInterpreter::new();
+        lox.exec("var total = 1;")?;
+        lox.set_observer(Box::new(Tracer));
+        lox.exec("fun add(n) {\n  return total + n;\n}\nprint add(2);")?;
+        MockLogger::entries(|entries| {
+            let events: Vec<&str> = entries
+                .iter()
+                .filter(|entry| entry.level == log::Level::Trace)
+                .map(|entry| entry.body.as_str())
+                .collect();
+            assert_eq!(events[0], "event=stmt line=0 code=\"(fun add (n) ((return (+ (identifier total) (identifier n)))))\"");
+            assert!(events
+                .iter()
+                .any(|event| event.starts_with("event=scope scope=")));
+            assert!(events.contains(&"event=expr line=3 column=10 code=\"(literal 2)\""));
+            assert!(events
+                .iter()
+                .any(|event| event.starts_with("event=resolve name=n line=1 ")
+                    && event.ends_with("depth=0 slot=0")));
+            assert!(events.iter().any(|event| event
+                .starts_with("event=resolve name=total line=1 ")
+                && event.ends_with("global=true")));
+            // Only the last statement printed, nothing else goes to the output
+            assert_eq!(
+                entries
+                    .iter()
+                    .filter(|entry| entry.level == log::Level::Info)
+                    .count(),
+                1
+            );
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn audit() -> LoxResult {
+        let names = Rc::new(RefCell::new(vec![]));
+        let mut lox = LoxInterpreter::new();
+        assert!(lox.audit_log().is_none());
+        lox.enable_audit();
+        lox.set_observer(Box::new(NativeRecorder(names.clone())));
+        lox.exec_capture(
+            "fun twice(x) {\n  return x + x;\n}\nvar items = Array();\nitems.push(twice(\"ab\"));\nprint get_prop(items, \"len\")();",
+        )?;
+        let log: Vec<(String, String, u32)> = lox
+            .audit_log()
+            .unwrap()
+            .iter()
+            .map(|call| (call.name.clone(), call.args.clone(), call.line))
+            .collect();
+        assert_eq!(
+            log,
+            vec![
+                ("init".into(), "".into(), 3),
+                ("push".into(), "\"abab\"".into(), 4),
+                ("get_prop".into(), "<instance Array>, \"len\"".into(), 5),
+                ("len".into(), "".into(), 5),
+            ]
+        );
+        assert_eq!(*names.borrow(), vec!["init", "push", "get_prop", "len"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+/// Destination for the output of `print`.
+pub trait OutputSink {
+    fn write_line(&mut self, text: &str);
+
+    /// Write `text` without ending the line, for `print` when
+    /// `LoxInterpreter::set_print_newline` turned newlines off. Sinks that
+    /// only deal in whole lines, like the log, end the line anyway.
+    fn write(&mut self, text: &str) {
+        self.write_line(text);
+    }
+}
+
+/// The default sink, logging each line at the info level.
+#[cfg(feature = "log")]
+pub struct LogSink;
+
+#[cfg(feature = "log")]
+impl OutputSink for LogSink {
+    fn write_line(&mut self, text: &str) {
+        log::info!("{}", text);
+    }
+}
+
+/// Prints each line to stdout, the default without the `log` feature.
+pub struct StdoutSink;
+
+impl OutputSink for StdoutSink {
+    fn write_line(&mut self, text: &str) {
+        println!("{}", text);
+    }
+
+    fn write(&mut self, text: &str) {
+        print!("{}", text);
+        // Partial lines otherwise wait in the buffer until the next newline
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Collects output into a shared buffer the host keeps a handle to.
+impl OutputSink for Rc<RefCell<String>> {
+    fn write_line(&mut self, text: &str) {
+        let mut buffer = self.borrow_mut();
+        buffer.push_str(text);
+        buffer.push('\n');
+    }
+
+    fn write(&mut self, text: &str) {
+        self.borrow_mut().push_str(text);
+    }
+}
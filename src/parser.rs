@@ -1,10 +1,13 @@
 use crate::{
     error::*,
     expr::{Expr, ExprKind},
-    scanner::{scan, ScanResult, Token, TokenKind},
-    stmt::Stmt,
+    resolver::Locals,
+    scanner::{Literal, ScanResult, Scanner, Token, TokenKind, KEYWORDS},
+    stmt::{FunKind, Stmt},
+    suggest::closest,
+    timings::{timed, PhaseTimings},
 };
-use log::error;
+use std::{collections::HashSet, fmt};
 
 const MAX_ARGUMENTS: usize = 255;
 
@@ -13,14 +16,162 @@ pub struct ParseResult {
     pub errors: Vec<LoxError>,
 }
 
+impl ParseResult {
+    pub fn info(&self) -> ProgramInfo {
+        ProgramInfo::from_statements(&self.statements)
+    }
+}
+
+/// Size and complexity metrics of a parsed program, e.g. to reject overly
+/// complex untrusted scripts before running them.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct ProgramInfo {
+    /// Statements at any depth.
+    pub statements: usize,
+    /// Function declarations, including methods.
+    pub functions: usize,
+    pub classes: usize,
+    /// Deepest statement nesting, top level statements have a depth of 1.
+    pub max_depth: usize,
+    pub expressions: usize,
+}
+
+impl ProgramInfo {
+    pub fn from_statements(statements: &[Stmt]) -> Self {
+        let mut info = Self::default();
+        let mut ids = HashSet::new();
+        for stmt in statements.iter() {
+            info.visit(stmt, 1);
+            stmt.collect_ids(&mut ids);
+        }
+        info.expressions = ids.len();
+        info
+    }
+
+    fn visit(&mut self, stmt: &Stmt, depth: usize) {
+        self.statements += 1;
+        self.max_depth = self.max_depth.max(depth);
+        match stmt {
+            Stmt::Block { statements, .. } => {
+                for stmt in statements.iter() {
+                    self.visit(stmt, depth + 1);
+                }
+            }
+            Stmt::IfElse {
+                body, else_branch, ..
+            } => {
+                self.visit(body, depth + 1);
+                if let Some(else_branch) = else_branch {
+                    self.visit(else_branch, depth + 1);
+                }
+            }
+            Stmt::ForLoop {
+                initializer, body, ..
+            } => {
+                self.visit(initializer, depth + 1);
+                self.visit(body, depth + 1);
+            }
+            Stmt::WhileLoop { body, .. }
+            | Stmt::ForIn { body, .. }
+            | Stmt::Defer { body, .. }
+            | Stmt::Using { body, .. } => {
+                self.visit(body, depth + 1);
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.visit(body, depth + 1);
+                if let Some((_, handler)) = catch {
+                    self.visit(handler, depth + 1);
+                }
+                if let Some(finally) = finally {
+                    self.visit(finally, depth + 1);
+                }
+            }
+            Stmt::Fun { body, .. } => {
+                self.functions += 1;
+                for stmt in body.iter() {
+                    self.visit(stmt, depth + 1);
+                }
+            }
+            Stmt::Class { methods, .. } => {
+                self.classes += 1;
+                for method in methods.iter() {
+                    self.visit(method, depth + 1);
+                }
+            }
+            Stmt::Expr(_)
+            | Stmt::Print(_)
+            | Stmt::Var { .. }
+            | Stmt::Destructure { .. }
+            | Stmt::Return(_)
+            | Stmt::Throw { .. } => {}
+        }
+    }
+}
+
+impl fmt::Display for ProgramInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "statements: {}", self.statements)?;
+        writeln!(f, "functions: {}", self.functions)?;
+        writeln!(f, "classes: {}", self.classes)?;
+        writeln!(f, "max depth: {}", self.max_depth)?;
+        write!(f, "expressions: {}", self.expressions)
+    }
+}
+
+/// Bounds on the size of a program, checked while scanning and parsing so
+/// adversarial input is rejected before it can use much memory. Every limit
+/// is off when `None`.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct ParseLimits {
+    pub max_source_bytes: Option<usize>,
+    pub max_tokens: Option<usize>,
+    /// Statements at any depth.
+    pub max_statements: Option<usize>,
+    /// Deepest nesting of statements and expressions.
+    pub max_depth: Option<usize>,
+}
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    /// Source lines, used to quote code in hints.
+    lines: Vec<String>,
+    /// Index of the first token of the statement being parsed.
+    statement_start: usize,
+    limits: ParseLimits,
+    /// Statements parsed so far and current nesting, checked against `limits`.
+    statement_count: usize,
+    depth: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            lines: vec![],
+            statement_start: 0,
+            limits: ParseLimits::default(),
+            statement_count: 0,
+            depth: 0,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_source(tokens: Vec<Token>, source: &str) -> Self {
+        Self {
+            lines: source.lines().map(String::from).collect(),
+            ..Self::new(tokens)
+        }
     }
 
     pub fn parse(&mut self) -> ParseResult {
@@ -32,6 +183,10 @@ impl Parser {
                     Ok(stmt) => {
                         statements.push(stmt);
                     }
+                    Err(err @ LoxError::LimitExceeded(_)) => {
+                        errors.push(err);
+                        break;
+                    }
                     Err(err) => {
                         errors.push(err);
                         self.synchronize();
@@ -46,12 +201,19 @@ impl Parser {
      * Statements
      */
     fn declaration(&mut self) -> LoxResult<Stmt> {
+        self.statement_start = self.current;
         if self.match_tokens(&[TokenKind::Class]) {
-            self.class()
+            self.count_statement()?;
+            self.nested(Self::class)
         } else if self.match_tokens(&[TokenKind::Fun]) {
-            self.function()
+            self.count_statement()?;
+            self.nested(Self::function)
         } else if self.match_tokens(&[TokenKind::Var]) {
+            self.count_statement()?;
             self.var_declaration()
+        } else if self.match_tokens(&[TokenKind::Const]) {
+            self.count_statement()?;
+            self.const_declaration()
         } else {
             self.statement()
         }
@@ -75,7 +237,7 @@ impl Parser {
         self.consume(TokenKind::LeftBrace, "Expected opening brace")?;
         let mut methods: Vec<Stmt> = vec![];
         while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
-            methods.push(self.function()?);
+            methods.push(self.method()?);
         }
         self.consume(TokenKind::RightBrace, "Expected closing brace")?;
         Ok(Stmt::Class {
@@ -86,54 +248,173 @@ impl Parser {
     }
 
     fn function(&mut self) -> LoxResult<Stmt> {
+        self.function_of_kind(FunKind::Function)
+    }
+
+    /// `static NAME = value;` in a class body, after `static`.
+    fn static_field(&mut self) -> LoxResult<Stmt> {
+        let name = self
+            .consume(TokenKind::Identifier, "Expected identifier")?
+            .clone();
+        self.consume(
+            TokenKind::Equal,
+            "Expected \"=\" after the static field name",
+        )?;
+        let initializer = self.expression()?;
+        self.consume(TokenKind::Semicolon, "Expected a semicolon")?;
+        Ok(Stmt::Var {
+            name,
+            initializer: Some(Box::new(initializer)),
+            mutable: false,
+        })
+    }
+
+    /// A method, getter, setter, static method or static field. `get`, `set`
+    /// and `static` are only keywords in front of another identifier, so
+    /// methods can still be named after them.
+    fn method(&mut self) -> LoxResult<Stmt> {
+        if self.peek().lexeme_str() == "static"
+            && self.check_ahead(1, TokenKind::Identifier)
+            && self.check_ahead(2, TokenKind::Equal)
+        {
+            self.advance();
+            return self.static_field();
+        }
+        let kind = match self.peek().lexeme_str().as_str() {
+            "get" if self.check_ahead(1, TokenKind::Identifier) => FunKind::Getter,
+            "set" if self.check_ahead(1, TokenKind::Identifier) => FunKind::Setter,
+            "static" if self.check_ahead(1, TokenKind::Identifier) => FunKind::Static,
+            _ => return self.function(),
+        };
+        self.advance();
+        let method = self.function_of_kind(kind)?;
+        if let Stmt::Fun {
+            name, params, rest, ..
+        } = &method
+        {
+            match (kind, params.len()) {
+                (FunKind::Getter, 0) | (FunKind::Static, _) => {}
+                (FunKind::Setter, 1) if !rest => {}
+                (FunKind::Getter, _) => {
+                    return Err(self.syntax_error("Getters take no parameters", name))
+                }
+                _ => return Err(self.syntax_error("Setters take one parameter", name)),
+            }
+        }
+        Ok(method)
+    }
+
+    fn function_of_kind(&mut self, kind: FunKind) -> LoxResult<Stmt> {
         let name = self
             .consume(TokenKind::Identifier, "Expected identifier")?
             .clone();
         self.consume(TokenKind::LeftParen, "Expected opening parenthesis")?;
-        let params: Vec<Token> = self.fun_parameters()?;
+        let (params, rest) = self.fun_parameters()?;
         self.consume(TokenKind::RightParen, "Expected closing parenthesis")?;
         self.consume(TokenKind::LeftBrace, "Expected opening brace")?;
         let mut body: Vec<Stmt> = vec![];
         while !self.match_tokens(&[TokenKind::RightBrace]) && !self.is_at_end() {
             body.push(self.declaration()?);
         }
-        Ok(Stmt::Fun { name, params, body })
+        Ok(Stmt::Fun {
+            name,
+            params,
+            rest,
+            body,
+            kind,
+        })
     }
 
-    fn fun_parameters(&mut self) -> LoxResult<Vec<Token>> {
-        if self.match_tokens(&[TokenKind::Identifier]) {
-            let mut params = vec![self.previous().clone()];
-            while self.match_tokens(&[TokenKind::Identifier]) {
-                self.consume(TokenKind::Comma, "Expected comma")?;
-                params.push(self.previous().clone());
+    /// Comma separated parameter names, the last of which may be a rest
+    /// parameter like `...rest`, optionally followed by a trailing comma.
+    /// Returns the names and whether the last is a rest parameter.
+    fn fun_parameters(&mut self) -> LoxResult<(Vec<Token>, bool)> {
+        let mut params = vec![];
+        let mut rest = false;
+        while !self.check(TokenKind::RightParen) {
+            if rest {
+                return Err(self.syntax_error(
+                    "A rest parameter must be the last parameter",
+                    self.previous(),
+                ));
+            }
+            if params.len() == MAX_ARGUMENTS {
+                return Err(self.syntax_error(
+                    &format!("Can't have more than {} parameters", MAX_ARGUMENTS),
+                    self.peek_or_previous(),
+                ));
+            }
+            rest = self.match_tokens(&[TokenKind::DotDotDot]);
+            params.push(
+                self.consume(TokenKind::Identifier, "Expected parameter name")?
+                    .clone(),
+            );
+            if !self.match_tokens(&[TokenKind::Comma]) {
+                break;
             }
-            Ok(params)
-        } else {
-            Ok(vec![])
         }
+        Ok((params, rest))
     }
 
     fn var_declaration(&mut self) -> LoxResult<Stmt> {
         let identifier = self
             .consume(TokenKind::Identifier, "Expected identifier")?
             .clone();
+        if self.check(TokenKind::Comma) {
+            let mut names = vec![identifier];
+            while self.match_tokens(&[TokenKind::Comma]) {
+                names.push(
+                    self.consume(TokenKind::Identifier, "Expected identifier")?
+                        .clone(),
+                );
+            }
+            self.consume(TokenKind::Equal, "Expected \"=\" to unpack into variables")?;
+            let initializer = Box::new(self.expression()?);
+            self.consume(TokenKind::Semicolon, "Expected a semicolon")?;
+            return Ok(Stmt::Destructure { names, initializer });
+        }
         let var = if self.match_tokens(&[TokenKind::Equal]) {
             let expr = self.expression()?;
             Stmt::Var {
                 name: identifier,
                 initializer: Some(Box::new(expr)),
+                mutable: true,
             }
         } else {
             Stmt::Var {
                 name: identifier,
                 initializer: None,
+                mutable: true,
             }
         };
         self.consume(TokenKind::Semicolon, "Expected a semicolon")?;
         Ok(var)
     }
 
+    /// A variable that can't be assigned to, so it needs a value up front.
+    fn const_declaration(&mut self) -> LoxResult<Stmt> {
+        let name = self
+            .consume(TokenKind::Identifier, "Expected identifier")?
+            .clone();
+        self.consume(
+            TokenKind::Equal,
+            "Expected \"=\" and a value for the constant",
+        )?;
+        let initializer = self.expression()?;
+        self.consume(TokenKind::Semicolon, "Expected a semicolon")?;
+        Ok(Stmt::Var {
+            name,
+            initializer: Some(Box::new(initializer)),
+            mutable: false,
+        })
+    }
+
     fn statement(&mut self) -> LoxResult<Stmt> {
+        self.count_statement()?;
+        self.nested(Self::statement_kind)
+    }
+
+    fn statement_kind(&mut self) -> LoxResult<Stmt> {
         if self.match_tokens(&[TokenKind::For]) {
             self.for_statement()
         } else if self.match_tokens(&[TokenKind::If]) {
@@ -144,21 +425,106 @@ impl Parser {
             self.return_statement()
         } else if self.match_tokens(&[TokenKind::While]) {
             self.while_statement()
+        } else if self.match_tokens(&[TokenKind::Throw]) {
+            self.throw_statement()
+        } else if self.match_tokens(&[TokenKind::Try]) {
+            self.try_statement()
         } else if self.match_tokens(&[TokenKind::LeftBrace]) {
             self.block()
+        } else if self.peek().lexeme_str() == "defer" && self.check_ahead(1, TokenKind::LeftBrace) {
+            self.defer_statement()
+        } else if self.peek().lexeme_str() == "using"
+            && self.check_ahead(1, TokenKind::LeftParen)
+            && self.check_ahead(2, TokenKind::Var)
+        {
+            self.using_statement()
         } else {
             self.expression_statement()
         }
     }
 
+    /// `defer` is only a keyword in front of a block, so it can still be used
+    /// as a name.
+    fn defer_statement(&mut self) -> LoxResult<Stmt> {
+        let keyword = self.advance().clone();
+        self.consume(TokenKind::LeftBrace, "Expected opening brace")?;
+        let body = self.block()?;
+        Ok(Stmt::Defer {
+            keyword,
+            body: Box::new(body),
+        })
+    }
+
+    fn throw_statement(&mut self) -> LoxResult<Stmt> {
+        let keyword = self.previous().clone();
+        let value = self.expression()?;
+        self.consume(TokenKind::Semicolon, "Expected a semicolon")?;
+        Ok(Stmt::Throw {
+            keyword,
+            value: Box::new(value),
+        })
+    }
+
+    fn try_statement(&mut self) -> LoxResult<Stmt> {
+        let keyword = self.previous().clone();
+        self.consume(TokenKind::LeftBrace, "Expected opening brace")?;
+        let body = self.block()?;
+        let catch = if self.match_tokens(&[TokenKind::Catch]) {
+            self.consume(TokenKind::LeftParen, "Expected opening parenthesis")?;
+            let name = self
+                .consume(
+                    TokenKind::Identifier,
+                    "Expected a name for the caught value",
+                )?
+                .clone();
+            self.consume(TokenKind::RightParen, "Expected closing parenthesis")?;
+            self.consume(TokenKind::LeftBrace, "Expected opening brace")?;
+            Some((name, Box::new(self.block()?)))
+        } else {
+            None
+        };
+        let finally = if self.match_tokens(&[TokenKind::Finally]) {
+            self.consume(TokenKind::LeftBrace, "Expected opening brace")?;
+            Some(Box::new(self.block()?))
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(self.syntax_error(
+                "Expected \"catch\" or \"finally\" after a try block",
+                self.peek_or_previous(),
+            ));
+        }
+        Ok(Stmt::Try {
+            keyword,
+            body: Box::new(body),
+            catch,
+            finally,
+        })
+    }
+
     fn expression_statement(&mut self) -> LoxResult<Stmt> {
         let expr = self.expression()?;
+        if let ExprKind::Binary { operator, .. } = &expr.kind {
+            if operator.kind == TokenKind::EqualEqual {
+                warn!(
+                    "Line {}: comparison result is unused, did you mean \"=\"?",
+                    expr.line()
+                );
+            }
+        }
         self.consume(TokenKind::Semicolon, "Expected a semicolon")?;
         Ok(Stmt::Expr(Box::new(expr)))
     }
 
     fn for_statement(&mut self) -> LoxResult<Stmt> {
         self.consume(TokenKind::LeftParen, "Expected opening parenthesis")?;
+        if self.check(TokenKind::Var)
+            && self.check_ahead(1, TokenKind::Identifier)
+            && self.check_ahead(2, TokenKind::In)
+        {
+            return self.for_in_statement();
+        }
         let initializer = if self.match_tokens(&[TokenKind::Var]) {
             self.var_declaration()?
         } else {
@@ -169,18 +535,34 @@ impl Parser {
         let iterator = self.expression()?;
         self.consume(TokenKind::RightParen, "Expected closing parenthesis")?;
         let body = self.statement()?;
-        Ok(Stmt::Block(vec![
-            initializer,
-            Stmt::WhileLoop {
-                condition: Box::new(condition),
-                body: Box::new(Stmt::Block(vec![body, Stmt::Expr(Box::new(iterator))])),
-            },
-        ]))
+        Ok(Stmt::ForLoop {
+            initializer: Box::new(initializer),
+            condition: Box::new(condition),
+            increment: Box::new(iterator),
+            body: Box::new(body),
+        })
+    }
+
+    fn for_in_statement(&mut self) -> LoxResult<Stmt> {
+        self.consume(TokenKind::Var, "Expected var")?;
+        let name = self
+            .consume(TokenKind::Identifier, "Expected an identifier")?
+            .clone();
+        self.consume(TokenKind::In, "Expected in")?;
+        let iterable = Box::new(self.expression()?);
+        self.consume(TokenKind::RightParen, "Expected closing parenthesis")?;
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::ForIn {
+            name,
+            iterable,
+            body,
+        })
     }
 
     fn if_statement(&mut self) -> LoxResult<Stmt> {
         self.consume(TokenKind::LeftParen, "Expected opening parenthesis")?;
         let condition = Box::new(self.expression()?);
+        self.lint_condition(&condition);
         self.consume(TokenKind::RightParen, "Expected closing parenthesis")?;
         let body = Box::new(self.statement()?);
         if self.match_tokens(&[TokenKind::Else]) {
@@ -215,7 +597,16 @@ impl Parser {
             ))
             .into()
         } else {
-            self.expression()?
+            let value = self.expression()?;
+            if self.check(TokenKind::Comma) {
+                let mut values = vec![value];
+                while self.match_tokens(&[TokenKind::Comma]) {
+                    values.push(self.expression()?);
+                }
+                ExprKind::Tuple(values).into()
+            } else {
+                value
+            }
         };
         self.consume(TokenKind::Semicolon, "Expected a semicolon")?;
         Ok(Stmt::Return(Box::new(value)))
@@ -224,18 +615,41 @@ impl Parser {
     fn while_statement(&mut self) -> LoxResult<Stmt> {
         self.consume(TokenKind::LeftParen, "Expected opening parenthesis")?;
         let condition = Box::new(self.expression()?);
+        self.lint_condition(&condition);
         self.consume(TokenKind::RightParen, "Expected closing parenthesis")?;
         let body = Box::new(self.statement()?);
         Ok(Stmt::WhileLoop { condition, body })
     }
 
+    /// Like `defer`, `using` is only a keyword in front of a declaration in
+    /// parentheses, since calls can't take a `var` argument.
+    fn using_statement(&mut self) -> LoxResult<Stmt> {
+        let keyword = self.advance().clone();
+        self.consume(TokenKind::LeftParen, "Expected opening parenthesis")?;
+        self.consume(TokenKind::Var, "Expected var")?;
+        let name = self
+            .consume(TokenKind::Identifier, "Expected identifier")?
+            .clone();
+        self.consume(TokenKind::Equal, "Expected =")?;
+        let initializer = self.expression()?;
+        self.consume(TokenKind::RightParen, "Expected closing parenthesis")?;
+        let body = self.statement()?;
+        Ok(Stmt::Using {
+            keyword,
+            name,
+            initializer: Box::new(initializer),
+            body: Box::new(body),
+        })
+    }
+
     fn block(&mut self) -> LoxResult<Stmt> {
+        let brace = self.previous().clone();
         let mut statements: Vec<Stmt> = vec![];
         while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
             statements.push(self.declaration()?);
         }
         self.consume(TokenKind::RightBrace, "Expected closing brace")?;
-        Ok(Stmt::Block(statements))
+        Ok(Stmt::Block { brace, statements })
     }
 
     /**
@@ -243,36 +657,24 @@ impl Parser {
      */
 
     fn expression(&mut self) -> LoxResult<Expr> {
-        self.assignment()
+        self.nested(Self::assignment)
     }
 
     fn assignment(&mut self) -> LoxResult<Expr> {
-        let mut left = self.logic_or()?;
+        let left = self.logic_or()?;
         if self.match_tokens(&[TokenKind::Equal]) {
-            if let ExprKind::Identifier(name) = left.kind {
-                let right = self.assignment()?;
-                left = ExprKind::Assignment {
-                    name,
-                    value: Box::new(right),
-                }
-                .into();
-            } else if let ExprKind::Get {
-                left: object,
-                right: identifier,
-            } = left.kind
-            {
-                left = ExprKind::Set {
-                    object,
-                    identifier,
-                    value: Box::new(self.assignment()?),
-                }
-                .into();
-            } else {
-                return Err(LoxError::Runtime(
-                    "Invalid assignment target".into(),
-                    left.line(),
-                ));
-            }
+            let value = self.assignment()?;
+            return assign(left, value);
+        }
+        if self.match_tokens(&[
+            TokenKind::PlusEqual,
+            TokenKind::MinusEqual,
+            TokenKind::StarEqual,
+            TokenKind::SlashEqual,
+        ]) {
+            let operator = binary_operator(self.previous());
+            let right = self.assignment()?;
+            return compound_assign(left, operator, right);
         }
         Ok(left)
     }
@@ -375,14 +777,35 @@ impl Parser {
     fn unary(&mut self) -> LoxResult<Expr> {
         if self.match_tokens(&[TokenKind::Bang, TokenKind::Minus]) {
             let operator = self.previous().clone();
-            let right = self.unary()?;
+            let right = self.nested(Self::unary)?;
             Ok(ExprKind::Unary {
                 operator,
                 right: Box::new(right),
             }
             .into())
+        } else if self.match_tokens(&[TokenKind::PlusPlus, TokenKind::MinusMinus]) {
+            // `++a` is `a = a + 1`
+            let operator = binary_operator(self.previous());
+            let target = self.nested(Self::unary)?;
+            increment(target, operator)
         } else {
-            self.call()
+            let left = self.call()?;
+            if self.match_tokens(&[TokenKind::PlusPlus, TokenKind::MinusMinus]) {
+                // `a++` is `(a = a + 1) - 1`, evaluating to the previous value
+                let operator = binary_operator(self.previous());
+                let undo = match operator.kind {
+                    TokenKind::Plus => operator_token(TokenKind::Minus, "-", &operator),
+                    _ => operator_token(TokenKind::Plus, "+", &operator),
+                };
+                let one = number_literal(&operator);
+                return Ok(ExprKind::Binary {
+                    operator: undo,
+                    left: Box::new(increment(left, operator)?),
+                    right: Box::new(one),
+                }
+                .into());
+            }
+            Ok(left)
         }
     }
 
@@ -391,21 +814,19 @@ impl Parser {
         loop {
             if self.match_tokens(&[TokenKind::LeftParen]) {
                 let mut arguments: Vec<Expr> = vec![];
-                if !self.match_tokens(&[TokenKind::RightParen]) {
-                    loop {
-                        arguments.push(self.expression()?);
-                        if arguments.len() > MAX_ARGUMENTS {
-                            return Err(LoxError::Runtime(
-                                "Exceeded maximum number of arguments".into(),
-                                self.previous().line,
-                            ));
-                        }
-                        if !self.match_tokens(&[TokenKind::Comma]) {
-                            break;
-                        }
+                while !self.check(TokenKind::RightParen) {
+                    if arguments.len() == MAX_ARGUMENTS {
+                        return Err(self.syntax_error(
+                            &format!("Can't have more than {} arguments", MAX_ARGUMENTS),
+                            self.peek_or_previous(),
+                        ));
+                    }
+                    arguments.push(self.expression()?);
+                    if !self.match_tokens(&[TokenKind::Comma]) {
+                        break;
                     }
-                    self.consume(TokenKind::RightParen, "Expected closing parenthesis")?;
                 }
+                self.consume(TokenKind::RightParen, "Expected closing parenthesis")?;
                 left = ExprKind::Call {
                     callee: Box::new(left),
                     arguments,
@@ -416,6 +837,7 @@ impl Parser {
                     self.consume(TokenKind::Identifier, "Expected identifier after \".\"")?;
                 left = ExprKind::Get {
                     left: Box::new(left),
+                    name: identifier.lexeme_str().into(),
                     right: identifier.clone(),
                 }
                 .into();
@@ -451,7 +873,7 @@ impl Parser {
             self.consume(TokenKind::RightParen, "Expected closing ')'")?;
             Ok(ExprKind::Grouping(Box::new(expr)).into())
         } else {
-            Err(self.syntax_error("Expected expression", self.peek().line))
+            Err(self.syntax_error("Expected expression", self.peek()))
         }
     }
 
@@ -477,6 +899,12 @@ impl Parser {
         }
     }
 
+    fn check_ahead(&self, offset: usize, kind: TokenKind) -> bool {
+        self.tokens
+            .get(self.current + offset)
+            .is_some_and(|token| token.kind == kind)
+    }
+
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -496,18 +924,143 @@ impl Parser {
         self.current >= self.tokens.len()
     }
 
-    fn syntax_error(&self, message: &str, line: u32) -> LoxError {
-        LoxError::Syntax(SyntaxError::new(message.into(), line))
+    fn syntax_error(&self, message: &str, token: &Token) -> LoxError {
+        LoxError::Syntax(SyntaxError::new(message.into(), token.line).with_column(token.column))
     }
 
     fn consume(&mut self, kind: TokenKind, err_msg: &str) -> LoxResult<&Token> {
         if self.check(kind) {
             Ok(self.advance())
         } else {
-            Err(self.syntax_error(err_msg, self.peek().line))
+            let token = self.peek_or_previous();
+            Err(LoxError::Syntax(
+                SyntaxError::new(err_msg.into(), token.line)
+                    .with_column(token.column)
+                    .with_hint(self.suggest(kind)),
+            ))
+        }
+    }
+
+    /// The next token, or the last one at the end of input.
+    fn peek_or_previous(&self) -> &Token {
+        if self.is_at_end() {
+            self.previous()
+        } else {
+            self.peek()
+        }
+    }
+
+    /// Suggest a fix for a common mistake, based on the token that was
+    /// expected, the one that was found and the one before it.
+    fn suggest(&self, expected: TokenKind) -> Option<String> {
+        if let Some(keyword) = self.keyword_typo() {
+            return Some(format!("Did you mean \"{}\"?", keyword));
+        }
+        let found = self.peek_or_previous();
+        let previous = self.previous();
+        match (expected, found.kind) {
+            (TokenKind::Semicolon, TokenKind::EqualEqual) => {
+                Some("Use \"=\" to assign a value, \"==\" compares values".into())
+            }
+            (TokenKind::Semicolon, TokenKind::LeftBrace) if previous.lexeme_str() == "then" => {
+                Some("Lox has no \"then\", put the body in braces right after the condition".into())
+            }
+            (TokenKind::Semicolon, _) if found.line > previous.line || self.is_at_end() => {
+                let code = self
+                    .lines
+                    .get(previous.line as usize)
+                    .map(|line| format!(": {}", line.trim()))
+                    .unwrap_or_default();
+                Some(format!(
+                    "Add a \";\" at the end of line {}{}",
+                    previous.line + 1,
+                    code
+                ))
+            }
+            (TokenKind::LeftParen, _)
+                if matches!(
+                    previous.kind,
+                    TokenKind::If | TokenKind::While | TokenKind::For
+                ) =>
+            {
+                Some(format!(
+                    "Wrap the condition in parentheses, e.g. \"{} (condition) {{ ... }}\"",
+                    previous.lexeme_str()
+                ))
+            }
+            (TokenKind::LeftBrace, _) => {
+                Some("Bodies of functions and classes must be wrapped in braces".into())
+            }
+            _ => None,
+        }
+    }
+
+    /// The keyword a statement starting with a misspelled one was likely meant
+    /// to start with, e.g. `retrun x;`.
+    fn keyword_typo(&self) -> Option<&'static str> {
+        let token = self.tokens.get(self.statement_start)?;
+        if token.kind != TokenKind::Identifier {
+            return None;
+        }
+        let name = token.lexeme_str();
+        // Keywords from other languages, mapped to their Lox equivalent
+        let aliases = [
+            ("function", "fun"),
+            ("func", "fun"),
+            ("def", "fun"),
+            ("let", "var"),
+        ];
+        if let Some((_, keyword)) = aliases.iter().find(|(alias, _)| *alias == name) {
+            return Some(keyword);
+        }
+        let candidates = KEYWORDS
+            .iter()
+            .map(|(keyword, _)| keyword)
+            .chain(aliases.iter().map(|(alias, _)| alias));
+        let closest = closest(&name, candidates.copied())?;
+        Some(
+            aliases
+                .iter()
+                .find(|(alias, _)| *alias == closest)
+                .map_or(closest, |(_, keyword)| keyword),
+        )
+    }
+
+    /// Warn about code that parses but is likely a mistake.
+    fn lint_condition(&self, condition: &Expr) {
+        if let ExprKind::Assignment { .. } = condition.kind {
+            warn!(
+                "Line {}: assignment used as a condition, did you mean \"==\"?",
+                condition.line()
+            );
+        }
+    }
+
+    fn count_statement(&mut self) -> LoxResult {
+        self.statement_count += 1;
+        match self.limits.max_statements {
+            Some(max) if self.statement_count > max => Err(LoxError::LimitExceeded(format!(
+                "Program has more than {} statements",
+                max
+            ))),
+            _ => Ok(()),
         }
     }
 
+    /// Run `parse` one level deeper, failing if that goes over the depth limit.
+    fn nested<T>(&mut self, parse: fn(&mut Self) -> LoxResult<T>) -> LoxResult<T> {
+        self.depth += 1;
+        let result = match self.limits.max_depth {
+            Some(max) if self.depth > max => Err(LoxError::LimitExceeded(format!(
+                "Program is nested more than {} levels deep",
+                max
+            ))),
+            _ => parse(self),
+        };
+        self.depth -= 1;
+        result
+    }
+
     fn synchronize(&mut self) {
         self.advance();
         while !self.is_at_end() {
@@ -517,11 +1070,14 @@ impl Parser {
                     TokenKind::Class
                         | TokenKind::Fun
                         | TokenKind::Var
+                        | TokenKind::Const
                         | TokenKind::For
                         | TokenKind::If
                         | TokenKind::While
                         | TokenKind::Print
                         | TokenKind::Return
+                        | TokenKind::Throw
+                        | TokenKind::Try
                 )
             {
                 return;
@@ -532,18 +1088,299 @@ impl Parser {
 }
 
 pub fn parse(source: &str) -> ParseResult {
-    let ScanResult { tokens, errors } = scan(source);
-    for err in errors {
-        error!("Scan Error: {}", err);
+    parse_with_limits(source, ParseLimits::default())
+}
+
+/// Parse untrusted `source`, stopping at the first limit exceeded.
+pub fn parse_with_limits(source: &str, limits: ParseLimits) -> ParseResult {
+    parse_timed(source, limits, &mut PhaseTimings::default())
+}
+
+/// Like `parse_with_limits`, adding the time spent scanning and parsing to
+/// `timings`.
+pub fn parse_timed(source: &str, limits: ParseLimits, timings: &mut PhaseTimings) -> ParseResult {
+    let limit_exceeded = |err| ParseResult {
+        statements: vec![],
+        errors: vec![err],
+    };
+    if let Some(max) = limits.max_source_bytes {
+        if source.len() > max {
+            return limit_exceeded(LoxError::LimitExceeded(format!(
+                "Source is longer than {} bytes",
+                max
+            )));
+        }
     }
-    let mut parser = Parser::new(tokens);
-    parser.parse()
+    let mut scanner = Scanner::new(source);
+    let scanned = timed(&mut timings.scan, || match limits.max_tokens {
+        Some(max) => scanner.scan_limited(max),
+        None => Ok(scanner.scan()),
+    });
+    let ScanResult { tokens, errors } = match scanned {
+        Ok(result) => result,
+        Err(err) => return limit_exceeded(err),
+    };
+    let mut parser = Parser::with_source(tokens, source).with_limits(limits);
+    let mut result = timed(&mut timings.parse, || parser.parse());
+    // The parser only sees what was scanned, so its errors may follow from
+    // these
+    result
+        .errors
+        .splice(0..0, errors.into_iter().map(LoxError::Syntax));
+    result
+}
+
+/// Assign `value` to `target`, which must be a variable or a property.
+fn assign(target: Expr, value: Expr) -> LoxResult<Expr> {
+    match target.kind {
+        ExprKind::Identifier(name) => Ok(ExprKind::Assignment {
+            name,
+            value: Box::new(value),
+        }
+        .into()),
+        ExprKind::Get {
+            left: object,
+            right: identifier,
+            name,
+        } => Ok(ExprKind::Set {
+            object,
+            identifier,
+            name,
+            operator: None,
+            value: Box::new(value),
+        }
+        .into()),
+        _ => Err(LoxError::Runtime(
+            "Invalid assignment target".into(),
+            target.line(),
+        )),
+    }
+}
+
+/// `target = target <operator> value`. A variable is read again, while the
+/// object of a property is evaluated only once by a compound Set.
+fn compound_assign(target: Expr, operator: Token, value: Expr) -> LoxResult<Expr> {
+    match target.kind {
+        ExprKind::Identifier(_) => {
+            let value = ExprKind::Binary {
+                left: Box::new(target.duplicate(&mut Locals::new())),
+                operator,
+                right: Box::new(value),
+            }
+            .into();
+            assign(target, value)
+        }
+        ExprKind::Get {
+            left: object,
+            right: identifier,
+            name,
+        } => Ok(ExprKind::Set {
+            object,
+            identifier,
+            name,
+            operator: Some(operator),
+            value: Box::new(value),
+        }
+        .into()),
+        _ => Err(LoxError::Runtime(
+            "Invalid assignment target".into(),
+            target.line(),
+        )),
+    }
+}
+
+/// `target += 1`, or `-= 1` for a decrement `operator`.
+fn increment(target: Expr, operator: Token) -> LoxResult<Expr> {
+    let one = number_literal(&operator);
+    compound_assign(target, operator, one)
+}
+
+/// The binary operator applied by a compound assignment, increment or
+/// decrement token.
+fn binary_operator(token: &Token) -> Token {
+    match token.kind {
+        TokenKind::PlusEqual | TokenKind::PlusPlus => operator_token(TokenKind::Plus, "+", token),
+        TokenKind::MinusEqual | TokenKind::MinusMinus => {
+            operator_token(TokenKind::Minus, "-", token)
+        }
+        TokenKind::StarEqual => operator_token(TokenKind::Star, "*", token),
+        TokenKind::SlashEqual => operator_token(TokenKind::Slash, "/", token),
+        kind => unreachable!("Expected a compound operator, got {:?}", kind),
+    }
+}
+
+/// A token that isn't in the source, positioned at `at`.
+fn operator_token(kind: TokenKind, lexeme: &str, at: &Token) -> Token {
+    Token::new(kind, Some(lexeme.into()), None, at.line).at_column(at.column)
+}
+
+fn number_literal(at: &Token) -> Expr {
+    ExprKind::Literal(
+        Token::new(
+            TokenKind::Number,
+            Some("1".into()),
+            Some(Literal::Number(1.0)),
+            at.line,
+        )
+        .at_column(at.column),
+    )
+    .into()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::test_scripts::*;
+    use mock_logger::MockLogger;
+
+    fn first_hint(source: &str) -> Option<String> {
+        match &parse(source).errors[0] {
+            LoxError::Syntax(err) => err.hint().map(String::from),
+            _ => panic!("Expected a syntax error"),
+        }
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(
+            first_hint("var x = 1\nprint x;"),
+            Some("Add a \";\" at the end of line 1: var x = 1".into())
+        );
+        assert!(first_hint("var x == 1;").unwrap().contains("Use \"=\""));
+        assert!(first_hint("if x { print x; }")
+            .unwrap()
+            .contains("\"if (condition) { ... }\""));
+        assert!(first_hint("if (x) then { print x; }")
+            .unwrap()
+            .contains("no \"then\""));
+        assert!(first_hint("fun foo() print 1;").unwrap().contains("braces"));
+        assert_eq!(first_hint("print (1;"), None);
+    }
+
+    #[test]
+    fn keyword_typos() {
+        let did_you_mean = |keyword: &str| Some(format!("Did you mean \"{}\"?", keyword));
+        assert_eq!(first_hint("retrun x;"), did_you_mean("return"));
+        assert_eq!(
+            first_hint("whle (true) { print 1; }"),
+            did_you_mean("while")
+        );
+        assert_eq!(first_hint("funtion foo() {}"), did_you_mean("fun"));
+        assert_eq!(first_hint("let x = 1;"), did_you_mean("var"));
+        assert!(!first_hint("counter x;").is_some_and(|hint| hint.starts_with("Did you mean")));
+    }
+
+    #[test]
+    fn lints() {
+        mock_logger::init();
+        parse("var x; if (x = 1) print x; x == 2;");
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
+            assert!(entries[0].body.contains("did you mean \"==\""));
+            assert!(entries[1].body.contains("did you mean \"=\""));
+        });
+    }
+
+    #[test]
+    fn limits() {
+        let exceeded = |source: &str, limits: ParseLimits| {
+            let ParseResult { errors, .. } = parse_with_limits(source, limits);
+            match errors.last() {
+                Some(LoxError::LimitExceeded(message)) => Some(message.clone()),
+                _ => None,
+            }
+        };
+        let limits = ParseLimits {
+            max_source_bytes: Some(64),
+            max_tokens: Some(16),
+            max_statements: Some(4),
+            max_depth: Some(4),
+        };
+        assert_eq!(exceeded("var a = 1; { print a; }", limits), None);
+        assert_eq!(
+            exceeded(&"1;".repeat(40), limits),
+            Some("Source is longer than 64 bytes".into())
+        );
+        assert_eq!(
+            exceeded("print 1 + 2 + 3 + 4 + 5 + 6 + 7 + 8;", limits),
+            Some("Source has more than 16 tokens".into())
+        );
+        assert_eq!(
+            exceeded("1; 2; 3; 4; 5;", limits),
+            Some("Program has more than 4 statements".into())
+        );
+        assert_eq!(
+            exceeded("print ((((1))));", limits),
+            Some("Program is nested more than 4 levels deep".into())
+        );
+        assert_eq!(
+            exceeded(
+                "fun a() { fun b() { fun c() { fun d() {} } } }",
+                ParseLimits {
+                    max_depth: Some(3),
+                    ..ParseLimits::default()
+                }
+            ),
+            Some("Program is nested more than 3 levels deep".into())
+        );
+        assert_eq!(
+            exceeded(
+                &format!("{}1;", "!".repeat(100_000)),
+                ParseLimits {
+                    max_depth: Some(100),
+                    ..ParseLimits::default()
+                }
+            ),
+            Some("Program is nested more than 100 levels deep".into())
+        );
+    }
+
+    #[test]
+    fn parameter_lists() {
+        let first_error = |source: &str| match parse(source).errors.first() {
+            Some(LoxError::Syntax(err)) => Some(err.message().to_string()),
+            _ => None,
+        };
+        assert_eq!(first_error("fun f(a, b,) {} f(1, 2,); f(); f(1);"), None);
+        assert_eq!(first_error("fun f(a, ...rest,) {}"), None);
+        assert_eq!(
+            first_error("fun f(...rest, a) {}"),
+            Some("A rest parameter must be the last parameter".into())
+        );
+        assert_eq!(
+            first_error("fun f(a,,) {}"),
+            Some("Expected parameter name".into())
+        );
+        assert_eq!(first_error("f(1,,);"), Some("Expected expression".into()));
+        assert_eq!(first_error("f(,);"), Some("Expected expression".into()));
+
+        let names =
+            |count: usize| -> Vec<String> { (0..count).map(|i| format!("p{}", i)).collect() };
+        let params = |count: usize| format!("fun f({}) {{}}", names(count).join(", "));
+        let args = |count: usize| format!("f({});", vec!["1"; count].join(", "));
+        assert_eq!(first_error(&params(255)), None);
+        assert_eq!(
+            first_error(&params(256)),
+            Some("Can't have more than 255 parameters".into())
+        );
+        assert_eq!(first_error(&args(255)), None);
+        assert_eq!(
+            first_error(&args(256)),
+            Some("Can't have more than 255 arguments".into())
+        );
+    }
+
+    #[test]
+    fn program_info() {
+        let info = parse(CLASS_INHERITANCE_TEST).info();
+        assert_eq!(info.classes, 3);
+        assert_eq!(info.functions, 4);
+        assert_eq!(info.max_depth, 3);
+        assert_eq!(info.statements, 15);
+        let info = parse("print 1 + 2;").info();
+        assert_eq!(info.expressions, 3);
+        assert_eq!(info.max_depth, 1);
+    }
 
     #[test]
     fn print_var() {
@@ -580,6 +1417,14 @@ mod test {
         assert_eq!(statements.len(), 3);
     }
 
+    #[test]
+    fn for_in_loop() {
+        let ParseResult { statements, errors } = parse(FOR_IN_TEST);
+        assert_eq!(errors.len(), 0);
+        assert_eq!(statements.len(), 7);
+        assert!(matches!(statements[2], Stmt::ForIn { .. }));
+    }
+
     #[test]
     fn function() {
         let ParseResult { statements, errors } = parse(FUNCTION_TEST);
@@ -587,6 +1432,23 @@ mod test {
         assert_eq!(statements.len(), 3);
     }
 
+    #[test]
+    fn rest_parameters() {
+        let ParseResult { statements, errors } = parse("fun f(a, b, ...rest) {}");
+        assert_eq!(errors.len(), 0);
+        assert_eq!(statements[0].to_string(), "(fun f (a b ...rest) ())");
+        let ParseResult { errors, .. } = parse("fun f(...rest, a) {}");
+        assert!(matches!(
+            &errors[0],
+            LoxError::Syntax(err) if err.message() == "A rest parameter must be the last parameter"
+        ));
+        let ParseResult { errors, .. } = parse("class A { set a(...b) {} }");
+        assert!(matches!(
+            &errors[0],
+            LoxError::Syntax(err) if err.message() == "Setters take one parameter"
+        ));
+    }
+
     #[test]
     fn class() {
         let ParseResult { statements, errors } = parse(CLASS_TEST);
@@ -601,6 +1463,21 @@ mod test {
         assert_eq!(statements.len(), 7);
     }
 
+    #[test]
+    fn accessors() {
+        let ParseResult { statements, errors } = parse(ACCESSOR_TEST);
+        assert_eq!(errors.len(), 0);
+        match &statements[0] {
+            Stmt::Class { methods, .. } => assert!(methods[1].to_string().starts_with("(get area")),
+            _ => panic!("Expected a class"),
+        }
+        let ParseResult { errors, .. } = parse("class A { get a(b) {} set c() {} }");
+        assert!(matches!(
+            &errors[0],
+            LoxError::Syntax(err) if err.message() == "Getters take no parameters"
+        ));
+    }
+
     #[test]
     fn method_chaining() {
         let ParseResult { statements, errors } = parse(METHOD_CHAINING_TEST);
@@ -0,0 +1,198 @@
+use crate::{
+    environment::LoxProperties,
+    error::LoxResult,
+    object::LoxObject,
+    state::LoxState,
+    value::{sorted_entries, LoxValue},
+};
+use std::{cell::RefCell, rc::Rc};
+
+/// How deeply containers and instances nest before the rest is elided as
+/// `...`.
+pub const MAX_DEPTH: usize = 8;
+
+/// Render `value` for `print`: strings as they are, Arrays as `[1, "a"]`,
+/// Maps as `{"key": 1}` and instances as `Point { x: 1 }`, unless they
+/// define `to_string()`. A value containing itself renders the repeated
+/// reference as `<cycle>`.
+pub fn display(value: &LoxValue, state: &mut LoxState, line: u32) -> LoxResult<String> {
+    Printer::new(state, line).render(value, false)
+}
+
+/// Like `display`, with strings quoted, e.g. for the REPL to show results.
+pub fn repr(value: &LoxValue, state: &mut LoxState, line: u32) -> LoxResult<String> {
+    Printer::new(state, line).render(value, true)
+}
+
+struct Printer<'a> {
+    state: &'a mut LoxState,
+    line: u32,
+    /// The containers being rendered, outermost first.
+    path: Vec<*const ()>,
+}
+
+impl<'a> Printer<'a> {
+    fn new(state: &'a mut LoxState, line: u32) -> Self {
+        Self {
+            state,
+            line,
+            path: vec![],
+        }
+    }
+
+    fn render(&mut self, value: &LoxValue, quote: bool) -> LoxResult<String> {
+        match value {
+            LoxValue::String(value) if quote => Ok(format!("\"{}\"", value)),
+            LoxValue::Object(obj) => self.nested(obj, |printer| printer.object(obj)),
+            LoxValue::Vec(vec) => self.nested(vec, |printer| printer.items(vec)),
+            LoxValue::Map(map) => self.nested(map, |printer| printer.entries(map)),
+            value => Ok(value.to_string()),
+        }
+    }
+
+    /// Render a value containing other values, unless it's already being
+    /// rendered or nested too deeply.
+    fn nested<T>(
+        &mut self,
+        value: &Rc<RefCell<T>>,
+        render: impl FnOnce(&mut Self) -> LoxResult<String>,
+    ) -> LoxResult<String> {
+        let ptr = Rc::as_ptr(value) as *const ();
+        if self.path.contains(&ptr) {
+            return Ok("<cycle>".into());
+        }
+        if self.path.len() >= MAX_DEPTH {
+            return Ok("...".into());
+        }
+        self.path.push(ptr);
+        let result = render(self);
+        self.path.pop();
+        result
+    }
+
+    fn object(&mut self, obj: &Rc<RefCell<LoxObject>>) -> LoxResult<String> {
+        if let Some(method @ LoxValue::Function(_)) = LoxObject::lookup(obj, "to_string") {
+            return Ok(method.call(self.state, vec![], self.line)?.to_string());
+        }
+        // Arrays and Maps keep their contents in an internal prop
+        let (vec, map) = {
+            let obj = obj.borrow();
+            (obj.get("__vec__"), obj.get("__map__"))
+        };
+        if let Some(LoxValue::Vec(vec)) = vec {
+            return self.items(&vec);
+        }
+        if let Some(LoxValue::Map(map)) = map {
+            return self.entries(&map);
+        }
+        let fields: Vec<(String, LoxValue)> = LoxObject::field_names(obj)
+            .into_iter()
+            .filter_map(|name| obj.borrow().get(&name).map(|value| (name, value)))
+            .collect();
+        let class_name = obj.borrow().class_name.clone();
+        if fields.is_empty() {
+            return Ok(format!("{} {{}}", class_name));
+        }
+        let mut out = format!("{} {{ ", class_name);
+        for (i, (name, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            out.push_str(&format!("{}: {}", name, self.render(value, true)?));
+        }
+        out.push_str(" }");
+        Ok(out)
+    }
+
+    fn items(&mut self, vec: &Rc<RefCell<Vec<LoxValue>>>) -> LoxResult<String> {
+        // Rendering may call scripts, which may change the Vec
+        let items = vec.borrow().clone();
+        let mut rendered = vec![];
+        for item in items.iter() {
+            rendered.push(self.render(item, true)?);
+        }
+        Ok(format!("[{}]", rendered.join(", ")))
+    }
+
+    fn entries(&mut self, map: &Rc<RefCell<LoxProperties>>) -> LoxResult<String> {
+        let entries = sorted_entries(&map.borrow());
+        let mut rendered = vec![];
+        for (key, value) in entries.iter() {
+            rendered.push(format!("\"{}\": {}", key, self.render(value, true)?));
+        }
+        Ok(format!("{{{}}}", rendered.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::*, interpreter::*};
+    use mock_logger::MockLogger;
+
+    #[test]
+    fn pretty_print() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        lox.exec(
+            r#"
+            class Point {
+                init(x) {
+                    this.x = x;
+                    this.y = nil;
+                }
+            }
+            class Named {
+                to_string() {
+                    return "named";
+                }
+            }
+            var items = Array();
+            items.push(1);
+            items.push("two");
+            items.push(Point(3));
+            items.push(Named());
+            print items;
+            var map = Map();
+            map.set("b", items);
+            map.set("a", true);
+            print map;
+            print repr("text") + " " + repr(1) + " " + repr(Array());
+
+            // Instances referring to themselves
+            var point = Point(1);
+            point.y = point;
+            items.push(items);
+            print point;
+            print items;
+
+            var nested = Array();
+            var inner = nested;
+            for (var i = 0; i < 10; i = i + 1) {
+                var next = Array();
+                inner.push(next);
+                inner = next;
+            }
+            print nested;
+        "#,
+        )?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 6);
+            assert_eq!(
+                entries[0].body,
+                "[1, \"two\", Point { x: 3, y: nil }, named]"
+            );
+            assert_eq!(
+                entries[1].body,
+                "{\"a\": true, \"b\": [1, \"two\", Point { x: 3, y: nil }, named]}"
+            );
+            assert_eq!(entries[2].body, "\"text\" 1 []");
+            assert_eq!(entries[3].body, "Point { x: 1, y: <cycle> }");
+            assert_eq!(
+                entries[4].body,
+                "[1, \"two\", Point { x: 3, y: nil }, named, <cycle>]"
+            );
+            assert_eq!(entries[5].body, "[[[[[[[[...]]]]]]]]");
+        });
+        Ok(())
+    }
+}
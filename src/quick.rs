@@ -0,0 +1,76 @@
+use crate::{error::LoxResult, interpreter::LoxInterpreter, value::LoxValue};
+use std::cell::RefCell;
+
+thread_local! {
+    /// Created on first use, and kept for the life of the thread so globals
+    /// persist between evaluations.
+    static INTERPRETER: RefCell<LoxInterpreter> = RefCell::new(LoxInterpreter::new());
+}
+
+/// Evaluate an expression or statements with the thread's shared interpreter.
+///
+/// ```ignore
+/// let sum: f64 = lox::eval!("1 + 2")?.try_into()?;
+/// ```
+#[macro_export]
+macro_rules! eval {
+    ($source:expr) => {
+        $crate::eval($source)
+    };
+}
+
+/// Execute a script file with the thread's shared interpreter.
+///
+/// ```ignore
+/// lox::run_file!("rules.lox")?;
+/// ```
+#[macro_export]
+macro_rules! run_file {
+    ($path:expr) => {
+        $crate::with_interpreter(|lox| lox.exec_file($path))
+    };
+}
+
+/// Run `f` with the thread's shared interpreter, e.g. to register natives
+/// before using `eval!`. Panics if called again from inside `f`, like from a
+/// native function called by the script.
+pub fn with_interpreter<T>(f: impl FnOnce(&mut LoxInterpreter) -> T) -> T {
+    INTERPRETER.with(|lox| f(&mut lox.borrow_mut()))
+}
+
+/// Evaluate `source`, returning the value of its last expression statement.
+/// A trailing semicolon is optional, so a bare expression like `1 + 2` works.
+pub fn eval(source: &str) -> LoxResult<LoxValue> {
+    let trimmed = source.trim_end();
+    with_interpreter(|lox| {
+        if trimmed.ends_with(';') || trimmed.ends_with('}') {
+            lox.eval(source)
+        } else {
+            lox.eval(&format!("{};", trimmed))
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::*, value::LoxValue};
+    use std::{env, fs};
+
+    #[test]
+    fn macros() -> LoxResult {
+        let sum: f64 = eval!("1 + 2")?.try_into()?;
+        assert_eq!(sum, 3.0);
+        // Globals persist between evaluations
+        eval!("var limit = 10;")?;
+        let within: bool = eval!("limit > 5;")?.try_into()?;
+        assert!(within);
+        assert!(eval!("fun f() {}")? == LoxValue::Nil);
+        let path = env::temp_dir().join("lox_quick_run_file.lox");
+        fs::write(&path, "var from_file = limit * 2;\n")?;
+        run_file!(path.to_str().unwrap())?;
+        let doubled: f64 = eval!("from_file")?.try_into()?;
+        assert_eq!(doubled, 20.0);
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use super::{error::*, interpreter::*, value::LoxValue};
+use std::io::{self, BufRead, Write};
+
+/// Interactive prompt. Lines starting with `:` are commands, anything else
+/// is executed as Lox source.
+pub struct Repl<'a> {
+    lox: &'a mut LoxInterpreter,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(lox: &'a mut LoxInterpreter) -> Self {
+        Self { lox }
+    }
+
+    pub fn run(&mut self) -> LoxResult {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            print!("> ");
+            io::stdout().flush()?;
+            let Some(line) = lines.next() else {
+                println!();
+                return Ok(());
+            };
+            let line = line?;
+            match self.eval_line(&line) {
+                Ok(Some(output)) => println!("{}", output),
+                Ok(None) => {}
+                Err(LoxError::Exit(code)) => return Err(LoxError::Exit(code)),
+                Err(err) => eprintln!("{}", self.lox.render_error(&err, &line)),
+            }
+        }
+    }
+
+    /// Handle one line of input, returning the text to show for commands and
+    /// the value of expressions.
+    pub fn eval_line(&mut self, line: &str) -> LoxResult<Option<String>> {
+        match line.trim() {
+            "" => Ok(None),
+            ":gc" => {
+                let reclaimed = self.lox.compact();
+                Ok(Some(format!(
+                    "Reclaimed {} scope(s) and {} resolved expression(s)",
+                    reclaimed.scopes, reclaimed.locals
+                )))
+            }
+            ":stats" => {
+                let stats = self.lox.stats();
+                Ok(Some(format!(
+                    "{} scope(s), {} resolved expression(s)",
+                    stats.scopes, stats.locals
+                )))
+            }
+            command if command.starts_with(':') => Err(LoxError::General(format!(
+                "Unknown command \"{}\"",
+                command
+            ))),
+            source => match self.lox.eval(source)? {
+                LoxValue::Nil => Ok(None),
+                value => self.lox.repr(&value).map(Some),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock_logger::MockLogger;
+
+    #[test]
+    fn commands() -> LoxResult {
+        mock_logger::init();
+        let mut lox = LoxInterpreter::new();
+        let mut repl = Repl::new(&mut lox);
+        assert_eq!(repl.eval_line("   ")?, None);
+        repl.eval_line("fun square(x) { return x * x; }")?;
+        repl.eval_line("{ var y = 2; print square(y); }")?;
+        assert!(repl
+            .eval_line(":stats")?
+            .unwrap()
+            .ends_with("resolved expression(s)"));
+        assert_eq!(
+            repl.eval_line(":gc")?,
+            Some("Reclaimed 0 scope(s) and 1 resolved expression(s)".into())
+        );
+        repl.eval_line("print square(3);")?;
+        assert_eq!(repl.eval_line("square(4);")?, Some("16".into()));
+        assert_eq!(repl.eval_line("\"a\" + \"b\";")?, Some("\"ab\"".into()));
+        assert!(repl.eval_line(":unknown").is_err());
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[0].body, "4");
+            assert_eq!(entries[1].body, "9");
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn redefinition() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        let mut repl = Repl::new(&mut lox);
+        repl.eval_line("fun greet() { return \"hi\"; }")?;
+        repl.eval_line("var callbacks = Array(); callbacks.push(greet);")?;
+        repl.eval_line("class Dog { speak() { return \"woof\"; } }")?;
+        repl.eval_line("class Puppy < Dog {}")?;
+        repl.eval_line("var rex = Dog(); var bit = Puppy();")?;
+        repl.eval_line("fun greet() { return \"hello\"; }")?;
+        repl.eval_line("class Dog { speak() { return \"arf\"; } }")?;
+        // Values created before see the new definitions
+        assert_eq!(
+            repl.eval_line("callbacks.get(0)();")?,
+            Some("\"hello\"".into())
+        );
+        assert_eq!(repl.eval_line("rex.speak();")?, Some("\"arf\"".into()));
+        assert_eq!(repl.eval_line("bit.speak();")?, Some("\"arf\"".into()));
+        // Extending the old definition declares a new class
+        repl.eval_line("class Husky < Dog {}")?;
+        repl.eval_line("class Dog < Husky { speak() { return super.speak() + \"!\"; } }")?;
+        assert_eq!(repl.eval_line("Dog().speak();")?, Some("\"arf!\"".into()));
+        assert_eq!(repl.eval_line("rex.speak();")?, Some("\"arf\"".into()));
+        // Assigning a variable still just replaces it
+        repl.eval_line("var copy = greet; var greet = 1;")?;
+        assert_eq!(repl.eval_line("copy();")?, Some("\"hello\"".into()));
+        Ok(())
+    }
+}
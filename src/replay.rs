@@ -0,0 +1,307 @@
+use super::{
+    clock::{Clock, SystemClock},
+    error::*,
+};
+use std::{
+    collections::{hash_map::RandomState, VecDeque},
+    env,
+    fmt::Display,
+    fs,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// A single nondeterministic input observed while running a script.
+#[derive(PartialEq, Clone, Debug)]
+pub enum TraceEntry {
+    Time(f64),
+    Args(Vec<String>),
+    Random(f64),
+}
+
+/// An ordered log of nondeterministic inputs, serialized as one entry per line.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Trace {
+    entries: VecDeque<TraceEntry>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &str) -> LoxResult<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: &str) -> LoxResult {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn parse(source: &str) -> LoxResult<Self> {
+        let mut entries = VecDeque::new();
+        let mut lines = source.lines();
+        while let Some(line) = lines.next() {
+            let (kind, value) = line.split_once(' ').unwrap_or((line, ""));
+            let entry = match kind {
+                "time" => TraceEntry::Time(value.parse().map_err(|_| {
+                    LoxError::General(format!("Invalid time in trace: \"{}\"", value))
+                })?),
+                "random" => TraceEntry::Random(value.parse().map_err(|_| {
+                    LoxError::General(format!("Invalid random number in trace: \"{}\"", value))
+                })?),
+                "args" => {
+                    let count: usize = value.parse().map_err(|_| {
+                        LoxError::General(format!("Invalid argument count in trace: \"{}\"", value))
+                    })?;
+                    let mut args = vec![];
+                    for _ in 0..count {
+                        let arg = lines
+                            .next()
+                            .ok_or_else(|| LoxError::General("Unexpected end of trace".into()))?;
+                        args.push(unescape(arg));
+                    }
+                    TraceEntry::Args(args)
+                }
+                _ => {
+                    return Err(LoxError::General(format!(
+                        "Unknown trace entry \"{}\"",
+                        kind
+                    )))
+                }
+            };
+            entries.push_back(entry);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn push(&mut self, entry: TraceEntry) {
+        self.entries.push_back(entry);
+    }
+
+    fn next(&mut self, expected: &str, line: u32) -> LoxResult<TraceEntry> {
+        self.entries.pop_front().ok_or_else(|| {
+            LoxError::Runtime(
+                format!("Replay trace exhausted, expected {} entry", expected),
+                line,
+            )
+        })
+    }
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for entry in self.entries.iter() {
+            match entry {
+                TraceEntry::Time(value) => writeln!(f, "time {}", value)?,
+                TraceEntry::Random(value) => writeln!(f, "random {}", value)?,
+                TraceEntry::Args(args) => {
+                    writeln!(f, "args {}", args.len())?;
+                    for arg in args.iter() {
+                        writeln!(f, "{}", escape(arg))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[derive(Default)]
+pub enum InputMode {
+    #[default]
+    Live,
+    Record(Trace),
+    Replay(Trace),
+}
+
+/// Source of every nondeterministic value a script can observe.
+/// Builtins must read through this so runs can be recorded and replayed.
+pub struct Inputs {
+    pub mode: InputMode,
+    args: Vec<String>,
+    clock: Box<dyn Clock>,
+    /// State of the random number generator.
+    rng: u64,
+}
+
+impl Default for Inputs {
+    fn default() -> Self {
+        Self {
+            mode: InputMode::Live,
+            args: env::args().collect(),
+            clock: Box::new(SystemClock),
+            rng: RandomState::new().build_hasher().finish(),
+        }
+    }
+}
+
+impl Inputs {
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// The current time from the clock, bypassing recording and replay, for
+    /// the host's own bookkeeping like the audit log.
+    pub fn now(&self) -> LoxResult<Duration> {
+        self.clock.now()
+    }
+
+    /// Make `random` produce the same sequence on every run.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.rng = seed;
+    }
+
+    /// A random number between 0 (inclusive) and 1 (exclusive).
+    pub fn random(&mut self, line: u32) -> LoxResult<f64> {
+        match &mut self.mode {
+            InputMode::Replay(trace) => match trace.next("random", line)? {
+                TraceEntry::Random(value) => Ok(value),
+                _ => Err(LoxError::Runtime(
+                    "Replay trace diverged, expected random entry".into(),
+                    line,
+                )),
+            },
+            mode => {
+                // SplitMix64, keeping the top 53 bits as the fraction
+                self.rng = self.rng.wrapping_add(0x9E3779B97F4A7C15);
+                let mut z = self.rng;
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+                z ^= z >> 31;
+                let value = (z >> 11) as f64 / (1u64 << 53) as f64;
+                if let InputMode::Record(trace) = mode {
+                    trace.push(TraceEntry::Random(value));
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    pub fn time(&mut self, line: u32) -> LoxResult<f64> {
+        match &mut self.mode {
+            InputMode::Replay(trace) => match trace.next("time", line)? {
+                TraceEntry::Time(value) => Ok(value),
+                _ => Err(LoxError::Runtime(
+                    "Replay trace diverged, expected time entry".into(),
+                    line,
+                )),
+            },
+            mode => {
+                let value = self.clock.now()?.as_millis() as f64;
+                if let InputMode::Record(trace) = mode {
+                    trace.push(TraceEntry::Time(value));
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    pub fn args(&mut self, line: u32) -> LoxResult<Vec<String>> {
+        match &mut self.mode {
+            InputMode::Replay(trace) => match trace.next("args", line)? {
+                TraceEntry::Args(args) => Ok(args),
+                _ => Err(LoxError::Runtime(
+                    "Replay trace diverged, expected args entry".into(),
+                    line,
+                )),
+            },
+            InputMode::Record(trace) => {
+                trace.push(TraceEntry::Args(self.args.clone()));
+                Ok(self.args.clone())
+            }
+            InputMode::Live => Ok(self.args.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::*;
+    use mock_logger::MockLogger;
+
+    #[test]
+    fn serialize() -> LoxResult {
+        let mut trace = Trace::new();
+        trace.push(TraceEntry::Time(1234.0));
+        trace.push(TraceEntry::Random(0.25));
+        trace.push(TraceEntry::Args(vec!["lox".into(), "multi\nline\\".into()]));
+        let parsed = Trace::parse(&trace.to_string())?;
+        assert_eq!(parsed, trace);
+        Ok(())
+    }
+
+    #[test]
+    fn record_replay() -> LoxResult {
+        mock_logger::init();
+        let source = r#"
+            print time();
+            print get_args().get(1);
+            print random();
+        "#;
+        let mut lox = LoxInterpreter::new();
+        lox.set_args(vec!["lox".into(), "recorded".into()]);
+        lox.record();
+        lox.exec(source)?;
+        let trace = lox.take_trace().expect("Expected a recorded trace");
+        assert_eq!(trace.len(), 3);
+
+        let mut lox = LoxInterpreter::new();
+        lox.set_args(vec!["lox".into(), "live".into()]);
+        lox.replay(trace);
+        lox.exec(source)?;
+        MockLogger::entries(|entries| {
+            assert_eq!(entries.len(), 6);
+            assert_eq!(entries[0].body, entries[3].body);
+            assert_eq!(entries[1].body, "recorded");
+            assert_eq!(entries[4].body, "recorded");
+            assert_eq!(entries[2].body, entries[5].body);
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn replay_exhausted() {
+        let mut lox = LoxInterpreter::new();
+        lox.replay(Trace::new());
+        assert!(matches!(
+            lox.exec("print time();"),
+            Err(LoxError::Runtime(_, _))
+        ));
+    }
+}
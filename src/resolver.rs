@@ -1,13 +1,29 @@
 use crate::{error::*, expr::*, scanner::*, stmt::*};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-pub type Locals = HashMap<Expr, usize>;
+pub type Locals = HashMap<Expr, Local>;
+
+/// Where a variable reference finds its variable: the number of scopes to
+/// walk up, and the variable's position in that scope.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Local {
+    pub depth: usize,
+    pub slot: usize,
+}
+
+/// A variable in a scope being resolved.
+struct Variable {
+    initialized: bool,
+    slot: usize,
+    constant: bool,
+}
 
 #[derive(PartialEq, Clone, Copy)]
 enum FunctionType {
     Function,
     Constructor,
     Method,
+    StaticMethod,
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -17,10 +33,17 @@ enum ClassType {
 }
 
 pub struct Resolver {
-    locals_stack: Vec<HashMap<String, bool>>,
+    locals_stack: Vec<HashMap<String, Variable>>,
     locals: Locals,
     functions_stack: Vec<FunctionType>,
     current_class: ClassType,
+    /// Whether the class being resolved has a superclass.
+    has_superclass: bool,
+    /// Whether a deferred block of the current function is being resolved.
+    deferring: bool,
+    /// Global constants declared so far. Globals declared by earlier runs
+    /// are checked when assigned instead.
+    global_constants: HashSet<String>,
 }
 
 impl Resolver {
@@ -30,6 +53,9 @@ impl Resolver {
             locals: HashMap::new(),
             functions_stack: vec![],
             current_class: ClassType::None,
+            has_superclass: false,
+            deferring: false,
+            global_constants: HashSet::new(),
         };
         for stmt in statements.iter() {
             resolver.bind_stmt(stmt)?;
@@ -39,14 +65,18 @@ impl Resolver {
 
     fn bind_stmt(&mut self, stmt: &Stmt) -> LoxResult {
         match stmt {
-            Stmt::Block(statements) => {
+            Stmt::Block { statements, .. } => {
                 self.push();
                 for stmt in statements.iter() {
                     self.bind_stmt(stmt)?;
                 }
                 self.pop();
             }
-            Stmt::Var { name, initializer } => {
+            Stmt::Var {
+                name,
+                initializer,
+                mutable,
+            } => {
                 if self.has_name(&name.lexeme_str()) {
                     return Err(LoxError::Runtime(
                         format!(
@@ -61,8 +91,29 @@ impl Resolver {
                     self.bind_expr(init)?;
                 }
                 self.define(name.lexeme_str(), stmt.line());
+                self.set_constant(name.lexeme_str(), !mutable);
             }
-            Stmt::Fun { name, params, body } => {
+            Stmt::Destructure { names, initializer } => {
+                for name in names.iter() {
+                    if self.has_name(&name.lexeme_str()) {
+                        return Err(LoxError::Runtime(
+                            format!(
+                                "Cannot redeclare variable \"{}\" in the same scope",
+                                name.lexeme_str()
+                            ),
+                            stmt.line(),
+                        ));
+                    }
+                    self.declare(name.lexeme_str(), stmt.line());
+                }
+                self.bind_expr(initializer)?;
+                for name in names.iter() {
+                    self.define(name.lexeme_str(), stmt.line());
+                }
+            }
+            Stmt::Fun {
+                name, params, body, ..
+            } => {
                 self.resolve_function(name, params, body, FunctionType::Function, stmt.line())?;
             }
             Stmt::Expr(expr) => {
@@ -96,20 +147,105 @@ impl Resolver {
                         stmt.line(),
                     ));
                 }
+                if self.deferring {
+                    return Err(LoxError::Resolution(
+                        "Cannot return from a deferred block".into(),
+                        stmt.line(),
+                    ));
+                }
                 self.bind_expr(expr)?;
             }
+            Stmt::Using {
+                name,
+                initializer,
+                body,
+                ..
+            } => {
+                self.bind_expr(initializer)?;
+                self.push();
+                self.declare(name.lexeme_str(), stmt.line());
+                self.define(name.lexeme_str(), stmt.line());
+                self.bind_stmt(body)?;
+                self.pop();
+            }
+            Stmt::Throw { value, .. } => self.bind_expr(value)?,
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.bind_stmt(body)?;
+                if let Some((name, handler)) = catch {
+                    self.push();
+                    self.declare(name.lexeme_str(), stmt.line());
+                    self.define(name.lexeme_str(), stmt.line());
+                    self.bind_stmt(handler)?;
+                    self.pop();
+                }
+                if let Some(finally) = finally {
+                    self.bind_stmt(finally)?;
+                }
+            }
+            Stmt::Defer { body, .. } => {
+                if self.functions_stack.is_empty() {
+                    return Err(LoxError::Resolution(
+                        "Cannot defer outside of a function".into(),
+                        stmt.line(),
+                    ));
+                }
+                let outer = std::mem::replace(&mut self.deferring, true);
+                self.bind_stmt(body)?;
+                self.deferring = outer;
+            }
             Stmt::WhileLoop { condition, body } => {
                 self.push();
                 self.bind_expr(condition)?;
                 self.bind_stmt(body)?;
                 self.pop();
             }
+            Stmt::ForLoop {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.push();
+                self.bind_stmt(initializer)?;
+                self.bind_expr(condition)?;
+                self.bind_stmt(body)?;
+                self.bind_expr(increment)?;
+                self.pop();
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                self.bind_expr(iterable)?;
+                self.push();
+                self.declare(name.lexeme_str(), stmt.line());
+                self.define(name.lexeme_str(), stmt.line());
+                self.bind_stmt(body)?;
+                self.pop();
+            }
             Stmt::Class {
                 name,
                 superclass,
                 methods,
             } => {
+                // Static fields are evaluated where the class is declared
+                for member in methods.iter() {
+                    if let Stmt::Var {
+                        initializer: Some(initializer),
+                        ..
+                    } = member
+                    {
+                        self.bind_expr(initializer)?;
+                    }
+                }
                 self.current_class = ClassType::Class;
+                self.has_superclass = superclass.is_some();
                 self.declare(name.lexeme_str(), stmt.line());
                 if let Some(superclass) = superclass {
                     if let ExprKind::Identifier(supername) = &superclass.kind {
@@ -129,12 +265,21 @@ impl Resolver {
                     }
                 }
                 for method in methods.iter() {
-                    if let Stmt::Fun { name, params, body } = method {
+                    if let Stmt::Fun {
+                        name,
+                        params,
+                        body,
+                        kind,
+                        ..
+                    } = method
+                    {
                         self.resolve_function(
                             name,
                             params,
                             body,
-                            if name.lexeme_str() == *"init" {
+                            if *kind == FunKind::Static {
+                                FunctionType::StaticMethod
+                            } else if name.lexeme_str() == *"init" {
                                 FunctionType::Constructor
                             } else {
                                 FunctionType::Method
@@ -145,6 +290,7 @@ impl Resolver {
                 }
                 self.define(name.lexeme_str(), stmt.line());
                 self.current_class = ClassType::None;
+                self.has_superclass = false;
             }
         }
         Ok(())
@@ -153,7 +299,6 @@ impl Resolver {
     fn bind_expr(&mut self, expr: &Expr) -> LoxResult {
         match &expr.kind {
             ExprKind::Identifier(name) => {
-                println!("bind {expr}({}) on line {}", expr.id(), expr.line());
                 if !self.locals_stack.is_empty() && !self.is_initialized(&name.lexeme_str()) {
                     return Err(LoxError::Resolution(
                         "Attempted to resolve variable in its own initializer".into(),
@@ -163,6 +308,12 @@ impl Resolver {
                 self.resolve_local(expr, name.lexeme_str());
             }
             ExprKind::Assignment { name, value } => {
+                if self.is_constant(&name.lexeme_str()) {
+                    return Err(LoxError::Resolution(
+                        format!("Cannot assign to constant \"{}\"", name.lexeme_str()),
+                        expr.line(),
+                    ));
+                }
                 self.bind_expr(value)?;
                 self.resolve_local(expr, name.lexeme_str());
             }
@@ -197,8 +348,14 @@ impl Resolver {
             ExprKind::Get { left, .. } => {
                 self.bind_expr(left)?;
             }
-            ExprKind::Set { object, .. } => {
+            ExprKind::Set { object, value, .. } => {
                 self.bind_expr(object)?;
+                self.bind_expr(value)?;
+            }
+            ExprKind::Tuple(values) => {
+                for value in values.iter() {
+                    self.bind_expr(value)?;
+                }
             }
             ExprKind::This(_) => {
                 if self.current_class == ClassType::None {
@@ -207,6 +364,7 @@ impl Resolver {
                         expr.line(),
                     ));
                 }
+                self.resolve_local(expr, "this".into());
             }
             ExprKind::Super(..) => {
                 if self.current_class == ClassType::None {
@@ -215,6 +373,20 @@ impl Resolver {
                         expr.line(),
                     ));
                 }
+                // Static methods are bound to the class, there's no instance
+                // for superclass methods to act on
+                let method = self
+                    .functions_stack
+                    .iter()
+                    .rev()
+                    .find(|func_type| **func_type != FunctionType::Function);
+                if method == Some(&FunctionType::StaticMethod) {
+                    return Err(LoxError::Resolution(
+                        "Cannot use \"super\" in a static method".into(),
+                        expr.line(),
+                    ));
+                }
+                self.resolve_local(expr, "super".into());
             }
             _ => {}
         }
@@ -223,8 +395,12 @@ impl Resolver {
 
     fn resolve_local(&mut self, expr: &Expr, name: String) {
         for (i, frame) in self.locals_stack.iter().rev().enumerate() {
-            if frame.contains_key(&name) {
-                self.resolve(expr, i);
+            if let Some(variable) = frame.get(&name) {
+                let local = Local {
+                    depth: i,
+                    slot: variable.slot,
+                };
+                self.resolve(expr, local);
                 break;
             }
         }
@@ -240,23 +416,29 @@ impl Resolver {
     ) -> LoxResult {
         self.define(name.lexeme_str(), line);
         self.functions_stack.push(func_type);
+        let deferring = std::mem::replace(&mut self.deferring, false);
         self.push();
-        if func_type == FunctionType::Method {
-            self.define("this".into(), line);
-        }
+        // In the order calls declare them, see `LoxFunction::call`
         for param in params.iter() {
             self.define(param.lexeme_str(), line);
         }
+        if func_type != FunctionType::Function {
+            self.define("this".into(), line);
+            if self.has_superclass {
+                self.define("super".into(), line);
+            }
+        }
         for stmt in body.iter() {
             self.bind_stmt(stmt)?;
         }
         self.pop();
+        self.deferring = deferring;
         self.functions_stack.pop();
         Ok(())
     }
 
-    fn resolve(&mut self, expr: &Expr, depth: usize) {
-        self.locals.insert(expr.clone(), depth);
+    fn resolve(&mut self, expr: &Expr, local: Local) {
+        self.locals.insert(expr.clone(), local);
     }
 
     fn push(&mut self) {
@@ -268,23 +450,61 @@ impl Resolver {
     }
 
     fn declare(&mut self, name: String, _line: u32) {
-        if !self.locals_stack.is_empty() {
-            self.peek_mut().insert(name, false);
-        }
+        self.insert(name, false);
     }
 
     fn define(&mut self, name: String, _line: u32) {
-        if !self.locals_stack.is_empty() {
-            self.peek_mut().insert(name, true);
+        self.insert(name, true);
+    }
+
+    /// Add a variable to the innermost scope, taking the next slot unless it
+    /// was declared already.
+    fn insert(&mut self, name: String, initialized: bool) {
+        if self.locals_stack.is_empty() {
+            return;
+        }
+        let frame = self.peek_mut();
+        let slot = frame.len();
+        frame
+            .entry(name)
+            .and_modify(|variable| variable.initialized = initialized)
+            .or_insert(Variable {
+                initialized,
+                slot,
+                constant: false,
+            });
+    }
+
+    /// Mark the variable `name` just declared in the innermost scope as a
+    /// constant, or not.
+    fn set_constant(&mut self, name: String, constant: bool) {
+        if self.locals_stack.is_empty() {
+            if constant {
+                self.global_constants.insert(name);
+            } else {
+                self.global_constants.remove(&name);
+            }
+        } else if let Some(variable) = self.peek_mut().get_mut(&name) {
+            variable.constant = constant;
         }
     }
 
-    fn peek(&self) -> &HashMap<String, bool> {
+    /// Whether `name` refers to a constant from where it's used.
+    fn is_constant(&self, name: &str) -> bool {
+        self.locals_stack
+            .iter()
+            .rev()
+            .find_map(|frame| frame.get(name))
+            .map(|variable| variable.constant)
+            .unwrap_or_else(|| self.global_constants.contains(name))
+    }
+
+    fn peek(&self) -> &HashMap<String, Variable> {
         let last = self.locals_stack.len() - 1;
         &self.locals_stack[last]
     }
 
-    fn peek_mut(&mut self) -> &mut HashMap<String, bool> {
+    fn peek_mut(&mut self) -> &mut HashMap<String, Variable> {
         let last = self.locals_stack.len() - 1;
         &mut self.locals_stack[last]
     }
@@ -298,7 +518,10 @@ impl Resolver {
     }
 
     fn is_initialized(&self, name: &str) -> bool {
-        self.peek().get(name).copied().unwrap_or(true)
+        self.peek()
+            .get(name)
+            .map(|variable| variable.initialized)
+            .unwrap_or(true)
     }
 }
 
@@ -306,6 +529,11 @@ impl Resolver {
 mod test {
     use super::*;
     use crate::{parser::*, test_scripts::*};
+    use std::collections::HashSet;
+
+    fn depth(locals: &Locals, key: &Expr) -> Option<usize> {
+        locals.get(key).map(|local| local.depth)
+    }
 
     fn local_keys(locals: &Locals) -> Vec<&Expr> {
         let mut keys = locals.keys().collect::<Vec<&Expr>>();
@@ -322,7 +550,7 @@ mod test {
         let locals = Resolver::bind(&statements)?;
         let keys = local_keys(&locals);
         assert_eq!(locals.len(), 1);
-        assert_eq!(locals.get(keys[0]), Some(&0));
+        assert_eq!(depth(&locals, keys[0]), Some(0));
         Ok(())
     }
 
@@ -335,10 +563,11 @@ mod test {
         let locals = Resolver::bind(&statements)?;
         let keys = local_keys(&locals);
         assert_eq!(locals.len(), 4);
-        assert_eq!(locals.get(keys[0]), Some(&1));
-        assert_eq!(locals.get(keys[1]), Some(&2));
-        assert_eq!(locals.get(keys[2]), Some(&2));
-        assert_eq!(locals.get(keys[3]), Some(&3));
+        // The condition and increment share the scope of the initializer
+        assert_eq!(depth(&locals, keys[0]), Some(0));
+        assert_eq!(depth(&locals, keys[1]), Some(0));
+        assert_eq!(depth(&locals, keys[2]), Some(0));
+        assert_eq!(depth(&locals, keys[3]), Some(1));
         Ok(())
     }
 
@@ -351,8 +580,8 @@ mod test {
         let locals = Resolver::bind(&statements)?;
         let keys = local_keys(&locals);
         assert_eq!(locals.len(), 2);
-        assert_eq!(locals.get(keys[0]), Some(&1));
-        assert_eq!(locals.get(keys[1]), Some(&0));
+        assert_eq!(depth(&locals, keys[0]), Some(1));
+        assert_eq!(depth(&locals, keys[1]), Some(0));
         Ok(())
     }
 
@@ -365,10 +594,10 @@ mod test {
         let locals = Resolver::bind(&statements)?;
         let keys = local_keys(&locals);
         assert_eq!(locals.len(), 4);
-        assert_eq!(locals.get(keys[0]), Some(&1));
-        assert_eq!(locals.get(keys[1]), Some(&1));
-        assert_eq!(locals.get(keys[2]), Some(&1));
-        assert_eq!(locals.get(keys[3]), Some(&0));
+        assert_eq!(depth(&locals, keys[0]), Some(1));
+        assert_eq!(depth(&locals, keys[1]), Some(1));
+        assert_eq!(depth(&locals, keys[2]), Some(1));
+        assert_eq!(depth(&locals, keys[3]), Some(0));
         Ok(())
     }
 
@@ -381,8 +610,8 @@ mod test {
         let locals = Resolver::bind(&statements)?;
         let keys = local_keys(&locals);
         assert_eq!(locals.len(), 2);
-        assert_eq!(locals.get(keys[0]), Some(&0));
-        assert_eq!(locals.get(keys[1]), Some(&0));
+        assert_eq!(depth(&locals, keys[0]), Some(0));
+        assert_eq!(depth(&locals, keys[1]), Some(0));
         Ok(())
     }
 
@@ -393,9 +622,9 @@ mod test {
             errors: _,
         } = parse(CLASS_TEST);
         let locals = Resolver::bind(&statements)?;
-        let keys = local_keys(&locals);
-        assert_eq!(locals.len(), 1);
-        assert_eq!(locals.get(keys[0]), Some(&0));
+        // Parameters and `this` in both methods
+        assert_eq!(locals.len(), 4);
+        assert!(locals.values().all(|local| local.depth == 0));
         Ok(())
     }
 
@@ -420,6 +649,28 @@ mod test {
         ));
     }
 
+    #[test]
+    fn static_super() {
+        let ParseResult {
+            statements,
+            errors: _,
+        } = parse(
+            r#"
+            class Base {}
+            class Derived < Base {
+                static create() {
+                    return super.create();
+                }
+            }
+        "#,
+        );
+        let result = Resolver::bind(&statements);
+        assert!(matches!(
+            result,
+            Err(LoxError::Resolution(message, _)) if message == "Cannot use \"super\" in a static method"
+        ));
+    }
+
     #[test]
     fn constructor_return() {
         let ParseResult {
@@ -441,4 +692,78 @@ mod test {
             Err(LoxError::Resolution(message, _)) if message == "Cannot return from constructor".to_string()
         ));
     }
+
+    #[test]
+    fn constants() -> LoxResult {
+        let assign_error = |source: &str| match Resolver::bind(&parse(source).statements) {
+            Err(LoxError::Resolution(message, line)) => Some((message, line)),
+            _ => None,
+        };
+        assert_eq!(
+            assign_error("const a = 1;\n{\n    const b = 2;\n    b = 3;\n}"),
+            Some(("Cannot assign to constant \"b\"".into(), 3))
+        );
+        assert_eq!(
+            assign_error("const a = 1;\nfun f(x) {\n    a += x;\n}"),
+            Some(("Cannot assign to constant \"a\"".into(), 2))
+        );
+        // Shadowing and redeclaring make the name assignable
+        assert_eq!(
+            assign_error("const a = 1;\nfun f(x) {\n    var a = x;\n    a = 2;\n}"),
+            None
+        );
+        assert_eq!(assign_error("const a = 1;\nvar a = 2;\na = 3;"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn duplicated_ast() -> LoxResult {
+        let ParseResult {
+            statements,
+            errors: _,
+        } = parse(
+            r#"
+            fun outer() {
+                var a = 1;
+                fun inner() {
+                    return a;
+                }
+                return inner();
+            }
+        "#,
+        );
+        let locals = Resolver::bind(&statements)?;
+        let depths = |locals: &Locals| {
+            let mut depths: Vec<usize> = locals.values().map(|local| local.depth).collect();
+            depths.sort_unstable();
+            depths
+        };
+
+        // A clone is the same node and shares resolution with the original
+        let cloned = statements.clone();
+        assert!(Resolver::bind(&cloned)? == locals);
+
+        // A duplicate is a distinct node with its own entries
+        let mut rekeyed = locals.clone();
+        let duplicate: Vec<Stmt> = statements
+            .iter()
+            .map(|stmt| stmt.duplicate(&mut rekeyed))
+            .collect();
+        let mut original_ids = HashSet::new();
+        let mut duplicate_ids = HashSet::new();
+        for (original, copy) in statements.iter().zip(duplicate.iter()) {
+            original.collect_ids(&mut original_ids);
+            copy.collect_ids(&mut duplicate_ids);
+        }
+        assert!(original_ids.is_disjoint(&duplicate_ids));
+        assert_eq!(rekeyed.len(), locals.len() * 2);
+
+        // Resolving the duplicate from scratch agrees with the carried over entries
+        let resolved = Resolver::bind(&duplicate)?;
+        assert_eq!(depths(&resolved), depths(&locals));
+        for (expr, local) in resolved.iter() {
+            assert_eq!(rekeyed.get(expr), Some(local));
+        }
+        Ok(())
+    }
 }
@@ -2,7 +2,11 @@ use std::{fmt::Display, mem::take};
 
 use super::error::*;
 
-#[derive(PartialEq, Copy, Clone, Debug)]
+/// The kinds of tokens the scanner produces. New kinds may be added in minor
+/// releases, so matches outside the crate need a wildcard arm; the `is_*`
+/// methods categorize kinds for tools like highlighters.
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+#[non_exhaustive]
 pub enum TokenKind {
     LeftParen,
     RightParen,
@@ -10,11 +14,18 @@ pub enum TokenKind {
     RightBrace,
     Comma,
     Dot,
+    DotDotDot,
     Minus,
+    MinusEqual,
+    MinusMinus,
     Plus,
+    PlusEqual,
+    PlusPlus,
     Semicolon,
     Slash,
+    SlashEqual,
     Star,
+    StarEqual,
     Bang,
     BangEqual,
     Equal,
@@ -28,18 +39,24 @@ pub enum TokenKind {
     Number,
     And,
     Class,
+    Const,
     Else,
     False,
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
+    Catch,
+    Finally,
     Var,
     While,
     Eof,
@@ -64,12 +81,92 @@ impl Display for Literal {
     }
 }
 
+impl TokenKind {
+    /// The kind of the reserved word `name`, if it is one.
+    pub fn keyword(name: &str) -> Option<TokenKind> {
+        KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == name)
+            .map(|(_, kind)| *kind)
+    }
+
+    /// Whether the kind is a reserved word, see `KEYWORDS`.
+    pub fn is_keyword(&self) -> bool {
+        KEYWORDS.iter().any(|(_, kind)| kind == self)
+    }
+
+    /// Whether the kind is an arithmetic, comparison, logical or assignment
+    /// operator. `and` and `or` are also keywords.
+    pub fn is_operator(&self) -> bool {
+        matches!(
+            self,
+            Self::Minus
+                | Self::MinusEqual
+                | Self::MinusMinus
+                | Self::Plus
+                | Self::PlusEqual
+                | Self::PlusPlus
+                | Self::Slash
+                | Self::SlashEqual
+                | Self::Star
+                | Self::StarEqual
+                | Self::Bang
+                | Self::BangEqual
+                | Self::Equal
+                | Self::EqualEqual
+                | Self::Greater
+                | Self::GreaterEqual
+                | Self::Less
+                | Self::LessEqual
+                | Self::And
+                | Self::Or
+        )
+    }
+
+    /// Whether the kind is a literal value. `true`, `false` and `nil` are
+    /// also keywords.
+    pub fn is_literal(&self) -> bool {
+        matches!(
+            self,
+            Self::String | Self::Number | Self::True | Self::False | Self::Nil
+        )
+    }
+}
+
+/// Every reserved word of the language, with the kind of token it scans as.
+pub const KEYWORDS: &[(&str, TokenKind)] = &[
+    ("and", TokenKind::And),
+    ("catch", TokenKind::Catch),
+    ("class", TokenKind::Class),
+    ("const", TokenKind::Const),
+    ("else", TokenKind::Else),
+    ("false", TokenKind::False),
+    ("finally", TokenKind::Finally),
+    ("for", TokenKind::For),
+    ("fun", TokenKind::Fun),
+    ("if", TokenKind::If),
+    ("in", TokenKind::In),
+    ("nil", TokenKind::Nil),
+    ("or", TokenKind::Or),
+    ("print", TokenKind::Print),
+    ("return", TokenKind::Return),
+    ("super", TokenKind::Super),
+    ("this", TokenKind::This),
+    ("throw", TokenKind::Throw),
+    ("true", TokenKind::True),
+    ("try", TokenKind::Try),
+    ("var", TokenKind::Var),
+    ("while", TokenKind::While),
+];
+
 #[derive(PartialEq, Clone, Debug)]
 pub struct Token {
     pub kind: TokenKind,
     pub lexeme: Option<String>,
     pub literal: Option<Literal>,
     pub line: u32,
+    /// Zero based column of the first character of the lexeme.
+    pub column: u32,
 }
 
 impl Token {
@@ -84,9 +181,15 @@ impl Token {
             lexeme,
             literal,
             line,
+            column: 0,
         }
     }
 
+    pub fn at_column(mut self, column: u32) -> Self {
+        self.column = column;
+        self
+    }
+
     pub fn lexeme_str(&self) -> String {
         match &self.lexeme {
             Some(lexeme) => lexeme.clone(),
@@ -106,6 +209,13 @@ pub struct ScanResult {
     pub errors: Vec<SyntaxError>,
 }
 
+// Part of a number literal being scanned
+#[derive(Clone, Copy)]
+enum NumberPart {
+    Integer,
+    Fraction,
+}
+
 // Lexical Scanner
 // Produces tokens
 pub struct Scanner {
@@ -115,8 +225,13 @@ pub struct Scanner {
     tokens: Vec<Token>,
     // Syntax errors
     errors: Vec<SyntaxError>,
-    // Current line being scanned
+    // Current line being scanned, advanced only by `advance()`
     line: usize,
+    // Current column being scanned, advanced only by `advance()`
+    column: usize,
+    // Line and column the current lexeme starts on
+    start_line: usize,
+    start_column: usize,
     // Starting offset of current lexeme being scanned
     start: usize,
     // Current offset of the lexeme being scanned
@@ -130,6 +245,9 @@ impl Scanner {
             tokens: vec![],
             errors: vec![],
             line: 0,
+            column: 0,
+            start_line: 0,
+            start_column: 0,
             start: 0,
             current: 0,
         }
@@ -138,11 +256,39 @@ impl Scanner {
     // Do a full scan of the source.
     pub fn scan(&mut self) -> ScanResult {
         while !self.id_at_end() {
-            self.start = self.current;
-            self.scan_token();
+            self.scan_next();
+        }
+        self.finish()
+    }
+
+    // Do a full scan of the source, giving up once more than `max_tokens`
+    // tokens have been produced.
+    pub fn scan_limited(&mut self, max_tokens: usize) -> LoxResult<ScanResult> {
+        while !self.id_at_end() {
+            self.scan_next();
+            if self.tokens.len() > max_tokens {
+                return Err(LoxError::LimitExceeded(format!(
+                    "Source has more than {} tokens",
+                    max_tokens
+                )));
+            }
         }
-        self.tokens
-            .push(Token::new(TokenKind::Eof, None, None, self.line as u32 + 1));
+        Ok(self.finish())
+    }
+
+    // Scan the next lexeme, which may not produce a token.
+    fn scan_next(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
+        self.scan_token();
+    }
+
+    // Append the end of file token and hand over the results.
+    fn finish(&mut self) -> ScanResult {
+        self.tokens.push(
+            Token::new(TokenKind::Eof, None, None, self.line as u32).at_column(self.column as u32),
+        );
         ScanResult {
             tokens: take(&mut self.tokens),
             errors: take(&mut self.errors),
@@ -152,64 +298,94 @@ impl Scanner {
     // Scan a single token.
     fn scan_token(&mut self) {
         match self.advance() {
-            ' ' | '\r' | '\t' => {}
-            '\n' => self.line += 1,
+            ' ' | '\r' | '\t' | '\n' => {}
             '(' => self.add_token(TokenKind::LeftParen, None),
             ')' => self.add_token(TokenKind::RightParen, None),
             '{' => self.add_token(TokenKind::LeftBrace, None),
             '}' => self.add_token(TokenKind::RightBrace, None),
             ',' => self.add_token(TokenKind::Comma, None),
-            '.' => self.add_token(TokenKind::Dot, None),
-            '-' => self.add_token(TokenKind::Minus, None),
-            '+' => self.add_token(TokenKind::Plus, None),
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == Some(&'.') {
+                    self.advance();
+                    self.advance();
+                    self.add_token(TokenKind::DotDotDot, None);
+                } else {
+                    self.add_token(TokenKind::Dot, None);
+                }
+            }
+            '-' => {
+                if self.match_next('=') {
+                    self.add_token(TokenKind::MinusEqual, None);
+                } else if self.match_next('-') {
+                    self.add_token(TokenKind::MinusMinus, None);
+                } else {
+                    self.add_token(TokenKind::Minus, None);
+                }
+            }
+            '+' => {
+                if self.match_next('=') {
+                    self.add_token(TokenKind::PlusEqual, None);
+                } else if self.match_next('+') {
+                    self.add_token(TokenKind::PlusPlus, None);
+                } else {
+                    self.add_token(TokenKind::Plus, None);
+                }
+            }
             ';' => self.add_token(TokenKind::Semicolon, None),
-            '*' => self.add_token(TokenKind::Star, None),
+            '*' => {
+                if self.match_next('=') {
+                    self.add_token(TokenKind::StarEqual, None);
+                } else {
+                    self.add_token(TokenKind::Star, None);
+                }
+            }
             '!' => {
-                if *self.peek() == '=' {
+                if self.match_next('=') {
                     self.add_token(TokenKind::BangEqual, None);
-                    self.advance();
                 } else {
                     self.add_token(TokenKind::Bang, None);
                 }
             }
             '=' => {
-                if *self.peek() == '=' {
+                if self.match_next('=') {
                     self.add_token(TokenKind::EqualEqual, None);
-                    self.advance();
                 } else {
                     self.add_token(TokenKind::Equal, None);
                 }
             }
             '<' => {
-                if *self.peek() == '=' {
+                if self.match_next('=') {
                     self.add_token(TokenKind::LessEqual, None);
-                    self.advance();
                 } else {
                     self.add_token(TokenKind::Less, None);
                 }
             }
             '>' => {
-                if *self.peek() == '=' {
+                if self.match_next('=') {
                     self.add_token(TokenKind::GreaterEqual, None);
-                    self.advance();
                 } else {
                     self.add_token(TokenKind::Greater, None);
                 }
             }
             '/' => {
-                if *self.peek() == '/' {
+                if self.peek() == '/' {
                     self.scan_comment();
+                } else if self.match_next('=') {
+                    self.add_token(TokenKind::SlashEqual, None);
                 } else {
                     self.add_token(TokenKind::Slash, None);
                 }
             }
             '"' => self.scan_string(),
             '0'..='9' => self.scan_number(),
-            _ => {
-                if self.previous().is_alphabetic() {
+            c => {
+                if c.is_alphabetic() || c == '_' {
                     self.scan_identifier();
                 } else {
-                    self.add_syntax_error(format!("Unknown character \"{}\"", self.previous()));
+                    self.add_syntax_error(format!(
+                        "Unknown character \"{}\" at column {}",
+                        c, self.start_column
+                    ));
                 }
             }
         }
@@ -217,18 +393,14 @@ impl Scanner {
 
     // Ignore a comment line and advance to the next line.
     fn scan_comment(&mut self) {
-        while *self.peek() != '\n' && !self.id_at_end() {
+        while !self.id_at_end() && self.peek() != '\n' {
             self.advance();
         }
     }
 
     // Scan a string token.
     fn scan_string(&mut self) {
-        let mut line = self.line;
-        while *self.peek() != '"' && !self.id_at_end() {
-            if *self.peek() == '\n' {
-                line += 1;
-            }
+        while !self.id_at_end() && self.peek() != '"' {
             self.advance();
         }
         if self.id_at_end() {
@@ -237,56 +409,65 @@ impl Scanner {
             self.advance();
             let lexeme = self.get_lexeme();
             let literal = lexeme[1..lexeme.len() - 1].to_string();
-            self.tokens.push(Token::new(
-                TokenKind::String,
-                Some(lexeme),
-                Some(Literal::String(literal)),
-                self.line as u32,
-            ));
-            self.line = line;
+            self.add_token(TokenKind::String, Some(Literal::String(literal)));
         }
     }
 
-    // Scan a number token.
+    // Scan a number token, first the integer part and then an optional
+    // fractional part. Malformed numbers are reported as syntax errors.
     fn scan_number(&mut self) {
-        while !self.id_at_end() && self.is_digit() {
-            self.advance();
+        let mut part = NumberPart::Integer;
+        while !self.id_at_end() {
+            let next = self.peek_next().copied();
+            match (part, self.peek()) {
+                (_, '0'..='9') => {
+                    self.advance();
+                }
+                (NumberPart::Integer, '.') => match next {
+                    Some(next) if next.is_ascii_digit() => {
+                        self.advance();
+                        part = NumberPart::Fraction;
+                    }
+                    // A property access, e.g. `1.foo`
+                    Some(next) if next.is_alphabetic() || next == '_' => break,
+                    _ => {
+                        self.advance();
+                        self.add_syntax_error(format!(
+                            "Expected a digit after the decimal point in \"{}\"",
+                            self.get_lexeme()
+                        ));
+                        return;
+                    }
+                },
+                (NumberPart::Fraction, '.') if next.is_some_and(|next| next.is_ascii_digit()) => {
+                    // Consume the rest of the malformed number so it's reported once
+                    while !self.id_at_end() && (self.peek().is_ascii_digit() || self.peek() == '.')
+                    {
+                        self.advance();
+                    }
+                    self.add_syntax_error(format!(
+                        "Unexpected \".\" in number \"{}\"",
+                        self.get_lexeme()
+                    ));
+                    return;
+                }
+                _ => break,
+            }
         }
         let s = self.get_lexeme();
-        let num = s.parse::<f64>().expect("Invalid number");
-        self.tokens.push(Token::new(
-            TokenKind::Number,
-            Some(s),
-            Some(Literal::Number(num)),
-            self.line as u32,
-        ));
+        match s.parse::<f64>() {
+            Ok(num) => self.add_token(TokenKind::Number, Some(Literal::Number(num))),
+            Err(_) => self.add_syntax_error(format!("Invalid number \"{}\"", s)),
+        }
     }
 
     // Scan an identifier
     fn scan_identifier(&mut self) {
-        while !self.id_at_end() && (self.peek().is_alphanumeric() || *self.peek() == '_') {
+        while !self.id_at_end() && (self.peek().is_alphanumeric() || self.peek() == '_') {
             self.advance();
         }
         let lexeme = self.get_lexeme();
-        let kind = match lexeme.as_str() {
-            "and" => TokenKind::And,
-            "class" => TokenKind::Class,
-            "else" => TokenKind::Else,
-            "false" => TokenKind::False,
-            "for" => TokenKind::For,
-            "fun" => TokenKind::Fun,
-            "if" => TokenKind::If,
-            "nil" => TokenKind::Nil,
-            "or" => TokenKind::Or,
-            "print" => TokenKind::Print,
-            "return" => TokenKind::Return,
-            "super" => TokenKind::Super,
-            "this" => TokenKind::This,
-            "true" => TokenKind::True,
-            "var" => TokenKind::Var,
-            "while" => TokenKind::While,
-            _ => TokenKind::Identifier,
-        };
+        let kind = TokenKind::keyword(&lexeme).unwrap_or(TokenKind::Identifier);
         let literal = match kind {
             TokenKind::True => Some(Literal::True),
             TokenKind::False => Some(Literal::False),
@@ -297,62 +478,57 @@ impl Scanner {
 
     // Add a token
     fn add_token(&mut self, kind: TokenKind, literal: Option<Literal>) {
-        self.tokens.push(Token::new(
-            kind,
-            Some(self.get_lexeme()),
-            literal,
-            self.line as u32,
-        ));
+        self.tokens.push(
+            Token::new(
+                kind,
+                Some(self.get_lexeme()),
+                literal,
+                self.start_line as u32,
+            )
+            .at_column(self.start_column as u32),
+        );
     }
 
     // Grab the current character.
-    fn peek(&self) -> &char {
-        &self.source[self.current]
-    }
-
-    // Grab the last character.
-    fn previous(&self) -> &char {
-        &self.source[self.current - 1]
+    // Returns "\0" at the end of the source.
+    fn peek(&self) -> char {
+        self.source.get(self.current).copied().unwrap_or('\0')
     }
 
     // Grab the next character.
     fn peek_next(&self) -> Option<&char> {
-        if (self.current + 1) < self.source.len() {
-            Some(&self.source[self.current + 1])
+        self.source.get(self.current + 1)
+    }
+
+    // Consumes the current character, returning it and advancing the
+    // character pointer, line and column.
+    fn advance(&mut self) -> char {
+        let c = self.source[self.current];
+        self.current += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 0;
         } else {
-            None
+            self.column += 1;
         }
+        c
     }
 
-    // Check if the current charater is a digit.
-    // If the current character is a dot (".") it will check if the next
-    // character is a digit to verify if the dot is meant as a decimal.
-    fn is_digit(&self) -> bool {
-        if self.peek().is_ascii_digit() {
+    // Consumes the current character if it is `expected`.
+    fn match_next(&mut self, expected: char) -> bool {
+        if !self.id_at_end() && self.peek() == expected {
+            self.advance();
             true
-        } else if *self.peek() == '.' {
-            if let Some(next) = self.peek_next() {
-                next.is_ascii_digit()
-            } else {
-                false
-            }
         } else {
             false
         }
     }
 
-    // Consumes the current character, returning it and incrementing
-    // the character pointer.
-    fn advance(&mut self) -> &char {
-        let c = &self.source[self.current];
-        self.current += 1;
-        c
-    }
-
     // Add a syntax error.
     fn add_syntax_error(&mut self, message: String) {
-        self.errors
-            .push(SyntaxError::new(message, self.line as u32));
+        self.errors.push(
+            SyntaxError::new(message, self.start_line as u32).with_column(self.start_column as u32),
+        );
     }
 
     // Generate the current token lexeme.
@@ -376,6 +552,70 @@ mod test {
     use super::*;
     use crate::test_scripts::*;
 
+    #[test]
+    fn token_categories() {
+        for (keyword, kind) in KEYWORDS.iter() {
+            let ScanResult { tokens, .. } = scan(keyword);
+            assert_eq!(tokens[0].kind, *kind);
+            assert!(kind.is_keyword());
+        }
+        assert_eq!(TokenKind::keyword("while"), Some(TokenKind::While));
+        assert_eq!(TokenKind::keyword("whilst"), None);
+        let kinds = |source: &str| -> Vec<TokenKind> {
+            scan(source).tokens.iter().map(|token| token.kind).collect()
+        };
+        let categorized = |source: &str, category: fn(&TokenKind) -> bool| -> Vec<TokenKind> {
+            kinds(source).into_iter().filter(category).collect()
+        };
+        let source = "var x = 1 + \"a\"; if (!x and nil) x -= 2;";
+        assert_eq!(
+            categorized(source, TokenKind::is_keyword),
+            [
+                TokenKind::Var,
+                TokenKind::If,
+                TokenKind::And,
+                TokenKind::Nil
+            ]
+        );
+        assert_eq!(
+            categorized(source, TokenKind::is_operator),
+            [
+                TokenKind::Equal,
+                TokenKind::Plus,
+                TokenKind::Bang,
+                TokenKind::And,
+                TokenKind::MinusEqual
+            ]
+        );
+        assert_eq!(
+            categorized(source, TokenKind::is_literal),
+            [
+                TokenKind::Number,
+                TokenKind::String,
+                TokenKind::Nil,
+                TokenKind::Number
+            ]
+        );
+        assert!(!TokenKind::Identifier.is_keyword());
+        assert!(!TokenKind::Semicolon.is_operator());
+    }
+
+    #[test]
+    fn underscore_identifiers() {
+        let ScanResult { tokens, errors } = scan("__add__ _x x_1 _");
+        assert_eq!(errors.len(), 0);
+        let lexemes: Vec<String> = tokens
+            .iter()
+            .filter(|token| token.kind == TokenKind::Identifier)
+            .map(|token| token.lexeme_str())
+            .collect();
+        assert_eq!(lexemes, ["__add__", "_x", "x_1", "_"]);
+        // Digits still can't start one
+        let ScanResult { tokens, .. } = scan("1_");
+        assert_eq!(tokens[0].kind, TokenKind::Number);
+        assert_eq!(tokens[1].lexeme_str(), "_");
+    }
+
     #[test]
     fn expressions() {
         let ScanResult { tokens, errors } = scan(EXPRESSION_TEST);
@@ -424,4 +664,118 @@ mod test {
         assert_eq!(errors.len(), 0);
         assert_eq!(tokens.len(), 49);
     }
+
+    #[test]
+    fn numbers() {
+        let ScanResult { tokens, errors } = scan("1 2.5 3.foo");
+        assert_eq!(errors.len(), 0);
+        let literals: Vec<Option<Literal>> =
+            tokens.into_iter().map(|token| token.literal).collect();
+        assert_eq!(
+            literals,
+            vec![
+                Some(Literal::Number(1.0)),
+                Some(Literal::Number(2.5)),
+                Some(Literal::Number(3.0)),
+                None,
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        let messages = |source: &str| -> Vec<String> {
+            scan(source)
+                .errors
+                .into_iter()
+                .map(|error| error.to_string())
+                .collect()
+        };
+        assert_eq!(
+            messages("1.2.3;"),
+            vec!["Syntax error on line 1: Unexpected \".\" in number \"1.2.3\""]
+        );
+        assert_eq!(
+            messages("1.;"),
+            vec!["Syntax error on line 1: Expected a digit after the decimal point in \"1.\""]
+        );
+        assert_eq!(
+            messages("1."),
+            vec!["Syntax error on line 1: Expected a digit after the decimal point in \"1.\""]
+        );
+        assert_eq!(
+            messages("1..2"),
+            vec!["Syntax error on line 1: Expected a digit after the decimal point in \"1.\""]
+        );
+    }
+
+    #[test]
+    fn lines() {
+        let ScanResult { tokens, errors } =
+            scan("var s = \"one\ntwo\";\n// comment\n\n  @ print s;\n// trailing comment");
+        let lines: Vec<u32> = tokens.iter().map(|token| token.line).collect();
+        assert_eq!(lines, vec![0, 0, 0, 0, 1, 4, 4, 4, 5]);
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec!["Syntax error on line 5: Unknown character \"@\" at column 2"]
+        );
+        assert_eq!(tokens[1].lexeme_str(), "s");
+        let columns: Vec<u32> = tokens.iter().map(|token| token.column).collect();
+        assert_eq!(columns, vec![0, 4, 6, 8, 4, 4, 10, 11, 19]);
+        assert_eq!(errors[0].column(), Some(2));
+    }
+
+    #[test]
+    fn two_character_tokens() {
+        let ScanResult { tokens, errors } = scan("a != b >= c =");
+        assert_eq!(errors.len(), 0);
+        let lexemes: Vec<String> = tokens[..tokens.len() - 1]
+            .iter()
+            .map(|token| token.lexeme_str())
+            .collect();
+        assert_eq!(lexemes, vec!["a", "!=", "b", ">=", "c", "="]);
+
+        let ScanResult { tokens, .. } = scan("a += b-- -= c *= /= d++ - -e ...f.g //");
+        let kinds: Vec<TokenKind> = tokens.iter().map(|token| token.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Identifier,
+                TokenKind::PlusEqual,
+                TokenKind::Identifier,
+                TokenKind::MinusMinus,
+                TokenKind::MinusEqual,
+                TokenKind::Identifier,
+                TokenKind::StarEqual,
+                TokenKind::SlashEqual,
+                TokenKind::Identifier,
+                TokenKind::PlusPlus,
+                TokenKind::Minus,
+                TokenKind::Minus,
+                TokenKind::Identifier,
+                TokenKind::DotDotDot,
+                TokenKind::Identifier,
+                TokenKind::Dot,
+                TokenKind::Identifier,
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let ScanResult { errors, .. } = scan("print 1;\nprint \"one\ntwo");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>(),
+            vec!["Syntax error on line 2: Unterminated string"]
+        );
+    }
 }
@@ -0,0 +1,78 @@
+use crate::value::{sorted_entries, LoxValue};
+use serde::ser::{Error, Serialize, SerializeMap, SerializeSeq, Serializer};
+
+/// How deeply values nest before serializing fails, e.g. because they
+/// contain themselves.
+const MAX_DEPTH: usize = 64;
+
+/// Values serialize like JSON: nil as a unit, Arrays as sequences, and Maps
+/// and other instances as maps of their entries or fields. Functions and
+/// classes can't be serialized.
+impl Serialize for LoxValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Nested(self, 0).serialize(serializer)
+    }
+}
+
+/// A value `depth` containers deep.
+struct Nested<'a>(&'a LoxValue, usize);
+
+impl Serialize for Nested<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Nested(value, depth) = *self;
+        if depth > MAX_DEPTH {
+            return Err(S::Error::custom(format!(
+                "Value is nested more than {} levels deep",
+                MAX_DEPTH
+            )));
+        }
+        match value {
+            LoxValue::Nil => serializer.serialize_unit(),
+            LoxValue::Boolean(value) => serializer.serialize_bool(*value),
+            LoxValue::Number(value) => serializer.serialize_f64(*value),
+            LoxValue::String(value) => serializer.serialize_str(value),
+            LoxValue::Vec(vec) => items(&vec.borrow(), depth, serializer),
+            LoxValue::Map(map) => entries(sorted_entries(&map.borrow()), depth, serializer),
+            LoxValue::Object(obj) => {
+                // Arrays keep their contents in an internal prop
+                let vec = obj.borrow().get("__vec__");
+                match vec {
+                    Some(LoxValue::Vec(vec)) => items(&vec.borrow(), depth, serializer),
+                    _ => entries(
+                        value.entries().map_err(S::Error::custom)?.collect(),
+                        depth,
+                        serializer,
+                    ),
+                }
+            }
+            value => Err(S::Error::custom(format!(
+                "Cannot serialize a {}",
+                value.type_str()
+            ))),
+        }
+    }
+}
+
+fn items<S: Serializer>(
+    items: &[LoxValue],
+    depth: usize,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut seq = serializer.serialize_seq(Some(items.len()))?;
+    for item in items.iter() {
+        seq.serialize_element(&Nested(item, depth + 1))?;
+    }
+    seq.end()
+}
+
+fn entries<S: Serializer>(
+    entries: Vec<(String, LoxValue)>,
+    depth: usize,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(entries.len()))?;
+    for (key, value) in entries.iter() {
+        map.serialize_entry(key, &Nested(value, depth + 1))?;
+    }
+    map.end()
+}
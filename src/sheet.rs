@@ -0,0 +1,288 @@
+use crate::{
+    error::*,
+    expr::{Expr, ExprKind},
+    interpreter::LoxInterpreter,
+    parser::parse,
+    stmt::Stmt,
+    value::LoxValue,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// A formula that couldn't be given a value.
+#[derive(PartialEq, Clone, Debug)]
+pub struct FormulaError {
+    pub name: String,
+    pub message: String,
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.message)
+    }
+}
+
+struct Formula {
+    source: String,
+    /// Every identifier the formula mentions, cells or not.
+    references: BTreeSet<String>,
+}
+
+/// Named inputs and formulas over them, spreadsheet style. Formulas are rules
+/// as in `LoxInterpreter::evaluate_rule` and refer to other cells by name.
+/// Changing a cell re-evaluates exactly the formulas depending on it, in
+/// dependency order. Formulas that fail, including those in a circular
+/// reference, have no value and a diagnostic instead.
+pub struct Sheet {
+    lox: LoxInterpreter,
+    inputs: BTreeMap<String, LoxValue>,
+    formulas: BTreeMap<String, Formula>,
+    values: BTreeMap<String, LoxValue>,
+    diagnostics: BTreeMap<String, String>,
+}
+
+impl Default for Sheet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sheet {
+    pub fn new() -> Self {
+        Self {
+            lox: LoxInterpreter::rule_engine(),
+            inputs: BTreeMap::new(),
+            formulas: BTreeMap::new(),
+            values: BTreeMap::new(),
+            diagnostics: BTreeMap::new(),
+        }
+    }
+
+    /// Set an input cell, returning the formulas re-evaluated as a result in
+    /// the order they ran.
+    pub fn set_input(&mut self, name: &str, value: LoxValue) -> Vec<String> {
+        self.formulas.remove(name);
+        self.values.remove(name);
+        self.diagnostics.remove(name);
+        self.inputs.insert(name.into(), value);
+        self.recalculate(name)
+    }
+
+    /// Set a formula cell and evaluate it, returning the formulas evaluated as
+    /// a result in the order they ran. Fails without changing the sheet if
+    /// `source` isn't a single expression.
+    pub fn set_formula(&mut self, name: &str, source: &str) -> LoxResult<Vec<String>> {
+        let references = references(source)?;
+        self.inputs.remove(name);
+        self.formulas.insert(
+            name.into(),
+            Formula {
+                source: source.into(),
+                references,
+            },
+        );
+        Ok(self.recalculate(name))
+    }
+
+    /// The value of an input, or of a formula that evaluated successfully.
+    pub fn get(&self, name: &str) -> Option<&LoxValue> {
+        self.inputs.get(name).or_else(|| self.values.get(name))
+    }
+
+    /// Why formulas have no value, sorted by name.
+    pub fn diagnostics(&self) -> Vec<FormulaError> {
+        self.diagnostics
+            .iter()
+            .map(|(name, message)| FormulaError {
+                name: name.clone(),
+                message: message.clone(),
+            })
+            .collect()
+    }
+
+    /// Re-evaluate the formula `changed`, if it is one, and every formula
+    /// depending on it, directly or not.
+    fn recalculate(&mut self, changed: &str) -> Vec<String> {
+        // Formulas to evaluate: the changed cell and its transitive dependents
+        let mut affected = BTreeSet::from([changed.to_string()]);
+        loop {
+            let dependents: Vec<String> = self
+                .formulas
+                .iter()
+                .filter(|(name, formula)| {
+                    !affected.contains(*name) && !formula.references.is_disjoint(&affected)
+                })
+                .map(|(name, _)| name.clone())
+                .collect();
+            if dependents.is_empty() {
+                break;
+            }
+            affected.extend(dependents);
+        }
+        affected.retain(|name| self.formulas.contains_key(name));
+        for name in affected.iter() {
+            self.values.remove(name);
+            self.diagnostics.remove(name);
+        }
+        // Evaluate in topological order, a formula once none of the affected
+        // formulas it references are pending
+        let mut order = vec![];
+        let mut pending = affected;
+        loop {
+            let ready: Vec<String> = pending
+                .iter()
+                .filter(|name| self.formulas[*name].references.is_disjoint(&pending))
+                .cloned()
+                .collect();
+            if ready.is_empty() {
+                break;
+            }
+            for name in ready {
+                pending.remove(&name);
+                self.evaluate(&name);
+                order.push(name);
+            }
+        }
+        // Whatever is left is in a cycle or depends on one
+        let cycle = pending.iter().cloned().collect::<Vec<String>>().join(", ");
+        for name in pending.iter() {
+            self.diagnostics.insert(
+                name.clone(),
+                format!("Circular reference between {}", cycle),
+            );
+        }
+        order
+    }
+
+    fn evaluate(&mut self, name: &str) {
+        let formula = &self.formulas[name];
+        let mut bindings = vec![];
+        for reference in formula.references.iter() {
+            if let Some(value) = self.get(reference) {
+                bindings.push((reference.as_str(), value.clone()));
+            } else if self.diagnostics.contains_key(reference) {
+                let message = format!("Depends on \"{}\", which has no value", reference);
+                self.diagnostics.insert(name.into(), message);
+                return;
+            }
+        }
+        match self.lox.evaluate_rule(&formula.source, &bindings) {
+            Ok(value) => {
+                self.values.insert(name.into(), value);
+            }
+            Err(err) => {
+                self.diagnostics.insert(name.into(), err.to_string());
+            }
+        }
+    }
+}
+
+/// Names of the identifiers in the single expression `source`.
+fn references(source: &str) -> LoxResult<BTreeSet<String>> {
+    let mut program = parse(&format!("{};", source.trim_end().trim_end_matches(';')));
+    if !program.errors.is_empty() {
+        return Err(program.errors.remove(0));
+    }
+    let [Stmt::Expr(expr)] = program.statements.as_slice() else {
+        return Err(LoxError::General(
+            "A formula must be a single expression".into(),
+        ));
+    };
+    let mut names = BTreeSet::new();
+    collect_identifiers(expr, &mut names);
+    Ok(names)
+}
+
+fn collect_identifiers(expr: &Expr, names: &mut BTreeSet<String>) {
+    match &expr.kind {
+        ExprKind::Identifier(name) | ExprKind::Assignment { name, .. } => {
+            names.insert(name.lexeme_str());
+            if let ExprKind::Assignment { value, .. } = &expr.kind {
+                collect_identifiers(value, names);
+            }
+        }
+        ExprKind::Literal(_) | ExprKind::This(_) | ExprKind::Super(_) => {}
+        ExprKind::Unary { right, .. } => collect_identifiers(right, names),
+        ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+            collect_identifiers(left, names);
+            collect_identifiers(right, names);
+        }
+        ExprKind::Grouping(inner) | ExprKind::Get { left: inner, .. } => {
+            collect_identifiers(inner, names)
+        }
+        ExprKind::Set { object, value, .. } => {
+            collect_identifiers(object, names);
+            collect_identifiers(value, names);
+        }
+        ExprKind::Call { callee, arguments } => {
+            collect_identifiers(callee, names);
+            for arg in arguments.iter() {
+                collect_identifiers(arg, names);
+            }
+        }
+        ExprKind::Tuple(values) => {
+            for value in values.iter() {
+                collect_identifiers(value, names);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recalculation() -> LoxResult {
+        let mut sheet = Sheet::new();
+        sheet.set_input("price", 10.0.into());
+        sheet.set_input("quantity", 3.0.into());
+        sheet.set_formula("total", "subtotal + tax")?;
+        sheet.set_formula("subtotal", "price * quantity")?;
+        sheet.set_formula("tax", "subtotal / 10")?;
+        sheet.set_formula("label", "\"items: \" + quantity")?;
+        assert!(sheet.get("total") == Some(&33.0.into()));
+        assert_eq!(
+            sheet.set_input("price", 20.0.into()),
+            vec!["subtotal", "tax", "total"]
+        );
+        assert!(sheet.get("total") == Some(&66.0.into()));
+        assert!(sheet.diagnostics().is_empty());
+
+        // A cycle leaves the formulas in it, and those after it, without a value
+        sheet.set_formula("subtotal", "total - tax")?;
+        assert!(sheet.get("total").is_none());
+        assert_eq!(
+            sheet
+                .diagnostics()
+                .iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "subtotal: Circular reference between subtotal, tax, total",
+                "tax: Circular reference between subtotal, tax, total",
+                "total: Circular reference between subtotal, tax, total",
+            ]
+        );
+        // Breaking the cycle recovers
+        sheet.set_formula("subtotal", "price")?;
+        assert!(sheet.get("total") == Some(&22.0.into()));
+        assert!(sheet.diagnostics().is_empty());
+
+        sheet.set_formula("broken", "price / zero")?;
+        sheet.set_formula("after", "broken + 1")?;
+        sheet.set_input("zero", 0.0.into());
+        assert_eq!(
+            sheet.diagnostics()[0].to_string(),
+            "after: Depends on \"broken\", which has no value"
+        );
+        assert!(sheet.diagnostics()[1]
+            .message
+            .starts_with("Runtime Error: Division by zero"));
+        assert!(sheet.set_formula("bad", "var x = 1;").is_err());
+        assert!(sheet.get("bad").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+//! The language specification as test cases: scripts with what they must
+//! print, and the error they must stop with, if any. Other implementations
+//! can run them to check they behave like this one.
+
+use crate::test_scripts::*;
+
+/// Bumped whenever a case is changed or removed, so downstream suites can
+/// tell which expectations they were written against. Adding cases doesn't
+/// change the version.
+pub const VERSION: u32 = 1;
+
+pub struct TestCase {
+    pub name: &'static str,
+    pub source: &'static str,
+    /// Everything printed, one line per `print`.
+    pub output: &'static str,
+    /// A message the error must contain, or `None` if the script succeeds.
+    /// Output printed before an error still has to match.
+    pub error: Option<&'static str>,
+}
+
+impl TestCase {
+    const fn ok(name: &'static str, source: &'static str, output: &'static str) -> Self {
+        Self {
+            name,
+            source,
+            output,
+            error: None,
+        }
+    }
+
+    const fn err(
+        name: &'static str,
+        source: &'static str,
+        output: &'static str,
+        error: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            source,
+            output,
+            error: Some(error),
+        }
+    }
+}
+
+/// Every case, each meant to run on a fresh interpreter. Nothing here depends
+/// on the clock, randomness or files.
+pub fn test_cases() -> Vec<TestCase> {
+    vec![
+        TestCase::err(
+            "missing_semicolon",
+            EXPRESSION_TEST,
+            "",
+            "Expected a semicolon",
+        ),
+        TestCase::ok("variables", VARIABLE_TEST, ""),
+        TestCase::ok("print", PRINT_TEST, "3.14\nnil\n"),
+        TestCase::ok("block_scope", BLOCK_SCOPE_TEST, "foo\nbar\n"),
+        TestCase::ok("control_flow", CONTROL_FLOW_TEST, "true\ntrue\n"),
+        TestCase::ok("while_loop", WHILE_LOOP_TEST, "4\n3\n2\n1\n"),
+        TestCase::ok("for_loop", FOR_LOOP_TEST, "0\n1\n2\n3\n42\n"),
+        TestCase::ok("function", FUNCTION_TEST, "Hello, world!\n"),
+        TestCase::ok("closure", FUNCTION_CLOSURE_TEST, "1\n2\n"),
+        TestCase::ok("shadowing", SHADOWING_TEST, "global\nglobal\n"),
+        TestCase::ok("class", CLASS_TEST, "Hello, world!\n"),
+        TestCase::ok(
+            "inheritance",
+            CLASS_INHERITANCE_TEST,
+            "Hello, world!\nHowdy, partner!\n",
+        ),
+        TestCase::ok("method_chaining", METHOD_CHAINING_TEST, "1\n"),
+        TestCase::ok("to_string", TO_STRING_TEST, "Point(1)\nPlain {}\n"),
+        TestCase::ok("accessors", ACCESSOR_TEST, "4\n27\n1\n9\n"),
+        TestCase::ok("static_methods", STATIC_METHOD_TEST, "9\n8\n16\n"),
+        TestCase::ok("for_in", FOR_IN_TEST, "x = 1\ny = 2\na\nb\n"),
+        TestCase::ok(
+            "operator_overloading",
+            OPERATOR_OVERLOAD_TEST,
+            "425\n3\n3\n1\n",
+        ),
+        TestCase::ok(
+            "early_return",
+            EARLY_RETURN_TEST,
+            "3\n1\ntruthy\nfalsy\ndone\n",
+        ),
+        TestCase::ok(
+            "compound_assignment",
+            COMPOUND_ASSIGNMENT_TEST,
+            "6\n7\n11\n11\n13\n13\n12\nabc\n1\n1\n",
+        ),
+        TestCase::err(
+            "undefined_variable",
+            "print 1; print missing;",
+            "1\n",
+            "Undefined variable \"missing\"",
+        ),
+        TestCase::err(
+            "operand_type",
+            "var a = 1; print a; a = a + \"x\" * 2;",
+            "1\n",
+            "Expected Number",
+        ),
+        TestCase::err(
+            "equality",
+            "print 1 == 1; print \"a\" == \"a\"; print nil == false; print 1 / 0 > 0; -\"a\";",
+            "true\ntrue\nfalse\ntrue\n",
+            "Invalid operand -a",
+        ),
+        TestCase::err(
+            "number_property",
+            "var i = 0; while (i < 3) { print i; i = i + 1; } i.field;",
+            "0\n1\n2\n",
+            "Undefined number method \"field\"",
+        ),
+        TestCase::err(
+            "undefined_property",
+            "class A {} var a = A(); a.x = 1; print a.x; a.y;",
+            "1\n",
+            "Undefined property \"y\"",
+        ),
+        TestCase::err(
+            "arity",
+            "fun f(n) { return n * 2; } print f(2); f();",
+            "4\n",
+            "takes 1 argument(s)",
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::LoxConfig,
+        interpreter::{Backend, LoxInterpreter},
+    };
+    use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+    #[test]
+    fn conformance() {
+        let cases = test_cases();
+        let names: HashSet<&str> = cases.iter().map(|case| case.name).collect();
+        assert_eq!(names.len(), cases.len());
+        for backend in Backend::ALL {
+            for case in cases.iter() {
+                let mut lox = LoxInterpreter::with_backend(LoxConfig::default(), backend);
+                let buffer = Rc::new(RefCell::new(String::new()));
+                lox.set_output(Box::new(buffer.clone()));
+                let result = lox.exec(case.source);
+                assert_eq!(buffer.take(), case.output, "{}", case.name);
+                match (result, case.error) {
+                    (Ok(()), None) => {}
+                    (Err(err), Some(error)) => {
+                        assert!(err.to_string().contains(error), "{}: {}", case.name, err)
+                    }
+                    (Ok(()), Some(error)) => panic!("{}: expected \"{}\"", case.name, error),
+                    (Err(err), None) => panic!("{}: {}", case.name, err),
+                }
+            }
+        }
+    }
+}
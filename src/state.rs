@@ -1,44 +1,590 @@
 use crate::{
-    environment::{ScopeHandle, GLOBAL_SCOPE},
-    error::{LoxError, LoxResult},
+    class::LoxClass,
+    environment::ScopeHandle,
+    error::{InternalError, LoxError, LoxResult, Span},
     expr::Expr,
+    file_system::{FileSystem, HostFileSystem},
+    function::LoxFunction,
+    object::{LoxObject, PropertyInterceptor},
+    stmt::Stmt,
+    suggest::did_you_mean,
 };
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use super::{
+    coverage::Coverage,
+    environment::Environment,
+    hotspots::Hotspots,
+    observer::{NativeCall, Observer},
+    output::OutputSink,
+    replay::Inputs,
+    resolver::{Local, Locals},
+    value::LoxValue,
+};
+
+/// How deeply script functions may call each other by default. How much
+/// Rust stack a call takes depends on the statements nesting it, from a few
+/// kilobytes in release builds to over a hundred in debug builds, so the
+/// stack is also guarded directly, see `DEFAULT_STACK_SIZE`.
+pub const DEFAULT_STACK_DEPTH: usize = 128;
+
+/// Bytes of Rust stack script function calls may use by default, measured
+/// from the outermost call. Half the 2 MiB Rust gives spawned threads, which
+/// leaves room for the host code around the calls and the last call itself.
+pub const DEFAULT_STACK_SIZE: usize = 1024 * 1024;
+
+/// Bounds on the work a single run may do, for untrusted scripts. Going over
+/// one raises `LoxError::LimitExceeded`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExecLimits {
+    /// Statements, expressions and bytecode instructions evaluated.
+    pub max_steps: Option<usize>,
+    /// Script function calls in progress at once.
+    pub max_call_depth: Option<usize>,
+    /// Wall-clock time.
+    pub timeout: Option<Duration>,
+}
 
-use super::{environment::Environment, resolver::Locals, value::LoxValue};
+/// The address of a local, telling how far down the Rust stack the caller
+/// is.
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// Whether declaring the function `name` should update `old`, which is only
+/// the case for a script function declared with that name, not a method
+/// or native function assigned to the variable.
+fn redefines_function(old: &LoxFunction, name: &str) -> bool {
+    old.name.as_deref() == Some(name) && old.this_value.is_none() && old.origin().is_some()
+}
+
+/// Like `redefines_function`, for classes. The new class can't inherit from
+/// the old one, since updating it would make it its own ancestor.
+fn redefines_class(old: &Rc<RefCell<LoxClass>>, new: &Rc<RefCell<LoxClass>>, name: &str) -> bool {
+    if old.borrow().name != name || old.borrow().origin.is_none() {
+        return false;
+    }
+    let mut ancestor = new.borrow().superclass.clone();
+    while let Some(class) = ancestor {
+        if Rc::ptr_eq(&class, old) {
+            return false;
+        }
+        ancestor = class.borrow().superclass.clone();
+    }
+    true
+}
 
 pub struct LoxState {
     pub env: Environment,
-    pub locals: Locals,
+    pub(crate) locals: Locals,
     pub stack: Vec<LoxValue>,
+    pub(crate) inputs: Inputs,
+    pub coverage: Option<Coverage>,
+    pub hotspots: Option<Hotspots>,
+    pub interceptor: Option<Rc<dyn PropertyInterceptor>>,
+    pub output: Box<dyn OutputSink>,
+    pub observer: Option<Box<dyn Observer>>,
+    /// Every native function invocation, when auditing.
+    pub audit: Option<Vec<NativeCall>>,
+    /// Reject arithmetic results that aren't finite.
+    pub checked_math: bool,
+    /// End the output of each `print` statement with a newline.
+    pub print_newline: bool,
+    /// Only let an instance's `init` add properties to it.
+    pub strict_properties: bool,
+    /// The instances whose `init` is running, innermost last.
+    pub initializing: Vec<Rc<RefCell<LoxObject>>>,
+    /// Where the innermost expression that raised the current error starts,
+    /// since runtime errors only carry a line.
+    pub error_span: Option<Span>,
+    /// Where the file builtins read and write.
+    pub fs: Box<dyn FileSystem>,
+    /// The blocks deferred by each function call in progress, innermost
+    /// call last.
+    pub(crate) deferred: Vec<Vec<(Stmt, ScopeHandle)>>,
+    /// Called with errors escaping a top-level statement, see `recover`.
+    pub error_handler: Option<LoxValue>,
+    handling_error: bool,
+    pub limits: ExecLimits,
+    /// The value being thrown while a `LoxError::Thrown` unwinds.
+    pub thrown: Option<LoxValue>,
+    /// Calls in progress past which a call raises a "Stack overflow" runtime
+    /// error, rather than overflowing the Rust stack.
+    pub stack_depth: usize,
+    /// Bytes of Rust stack past which a call raises a "Stack overflow"
+    /// runtime error.
+    pub stack_size: usize,
+    /// Where the outermost call in progress was on the Rust stack.
+    stack_base: usize,
+    /// Steps taken and when time runs out in the current run.
+    steps: usize,
+    deadline: Option<Instant>,
 }
 
 impl LoxState {
-    pub fn new(locals: Locals) -> Self {
+    pub fn new(env: Environment) -> Self {
         Self {
-            env: Environment::new(),
-            locals,
+            env,
+            locals: Locals::new(),
             stack: vec![],
+            inputs: Inputs::default(),
+            coverage: None,
+            hotspots: None,
+            interceptor: None,
+            #[cfg(feature = "log")]
+            output: Box::new(crate::output::LogSink),
+            #[cfg(not(feature = "log"))]
+            output: Box::new(crate::output::StdoutSink),
+            observer: None,
+            audit: None,
+            checked_math: false,
+            print_newline: true,
+            strict_properties: false,
+            initializing: vec![],
+            error_span: None,
+            deferred: vec![],
+            fs: Box::new(HostFileSystem),
+            error_handler: None,
+            handling_error: false,
+            limits: ExecLimits::default(),
+            thrown: None,
+            stack_depth: DEFAULT_STACK_DEPTH,
+            stack_size: DEFAULT_STACK_SIZE,
+            stack_base: 0,
+            steps: 0,
+            deadline: None,
+        }
+    }
+
+    /// Drop what an interrupted run left behind: values on the stack, pending
+    /// deferred blocks and the location of its error.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.deferred.clear();
+        self.error_span = None;
+        self.handling_error = false;
+        self.thrown = None;
+        self.initializing.clear();
+    }
+
+    /// The value a `catch` binds for `err`: the thrown value, or the message
+    /// of other errors. `None` for what can't be caught: the unwinding of
+    /// `return`, `exit`, exceeded limits and internal errors.
+    pub fn catch(&mut self, err: &LoxError) -> Option<LoxValue> {
+        let value = match err {
+            LoxError::Return
+            | LoxError::Exit(_)
+            | LoxError::LimitExceeded(_)
+            | LoxError::Internal(_) => return None,
+            LoxError::Thrown(..) => self.thrown.take().unwrap_or(LoxValue::Nil),
+            err => err.to_string().into(),
+        };
+        self.error_span = None;
+        Some(value)
+    }
+
+    /// Send the output of a `print` statement to the output sink.
+    pub fn print(&mut self, text: &str) {
+        if self.print_newline {
+            self.output.write_line(text);
+        } else {
+            self.output.write(text);
+        }
+    }
+
+    /// Start counting steps and time against the limits again, at the
+    /// beginning of a run.
+    pub fn reset_budget(&mut self) {
+        self.steps = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// Count a statement, expression or instruction against the limits.
+    pub fn step(&mut self) -> LoxResult {
+        self.steps += 1;
+        if let Some(max) = self.limits.max_steps {
+            if self.steps > max {
+                return Err(LoxError::LimitExceeded(format!(
+                    "Script took more than {} steps",
+                    max
+                )));
+            }
+        }
+        // Reading the clock is slow compared to a step, so only do it now
+        // and then
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+            if self.steps.is_multiple_of(256) && Instant::now() > deadline {
+                return Err(LoxError::LimitExceeded(format!(
+                    "Script ran for more than {:?}",
+                    timeout
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check there's room for another script function call, both in calls
+    /// and in Rust stack. Each call in progress has a frame in `deferred`.
+    pub fn check_call_depth(&mut self, line: u32) -> LoxResult {
+        let depth = self.deferred.len();
+        let address = stack_address();
+        if depth == 0 {
+            self.stack_base = address;
+        }
+        match self.limits.max_call_depth {
+            Some(max) if depth >= max => Err(LoxError::LimitExceeded(format!(
+                "More than {} nested calls",
+                max
+            ))),
+            _ if depth >= self.stack_depth
+                || self.stack_base.abs_diff(address) > self.stack_size =>
+            {
+                Err(LoxError::Runtime("Stack overflow".into(), line))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Pass `err`, raised by a top-level statement, to the error handler.
+    /// Returning nil lets the error propagate, any other value replaces it so
+    /// execution carries on with the next statement. Errors raised by the
+    /// handler itself, `exit` and exceeded limits aren't handled.
+    pub fn recover(&mut self, err: LoxError) -> LoxResult<LoxValue> {
+        let Some(handler) = self.error_handler.clone() else {
+            return Err(err);
+        };
+        if self.handling_error
+            || matches!(
+                err,
+                LoxError::Exit(_) | LoxError::Return | LoxError::LimitExceeded(_)
+            )
+        {
+            return Err(err);
+        }
+        let line = err.line().unwrap_or(0);
+        self.handling_error = true;
+        let result = handler.call(self, vec![err.to_string().into()], line);
+        self.handling_error = false;
+        match result? {
+            LoxValue::Nil => Err(err),
+            value => {
+                self.error_span = None;
+                Ok(value)
+            }
+        }
+    }
+
+    /// Remember the position of the expression an error was raised in. Errors
+    /// propagate outwards, so the first expression on the error's line wins.
+    pub fn locate_error(&mut self, err: &LoxError, line: u32, column: u32) {
+        if self.error_span.is_none() && err.line() == Some(line) {
+            self.error_span = Some(Span {
+                line,
+                column: Some(column),
+            });
+        }
+    }
+
+    /// Declare a function or class. Declaring a script function or class again
+    /// in a root scope, like entering a definition again at the REPL, updates
+    /// the existing one in place: instances of a class pick up its new
+    /// methods, and values holding a function call the new body.
+    pub fn declare_definition(&mut self, scope: ScopeHandle, name: String, value: LoxValue) {
+        let previous = match self.env.root_scope(scope) == scope {
+            true => self.env.get_declared(scope, &name),
+            false => None,
+        };
+        let value = match (previous, value) {
+            (Some(LoxValue::Function(old)), LoxValue::Function(new))
+                if redefines_function(&old.borrow(), &name) =>
+            {
+                let new = new.borrow().clone();
+                *old.borrow_mut() = new;
+                LoxValue::Function(old)
+            }
+            (Some(LoxValue::Class(old)), LoxValue::Class(new))
+                if redefines_class(&old, &new, &name) =>
+            {
+                let new = new.borrow().clone();
+                *old.borrow_mut() = new;
+                LoxValue::Class(old)
+            }
+            (_, value) => value,
+        };
+        self.env.declare(Some(scope), name, value);
+    }
+
+    /// Create a scope for running code nested in `parent`.
+    pub fn new_scope(&mut self, parent: ScopeHandle) -> ScopeHandle {
+        let scope = self.env.new_scope(Some(parent));
+        if let Some(observer) = &mut self.observer {
+            observer.scope_created(scope, parent);
+        }
+        scope
+    }
+
+    /// See `Environment::copy_scope`.
+    pub fn copy_scope(&mut self, scope: ScopeHandle) -> ScopeHandle {
+        let copy = self.env.copy_scope(scope);
+        if let Some(observer) = &mut self.observer {
+            if let Some(parent) = self.env.parent_scope(copy) {
+                observer.scope_created(copy, parent);
+            }
+        }
+        copy
+    }
+
+    /// Run `body` in `scope` when the innermost function call finishes.
+    pub fn defer(&mut self, body: Stmt, scope: ScopeHandle, line: u32) -> LoxResult {
+        match self.deferred.last_mut() {
+            Some(frame) => {
+                frame.push((body, scope));
+                Ok(())
+            }
+            None => Err(LoxError::Runtime(
+                "Cannot defer outside of a function".into(),
+                line,
+            )),
+        }
+    }
+
+    /// Run the blocks deferred by the function call finishing with `result`,
+    /// most recently deferred first. Every block runs even if an earlier one
+    /// fails, and an error ending the call wins over errors from the blocks.
+    pub fn run_deferred(&mut self, mut result: LoxResult) -> LoxResult {
+        let frame = self.deferred.pop().unwrap_or_default();
+        for (body, scope) in frame.into_iter().rev() {
+            let outcome = body.eval(self, scope);
+            if let (Ok(()) | Err(LoxError::Return), Err(err)) = (&result, outcome) {
+                result = Err(err);
+            }
         }
+        result
+    }
+
+    /// Record an invocation of a native function for the audit log and the
+    /// observer.
+    pub fn audit_native(&mut self, name: &str, args: &[LoxValue], line: u32) {
+        if self.audit.is_none() && self.observer.is_none() {
+            return;
+        }
+        let timestamp = self.inputs.now().unwrap_or_default();
+        let call = NativeCall::new(name, args, line, timestamp);
+        if let Some(observer) = &mut self.observer {
+            observer.native_call(&call);
+        }
+        if let Some(audit) = &mut self.audit {
+            audit.push(call);
+        }
+    }
+
+    /// Look up a method of the builtin class for primitive values, e.g.
+    /// String, bound to `value`.
+    pub fn get_primitive_method(
+        &self,
+        class_name: &str,
+        value: LoxValue,
+        name: &str,
+        line: u32,
+    ) -> LoxResult<LoxValue> {
+        let class = self
+            .env
+            .get(None, class_name)
+            .ok_or_else(|| {
+                LoxError::Runtime(
+                    format!("Expected the {} builtin to exist", class_name),
+                    line,
+                )
+            })?
+            .get_class(line)?;
+        let (method, owner) = LoxClass::find_method(&class, name).ok_or_else(|| {
+            let suggestion = did_you_mean(name, &class.borrow().method_names());
+            LoxError::Runtime(
+                format!(
+                    "Undefined {} method \"{}\"{}",
+                    class_name.to_lowercase(),
+                    name,
+                    suggestion
+                ),
+                line,
+            )
+        })?;
+        Ok(LoxClass::bind_method(method, &owner, value).into())
+    }
+
+    /// Read a property, giving the interceptor a chance to veto or supply it
+    /// for native objects. Getters are called for their value.
+    pub fn get_property(
+        &mut self,
+        obj: &Rc<RefCell<LoxObject>>,
+        key: &str,
+        line: u32,
+    ) -> LoxResult<Option<LoxValue>> {
+        if let Some(interceptor) = &self.interceptor {
+            let mut obj = obj.borrow_mut();
+            if obj.native {
+                if let Some(value) = interceptor.get(&mut obj, key, line)? {
+                    return Ok(Some(value));
+                }
+            }
+        }
+        match LoxObject::lookup(obj, key) {
+            Some(LoxValue::Function(fun)) if fun.borrow().is_getter => {
+                fun.borrow().call(self, vec![], line).map(Some)
+            }
+            value => Ok(value),
+        }
+    }
+
+    /// Write a property, giving the interceptor a chance to veto it for
+    /// native objects. Setters are called with the value instead.
+    pub fn set_property(
+        &mut self,
+        obj: &Rc<RefCell<LoxObject>>,
+        key: &str,
+        value: LoxValue,
+        line: u32,
+    ) -> LoxResult {
+        let class = obj.borrow().class.clone();
+        if let Some((setter, owner)) = class.and_then(|class| LoxClass::find_setter(&class, key)) {
+            LoxClass::bind_method(setter, &owner, obj.clone().into()).call(
+                self,
+                vec![value],
+                line,
+            )?;
+            return Ok(());
+        }
+        if self.strict_properties && !self.may_add_property(obj, key) {
+            return Err(LoxError::Runtime(
+                format!(
+                    "Cannot add property \"{}\" to \"{}\" outside of init in strict mode{}",
+                    key,
+                    obj.borrow().class_name,
+                    did_you_mean(key, &LoxObject::field_names(obj))
+                ),
+                line,
+            ));
+        }
+        let mut obj = obj.borrow_mut();
+        if let Some(interceptor) = &self.interceptor {
+            if obj.native {
+                interceptor.set(&mut obj, key, &value, line)?;
+            }
+        }
+        // Only allocate the key for new properties
+        match obj.props.get_mut(key) {
+            Some(prop) => *prop = value,
+            None => {
+                obj.set(key.into(), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether writing `key` on `obj` is allowed in strict mode: the
+    /// property exists, `obj` is being initialized, or it has no class.
+    fn may_add_property(&self, obj: &Rc<RefCell<LoxObject>>, key: &str) -> bool {
+        let borrowed = obj.borrow();
+        borrowed.props.contains_key(key)
+            || borrowed.class.is_none()
+            || self
+                .initializing
+                .iter()
+                .any(|initializing| Rc::ptr_eq(initializing, obj))
     }
 
     pub fn resolve_local(
+        &mut self,
+        scope: ScopeHandle,
+        expr: &Expr,
+        key: &str,
+        line: u32,
+    ) -> LoxResult<LoxValue> {
+        let (scope, slot) = self.locate(scope, expr, key, line)?;
+        if let Some(observer) = &mut self.observer {
+            observer.resolve_variable(key, line, scope, self.locals.get(expr).copied());
+        }
+        self.get_variable_at(scope, slot, key, line)
+    }
+
+    /// Find the scope, and slot if local, of the variable `expr` refers to
+    /// from `scope`, following the resolver.
+    pub fn locate(
         &self,
         scope: ScopeHandle,
         expr: &Expr,
         key: &str,
         line: u32,
+    ) -> LoxResult<(ScopeHandle, Option<usize>)> {
+        let local = self.locals.get(expr).copied();
+        match local {
+            Some(Local { depth, slot }) => match self.env.ancestor_scope(scope, depth) {
+                Some(scope) => Ok((scope, Some(slot))),
+                None => Err(
+                    InternalError::new(format!("Invalid ancestor scope for \"{}\"", key))
+                        .with_line(line)
+                        .with_node(expr)
+                        .with_local(local)
+                        .into(),
+                ),
+            },
+            None => Ok((self.env.root_scope(scope), None)),
+        }
+    }
+
+    /// Assign to the variable `key` declared in `scope`, unless it's a
+    /// constant. See `Environment::assign_at` for `slot`.
+    pub fn assign_variable_at(
+        &mut self,
+        scope: ScopeHandle,
+        slot: Option<usize>,
+        key: &str,
+        value: LoxValue,
+        line: u32,
+    ) -> LoxResult {
+        if self.env.is_constant_at(scope, slot, key) {
+            return Err(LoxError::Runtime(
+                format!("Cannot assign to constant \"{}\"", key),
+                line,
+            ));
+        }
+        match self.env.assign_at(scope, slot, key, value) {
+            Some(_) => Ok(()),
+            None => Err(self.undefined_variable(scope, key, line)),
+        }
+    }
+
+    /// Read the variable `key` declared in `scope`.
+    pub fn get_variable(&self, scope: ScopeHandle, key: &str, line: u32) -> LoxResult<LoxValue> {
+        self.get_variable_at(scope, None, key, line)
+    }
+
+    /// Read the variable `key` declared in `scope`, from `slot` if it's there.
+    fn get_variable_at(
+        &self,
+        scope: ScopeHandle,
+        slot: Option<usize>,
+        key: &str,
+        line: u32,
     ) -> LoxResult<LoxValue> {
-        let scope = match self.locals.get(expr) {
-            Some(depth) => self
-                .env
-                .ancestor_scope(scope, *depth)
-                .ok_or_else(|| LoxError::Runtime("Invalid scope".into(), line)),
-            None => Ok(GLOBAL_SCOPE),
-        }?;
-        let expr_id = expr.id();
-        println!("get {expr}({expr_id}) from scope {scope}");
         self.env
-            .get(Some(scope), key)
-            .ok_or_else(|| LoxError::Runtime(format!("Undefined variable \"{}\"", key), line))
+            .get_at(scope, slot, key)
+            .ok_or_else(|| self.undefined_variable(scope, key, line))
+    }
+
+    /// The error for using `key` where no variable of that name is visible
+    /// from `scope`, suggesting a similar one.
+    fn undefined_variable(&self, scope: ScopeHandle, key: &str, line: u32) -> LoxError {
+        let suggestion = did_you_mean(key, &self.env.visible_names(scope));
+        LoxError::Runtime(
+            format!("Undefined variable \"{}\"{}", key, suggestion),
+            line,
+        )
     }
 }
@@ -4,12 +4,40 @@ use super::{
     error::*,
     expr::{Expr, ExprKind},
     function::*,
+    object::LoxObject,
+    resolver::Locals,
     scanner::Token,
     state::LoxState,
     value::LoxValue,
 };
-use log::info;
-use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
+    rc::Rc,
+};
+
+/// Functions and methods, or the accessors a class can declare with `get` and
+/// `set`, which run when the property of that name is read or written.
+/// `static` methods are called on the class itself, with `this` bound to it.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum FunKind {
+    Function,
+    Getter,
+    Setter,
+    Static,
+}
+
+impl fmt::Display for FunKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Function => write!(f, "fun"),
+            Self::Getter => write!(f, "get"),
+            Self::Setter => write!(f, "set"),
+            Self::Static => write!(f, "static"),
+        }
+    }
+}
 
 #[derive(PartialEq, Clone)]
 pub enum Stmt {
@@ -18,8 +46,19 @@ pub enum Stmt {
     Var {
         name: Token,
         initializer: Option<Box<Expr>>,
+        /// `false` for `const` declarations.
+        mutable: bool,
+    },
+    /// `var a, b = expr;`, unpacking an Array into one variable per name.
+    Destructure {
+        names: Vec<Token>,
+        initializer: Box<Expr>,
+    },
+    Block {
+        /// The opening brace, locating blocks without statements.
+        brace: Token,
+        statements: Vec<Stmt>,
     },
-    Block(Vec<Stmt>),
     IfElse {
         condition: Box<Expr>,
         body: Box<Stmt>,
@@ -29,12 +68,59 @@ pub enum Stmt {
         condition: Box<Expr>,
         body: Box<Stmt>,
     },
+    /// `for (initializer; condition; increment) body`. The variables declared
+    /// by `initializer` are bound anew for every iteration, so closures created
+    /// in the body keep the values of their own iteration.
+    ForLoop {
+        initializer: Box<Stmt>,
+        condition: Box<Expr>,
+        increment: Box<Expr>,
+        body: Box<Stmt>,
+    },
+    ForIn {
+        name: Token,
+        iterable: Box<Expr>,
+        body: Box<Stmt>,
+    },
     Fun {
         name: Token,
         params: Vec<Token>,
+        /// Whether the last parameter collects the remaining arguments into
+        /// an Array.
+        rest: bool,
         body: Vec<Stmt>,
+        kind: FunKind,
     },
     Return(Box<Expr>),
+    /// `defer { ... }`, running the block when the enclosing function
+    /// returns or fails.
+    Defer {
+        keyword: Token,
+        body: Box<Stmt>,
+    },
+    /// `throw value;`, unwinding to the nearest `catch`.
+    Throw {
+        keyword: Token,
+        value: Box<Expr>,
+    },
+    /// `try body catch (name) handler finally finally`, where either clause
+    /// may be left out but not both.
+    Try {
+        keyword: Token,
+        body: Box<Stmt>,
+        catch: Option<(Token, Box<Stmt>)>,
+        finally: Option<Box<Stmt>>,
+    },
+    /// `using (var name = initializer) body`, calling `close()` on the value
+    /// however the body exits.
+    Using {
+        keyword: Token,
+        name: Token,
+        initializer: Box<Expr>,
+        body: Box<Stmt>,
+    },
+    /// `methods` holds `Fun` statements, and static fields like
+    /// `static RED = "red";` as constant `Var` statements.
     Class {
         name: Token,
         superclass: Option<Box<Expr>>,
@@ -48,38 +134,312 @@ impl Stmt {
             Self::Expr(expr) => expr.line(),
             Self::Print(expr) => expr.line(),
             Self::Var { name, .. } => name.line,
-            Self::Block(stmts) => stmts[0].line(),
+            Self::Destructure { names, .. } => names[0].line,
+            Self::Block { brace, .. } => brace.line,
             Self::IfElse { condition, .. } => condition.line(),
             Self::WhileLoop { condition, .. } => condition.line(),
+            Self::ForLoop { initializer, .. } => initializer.line(),
+            Self::ForIn { name, .. } => name.line,
             Self::Fun { name, .. } => name.line,
             Self::Return(expr) => expr.line(),
+            Self::Defer { keyword, .. }
+            | Self::Using { keyword, .. }
+            | Self::Throw { keyword, .. }
+            | Self::Try { keyword, .. } => keyword.line,
             Self::Class { name, .. } => name.line,
         }
     }
 
+    /// Collect the ids of every expression in this statement.
+    pub fn collect_ids(&self, ids: &mut HashSet<usize>) {
+        match self {
+            Self::Expr(expr)
+            | Self::Print(expr)
+            | Self::Return(expr)
+            | Self::Destructure {
+                initializer: expr, ..
+            } => expr.collect_ids(ids),
+            Self::Var { initializer, .. } => {
+                if let Some(expr) = initializer {
+                    expr.collect_ids(ids);
+                }
+            }
+            Self::Block { statements, .. }
+            | Self::Fun {
+                body: statements, ..
+            } => {
+                for stmt in statements.iter() {
+                    stmt.collect_ids(ids);
+                }
+            }
+            Self::IfElse {
+                condition,
+                body,
+                else_branch,
+            } => {
+                condition.collect_ids(ids);
+                body.collect_ids(ids);
+                if let Some(else_branch) = else_branch {
+                    else_branch.collect_ids(ids);
+                }
+            }
+            Self::WhileLoop { condition, body } => {
+                condition.collect_ids(ids);
+                body.collect_ids(ids);
+            }
+            Self::ForLoop {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                initializer.collect_ids(ids);
+                condition.collect_ids(ids);
+                increment.collect_ids(ids);
+                body.collect_ids(ids);
+            }
+            Self::ForIn { iterable, body, .. } => {
+                iterable.collect_ids(ids);
+                body.collect_ids(ids);
+            }
+            Self::Defer { body, .. } => body.collect_ids(ids),
+            Self::Throw { value, .. } => value.collect_ids(ids),
+            Self::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                body.collect_ids(ids);
+                if let Some((_, handler)) = catch {
+                    handler.collect_ids(ids);
+                }
+                if let Some(finally) = finally {
+                    finally.collect_ids(ids);
+                }
+            }
+            Self::Using {
+                initializer, body, ..
+            } => {
+                initializer.collect_ids(ids);
+                body.collect_ids(ids);
+            }
+            Self::Class {
+                superclass,
+                methods,
+                ..
+            } => {
+                if let Some(superclass) = superclass {
+                    superclass.collect_ids(ids);
+                }
+                for method in methods.iter() {
+                    method.collect_ids(ids);
+                }
+            }
+        }
+    }
+
+    /// Deep copy this statement with fresh expression ids, carrying over any
+    /// entries in `locals`. See `Expr::duplicate`.
+    pub fn duplicate(&self, locals: &mut Locals) -> Stmt {
+        let dup_all = |statements: &[Stmt], locals: &mut Locals| {
+            statements
+                .iter()
+                .map(|stmt| stmt.duplicate(locals))
+                .collect()
+        };
+        match self {
+            Self::Expr(expr) => Self::Expr(Box::new(expr.duplicate(locals))),
+            Self::Print(expr) => Self::Print(Box::new(expr.duplicate(locals))),
+            Self::Return(expr) => Self::Return(Box::new(expr.duplicate(locals))),
+            Self::Var {
+                name,
+                initializer,
+                mutable,
+            } => Self::Var {
+                name: name.clone(),
+                mutable: *mutable,
+                initializer: initializer
+                    .as_ref()
+                    .map(|expr| Box::new(expr.duplicate(locals))),
+            },
+            Self::Destructure { names, initializer } => Self::Destructure {
+                names: names.clone(),
+                initializer: Box::new(initializer.duplicate(locals)),
+            },
+            Self::Block { brace, statements } => Self::Block {
+                brace: brace.clone(),
+                statements: dup_all(statements, locals),
+            },
+            Self::IfElse {
+                condition,
+                body,
+                else_branch,
+            } => Self::IfElse {
+                condition: Box::new(condition.duplicate(locals)),
+                body: Box::new(body.duplicate(locals)),
+                else_branch: else_branch
+                    .as_ref()
+                    .map(|stmt| Box::new(stmt.duplicate(locals))),
+            },
+            Self::WhileLoop { condition, body } => Self::WhileLoop {
+                condition: Box::new(condition.duplicate(locals)),
+                body: Box::new(body.duplicate(locals)),
+            },
+            Self::ForLoop {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => Self::ForLoop {
+                initializer: Box::new(initializer.duplicate(locals)),
+                condition: Box::new(condition.duplicate(locals)),
+                increment: Box::new(increment.duplicate(locals)),
+                body: Box::new(body.duplicate(locals)),
+            },
+            Self::ForIn {
+                name,
+                iterable,
+                body,
+            } => Self::ForIn {
+                name: name.clone(),
+                iterable: Box::new(iterable.duplicate(locals)),
+                body: Box::new(body.duplicate(locals)),
+            },
+            Self::Defer { keyword, body } => Self::Defer {
+                keyword: keyword.clone(),
+                body: Box::new(body.duplicate(locals)),
+            },
+            Self::Throw { keyword, value } => Self::Throw {
+                keyword: keyword.clone(),
+                value: Box::new(value.duplicate(locals)),
+            },
+            Self::Try {
+                keyword,
+                body,
+                catch,
+                finally,
+            } => Self::Try {
+                keyword: keyword.clone(),
+                body: Box::new(body.duplicate(locals)),
+                catch: catch
+                    .as_ref()
+                    .map(|(name, handler)| (name.clone(), Box::new(handler.duplicate(locals)))),
+                finally: finally
+                    .as_ref()
+                    .map(|finally| Box::new(finally.duplicate(locals))),
+            },
+            Self::Using {
+                keyword,
+                name,
+                initializer,
+                body,
+            } => Self::Using {
+                keyword: keyword.clone(),
+                name: name.clone(),
+                initializer: Box::new(initializer.duplicate(locals)),
+                body: Box::new(body.duplicate(locals)),
+            },
+            Self::Fun {
+                name,
+                params,
+                rest,
+                body,
+                kind,
+            } => Self::Fun {
+                name: name.clone(),
+                params: params.clone(),
+                rest: *rest,
+                body: dup_all(body, locals),
+                kind: *kind,
+            },
+            Self::Class {
+                name,
+                superclass,
+                methods,
+            } => Self::Class {
+                name: name.clone(),
+                superclass: superclass
+                    .as_ref()
+                    .map(|expr| Box::new(expr.duplicate(locals))),
+                methods: dup_all(methods, locals),
+            },
+        }
+    }
+
     pub fn eval(&self, state: &mut LoxState, scope: ScopeHandle) -> LoxResult {
-        // println!("{self}");
+        self.eval_value(state, scope).map(drop)
+    }
+
+    /// Evaluate the statement, returning the value of expression statements
+    /// and `nil` for everything else.
+    pub fn eval_value(&self, state: &mut LoxState, scope: ScopeHandle) -> LoxResult<LoxValue> {
+        state.step()?;
+        if !matches!(self, Stmt::Block { .. }) {
+            if let Some(coverage) = &mut state.coverage {
+                coverage.hit(self.line());
+            }
+            if let Some(hotspots) = &mut state.hotspots {
+                hotspots.hit(self.line());
+            }
+            if let Some(observer) = &mut state.observer {
+                observer.before_stmt(self);
+            }
+        }
         match self {
             Stmt::Expr(expr) => {
-                expr.eval(state, scope)?;
+                return expr.eval(state, scope);
             }
             Stmt::Print(expr) => {
                 let value = expr.eval(state, scope)?;
-                info!("{}", value.to_string());
+                let text = value.display(state, self.line())?;
+                state.print(&text);
             }
-            Stmt::Var { name, initializer } => {
+            Stmt::Var {
+                name,
+                initializer,
+                mutable,
+            } => {
                 let value = match initializer {
                     Some(expr) => expr.eval(state, scope)?,
                     None => LoxValue::Nil,
                 };
-                state.env.declare(Some(scope), name.lexeme_str(), value);
+                if *mutable {
+                    state.env.declare(Some(scope), name.lexeme_str(), value);
+                } else {
+                    state
+                        .env
+                        .declare_constant(Some(scope), name.lexeme_str(), value);
+                }
             }
-            Stmt::Block(statements) => {
-                let block_scope = state.env.new_scope(Some(scope));
-                for stmt in statements.iter() {
-                    stmt.eval(state, block_scope)?;
+            Stmt::Destructure { names, initializer } => {
+                let values: Vec<LoxValue> = initializer
+                    .eval(state, scope)?
+                    .items()
+                    .map_err(|err| err.at_line(self.line()))?
+                    .collect();
+                if values.len() != names.len() {
+                    return Err(LoxError::Runtime(
+                        format!(
+                            "Expected {} values to unpack, got {}",
+                            names.len(),
+                            values.len()
+                        ),
+                        self.line(),
+                    ));
+                }
+                for (name, value) in names.iter().zip(values) {
+                    state.env.declare(Some(scope), name.lexeme_str(), value);
                 }
             }
+            Stmt::Block { statements, .. } => {
+                let block_scope = state.new_scope(scope);
+                let result = statements
+                    .iter()
+                    .try_for_each(|stmt| stmt.eval(state, block_scope));
+                state.env.release(block_scope);
+                result?;
+            }
             Stmt::IfElse {
                 condition,
                 body,
@@ -93,30 +453,167 @@ impl Stmt {
                 }
             }
             Stmt::WhileLoop { condition, body } => {
-                let while_scope = state.env.new_scope(Some(scope));
-                while condition.eval(state, while_scope)?.is_truthy() {
-                    body.eval(state, while_scope)?;
+                let while_scope = state.new_scope(scope);
+                let result: LoxResult = (|| {
+                    while condition.eval(state, while_scope)?.is_truthy() {
+                        body.eval(state, while_scope)?;
+                    }
+                    Ok(())
+                })();
+                state.env.release(while_scope);
+                result?;
+            }
+            Stmt::ForLoop {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                let mut loop_scope = state.new_scope(scope);
+                let result: LoxResult = (|| {
+                    initializer.eval(state, loop_scope)?;
+                    while condition.eval(state, loop_scope)?.is_truthy() {
+                        body.eval(state, loop_scope)?;
+                        // The next iteration works on copies of the loop
+                        // variables, closures keep the ones they captured
+                        let next = state.copy_scope(loop_scope);
+                        state.env.release(loop_scope);
+                        loop_scope = next;
+                        increment.eval(state, loop_scope)?;
+                    }
+                    Ok(())
+                })();
+                state.env.release(loop_scope);
+                result?;
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                let items = iterable.eval(state, scope)?.iter_items(self.line())?;
+                for item in items.into_iter() {
+                    // Every iteration gets a fresh binding for closures to capture
+                    let item_scope = state.new_scope(scope);
+                    state.env.declare(Some(item_scope), name.lexeme_str(), item);
+                    let result = body.eval(state, item_scope);
+                    state.env.release(item_scope);
+                    result?;
                 }
             }
             Stmt::Fun { name, .. } => {
-                let fun = LoxFunction::from_stmt(self, state.env.new_scope(Some(scope)))?;
-                state
-                    .env
-                    .declare(Some(scope), name.lexeme_str(), fun.into());
+                // The declaring scope is the closure
+                state.env.capture(scope);
+                let fun = LoxFunction::from_stmt(self, scope)?;
+                state.declare_definition(scope, name.lexeme_str(), fun.into());
             }
             Stmt::Return(expr) => {
                 let last = state.stack.len() - 1;
                 state.stack[last] = expr.eval(state, scope)?;
+                return Err(LoxError::Return);
+            }
+            Stmt::Throw { value, .. } => {
+                let value = value.eval(state, scope)?;
+                let text = value.display(state, self.line())?;
+                state.thrown = Some(value);
+                return Err(LoxError::Thrown(text, self.line()));
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                let mut result = body.eval(state, scope);
+                if let (Err(err), Some((name, handler))) = (&result, catch) {
+                    if let Some(value) = state.catch(err) {
+                        let catch_scope = state.new_scope(scope);
+                        state
+                            .env
+                            .declare(Some(catch_scope), name.lexeme_str(), value);
+                        result = handler.eval(state, catch_scope);
+                        state.env.release(catch_scope);
+                    }
+                }
+                if let Some(finally) = finally {
+                    // Keep the value still being thrown from a throw caught
+                    // inside the finally block. An error leaving the block
+                    // wins over the outcome of the rest.
+                    let thrown = state.thrown.take();
+                    finally.eval(state, scope)?;
+                    state.thrown = thrown;
+                }
+                result?;
+            }
+            Stmt::Defer { body, .. } => {
+                // The block may outlive the scope it was deferred in
+                state.env.capture(scope);
+                state.defer((**body).clone(), scope, self.line())?;
+            }
+            Stmt::Using {
+                name,
+                initializer,
+                body,
+                ..
+            } => {
+                let value = initializer.eval(state, scope)?;
+                let close = match &value {
+                    LoxValue::Object(obj) => LoxObject::lookup(obj, "close"),
+                    _ => None,
+                }
+                .filter(|close| matches!(close, LoxValue::Function(_)))
+                .ok_or_else(|| {
+                    LoxError::Type("a value with a close() method".into(), value.type_str())
+                        .at_line(self.line())
+                })?;
+                let using_scope = state.new_scope(scope);
+                state
+                    .env
+                    .declare(Some(using_scope), name.lexeme_str(), value);
+                let result = body.eval(state, using_scope);
+                state.env.release(using_scope);
+                // An error leaving the body wins over one from closing
+                let closed = close.call(state, vec![], self.line()).map(drop);
+                match (result, closed) {
+                    (Ok(()) | Err(LoxError::Return), Err(err)) => return Err(err),
+                    (result, _) => result?,
+                }
             }
             Stmt::Class {
                 name,
                 superclass,
                 methods: method_defs,
             } => {
+                // Methods use the declaring scope as their closure
+                state.env.capture(scope);
                 let mut methods = HashMap::<String, LoxFunction>::new();
+                let mut setters = HashMap::<String, LoxFunction>::new();
+                let mut statics = HashMap::<String, LoxFunction>::new();
+                let mut static_fields = HashMap::<String, LoxValue>::new();
                 for def in method_defs.iter() {
+                    if let Stmt::Var {
+                        name,
+                        initializer: Some(initializer),
+                        ..
+                    } = def
+                    {
+                        let value = initializer.eval(state, scope)?;
+                        static_fields.insert(name.lexeme_str(), value);
+                        continue;
+                    }
                     let fun = LoxFunction::from_stmt(def, scope)?;
-                    methods.insert(fun.name.clone().unwrap(), fun);
+                    let name = fun.name.clone().unwrap();
+                    match def {
+                        Stmt::Fun {
+                            kind: FunKind::Setter,
+                            ..
+                        } => setters.insert(name, fun),
+                        Stmt::Fun {
+                            kind: FunKind::Static,
+                            ..
+                        } => statics.insert(name, fun),
+                        _ => methods.insert(name, fun),
+                    };
                 }
                 let mut superclass_ref: Option<Rc<RefCell<LoxClass>>> = None;
                 if let Some(expr) = superclass {
@@ -131,19 +628,23 @@ impl Stmt {
                         unreachable!("Expected an identifier");
                     }
                 }
-                state.env.declare(
-                    Some(scope),
+                state.declare_definition(
+                    scope,
                     name.lexeme_str(),
                     LoxClass {
                         name: name.lexeme_str(),
                         superclass: superclass_ref,
                         methods,
+                        setters,
+                        statics,
+                        static_fields,
+                        origin: Some(self.line()),
                     }
                     .into(),
                 );
             }
         }
-        Ok(())
+        Ok(LoxValue::Nil)
     }
 }
 
@@ -152,11 +653,28 @@ impl fmt::Display for Stmt {
         match self {
             Self::Expr(expr) => write!(f, "(expr {})", expr),
             Self::Print(expr) => write!(f, "(print {})", expr),
-            Self::Var { name, initializer } => match initializer {
-                Some(expr) => write!(f, "(var {} {})", name.lexeme_str(), expr),
-                None => write!(f, "(var {})", name.lexeme_str()),
-            },
-            Self::Block(statements) => {
+            Self::Var {
+                name,
+                initializer,
+                mutable,
+            } => {
+                let keyword = if *mutable { "var" } else { "const" };
+                match initializer {
+                    Some(expr) => write!(f, "({} {} {})", keyword, name.lexeme_str(), expr),
+                    None => write!(f, "({} {})", keyword, name.lexeme_str()),
+                }
+            }
+            Self::Destructure { names, initializer } => write!(
+                f,
+                "(var ({}) {})",
+                names
+                    .iter()
+                    .map(|name| name.lexeme_str())
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                initializer
+            ),
+            Self::Block { statements, .. } => {
                 write!(f, "(block ")?;
                 for stmt in statements.iter() {
                     write!(f, "{}", stmt)?;
@@ -169,25 +687,52 @@ impl fmt::Display for Stmt {
                 else_branch,
             } => match else_branch {
                 Some(else_stmt) => {
-                    write!(f, "(if {} {} else {}", condition, body, else_stmt)
+                    write!(f, "(if {} {} else {})", condition, body, else_stmt)
                 }
                 None => {
                     write!(f, "(if {} {})", condition, body)
                 }
             },
             Self::WhileLoop { condition, body } => {
-                write!(f, "(while {} {}", condition, body)
+                write!(f, "(while {} {})", condition, body)
+            }
+            Self::ForLoop {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                write!(
+                    f,
+                    "(for {} {} {} {})",
+                    initializer, condition, increment, body
+                )
             }
-            Self::Fun { name, params, body } => {
+            Self::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                write!(f, "(for {} in {} {})", name.lexeme_str(), iterable, body)
+            }
+            Self::Fun {
+                name,
+                params,
+                rest,
+                body,
+                kind,
+            } => {
+                let mut params: Vec<String> =
+                    params.iter().map(|param| param.lexeme_str()).collect();
+                if let (true, Some(last)) = (rest, params.last_mut()) {
+                    last.insert_str(0, "...");
+                }
                 write!(
                     f,
-                    "(fun {} ({}) ({}))",
+                    "({} {} ({}) ({}))",
+                    kind,
                     name.lexeme_str(),
-                    params
-                        .iter()
-                        .map(|param| param.lexeme_str())
-                        .collect::<Vec<String>>()
-                        .join(" "),
+                    params.join(" "),
                     body.iter()
                         .map(|stmt| stmt.to_string())
                         .collect::<Vec<String>>()
@@ -197,6 +742,29 @@ impl fmt::Display for Stmt {
             Self::Return(value) => {
                 write!(f, "(return {})", value)
             }
+            Self::Defer { body, .. } => write!(f, "(defer {})", body),
+            Self::Throw { value, .. } => write!(f, "(throw {})", value),
+            Self::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                write!(f, "(try {}", body)?;
+                if let Some((name, handler)) = catch {
+                    write!(f, " (catch {} {})", name.lexeme_str(), handler)?;
+                }
+                if let Some(finally) = finally {
+                    write!(f, " (finally {})", finally)?;
+                }
+                write!(f, ")")
+            }
+            Self::Using {
+                name,
+                initializer,
+                body,
+                ..
+            } => write!(f, "(using {} {} {})", name.lexeme_str(), initializer, body),
             Self::Class {
                 name,
                 superclass,
@@ -0,0 +1,71 @@
+//! Near-miss matching used to suggest fixes for typos.
+
+/// Edit distance between `a` and `b`, counting insertions, deletions,
+/// substitutions and swaps of adjacent characters as one edit each.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The candidate closest to `name`, if it is close enough to be a likely typo.
+/// Exact matches are not suggestions and are skipped.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance > 0 && *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// A `, did you mean "x"?` suffix for an error about `name`, or an empty
+/// string when no candidate is close enough.
+pub fn did_you_mean(name: &str, candidates: &[String]) -> String {
+    match closest(name, candidates.iter().map(String::as_str)) {
+        Some(candidate) => format!(", did you mean \"{}\"?", candidate),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("while", "whle"), 1);
+        assert_eq!(edit_distance("return", "retrun"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn closest_candidate() {
+        let candidates = ["counter", "count", "total"];
+        assert_eq!(closest("countr", candidates), Some("counter"));
+        assert_eq!(closest("cont", candidates), Some("count"));
+        assert_eq!(closest("count", candidates), None);
+        assert_eq!(closest("xyz", candidates), None);
+    }
+}
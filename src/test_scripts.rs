@@ -1,159 +1,440 @@
-pub const EXPRESSION_TEST: &str = r#"
-    "foo" + (1 + (3 / 2) - (8 * 4))
-"#;
-
-pub const VARIABLE_TEST: &str = r#"
-    var i = 5;
-    var foo = "bar";
-    var is_okay = true;
-"#;
-
-pub const PRINT_TEST: &str = r#"
-    var pi = 3.14;
-    print pi;
-    var foo;
-    print foo;
-"#;
-
-pub const BLOCK_SCOPE_TEST: &str = r#"
-    var foo = "foo";
-    {
-        print foo;
-        var foo = "bar";
-        print foo;
-    }
-"#;
-
-pub const CONTROL_FLOW_TEST: &str = r#"
-    if (true and (nil or "truthy")) {
-        print "true";
-    } else {
-        print "false";
-    }
-    if (false) {
-        print "false";
-    } else {
-        print "true";
-    }
-"#;
-
-pub const WHILE_LOOP_TEST: &str = r#"
-    var index = 4;
-    while (index > 0) {
-        print index;
-        index = index - 1;
-    }
-"#;
-
-pub const FOR_LOOP_TEST: &str = r#"
-    var index = 42;
-    for (var index = 0; index < 4; index = index + 1) {
-        print index;
-    }
-    print index;
-"#;
-
-pub const BUILTINS_TEST: &str = r#"
-    print time();
-"#;
-
-pub const FUNCTION_TEST: &str = r#"
-    fun greet(name) {
-        fun greeting() {
-            return "Hello, " + name + "!";
-        }
-
-        print greeting();
-    }
-
-    fun get_name() {
-        return "world";
-    }
-
-    greet(get_name());
-"#;
-
-pub const FUNCTION_CLOSURE_TEST: &str = r#"
-    fun make_counter() {
-        var i = 0;
-        fun count() {
-            i = i + 1;
-            print i;
-        }
-
-        return count;
-    }
-
-    var counter = make_counter();
-    counter();
-    counter();
-"#;
-
-pub const SHADOWING_TEST: &str = r#"
-    var a = "global";
-    {
-        fun print_a() {
-            print a;
-        }
-
-        print_a();
-        var a = "block";
-        print_a();
-    }
-"#;
-
-pub const CLASS_TEST: &str = r#"
-    class Greeter {
-        init(greeting) {
-            this.greeting = greeting;
-        }
-
-        greet(name) {
-            print this.greeting + ", " + name + "!";
-        }
-    }
-
-    var greeter = Greeter("Hello");
-    greeter.greet("world");
-"#;
-
-pub const CLASS_INHERITANCE_TEST: &str = r#"
-    class Greeter {
-        init(greeting) {
-            this.greeting = greeting;
-        }
-
-        greet(name) {
-            print this.greeting + ", " + name + "!";
-        }
-    }
-
-    class HelloGreeter < Greeter {
-        init() {
-            super.init("Hello");
-        }
-    }
-
-    class HowdyGreeter < Greeter {
-        init() {
-            super.init("Howdy");
-        }
-    }
-
-    var hello = HelloGreeter();
-    hello.greet("world");
-    
-    var howdy = HowdyGreeter();
-    howdy.greet("partner");
-"#;
-
-pub const METHOD_CHAINING_TEST: &str = r#"
-    fun get_arr() {
-        var arr = Array();
-        arr.push(1);
-        arr.push(2);
-        return arr;
-    }
-
-    print get_arr().get(0);
-"#;
+pub const EXPRESSION_TEST: &str = r#"
+    "foo" + (1 + (3 / 2) - (8 * 4))
+"#;
+
+pub const VARIABLE_TEST: &str = r#"
+    var i = 5;
+    var foo = "bar";
+    var is_okay = true;
+"#;
+
+pub const PRINT_TEST: &str = r#"
+    var pi = 3.14;
+    print pi;
+    var foo;
+    print foo;
+"#;
+
+pub const BLOCK_SCOPE_TEST: &str = r#"
+    var foo = "foo";
+    {
+        print foo;
+        var foo = "bar";
+        print foo;
+    }
+"#;
+
+pub const CONTROL_FLOW_TEST: &str = r#"
+    if (true and (nil or "truthy")) {
+        print "true";
+    } else {
+        print "false";
+    }
+    if (false) {
+        print "false";
+    } else {
+        print "true";
+    }
+"#;
+
+pub const WHILE_LOOP_TEST: &str = r#"
+    var index = 4;
+    while (index > 0) {
+        print index;
+        index = index - 1;
+    }
+"#;
+
+pub const FOR_LOOP_TEST: &str = r#"
+    var index = 42;
+    for (var index = 0; index < 4; index = index + 1) {
+        print index;
+    }
+    print index;
+"#;
+
+pub const FOR_CLOSURE_TEST: &str = r#"
+    var closures = Array();
+    for (var i = 0; i < 3; i = i + 1) {
+        fun show() {
+            print i;
+        }
+        closures.push(show);
+    }
+    for (var c in closures) c();
+
+    var skipped = Array();
+    for (var j = 0; j < 6; j = j + 1) {
+        j = j + 1;
+        fun get() {
+            return j;
+        }
+        skipped.push(get);
+    }
+    print skipped.len();
+    print skipped.get(0)();
+"#;
+
+pub const BUILTINS_TEST: &str = r#"
+    print time();
+"#;
+
+pub const FUNCTION_TEST: &str = r#"
+    fun greet(name) {
+        fun greeting() {
+            return "Hello, " + name + "!";
+        }
+
+        print greeting();
+    }
+
+    fun get_name() {
+        return "world";
+    }
+
+    greet(get_name());
+"#;
+
+pub const FUNCTION_CLOSURE_TEST: &str = r#"
+    fun make_counter() {
+        var i = 0;
+        fun count() {
+            i = i + 1;
+            print i;
+        }
+
+        return count;
+    }
+
+    var counter = make_counter();
+    counter();
+    counter();
+"#;
+
+pub const SHADOWING_TEST: &str = r#"
+    var a = "global";
+    {
+        fun print_a() {
+            print a;
+        }
+
+        print_a();
+        var a = "block";
+        print_a();
+    }
+"#;
+
+pub const CLASS_TEST: &str = r#"
+    class Greeter {
+        init(greeting) {
+            this.greeting = greeting;
+        }
+
+        greet(name) {
+            print this.greeting + ", " + name + "!";
+        }
+    }
+
+    var greeter = Greeter("Hello");
+    greeter.greet("world");
+"#;
+
+pub const CLASS_INHERITANCE_TEST: &str = r#"
+    class Greeter {
+        init(greeting) {
+            this.greeting = greeting;
+        }
+
+        greet(name) {
+            print this.greeting + ", " + name + "!";
+        }
+    }
+
+    class HelloGreeter < Greeter {
+        init() {
+            super.init("Hello");
+        }
+    }
+
+    class HowdyGreeter < Greeter {
+        init() {
+            super.init("Howdy");
+        }
+    }
+
+    var hello = HelloGreeter();
+    hello.greet("world");
+    
+    var howdy = HowdyGreeter();
+    howdy.greet("partner");
+"#;
+
+pub const METHOD_CHAINING_TEST: &str = r#"
+    fun get_arr() {
+        var arr = Array();
+        arr.push(1);
+        arr.push(2);
+        return arr;
+    }
+
+    print get_arr().get(0);
+"#;
+
+pub const TO_STRING_TEST: &str = r#"
+    class Point {
+        init(x) {
+            this.x = x;
+        }
+
+        to_string() {
+            return "Point(" + this.x + ")";
+        }
+    }
+
+    class Plain {}
+
+    print Point("1");
+    print Plain();
+"#;
+
+pub const ACCESSOR_TEST: &str = r#"
+    class Square {
+        init(size) {
+            this.size = size;
+        }
+
+        get area() {
+            return this.size * this.size;
+        }
+
+        set side(value) {
+            this.size = value;
+        }
+
+        get(index) {
+            return index;
+        }
+    }
+
+    class Cube < Square {
+        get volume() {
+            return this.area * this.size;
+        }
+    }
+
+    var cube = Cube(2);
+    print cube.area;
+    cube.side = 3;
+    print cube.volume;
+    print cube.get(1);
+    print get_prop(cube, "area");
+"#;
+
+pub const STATIC_METHOD_TEST: &str = r#"
+    class Math {
+        static square(n) {
+            return n * n;
+        }
+
+        static cube(n) {
+            return this.square(n) * n;
+        }
+    }
+
+    class Geometry < Math {
+        static create() {
+            return Geometry();
+        }
+
+        area(side) {
+            return Math.square(side);
+        }
+    }
+
+    print Math.square(3);
+    print Geometry.cube(2);
+    print Geometry.create().area(4);
+"#;
+
+pub const STATIC_FIELD_TEST: &str = r#"
+    class Color {
+        static RED = "red";
+        static COUNT = 3;
+
+        static shout() {
+            return this.RED + "!";
+        }
+    }
+
+    class Shade < Color {
+        static DOUBLE = Color.COUNT * 2;
+    }
+
+    print Color.RED;
+    print Color.shout();
+    print Shade.RED;
+    print Shade.DOUBLE;
+"#;
+
+pub const FOR_IN_TEST: &str = r#"
+    class Point {
+        init(x) {
+            this.x = x;
+            this.y = x + 1;
+        }
+
+        sum() {
+            return this.x + this.y;
+        }
+    }
+
+    var point = Point(1);
+    for (var name in point) {
+        print name + " = " + get_prop(point, name);
+    }
+
+    var arr = Array();
+    arr.push("a");
+    arr.push("b");
+    for (var item in arr) print item;
+"#;
+
+pub const OPERATOR_OVERLOAD_TEST: &str = r#"
+    class Money {
+        init(cents) {
+            this.cents = cents;
+        }
+
+        __add__(other) {
+            return Money(this.cents + other.cents);
+        }
+    }
+
+    var total = Money(150) + Money(275);
+    print total.cents;
+
+    var left = Array();
+    left.push(1);
+    var right = Array();
+    right.push(2);
+    right.push(3);
+    var both = left + right;
+    print both.len();
+    print both.get(2);
+    print left.len();
+"#;
+
+pub const EARLY_RETURN_TEST: &str = r#"
+    fun find(n) {
+        var i = 0;
+        while (true) {
+            if (i == n) {
+                return i;
+            }
+            i = i + 1;
+        }
+        print "unreachable";
+    }
+    print find(3);
+
+    fun first(items) {
+        for (var item in items) {
+            {
+                return item;
+            }
+        }
+        return "empty";
+    }
+    var items = Array();
+    items.push(1);
+    items.push(2);
+    print first(items);
+
+    fun check(n) {
+        if (n) {
+            return "truthy";
+        } else {
+            print "falsy";
+        }
+        return "done";
+    }
+    print check(true);
+    print check(false);
+"#;
+
+pub const COMPOUND_ASSIGNMENT_TEST: &str = r#"
+    var x = 10;
+    x += 2 * 3;
+    x -= 1;
+    x *= 2;
+    x /= 5;
+    print x;
+
+    // The right side is evaluated after the current value is read
+    fun bump(n) {
+        x = 100;
+        return n;
+    }
+    x += bump(1);
+    print x;
+
+    class Counter {
+        init(count) {
+            this.count = count;
+        }
+    }
+    var counter = Counter(1);
+    counter.count += 10;
+    print counter.count;
+    print counter.count++;
+    print ++counter.count;
+    print counter.count--;
+    print counter.count;
+
+    var i = 0;
+    var name = "a";
+    name += "b" + "c";
+    print name;
+    print i++ + i++;
+    print --i;
+"#;
+
+pub const GC_CYCLES_TEST: &str = r#"
+    fun make_counter() {
+        var count = 0;
+        fun increment() {
+            count = count + 1;
+            return count;
+        }
+        return increment;
+    }
+
+    var counter = make_counter();
+    counter();
+
+    class Node {
+        init(value) {
+            this.value = value;
+            this.next = nil;
+        }
+    }
+
+    {
+        fun helper() {
+            return counter;
+        }
+        helper()();
+
+        var a = Node(1);
+        var b = Node(a);
+        set_prop(a, "next", b);
+
+        var i = 0;
+        while (i < 10) {
+            var square = i * i;
+            i = i + 1;
+        }
+    }
+
+    print counter();
+"#;
@@ -0,0 +1,76 @@
+use std::{
+    fmt,
+    ops::AddAssign,
+    time::{Duration, Instant},
+};
+
+/// Time spent in each phase of running scripts, summed over every run since
+/// timing was enabled. See `LoxInterpreter::enable_timings`.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct PhaseTimings {
+    pub scan: Duration,
+    pub parse: Duration,
+    pub resolve: Duration,
+    pub exec: Duration,
+}
+
+impl PhaseTimings {
+    pub fn total(&self) -> Duration {
+        self.scan + self.parse + self.resolve + self.exec
+    }
+}
+
+impl AddAssign for PhaseTimings {
+    fn add_assign(&mut self, other: Self) {
+        self.scan += other.scan;
+        self.parse += other.parse;
+        self.resolve += other.resolve;
+        self.exec += other.exec;
+    }
+}
+
+impl fmt::Display for PhaseTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "scan {:.3?}  parse {:.3?}  resolve {:.3?}  exec {:.3?}  total {:.3?}",
+            self.scan,
+            self.parse,
+            self.resolve,
+            self.exec,
+            self.total()
+        )
+    }
+}
+
+/// Run `f`, adding the time it took to `phase`.
+pub fn timed<T>(phase: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    *phase += start.elapsed();
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{error::*, interpreter::*};
+
+    #[test]
+    fn phase_timings() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        lox.exec("var a = 1;")?;
+        assert_eq!(lox.timings(), None);
+        lox.enable_timings();
+        lox.exec("var total = 0; for (var i = 0; i < 1000; i += 1) total += i;")?;
+        let first = *lox.timings().expect("Expected timings");
+        assert!(first.exec > Duration::ZERO);
+        assert!(first.scan + first.parse > Duration::ZERO);
+        // Later runs add up
+        lox.exec("total += 1;")?;
+        let second = *lox.timings().expect("Expected timings");
+        assert!(second.total() > first.total());
+        assert!(second.to_string().starts_with("scan "));
+        Ok(())
+    }
+}
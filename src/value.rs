@@ -1,10 +1,11 @@
 use std::{cell::RefCell, rc::Rc};
 
-use crate::environment::LoxProperties;
+use super::{
+    class::*, environment::LoxProperties, error::*, function::*, object::*, pretty, scanner::*,
+    state::LoxState,
+};
 
-use super::{class::*, error::*, function::*, object::*, scanner::*};
-
-#[derive(PartialEq, Clone)]
+#[derive(Clone)]
 pub enum LoxValue {
     Nil,
     Boolean(bool),
@@ -13,11 +14,40 @@ pub enum LoxValue {
     Function(Rc<RefCell<LoxFunction>>),
     Class(Rc<RefCell<LoxClass>>),
     Object(Rc<RefCell<LoxObject>>),
-    Super(Rc<LoxProperties>),
+    Super(Rc<LoxSuper>),
     Vec(Rc<RefCell<Vec<LoxValue>>>),
+    Map(Rc<RefCell<LoxProperties>>),
+}
+
+/// `==` in scripts: primitives compare by value, everything else by
+/// reference. See `deep_equals` for comparing contents.
+impl PartialEq for LoxValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Nil, Self::Nil) => true,
+            (Self::Boolean(a), Self::Boolean(b)) => a == b,
+            (Self::Number(a), Self::Number(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Function(a), Self::Function(b)) => Rc::ptr_eq(a, b),
+            (Self::Class(a), Self::Class(b)) => Rc::ptr_eq(a, b),
+            (Self::Object(a), Self::Object(b)) => Rc::ptr_eq(a, b),
+            (Self::Super(a), Self::Super(b)) => Rc::ptr_eq(a, b),
+            (Self::Vec(a), Self::Vec(b)) => Rc::ptr_eq(a, b),
+            (Self::Map(a), Self::Map(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
 }
 
 impl LoxValue {
+    /// Whether the values have the same contents: instances of the same
+    /// class with deeply equal props, including the elements of Arrays and
+    /// entries of Maps. Functions and classes compare by reference. Values
+    /// containing themselves compare equal where their cycles line up.
+    pub fn deep_equals(&self, other: &Self) -> bool {
+        deep_equals(self, other, &mut vec![])
+    }
+
     pub fn type_str(&self) -> String {
         match self {
             Self::Nil => "nil".into(),
@@ -29,15 +59,14 @@ impl LoxValue {
             Self::Object(_) => "Object".into(),
             Self::Super(_) => "Super".into(),
             Self::Vec(_) => "Vec".into(),
+            Self::Map(_) => "Map".into(),
         }
     }
 
-    #[allow(dead_code)]
     pub fn is_nil(&self) -> bool {
         matches!(self, Self::Nil)
     }
 
-    #[allow(dead_code)]
     pub fn is_boolean(&self) -> bool {
         matches!(self, Self::Boolean(_))
     }
@@ -50,33 +79,32 @@ impl LoxValue {
         matches!(self, Self::String(_))
     }
 
-    #[allow(dead_code)]
     pub fn is_fun(&self) -> bool {
         matches!(self, Self::Function(_))
     }
 
-    #[allow(dead_code)]
     pub fn is_class(&self) -> bool {
         matches!(self, Self::Class(_))
     }
 
-    #[allow(dead_code)]
     pub fn is_object(&self) -> bool {
         matches!(self, Self::Object(_))
     }
 
-    #[allow(dead_code)]
     pub fn is_super(&self) -> bool {
         matches!(self, Self::Super(_))
     }
 
-    #[allow(dead_code)]
     pub fn get_boolean(&self, line: u32) -> LoxResult<bool> {
         if let Self::Boolean(value) = self {
             Ok(*value)
         } else {
             Err(LoxError::Runtime(
-                format!("Expected Boolean, got \"{}\"", self.type_str()),
+                format!(
+                    "Expected Boolean, got \"{}\"{}",
+                    self.type_str(),
+                    self.origin_note()
+                ),
                 line,
             ))
         }
@@ -87,19 +115,26 @@ impl LoxValue {
             Ok(*value)
         } else {
             Err(LoxError::Runtime(
-                format!("Expected Number, got \"{}\"", self.type_str()),
+                format!(
+                    "Expected Number, got \"{}\"{}",
+                    self.type_str(),
+                    self.origin_note()
+                ),
                 line,
             ))
         }
     }
 
-    #[allow(dead_code)]
     pub fn get_string(&self, line: u32) -> LoxResult<String> {
         if let Self::String(value) = self {
             Ok(value.clone())
         } else {
             Err(LoxError::Runtime(
-                format!("Expected String, got \"{}\"", self.type_str()),
+                format!(
+                    "Expected String, got \"{}\"{}",
+                    self.type_str(),
+                    self.origin_note()
+                ),
                 line,
             ))
         }
@@ -110,7 +145,11 @@ impl LoxValue {
             Ok(fun.clone())
         } else {
             Err(LoxError::Runtime(
-                format!("Expected Function, got \"{}\"", self.type_str()),
+                format!(
+                    "Expected Function, got \"{}\"{}",
+                    self.type_str(),
+                    self.origin_note()
+                ),
                 line,
             ))
         }
@@ -121,7 +160,11 @@ impl LoxValue {
             Ok(class.clone())
         } else {
             Err(LoxError::Runtime(
-                format!("Expected Class, got \"{}\"", self.type_str()),
+                format!(
+                    "Expected Class, got \"{}\"{}",
+                    self.type_str(),
+                    self.origin_note()
+                ),
                 line,
             ))
         }
@@ -132,18 +175,26 @@ impl LoxValue {
             Ok(obj.clone())
         } else {
             Err(LoxError::Runtime(
-                format!("Expected Object, got \"{}\"", self.type_str()),
+                format!(
+                    "Expected Object, got \"{}\"{}",
+                    self.type_str(),
+                    self.origin_note()
+                ),
                 line,
             ))
         }
     }
 
-    pub fn get_super(&self, line: u32) -> LoxResult<Rc<LoxProperties>> {
-        if let Self::Super(methods) = self {
-            Ok(methods.clone())
+    pub fn get_super(&self, line: u32) -> LoxResult<Rc<LoxSuper>> {
+        if let Self::Super(value) = self {
+            Ok(value.clone())
         } else {
             Err(LoxError::Runtime(
-                format!("Expected Super, got \"{}\"", self.type_str()),
+                format!(
+                    "Expected Super, got \"{}\"{}",
+                    self.type_str(),
+                    self.origin_note()
+                ),
                 line,
             ))
         }
@@ -154,12 +205,196 @@ impl LoxValue {
             Ok(vec.clone())
         } else {
             Err(LoxError::Runtime(
-                format!("Expected Vec, got \"{}\"", self.type_str()),
+                format!(
+                    "Expected Vec, got \"{}\"{}",
+                    self.type_str(),
+                    self.origin_note()
+                ),
+                line,
+            ))
+        }
+    }
+
+    pub fn get_map(&self, line: u32) -> LoxResult<Rc<RefCell<LoxProperties>>> {
+        if let Self::Map(map) = self {
+            Ok(map.clone())
+        } else {
+            Err(LoxError::Runtime(
+                format!(
+                    "Expected Map, got \"{}\"{}",
+                    self.type_str(),
+                    self.origin_note()
+                ),
                 line,
             ))
         }
     }
 
+    /// Call a function or instantiate a class with already evaluated arguments.
+    pub fn call(
+        &self,
+        state: &mut LoxState,
+        args: Vec<LoxValue>,
+        line: u32,
+    ) -> LoxResult<LoxValue> {
+        match self {
+            Self::Function(func) => func.borrow().call(state, args, line),
+            Self::Class(class) => LoxObject::instantiate(class.clone(), state, args, line),
+            _ => Err(LoxError::Runtime(
+                format!(
+                    "Cannot call a non-function, got \"{}\"{}",
+                    self.type_str(),
+                    self.origin_note()
+                ),
+                line,
+            )),
+        }
+    }
+
+    /// Line where a function or class was defined, or an object was created.
+    /// Natives have no source position.
+    pub fn origin(&self) -> Option<u32> {
+        match self {
+            Self::Function(func) => func.borrow().origin(),
+            Self::Class(class) => class.borrow().origin,
+            Self::Object(obj) => obj.borrow().origin,
+            _ => None,
+        }
+    }
+
+    /// Suffix for error messages pointing at where this value came from.
+    pub fn origin_note(&self) -> String {
+        match (self, self.origin()) {
+            (Self::Object(_), Some(line)) => format!(" (created on line {})", line + 1),
+            (_, Some(line)) => format!(" (defined on line {})", line + 1),
+            (_, None) => String::new(),
+        }
+    }
+
+    /// The storage of an Array instance, or of a raw vec.
+    fn array_handle(&self) -> LoxResult<Rc<RefCell<Vec<LoxValue>>>> {
+        match self {
+            Self::Vec(vec) => Ok(vec.clone()),
+            Self::Object(obj) => match obj.borrow().get("__vec__") {
+                Some(Self::Vec(vec)) => Ok(vec),
+                _ => Err(LoxError::Type(
+                    "Array".into(),
+                    obj.borrow().class_name.clone(),
+                )),
+            },
+            _ => Err(LoxError::Type("Array".into(), self.type_str())),
+        }
+    }
+
+    /// The element at `index` of an Array.
+    pub fn index(&self, index: usize) -> LoxResult<LoxValue> {
+        let vec = self.array_handle()?;
+        let vec = vec.borrow();
+        vec.get(index).cloned().ok_or_else(|| {
+            LoxError::General(format!(
+                "Index {} is out of range for an Array of length {}",
+                index,
+                vec.len()
+            ))
+        })
+    }
+
+    /// Follow a dot separated path of properties and Array indices, e.g.
+    /// `"points.0.x"`.
+    pub fn get_path(&self, path: &str) -> LoxResult<LoxValue> {
+        let mut current = self.clone();
+        for segment in path.split('.') {
+            current = match (&current, segment.parse::<usize>()) {
+                (Self::Object(obj), Err(_)) => {
+                    LoxObject::lookup(obj, segment).ok_or_else(|| {
+                        LoxError::General(format!(
+                            "Undefined property \"{}\" in path \"{}\"",
+                            segment, path
+                        ))
+                    })?
+                }
+                (_, Ok(index)) => current.index(index)?,
+                _ => return Err(LoxError::Type("Object".into(), current.type_str())),
+            };
+        }
+        Ok(current)
+    }
+
+    /// The elements of an Array.
+    pub fn items(&self) -> LoxResult<impl Iterator<Item = LoxValue>> {
+        Ok(self.array_handle()?.borrow().clone().into_iter())
+    }
+
+    /// The entries of a Map, or the fields of any other instance, as name and
+    /// value pairs sorted by name.
+    pub fn entries(&self) -> LoxResult<impl Iterator<Item = (String, LoxValue)>> {
+        let obj = match self {
+            Self::Object(obj) => obj,
+            _ => return Err(LoxError::Type("Object".into(), self.type_str())),
+        };
+        if let Some(Self::Map(map)) = obj.borrow().get("__map__") {
+            return Ok(sorted_entries(&map.borrow()).into_iter());
+        }
+        let entries: Vec<(String, LoxValue)> = LoxObject::field_names(obj)
+            .into_iter()
+            .filter_map(|name| {
+                let value = obj.borrow().get(&name)?;
+                Some((name, value))
+            })
+            .collect();
+        Ok(entries.into_iter())
+    }
+
+    /// Values visited by `for-in`: the elements of an Array, the sorted keys
+    /// of a Map, or the field names of any other instance. Instances of
+    /// classes extending Array or Map count as such.
+    pub fn iter_items(&self, line: u32) -> LoxResult<Vec<LoxValue>> {
+        let obj = self.get_object(line)?;
+        let vec = obj.borrow().get("__vec__");
+        let map = obj.borrow().get("__map__");
+        match (vec, map) {
+            (Some(Self::Vec(vec)), _)
+                if obj
+                    .borrow()
+                    .class
+                    .as_ref()
+                    .is_some_and(|class| LoxClass::extends_builtin(class, "Array")) =>
+            {
+                Ok(vec.borrow().clone())
+            }
+            (_, Some(Self::Map(map))) => Ok(sorted_entries(&map.borrow())
+                .into_iter()
+                .map(|(key, _)| LoxValue::from(key))
+                .collect()),
+            _ => Ok(LoxObject::field_names(&obj)
+                .into_iter()
+                .map(LoxValue::from)
+                .collect()),
+        }
+    }
+
+    /// The method overloading a binary operator on this instance, e.g.
+    /// `__add__` for `+`.
+    pub fn operator_method(&self, operator: TokenKind) -> Option<LoxValue> {
+        let name = match operator {
+            TokenKind::Plus => "__add__",
+            _ => return None,
+        };
+        match self {
+            Self::Object(obj) => match LoxObject::lookup(obj, name) {
+                Some(method @ Self::Function(_)) => Some(method),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Render the value for `print`, using the instance's `to_string()`
+    /// method when it defines one. See `pretty::display`.
+    pub fn display(&self, state: &mut LoxState, line: u32) -> LoxResult<String> {
+        pretty::display(self, state, line)
+    }
+
     pub fn is_truthy(&self) -> bool {
         match self {
             Self::Nil => false,
@@ -169,6 +404,81 @@ impl LoxValue {
     }
 }
 
+/// `LoxValue::deep_equals`, assuming the pairs of containers in `visiting`,
+/// which are being compared further up, are equal.
+fn deep_equals(a: &LoxValue, b: &LoxValue, visiting: &mut Vec<(*const (), *const ())>) -> bool {
+    if a == b {
+        return true;
+    }
+    let pair = match (a, b) {
+        (LoxValue::Object(a), LoxValue::Object(b)) => {
+            (Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ())
+        }
+        (LoxValue::Vec(a), LoxValue::Vec(b)) => {
+            (Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ())
+        }
+        (LoxValue::Map(a), LoxValue::Map(b)) => {
+            (Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ())
+        }
+        _ => return false,
+    };
+    if visiting.contains(&pair) {
+        return true;
+    }
+    visiting.push(pair);
+    let equal = match (a, b) {
+        (LoxValue::Object(a), LoxValue::Object(b)) => {
+            let (a, b) = (a.borrow(), b.borrow());
+            let same_class = match (&a.class, &b.class) {
+                (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            };
+            same_class && props_equal(&a.props, &b.props, visiting)
+        }
+        (LoxValue::Vec(a), LoxValue::Vec(b)) => {
+            let (a, b) = (a.borrow(), b.borrow());
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b.iter())
+                    .all(|(a, b)| deep_equals(a, b, visiting))
+        }
+        (LoxValue::Map(a), LoxValue::Map(b)) => props_equal(&a.borrow(), &b.borrow(), visiting),
+        _ => false,
+    };
+    visiting.pop();
+    equal
+}
+
+fn props_equal(
+    a: &LoxProperties,
+    b: &LoxProperties,
+    visiting: &mut Vec<(*const (), *const ())>,
+) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .all(|(key, a)| b.get(key).is_some_and(|b| deep_equals(a, b, visiting)))
+}
+
+/// How numbers read when printed or converted to strings: integers without a
+/// fraction, other numbers with the fewest digits that read back the same,
+/// and exponents only for very large and very small magnitudes.
+/// `Number.to_fixed` gives control over the number of decimals.
+pub fn format_number(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".into()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Infinity" } else { "-Infinity" }.into()
+    } else if value == 0.0 {
+        // Including negative zero
+        "0".into()
+    } else if value.abs() >= 1e21 || value.abs() < 1e-6 {
+        format!("{:e}", value)
+    } else {
+        value.to_string()
+    }
+}
+
 impl From<bool> for LoxValue {
     fn from(value: bool) -> Self {
         Self::Boolean(value)
@@ -205,6 +515,12 @@ impl From<LoxClass> for LoxValue {
     }
 }
 
+impl From<Rc<RefCell<LoxClass>>> for LoxValue {
+    fn from(value: Rc<RefCell<LoxClass>>) -> Self {
+        Self::Class(value)
+    }
+}
+
 impl From<LoxObject> for LoxValue {
     fn from(value: LoxObject) -> Self {
         Self::Object(Rc::new(RefCell::new(value)))
@@ -217,15 +533,24 @@ impl From<Rc<RefCell<LoxObject>>> for LoxValue {
     }
 }
 
-impl From<Rc<LoxProperties>> for LoxValue {
-    fn from(value: Rc<LoxProperties>) -> Self {
-        Self::Super(value)
+impl From<LoxSuper> for LoxValue {
+    fn from(value: LoxSuper) -> Self {
+        Self::Super(Rc::new(value))
+    }
+}
+
+impl<T: Into<LoxValue>> From<Vec<T>> for LoxValue {
+    fn from(value: Vec<T>) -> Self {
+        Self::Vec(Rc::new(RefCell::new(
+            value.into_iter().map(Into::into).collect(),
+        )))
     }
 }
 
-impl From<Vec<LoxValue>> for LoxValue {
-    fn from(value: Vec<LoxValue>) -> Self {
-        Self::Vec(Rc::new(RefCell::new(value)))
+/// `None` becomes nil.
+impl<T: Into<LoxValue>> From<Option<T>> for LoxValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Nil, Into::into)
     }
 }
 
@@ -235,6 +560,82 @@ impl From<Rc<RefCell<Vec<LoxValue>>>> for LoxValue {
     }
 }
 
+impl From<LoxProperties> for LoxValue {
+    fn from(value: LoxProperties) -> Self {
+        Self::Map(Rc::new(RefCell::new(value)))
+    }
+}
+
+impl From<Rc<RefCell<LoxProperties>>> for LoxValue {
+    fn from(value: Rc<RefCell<LoxProperties>>) -> Self {
+        Self::Map(value)
+    }
+}
+
+impl From<()> for LoxValue {
+    fn from(_: ()) -> Self {
+        Self::Nil
+    }
+}
+
+impl TryFrom<&LoxValue> for f64 {
+    type Error = LoxError;
+
+    fn try_from(value: &LoxValue) -> Result<Self, Self::Error> {
+        match value {
+            LoxValue::Number(value) => Ok(*value),
+            _ => Err(LoxError::Type("Number".into(), value.type_str())),
+        }
+    }
+}
+
+impl TryFrom<&LoxValue> for bool {
+    type Error = LoxError;
+
+    fn try_from(value: &LoxValue) -> Result<Self, Self::Error> {
+        match value {
+            LoxValue::Boolean(value) => Ok(*value),
+            _ => Err(LoxError::Type("Boolean".into(), value.type_str())),
+        }
+    }
+}
+
+impl TryFrom<&LoxValue> for String {
+    type Error = LoxError;
+
+    fn try_from(value: &LoxValue) -> Result<Self, Self::Error> {
+        match value {
+            LoxValue::String(value) => Ok(value.clone()),
+            _ => Err(LoxError::Type("String".into(), value.type_str())),
+        }
+    }
+}
+
+/// Accepts both raw vecs and instances of the builtin Array class.
+impl TryFrom<&LoxValue> for Vec<LoxValue> {
+    type Error = LoxError;
+
+    fn try_from(value: &LoxValue) -> Result<Self, Self::Error> {
+        Ok(value.array_handle()?.borrow().clone())
+    }
+}
+
+macro_rules! try_from_owned {
+    ($($ty:ty),*) => {
+        $(
+            impl TryFrom<LoxValue> for $ty {
+                type Error = LoxError;
+
+                fn try_from(value: LoxValue) -> Result<Self, Self::Error> {
+                    Self::try_from(&value)
+                }
+            }
+        )*
+    };
+}
+
+try_from_owned!(f64, bool, String, Vec<LoxValue>);
+
 impl From<Token> for LoxValue {
     fn from(token: Token) -> Self {
         match token.literal {
@@ -254,7 +655,7 @@ impl ToString for LoxValue {
         match self {
             Self::Nil => "nil".into(),
             Self::Boolean(value) => value.to_string(),
-            Self::Number(value) => value.to_string(),
+            Self::Number(value) => format_number(*value),
             Self::String(value) => value.clone(),
             Self::Function(func) => {
                 format!(
@@ -270,6 +671,84 @@ impl ToString for LoxValue {
             }
             Self::Super(_) => "<super>".into(),
             Self::Vec(_) => "<vec>".into(),
+            Self::Map(_) => "<map>".into(),
         }
     }
 }
+
+/// The entries of a Map sorted by key, so iteration order is stable.
+pub fn sorted_entries(map: &LoxProperties) -> Vec<(String, LoxValue)> {
+    let mut entries: Vec<(String, LoxValue)> = map
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    entries
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::LoxInterpreter;
+
+    #[test]
+    fn conversions() -> LoxResult {
+        assert_eq!(f64::try_from(LoxValue::from(1.5))?, 1.5);
+        assert!(bool::try_from(&LoxValue::from(true))?);
+        assert_eq!(String::try_from(LoxValue::from("lox"))?, "lox");
+        let values = vec![LoxValue::from(1.0), LoxValue::Nil];
+        assert!(Vec::<LoxValue>::try_from(LoxValue::from(values.clone()))? == values);
+        assert!(LoxValue::from(()).is_nil());
+        assert!(matches!(
+            f64::try_from(LoxValue::from("1")).map_err(|err| err.at_line(3)),
+            Err(LoxError::Runtime(message, 3)) if message == "Expected Number, got \"String\""
+        ));
+        assert!(matches!(
+            Vec::<LoxValue>::try_from(LoxValue::Nil),
+            Err(LoxError::Type(expected, actual)) if expected == "Array" && actual == "nil"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn traversal() -> LoxResult {
+        let mut lox = LoxInterpreter::new();
+        let (value, _) = lox.exec_capture(
+            r#"
+            class Point {
+                init(x) {
+                    this.x = x;
+                }
+            }
+            class Shape {}
+            var shape = Shape();
+            shape.points = Array();
+            shape.points.push(Point(1));
+            shape.points.push(Point(2));
+            shape.name = "line";
+            shape;
+        "#,
+        )?;
+        assert_eq!(f64::try_from(value.get_path("points.1.x")?)?, 2.0);
+        assert_eq!(
+            f64::try_from(value.get_path("points")?.index(0)?.get_path("x")?)?,
+            1.0
+        );
+        assert_eq!(value.get_path("points")?.items()?.count(), 2);
+        let names: Vec<String> = value.entries()?.map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["name", "points"]);
+        assert!(matches!(
+            value.get_path("points.2"),
+            Err(LoxError::General(message)) if message == "Index 2 is out of range for an Array of length 2"
+        ));
+        assert!(matches!(
+            value.get_path("points.0.y"),
+            Err(LoxError::General(message)) if message == "Undefined property \"y\" in path \"points.0.y\""
+        ));
+        assert!(matches!(
+            value.get_path("name.length"),
+            Err(LoxError::Type(expected, actual)) if expected == "Object" && actual == "String"
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{scanner::Token, value::LoxValue};
+
+pub enum OpCode {
+    Constant(usize),
+    Nil,
+    Pop,
+    /// Push a copy of the top of the stack.
+    Dup,
+    GetLocal(usize),
+    SetLocal(usize),
+    GetGlobal(String),
+    SetGlobal(String),
+    DefineGlobal(String),
+    GetProperty(String),
+    /// The property name, and the source of the receiver for errors.
+    SetProperty(String, String),
+    Unary(Token),
+    Binary(Token),
+    Print,
+    /// Jump to the absolute offset.
+    Jump(usize),
+    /// Jump to the absolute offset if the top of the stack is falsy, without
+    /// popping it.
+    JumpIfFalse(usize),
+    /// Call the callee below this many arguments.
+    Call(usize),
+}
+
+/// A compiled instruction stream with its constants and source lines.
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<OpCode>,
+    pub lines: Vec<u32>,
+    pub constants: Vec<LoxValue>,
+}
+
+impl Chunk {
+    /// Append an instruction, returning its offset.
+    pub fn write(&mut self, op: OpCode, line: u32) -> usize {
+        self.code.push(op);
+        self.lines.push(line);
+        self.code.len() - 1
+    }
+
+    pub fn add_constant(&mut self, value: LoxValue) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// Point the jump at `offset` to the next instruction written.
+    pub fn patch_jump(&mut self, offset: usize) {
+        let target = self.code.len();
+        match &mut self.code[offset] {
+            OpCode::Jump(to) | OpCode::JumpIfFalse(to) => *to = target,
+            _ => panic!("Expected a jump at offset {}", offset),
+        }
+    }
+}
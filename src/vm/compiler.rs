@@ -0,0 +1,253 @@
+use super::chunk::{Chunk, OpCode};
+use crate::{
+    expr::{Expr, ExprKind},
+    scanner::TokenKind,
+    stmt::Stmt,
+    value::LoxValue,
+};
+
+/// Lowers a top-level statement into a chunk. Functions, classes and the
+/// other constructs that need runtime scopes aren't supported, statements
+/// using them are left to the tree-walker.
+pub struct Compiler {
+    chunk: Chunk,
+    /// Names of the locals on the stack, with the block depth they were
+    /// declared at.
+    locals: Vec<(String, usize)>,
+    depth: usize,
+}
+
+impl Compiler {
+    /// Compile `stmt`, or `None` if it uses unsupported constructs. The chunk
+    /// leaves the value of an expression statement on the stack.
+    pub fn compile(stmt: &Stmt) -> Option<Chunk> {
+        let mut compiler = Compiler {
+            chunk: Chunk::default(),
+            locals: vec![],
+            depth: 0,
+        };
+        match stmt {
+            Stmt::Expr(expr) => compiler.expr(expr)?,
+            stmt => compiler.stmt(stmt)?,
+        }
+        Some(compiler.chunk)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Option<()> {
+        let line = stmt.line();
+        match stmt {
+            Stmt::Expr(expr) => {
+                self.expr(expr)?;
+                self.chunk.write(OpCode::Pop, line);
+            }
+            Stmt::Print(expr) => {
+                self.expr(expr)?;
+                self.chunk.write(OpCode::Print, line);
+            }
+            Stmt::Var {
+                name,
+                initializer,
+                mutable: true,
+            } => {
+                match initializer {
+                    Some(expr) => self.expr(expr)?,
+                    None => {
+                        self.chunk.write(OpCode::Nil, line);
+                    }
+                }
+                if self.depth == 0 {
+                    self.chunk
+                        .write(OpCode::DefineGlobal(name.lexeme_str()), line);
+                } else {
+                    // The value stays on the stack as the local's slot
+                    self.locals.push((name.lexeme_str(), self.depth));
+                }
+            }
+            Stmt::Block { statements, .. } => {
+                self.depth += 1;
+                for stmt in statements.iter() {
+                    self.stmt(stmt)?;
+                }
+                self.depth -= 1;
+                while self
+                    .locals
+                    .last()
+                    .is_some_and(|(_, depth)| *depth > self.depth)
+                {
+                    self.locals.pop();
+                    self.chunk.write(OpCode::Pop, line);
+                }
+            }
+            Stmt::IfElse {
+                condition,
+                body,
+                else_branch,
+            } => {
+                self.expr(condition)?;
+                let then_jump = self.chunk.write(OpCode::JumpIfFalse(0), line);
+                self.chunk.write(OpCode::Pop, line);
+                self.stmt(body)?;
+                let else_jump = self.chunk.write(OpCode::Jump(0), line);
+                self.chunk.patch_jump(then_jump);
+                self.chunk.write(OpCode::Pop, line);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch)?;
+                }
+                self.chunk.patch_jump(else_jump);
+            }
+            Stmt::WhileLoop { condition, body } => {
+                let start = self.chunk.code.len();
+                self.expr(condition)?;
+                let exit_jump = self.chunk.write(OpCode::JumpIfFalse(0), line);
+                self.chunk.write(OpCode::Pop, line);
+                self.stmt(body)?;
+                self.chunk.write(OpCode::Jump(start), line);
+                self.chunk.patch_jump(exit_jump);
+                self.chunk.write(OpCode::Pop, line);
+            }
+            // Closures aren't compiled, so nothing can capture the loop
+            // variables and a single binding for every iteration is fine
+            Stmt::ForLoop {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.depth += 1;
+                self.stmt(initializer)?;
+                let start = self.chunk.code.len();
+                self.expr(condition)?;
+                let exit_jump = self.chunk.write(OpCode::JumpIfFalse(0), line);
+                self.chunk.write(OpCode::Pop, line);
+                self.stmt(body)?;
+                self.expr(increment)?;
+                self.chunk.write(OpCode::Pop, line);
+                self.chunk.write(OpCode::Jump(start), line);
+                self.chunk.patch_jump(exit_jump);
+                self.chunk.write(OpCode::Pop, line);
+                self.depth -= 1;
+                while self
+                    .locals
+                    .last()
+                    .is_some_and(|(_, depth)| *depth > self.depth)
+                {
+                    self.locals.pop();
+                    self.chunk.write(OpCode::Pop, line);
+                }
+            }
+            // Constants are left to the tree-walker to record
+            Stmt::Var { mutable: false, .. }
+            | Stmt::ForIn { .. }
+            | Stmt::Fun { .. }
+            | Stmt::Return(_)
+            | Stmt::Defer { .. }
+            | Stmt::Using { .. }
+            | Stmt::Throw { .. }
+            | Stmt::Try { .. }
+            | Stmt::Class { .. }
+            | Stmt::Destructure { .. } => return None,
+        }
+        Some(())
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Option<()> {
+        let line = expr.line();
+        match &expr.kind {
+            ExprKind::Literal(token) => {
+                let constant = self.chunk.add_constant(LoxValue::from(token.clone()));
+                self.chunk.write(OpCode::Constant(constant), line);
+            }
+            ExprKind::Unary { operator, right } => {
+                self.expr(right)?;
+                self.chunk.write(OpCode::Unary(operator.clone()), line);
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                self.expr(left)?;
+                self.expr(right)?;
+                self.chunk.write(OpCode::Binary(operator.clone()), line);
+            }
+            ExprKind::Grouping(inner) => self.expr(inner)?,
+            ExprKind::Identifier(name) => {
+                let name = name.lexeme_str();
+                let op = match self.resolve(&name) {
+                    Some(slot) => OpCode::GetLocal(slot),
+                    None => OpCode::GetGlobal(name),
+                };
+                self.chunk.write(op, line);
+            }
+            ExprKind::Assignment { name, value } => {
+                self.expr(value)?;
+                let name = name.lexeme_str();
+                let op = match self.resolve(&name) {
+                    Some(slot) => OpCode::SetLocal(slot),
+                    None => OpCode::SetGlobal(name),
+                };
+                self.chunk.write(op, line);
+            }
+            ExprKind::Logical {
+                operator,
+                left,
+                right,
+            } => {
+                self.expr(left)?;
+                let short_circuit = match operator.kind {
+                    TokenKind::And => self.chunk.write(OpCode::JumpIfFalse(0), line),
+                    _ => {
+                        let else_jump = self.chunk.write(OpCode::JumpIfFalse(0), line);
+                        let end_jump = self.chunk.write(OpCode::Jump(0), line);
+                        self.chunk.patch_jump(else_jump);
+                        end_jump
+                    }
+                };
+                self.chunk.write(OpCode::Pop, line);
+                self.expr(right)?;
+                self.chunk.patch_jump(short_circuit);
+            }
+            ExprKind::Call { callee, arguments } => {
+                self.expr(callee)?;
+                for arg in arguments.iter() {
+                    self.expr(arg)?;
+                }
+                self.chunk.write(OpCode::Call(arguments.len()), line);
+            }
+            ExprKind::Get { left, name, .. } => {
+                self.expr(left)?;
+                self.chunk
+                    .write(OpCode::GetProperty(name.to_string()), line);
+            }
+            ExprKind::Set {
+                object,
+                name,
+                operator,
+                value,
+                ..
+            } => {
+                self.expr(object)?;
+                if let Some(operator) = operator {
+                    self.chunk.write(OpCode::Dup, line);
+                    self.chunk
+                        .write(OpCode::GetProperty(name.to_string()), line);
+                    self.expr(value)?;
+                    self.chunk.write(OpCode::Binary(operator.clone()), line);
+                } else {
+                    self.expr(value)?;
+                }
+                self.chunk.write(
+                    OpCode::SetProperty(name.to_string(), object.to_source()),
+                    line,
+                );
+            }
+            ExprKind::This(_) | ExprKind::Super(_) | ExprKind::Tuple(_) => return None,
+        }
+        Some(())
+    }
+
+    /// Stack slot of the innermost local named `name`.
+    fn resolve(&self, name: &str) -> Option<usize> {
+        self.locals.iter().rposition(|(local, _)| local == name)
+    }
+}
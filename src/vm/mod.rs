@@ -0,0 +1,178 @@
+mod chunk;
+mod compiler;
+
+use self::chunk::OpCode;
+pub use self::{chunk::Chunk, compiler::Compiler};
+use crate::{
+    environment::ScopeHandle,
+    error::*,
+    expr::{eval_binary, eval_get, eval_unary, set_target},
+    state::LoxState,
+    value::LoxValue,
+};
+
+/// Executes compiled chunks on a value stack, sharing globals and the heap
+/// with the tree-walker.
+pub struct Vm {
+    stack: Vec<LoxValue>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self { stack: vec![] }
+    }
+
+    /// Run `chunk` with the globals of `scope`, returning the value it left
+    /// on the stack or `nil`.
+    pub fn run(
+        &mut self,
+        chunk: &Chunk,
+        state: &mut LoxState,
+        scope: ScopeHandle,
+    ) -> LoxResult<LoxValue> {
+        self.stack.clear();
+        let mut ip = 0;
+        while let Some(op) = chunk.code.get(ip) {
+            let line = chunk.lines[ip];
+            ip += 1;
+            state.step()?;
+            match op {
+                OpCode::Constant(index) => self.stack.push(chunk.constants[*index].clone()),
+                OpCode::Nil => self.stack.push(LoxValue::Nil),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::Dup => self.stack.push(self.peek().clone()),
+                OpCode::GetLocal(slot) => self.stack.push(self.stack[*slot].clone()),
+                OpCode::SetLocal(slot) => self.stack[*slot] = self.peek().clone(),
+                OpCode::GetGlobal(name) => {
+                    let value = state.get_variable(scope, name, line)?;
+                    self.stack.push(value);
+                }
+                OpCode::SetGlobal(name) => {
+                    let value = self.peek().clone();
+                    state.assign_variable_at(scope, None, name, value, line)?;
+                }
+                OpCode::DefineGlobal(name) => {
+                    let value = self.pop();
+                    state.env.declare(Some(scope), name.clone(), value);
+                }
+                OpCode::GetProperty(name) => {
+                    let object = self.pop();
+                    let value = eval_get(state, object, name, line)?;
+                    self.stack.push(value);
+                }
+                OpCode::SetProperty(name, receiver) => {
+                    let value = self.pop();
+                    let obj = set_target(self.pop(), name, || receiver.clone(), line)?;
+                    state.set_property(&obj, name, value.clone(), line)?;
+                    self.stack.push(value);
+                }
+                OpCode::Unary(operator) => {
+                    let right = self.pop();
+                    self.stack.push(eval_unary(operator, right, line)?);
+                }
+                OpCode::Binary(operator) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = eval_binary(state, operator, left, right, line)?;
+                    self.stack.push(value);
+                }
+                OpCode::Print => {
+                    let text = self.pop().display(state, line)?;
+                    state.print(&text);
+                }
+                OpCode::Jump(target) => ip = *target,
+                OpCode::JumpIfFalse(target) => {
+                    if !self.peek().is_truthy() {
+                        ip = *target;
+                    }
+                }
+                OpCode::Call(arg_count) => {
+                    let args = self.stack.split_off(self.stack.len() - arg_count);
+                    let callee = self.pop();
+                    let value = callee.call(state, args, line)?;
+                    self.stack.push(value);
+                }
+            }
+        }
+        Ok(self.stack.pop().unwrap_or(LoxValue::Nil))
+    }
+
+    fn pop(&mut self) -> LoxValue {
+        self.stack.pop().expect("Expected a value on the stack")
+    }
+
+    fn peek(&self) -> &LoxValue {
+        self.stack.last().expect("Expected a value on the stack")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::LoxConfig, interpreter::*, parser::*, test_scripts::*};
+
+    /// Run `source` on both backends, checking they print the same thing.
+    fn assert_same_output(source: &str) -> LoxResult {
+        let mut tree_walk = LoxInterpreter::new();
+        let mut bytecode = LoxInterpreter::with_backend(LoxConfig::default(), Backend::Bytecode);
+        let (expected_value, expected) = tree_walk.exec_capture(source)?;
+        let (value, output) = bytecode.exec_capture(source)?;
+        assert_eq!(output, expected);
+        assert!(value == expected_value);
+        Ok(())
+    }
+
+    #[test]
+    fn backends_agree() -> LoxResult {
+        for source in [
+            VARIABLE_TEST,
+            PRINT_TEST,
+            BLOCK_SCOPE_TEST,
+            CONTROL_FLOW_TEST,
+            FOR_LOOP_TEST,
+            FOR_CLOSURE_TEST,
+            FUNCTION_TEST,
+            CLASS_TEST,
+            EARLY_RETURN_TEST,
+            OPERATOR_OVERLOAD_TEST,
+        ] {
+            assert_same_output(source)?;
+        }
+        assert_same_output("var x = 1; {} print x;")?;
+        assert_same_output("while (false) {} print 1;")?;
+        assert_same_output(
+            r#"
+            var total = 0;
+            {
+                var i = 0;
+                while (i < 10) {
+                    var doubled = i * 2;
+                    if (doubled > 10 and i != 7 or i == 1) total = total + doubled;
+                    i = i + 1;
+                }
+            }
+            print total;
+            var words = "a,b".split(",");
+            print words.len();
+            total;
+        "#,
+        )
+    }
+
+    #[test]
+    fn unsupported() {
+        let compiles = |source: &str| {
+            let ParseResult { statements, errors } = parse(source);
+            assert_eq!(errors.len(), 0);
+            Compiler::compile(&statements[0]).is_some()
+        };
+        assert!(compiles("while (true) { var x = 1; print x; }"));
+        assert!(compiles("a.b += 1;"));
+        assert!(!compiles("fun f() {}"));
+        assert!(!compiles("{ class A {} }"));
+        assert!(compiles("for (var i = 0; i < 2; i = i + 1) print i;"));
+        assert!(!compiles("for (var x in y) print x;"));
+    }
+}